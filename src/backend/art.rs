@@ -0,0 +1,550 @@
+// Copyright © 2021-2022 Jakob L. Kreuze <zerodaysfordays@sdf.org>
+//
+// This file is part of Tunes.
+//
+// Tunes is free software; you can redistribute it and/or modify it
+// under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation; either version 3 of the
+// License, or (at your option) any later version.
+//
+// Tunes is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY
+// or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Affero General
+// Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public
+// License along with Tunes. If not, see <http://www.gnu.org/licenses/>.
+
+use futures::channel::mpsc;
+use gtk::prelude::*;
+use gtk::{gdk_pixbuf, gio, glib};
+use std::cell::RefCell;
+use std::collections::{HashSet, VecDeque};
+use std::io::Read;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tunes_core::{album_artist, default_genre_color, Client, DecodedArt, StateUpdateKind};
+
+use crate::backend::{connect_patiently, Options};
+use crate::models::{art_from_pixbuf, decode_art};
+
+/// The cover for `song`: a cover file from its directory if there is one, or
+/// else the picture embedded in the song itself. Plenty of albums only have
+/// the latter, especially ones that were never ripped into folders of their
+/// own.
+///
+/// Covers are kept in a cache on disk, so we only have to fetch each one over
+/// the network once.
+fn album_art(conn: &mut Client, song: &mpd::song::Song) -> anyhow::Result<Option<Vec<u8>>> {
+    let cache = AlbumArtCache::path(song);
+    if let Ok(data) = std::fs::read(&cache) {
+        return Ok(Some(data));
+    }
+
+    let data = match conn.albumart(song) {
+        Ok(data) if !data.is_empty() => Some(data),
+        // MPD says so when there's no cover file.
+        Ok(_) | Err(mpd::error::Error::Server(_)) => match conn.readpicture(song) {
+            Ok(data) => data,
+            // Servers older than 0.22 don't know how.
+            Err(mpd::error::Error::Server(_)) => None,
+            Err(e) => return Err(e.into()),
+        },
+        Err(e) => return Err(e.into()),
+    };
+    if let Some(data) = &data {
+        AlbumArtCache::store(&cache, data);
+    }
+    Ok(data)
+}
+
+/// Album art to fetch for the now playing view, scaled to `size` pixels.
+pub(crate) struct AlbumArtRequest {
+    pub(crate) song: mpd::song::Song,
+    pub(crate) size: i32,
+    pub(crate) generation: u64,
+}
+
+/// Start a thread that fetches and decodes album art, which can take a while
+/// over a slow connection or for a huge embedded picture. It has a connection
+/// of its own, and sends what it finds back as `AlbumArtLoaded`.
+pub(crate) fn spawn_album_art_loader(
+    options: &Options,
+    sender: mpsc::Sender<StateUpdateKind>,
+) -> std::sync::mpsc::Sender<AlbumArtRequest> {
+    let (requests, receiver) = std::sync::mpsc::channel::<AlbumArtRequest>();
+    let options = options.clone();
+    std::thread::spawn(move || {
+        let mut sender = sender;
+        let mut conn = None;
+        let mut server_changes = options.server_changes();
+        // Albums the Cover Art Archive has nothing for, so we don't ask again
+        // every time one of their songs comes on.
+        let mut online_misses = HashSet::new();
+        let mut last_online_lookup: Option<Instant> = None;
+        // Failed lookups in a row. A service that's down shouldn't hold up
+        // every song change, so it only gets so many.
+        let mut online_failures = 0;
+        let agent = ureq::AgentBuilder::new()
+            .timeout(ONLINE_ART_TIMEOUT)
+            .user_agent(USER_AGENT)
+            .build();
+        while let Ok(request) = receiver.recv() {
+            // Songs can change faster than we can keep up with, and only the
+            // newest matters.
+            let request = receiver.try_iter().last().unwrap_or(request);
+            // A connection to a server we've since moved on from is no good.
+            if conn.is_none() || server_changes != options.server_changes() {
+                server_changes = options.server_changes();
+                conn = connect_patiently(&options).ok();
+            }
+            let data = match conn.as_mut().map(|conn| album_art(conn, &request.song)) {
+                Some(Ok(Some(data))) => Some(data),
+                // Only once the server's sure there's no cover might the
+                // user have let us look further afield.
+                Some(Ok(None)) if ONLINE_ART.load(Ordering::Relaxed) => {
+                    let path = AlbumArtCache::path(&request.song);
+                    if online_misses.contains(&path) {
+                        None
+                    } else {
+                        if let Some(elapsed) = last_online_lookup.map(|x| x.elapsed()) {
+                            std::thread::sleep(ONLINE_ART_INTERVAL.saturating_sub(elapsed));
+                        }
+                        last_online_lookup = Some(Instant::now());
+                        let result = cover_art_archive(&agent, &request.song);
+                        online_failures = if result.is_ok() { 0 } else { online_failures + 1 };
+                        match result {
+                            Ok(Some(data)) => {
+                                AlbumArtCache::store(&path, &data);
+                                Some(data)
+                            }
+                            Ok(None) => {
+                                online_misses.insert(path);
+                                None
+                            }
+                            Err(e) if online_failures >= ONLINE_ART_FAILURES => {
+                                eprintln!("Couldn't look up album art online, so stopping for now: {}", e);
+                                online_failures = 0;
+                                ONLINE_ART.store(false, Ordering::Relaxed);
+                                sender
+                                    .try_send(StateUpdateKind::OnlineArtStopped)
+                                    .expect("Couldn't notify thread");
+                                None
+                            }
+                            Err(_) => None,
+                        }
+                    }
+                }
+                Some(Ok(None)) | None => None,
+                Some(Err(e)) => {
+                    eprintln!("Couldn't fetch album art: {}", e);
+                    // Most likely the connection's gone, so we'll get a new
+                    // one next time.
+                    conn = None;
+                    None
+                }
+            };
+            let art = data.and_then(|data| decode_art(&data, request.size));
+            sender
+                .try_send(StateUpdateKind::AlbumArtLoaded(request.generation, art))
+                .expect("Couldn't notify thread");
+        }
+    });
+    requests
+}
+
+/// Size (in pixels) of the covers on the album grid.
+pub(crate) const THUMBNAIL_SIZE: i32 = 96;
+
+/// Size (in pixels) of each collage on the playlist grid.
+pub(crate) const COLLAGE_SIZE: i32 = 128;
+
+/// How many threads make thumbnails at once. Each has a connection of its
+/// own, so there's no sense in more than a few.
+const THUMBNAIL_WORKERS: usize = 2;
+
+/// How many thumbnails are kept around in memory. A big library has far more
+/// albums than that, but only a screenful or two are ever needed at once.
+const THUMBNAIL_CACHE_SIZE: usize = 200;
+
+/// Something for the thumbnail loaders to make.
+pub(crate) enum ThumbnailRequest {
+    /// A cover for the album grid, asked for as of some scroll position.
+    Album { album: String, generation: u64 },
+    /// A collage for the playlist grid.
+    Collage(mpd::Playlist),
+}
+
+/// Start a few threads making thumbnails for the album and playlist grids,
+/// and return where to send them requests. Album requests from before the
+/// latest `generation` are dropped, since the grid's been scrolled since and
+/// the albums they're for may well be off screen.
+pub(crate) fn spawn_thumbnail_loaders(
+    options: &Options,
+    sender: mpsc::Sender<StateUpdateKind>,
+) -> (std::sync::mpsc::Sender<ThumbnailRequest>, Arc<AtomicU64>) {
+    let (requests, receiver) = std::sync::mpsc::channel::<ThumbnailRequest>();
+    let receiver = Arc::new(Mutex::new(receiver));
+    let generation = Arc::new(AtomicU64::new(0));
+    for _ in 0..THUMBNAIL_WORKERS {
+        let (options, receiver, generation) = (options.clone(), receiver.clone(), generation.clone());
+        let mut sender = sender.clone();
+        std::thread::spawn(move || {
+            let mut conn = None;
+            let mut server_changes = options.server_changes();
+            loop {
+                let request = match receiver.lock().expect("Couldn't take request").recv() {
+                    Ok(request) => request,
+                    Err(_) => break,
+                };
+                if let ThumbnailRequest::Album { generation: wanted, .. } = &request {
+                    if *wanted < generation.load(Ordering::Relaxed) {
+                        continue;
+                    }
+                }
+                if conn.is_none() || server_changes != options.server_changes() {
+                    server_changes = options.server_changes();
+                    conn = connect_patiently(&options).ok();
+                }
+                let made = conn.as_mut().map(|conn| match &request {
+                    ThumbnailRequest::Album { album, .. } => album_thumbnail(conn, album),
+                    ThumbnailRequest::Collage(playlist) => playlist_collage(conn, playlist),
+                });
+                let art = match made {
+                    Some(Ok(art)) => art,
+                    None => None,
+                    Some(Err(e)) => {
+                        eprintln!("Couldn't make thumbnail: {}", e);
+                        conn = None;
+                        None
+                    }
+                };
+                let event = match request {
+                    ThumbnailRequest::Album { album, .. } => StateUpdateKind::ThumbnailLoaded(album, art),
+                    ThumbnailRequest::Collage(playlist) => StateUpdateKind::CollageLoaded(playlist.name, art),
+                };
+                sender.try_send(event).expect("Couldn't notify thread");
+            }
+        });
+    }
+    (requests, generation)
+}
+
+/// The cover of `album`, sized for the album grid.
+fn album_thumbnail(conn: &mut Client, album: &str) -> anyhow::Result<Option<DecodedArt>> {
+    // Any song off the album will do for its cover.
+    let mut query = mpd::Query::new();
+    query.and(mpd::Term::Tag("Album".into()), album);
+    let data = match conn.find(&query, (0, 1))?.first() {
+        Some(song) => album_art(conn, song)?,
+        None => None,
+    };
+    Ok(data.and_then(|data| decode_art(&data, THUMBNAIL_SIZE)))
+}
+
+/// The thumbnails used most recently, up to a limit. Once it's full, the one
+/// that's gone unused longest makes way.
+pub(crate) struct ThumbnailCache {
+    pub(crate) entries: RefCell<VecDeque<(String, gdk_pixbuf::Pixbuf)>>,
+}
+
+impl ThumbnailCache {
+    pub(crate) fn get(&self, album: &str) -> Option<gdk_pixbuf::Pixbuf> {
+        let mut entries = self.entries.borrow_mut();
+        let i = entries.iter().position(|(x, _)| x == album)?;
+        let entry = entries.remove(i)?;
+        let pixbuf = entry.1.clone();
+        entries.push_back(entry);
+        Some(pixbuf)
+    }
+
+    pub(crate) fn clear(&self) {
+        self.entries.borrow_mut().clear();
+    }
+
+    pub(crate) fn insert(&self, album: String, pixbuf: gdk_pixbuf::Pixbuf) {
+        let mut entries = self.entries.borrow_mut();
+        entries.retain(|(x, _)| *x != album);
+        entries.push_back((album, pixbuf));
+        while entries.len() > THUMBNAIL_CACHE_SIZE {
+            entries.pop_front();
+        }
+    }
+}
+
+/// Where the switch for looking up covers online lives. It's off unless the
+/// user turns it on, since it tells outside services what they listen to.
+pub(crate) const ONLINE_ART_SECTION: &str = "album-art";
+
+pub(crate) const ONLINE_ART_KEY: &str = "online-lookup";
+
+/// Whether to look up covers online, as the switch says. It's read from the
+/// preferences once at startup, and the switch keeps it up to date after.
+pub(crate) static ONLINE_ART: AtomicBool = AtomicBool::new(false);
+
+/// MusicBrainz asks for no more than one lookup a second.
+const ONLINE_ART_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How many online lookups can fail in a row before they're turned off for
+/// the rest of the session.
+const ONLINE_ART_FAILURES: u32 = 3;
+
+/// How long an online lookup gets before it counts as failed.
+const ONLINE_ART_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// MusicBrainz asks that clients say who they are.
+const USER_AGENT: &str = concat!("Tunes/", env!("CARGO_PKG_VERSION"));
+
+/// Covers larger than this are more likely a mistake than a cover.
+const ONLINE_ART_LIMIT: u64 = 16 * 1024 * 1024;
+
+/// Find the front cover for `song`'s album on the Cover Art Archive, by
+/// looking up the release on MusicBrainz by album and artist.
+fn cover_art_archive(agent: &ureq::Agent, song: &mpd::song::Song) -> anyhow::Result<Option<Vec<u8>>> {
+    let album = match song.tags.get("Album") {
+        Some(album) => album,
+        None => return Ok(None),
+    };
+    let artist = album_artist(song).or(song.artist.as_deref()).unwrap_or_default();
+    // Lucene wants quotes inside phrases escaped.
+    let phrase = |x: &str| format!("\"{}\"", x.replace('\\', "\\\\").replace('"', "\\\""));
+    let mut query = format!("release:{}", phrase(album));
+    if !artist.is_empty() {
+        query.push_str(&format!(" AND artist:{}", phrase(artist)));
+    }
+
+    let releases: serde_json::Value = agent
+        .get("https://musicbrainz.org/ws/2/release/")
+        .query("query", &query)
+        .query("fmt", "json")
+        .query("limit", "1")
+        .call()?
+        .into_json()?;
+    let id = match releases["releases"][0]["id"].as_str() {
+        Some(id) => id,
+        None => return Ok(None),
+    };
+
+    let response = match agent
+        .get(&format!("https://coverartarchive.org/release/{}/front-500", id))
+        .call()
+    {
+        Ok(response) => response,
+        // Plenty of releases don't have any art uploaded.
+        Err(ureq::Error::Status(404, _)) => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    let mut data = Vec::new();
+    response
+        .into_reader()
+        .take(ONLINE_ART_LIMIT)
+        .read_to_end(&mut data)?;
+    Ok(Some(data))
+}
+
+/// Stand-in art for songs without any: the initials of `name` on a tile in a
+/// color picked from it, so the same album always looks the same. Without a
+/// name there's a note instead.
+pub(crate) fn placeholder_art(name: &str, size: i32) -> Option<gdk_pixbuf::Pixbuf> {
+    let initials: String = name
+        .split_whitespace()
+        .filter_map(|word| word.chars().find(|c| c.is_alphanumeric()))
+        .take(2)
+        .flat_map(char::to_uppercase)
+        .collect();
+    let text = if initials.is_empty() { "♪".into() } else { initials };
+    let color = gtk::gdk::RGBA::parse(&default_genre_color(name)).ok()?;
+
+    let surface = gtk::cairo::ImageSurface::create(gtk::cairo::Format::ARgb32, size, size).ok()?;
+    let context = gtk::cairo::Context::new(&surface).ok()?;
+    context.set_source_rgb(color.red(), color.green(), color.blue());
+    context.paint().ok()?;
+
+    context.set_source_rgb(1.0, 1.0, 1.0);
+    context.select_font_face("Sans", gtk::cairo::FontSlant::Normal, gtk::cairo::FontWeight::Bold);
+    context.set_font_size(f64::from(size) * 0.4);
+    let extents = context.text_extents(&text).ok()?;
+    context.move_to(
+        (f64::from(size) - extents.width()) / 2.0 - extents.x_bearing(),
+        (f64::from(size) - extents.height()) / 2.0 - extents.y_bearing(),
+    );
+    context.show_text(&text).ok()?;
+    drop(context);
+
+    gtk::gdk::pixbuf_get_from_surface(&surface, 0, 0, size, size)
+}
+
+/// Covers we've fetched before, one file per album under the user's cache
+/// directory.
+pub(crate) struct AlbumArtCache;
+
+impl AlbumArtCache {
+    /// Once the cache grows past this many bytes, the oldest covers go.
+    pub(crate) const LIMIT: u64 = 64 * 1024 * 1024;
+
+    pub(crate) fn directory() -> PathBuf {
+        glib::user_cache_dir().join("tunes").join("album-art")
+    }
+
+    /// Where the cover for `song` goes. Songs on the same album share one,
+    /// and songs that aren't on any album get their own.
+    pub(crate) fn path(song: &mpd::song::Song) -> PathBuf {
+        let key = match song.tags.get("Album") {
+            Some(album) => format!("{}\n{}", album_artist(song).unwrap_or_default(), album),
+            None => song.file.clone(),
+        };
+        let hash = glib::compute_checksum_for_string(glib::ChecksumType::Sha256, &key)
+            .expect("Couldn't hash album");
+        Self::directory().join(hash.as_str())
+    }
+
+    /// Save `data` at `path`, and then make room for it. Any of that going
+    /// wrong only means fetching the cover again next time.
+    pub(crate) fn store(path: &std::path::Path, data: &[u8]) {
+        if let Err(e) = std::fs::create_dir_all(Self::directory())
+            .and_then(|_| std::fs::write(path, data))
+        {
+            eprintln!("Couldn't cache album art: {}", e);
+            return;
+        }
+        trim_cache(&Self::directory());
+    }
+}
+
+/// Make room in a cache `directory` by removing the files that have gone
+/// longest without changing, until it's back under `AlbumArtCache::LIMIT`.
+fn trim_cache(directory: &std::path::Path) {
+    let mut entries: Vec<_> = match std::fs::read_dir(directory) {
+        Ok(entries) => entries
+            .filter_map(|entry| {
+                let entry = entry.ok()?;
+                let metadata = entry.metadata().ok()?;
+                Some((metadata.modified().ok()?, metadata.len(), entry.path()))
+            })
+            .collect(),
+        Err(_) => return,
+    };
+    let mut total: u64 = entries.iter().map(|(_, size, _)| size).sum();
+    entries.sort();
+    for (_, size, path) in entries {
+        if total <= AlbumArtCache::LIMIT {
+            break;
+        }
+        if std::fs::remove_file(&path).is_ok() {
+            total -= size;
+        }
+    }
+}
+
+/// Collages we've made before, one file per playlist under the user's cache
+/// directory.
+pub(crate) struct CollageCache;
+
+impl CollageCache {
+    pub(crate) fn directory() -> PathBuf {
+        glib::user_cache_dir().join("tunes").join("collages")
+    }
+
+    /// What every collage for the playlist called `name` starts with.
+    pub(crate) fn prefix(name: &str) -> String {
+        let hash = glib::compute_checksum_for_string(glib::ChecksumType::Sha256, name)
+            .expect("Couldn't hash playlist name");
+        format!("{}-", hash)
+    }
+
+    /// Where the collage for `playlist` is cached. The name includes when the
+    /// playlist last changed, so editing it makes for a fresh collage. An
+    /// empty file means it has no covers to make one from.
+    pub(crate) fn path(playlist: &mpd::Playlist) -> PathBuf {
+        Self::directory().join(format!("{}{}.png", Self::prefix(&playlist.name), playlist.last_mod.as_secs()))
+    }
+
+    /// Save `data` as the collage for `playlist`, in place of the ones from
+    /// before it last changed, and then make room for it.
+    pub(crate) fn store(playlist: &mpd::Playlist, data: &[u8]) {
+        let path = Self::path(playlist);
+        if let Err(e) = std::fs::create_dir_all(Self::directory()).and_then(|_| std::fs::write(&path, data)) {
+            eprintln!("Couldn't cache playlist collage: {}", e);
+            return;
+        }
+        let prefix = Self::prefix(&playlist.name);
+        if let Ok(entries) = std::fs::read_dir(Self::directory()) {
+            for entry in entries.filter_map(Result::ok) {
+                let stale = entry.file_name().to_str().map_or(false, |x| x.starts_with(&prefix));
+                if stale && entry.path() != path {
+                    let _ = std::fs::remove_file(entry.path());
+                }
+            }
+        }
+        trim_cache(&Self::directory());
+    }
+}
+
+/// A 2×2 collage of the covers of the first few albums in `playlist`, from the
+/// cache if it's been made before. Playlists with only one or two albums
+/// repeat them to fill the grid, and ones with no covers at all get nothing.
+fn playlist_collage(conn: &mut Client, playlist: &mpd::Playlist) -> anyhow::Result<Option<DecodedArt>> {
+    if let Ok(data) = std::fs::read(CollageCache::path(playlist)) {
+        return Ok(match data.is_empty() {
+            true => None,
+            false => decode_art(&data, COLLAGE_SIZE),
+        });
+    }
+
+    let songs = conn.playlist(&playlist.name)?;
+    let mut albums = Vec::new();
+    let mut covers = Vec::new();
+    for song in &songs {
+        let album = (album_artist(song).map(String::from), song.tags.get("Album").cloned());
+        if albums.contains(&album) {
+            continue;
+        }
+        albums.push(album);
+        let cover = album_art(conn, song)?.and_then(|data| {
+            gdk_pixbuf::Pixbuf::from_stream(
+                &gio::MemoryInputStream::from_bytes(&glib::Bytes::from(&data)),
+                gio::Cancellable::NONE,
+            )
+            .ok()
+        });
+        if let Some(cover) = cover {
+            covers.push(cover);
+            if covers.len() == 4 {
+                break;
+            }
+        }
+    }
+    let collage = match covers.is_empty() {
+        true => None,
+        false => collage(&covers),
+    };
+
+    // Not being able to cache it only means making it again next time.
+    match &collage {
+        Some(collage) => match collage.save_to_bufferv("png", &[]) {
+            Ok(data) => CollageCache::store(playlist, &data),
+            Err(e) => eprintln!("Couldn't cache playlist collage: {}", e),
+        },
+        None => CollageCache::store(playlist, &[]),
+    }
+    Ok(collage.as_ref().map(art_from_pixbuf))
+}
+
+/// Lay out `covers` two by two, going round again if there are fewer than four.
+fn collage(covers: &[gdk_pixbuf::Pixbuf]) -> Option<gdk_pixbuf::Pixbuf> {
+    let size = COLLAGE_SIZE;
+    let half = size / 2;
+    let surface = gtk::cairo::ImageSurface::create(gtk::cairo::Format::ARgb32, size, size).ok()?;
+    let context = gtk::cairo::Context::new(&surface).ok()?;
+    for (i, cover) in covers.iter().cycle().take(4).enumerate() {
+        let (x, y) = ((i as i32 % 2) * half, (i as i32 / 2) * half);
+        let cover = cover.scale_simple(half, half, gdk_pixbuf::InterpType::Bilinear)?;
+        context.set_source_pixbuf(&cover, f64::from(x), f64::from(y));
+        context.rectangle(f64::from(x), f64::from(y), f64::from(half), f64::from(half));
+        context.fill().ok()?;
+    }
+    drop(context);
+    gtk::gdk::pixbuf_get_from_surface(&surface, 0, 0, size, size)
+}
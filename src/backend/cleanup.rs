@@ -0,0 +1,130 @@
+// Copyright © 2021-2022 Jakob L. Kreuze <zerodaysfordays@sdf.org>
+//
+// This file is part of Tunes.
+//
+// Tunes is free software; you can redistribute it and/or modify it
+// under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation; either version 3 of the
+// License, or (at your option) any later version.
+//
+// Tunes is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY
+// or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Affero General
+// Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public
+// License along with Tunes. If not, see <http://www.gnu.org/licenses/>.
+
+use anyhow::Context;
+use gtk::glib;
+use std::collections::HashSet;
+use tunes_core::{Client, LABELS_STICKER, LAST_PLAYED_STICKER, RATING_STICKER};
+
+use crate::backend::{connect, AlbumArtCache, CollageCache, Options};
+
+/// What a cleanup got rid of.
+#[derive(Debug, Default)]
+pub(crate) struct CleanupReport {
+    covers: usize,
+    collages: usize,
+    stickers: usize,
+    /// Space freed up on disk, in bytes.
+    freed: u64,
+}
+
+impl CleanupReport {
+    /// A sentence summing it up, for the user.
+    pub(crate) fn summary(&self) -> String {
+        if self.covers == 0 && self.collages == 0 && self.stickers == 0 {
+            return "Nothing needed cleaning up".into();
+        }
+        let count = |n: usize, one: &str, many: &str| format!("{} {}", n, if n == 1 { one } else { many });
+        format!(
+            "Removed {}, {} and {} for music that's gone, freeing {}",
+            count(self.covers, "cover", "covers"),
+            count(self.collages, "collage", "collages"),
+            count(self.stickers, "sticker", "stickers"),
+            glib::format_size(self.freed),
+        )
+    }
+}
+
+/// Stickers we keep for songs, which outlive them when they're removed from
+/// the library.
+const SONG_STICKERS: [&str; 3] = [RATING_STICKER, LAST_PLAYED_STICKER, LABELS_STICKER];
+
+/// Clear out what's kept for music no longer in the library: cached covers
+/// for albums that are gone, collages for deleted playlists, and stickers on
+/// songs that have been removed or moved.
+pub(crate) fn clean_up(conn: &mut Client) -> anyhow::Result<CleanupReport> {
+    let songs = conn.listallinfo().context("Couldn't list the library")?;
+    // A library that's come up empty is far more likely the server having
+    // lost track of the music than all of it being gone.
+    if songs.is_empty() {
+        anyhow::bail!("The library's empty, so there's nothing to tell what's gone by");
+    }
+    let mut report = CleanupReport::default();
+
+    let covers: HashSet<_> = songs.iter().map(AlbumArtCache::path).collect();
+    for (path, size) in cached_files(&AlbumArtCache::directory()) {
+        if !covers.contains(&path) && std::fs::remove_file(&path).is_ok() {
+            report.covers += 1;
+            report.freed += size;
+        }
+    }
+
+    let prefixes: Vec<_> = conn
+        .playlists()
+        .context("Couldn't list playlists")?
+        .iter()
+        .map(|playlist| CollageCache::prefix(&playlist.name))
+        .collect();
+    for (path, size) in cached_files(&CollageCache::directory()) {
+        let name = path.file_name().and_then(|x| x.to_str()).unwrap_or_default();
+        if !prefixes.iter().any(|x| name.starts_with(x.as_str())) && std::fs::remove_file(&path).is_ok() {
+            report.collages += 1;
+            report.freed += size;
+        }
+    }
+
+    let files: HashSet<_> = songs.iter().map(|song| song.file.as_str()).collect();
+    for sticker in SONG_STICKERS {
+        // Servers without a sticker database have nothing to clean up.
+        let stuck = match conn.find_sticker("song", "", sticker) {
+            Ok(stuck) => stuck,
+            Err(_) => break,
+        };
+        for (file, _) in stuck {
+            if !files.contains(file.as_str()) {
+                conn.delete_sticker("song", &file, sticker)
+                    .with_context(|| format!("Couldn't remove the {} sticker from {}", sticker, file))?;
+                report.stickers += 1;
+            }
+        }
+    }
+    Ok(report)
+}
+
+/// Like `clean_up`, but with a connection of its own on a thread of its own,
+/// since listing the whole library takes a while.
+pub(crate) async fn clean_up_in_background(options: &Options) -> anyhow::Result<CleanupReport> {
+    let (sender, receiver) = futures::channel::oneshot::channel();
+    let options = options.clone();
+    std::thread::spawn(move || {
+        let _ = sender.send(connect(&options).and_then(|mut conn| clean_up(&mut conn)));
+    });
+    receiver.await.context("Couldn't clean up")?
+}
+
+/// Files in a cache `directory`, with their sizes.
+fn cached_files(directory: &std::path::Path) -> Vec<(std::path::PathBuf, u64)> {
+    match std::fs::read_dir(directory) {
+        Ok(entries) => entries
+            .filter_map(|entry| {
+                let entry = entry.ok()?;
+                Some((entry.path(), entry.metadata().ok()?.len()))
+            })
+            .collect(),
+        Err(_) => Vec::new(),
+    }
+}
@@ -0,0 +1,690 @@
+// Copyright © 2021-2022 Jakob L. Kreuze <zerodaysfordays@sdf.org>
+//
+// This file is part of Tunes.
+//
+// Tunes is free software; you can redistribute it and/or modify it
+// under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation; either version 3 of the
+// License, or (at your option) any later version.
+//
+// Tunes is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY
+// or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Affero General
+// Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public
+// License along with Tunes. If not, see <http://www.gnu.org/licenses/>.
+
+use anyhow::Context;
+use gtk::prelude::*;
+use gtk::{gio, glib};
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tunes_core::{
+    connect_tcp, parse_mpd_host, Client, Config, ServerProfile, Stream, COMMAND_TIMEOUT, CONNECTION_SECTION,
+};
+
+use crate::backend::{lookup_password, store_password};
+use crate::models::{config_path, PROFILE};
+
+/// Where the server is and how to log in to it.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct Server {
+    /// Address of the MPD server to talk to.
+    pub(crate) host: String,
+    /// Password to log in to the server with, if it wants one.
+    password: Option<String>,
+    /// Where to tunnel through to reach the server, over SSH, as in
+    /// "me@gateway". The host is then as that machine sees it.
+    ssh: Option<String>,
+}
+
+impl Server {
+    /// The server set in the preferences. Anything not set there comes from
+    /// the `MPD_HOST` and `MPD_PORT` environment variables, like it would for
+    /// mpc or ncmpcpp, and failing that, the usual local server.
+    pub(crate) fn from_config(config: &Config) -> Self {
+        let (env_password, _) = parse_mpd_host(std::env::var("MPD_HOST").ok());
+        let host = Self::configured_address(config);
+        // Older versions kept the password in the settings file, until it's
+        // moved into the keyring.
+        let saved_password = lookup_password(&host)
+            .map_err(|e| eprintln!("Couldn't look up the password for {}: {:#}", host, e))
+            .ok()
+            .flatten()
+            .or_else(|| config.get(CONNECTION_SECTION, "password").map(String::from));
+        Server {
+            password: saved_password.filter(|password| !password.is_empty()).or(env_password),
+            ssh: config
+                .get(CONNECTION_SECTION, "ssh")
+                .map(str::trim)
+                .filter(|ssh| !ssh.is_empty())
+                .map(String::from),
+            host,
+        }
+    }
+
+    /// Address of the server set in the preferences, which is also what its
+    /// password is saved under.
+    pub(crate) fn configured_address(config: &Config) -> String {
+        let (_, env_host) = parse_mpd_host(std::env::var("MPD_HOST").ok());
+        let host = config
+            .get(CONNECTION_SECTION, "host")
+            .map(String::from)
+            .or(env_host)
+            .unwrap_or_else(|| "127.0.0.1".to_string());
+        let port = config
+            .get(CONNECTION_SECTION, "port")
+            .map(String::from)
+            .or_else(|| std::env::var("MPD_PORT").ok())
+            .unwrap_or_else(|| "6600".to_string());
+        Self::address(host, &port)
+    }
+
+    /// Where to find `host` on `port`. Sockets have no port to go with them,
+    /// and IPv6 addresses need brackets to tell them from the port.
+    pub(crate) fn address(host: String, port: &str) -> String {
+        if host.starts_with('/') {
+            host
+        } else if host.contains(':') && !host.starts_with('[') {
+            format!("[{}]:{}", host, port)
+        } else {
+            format!("{}:{}", host, port)
+        }
+    }
+
+    /// Whether this is a Unix socket on this computer, rather than a host
+    /// and port. One at the far end of a tunnel doesn't count, since its
+    /// files aren't ours.
+    pub(crate) fn is_socket(&self) -> bool {
+        self.ssh.is_none() && self.host.starts_with('/')
+    }
+}
+
+/// Options given on the command line.
+#[derive(Debug, Clone)]
+pub(crate) struct Options {
+    /// The server to talk to. The preferences can change it while we're
+    /// running, so every copy of the options shares it.
+    pub(crate) server: Arc<Mutex<Server>>,
+    /// Bumped whenever the server changes, so connections to the old one can
+    /// tell they're out of date.
+    pub(crate) server_changes: Arc<AtomicU64>,
+    /// Sockets of connections that sit blocked waiting on the server, by
+    /// which thread has them. Changing servers shuts them down, so their
+    /// threads come back to the new one.
+    waiting: Arc<Mutex<BTreeMap<&'static str, Stream>>>,
+    /// The SSH tunnel to the server, when it's only reachable through one.
+    tunnel: Arc<Mutex<Option<SshTunnel>>>,
+    /// The server was given on the command line, so the preferences don't
+    /// get a say in it.
+    pub(crate) pinned_server: bool,
+    /// Settings profile to use instead of the usual config.
+    pub(crate) profile: Option<String>,
+    /// MPD partition to use instead of the default one.
+    pub(crate) partition: Option<String>,
+    /// Just mirror what the server is playing, without any way to change it.
+    pub(crate) follow: bool,
+    /// Fullscreen, read-only display that never gives up on the server.
+    pub(crate) kiosk: bool,
+    /// Report on timers and such to stderr.
+    pub(crate) diagnostics: bool,
+    /// Let time pass only when told to, for integration tests.
+    pub(crate) fake_clock: bool,
+    /// Macro to run once we're connected.
+    pub(crate) run_macro: Option<String>,
+}
+
+/// What was given on the command line, before the preferences are brought
+/// in to fill the gaps. The comments on each are their help.
+#[derive(Debug, PartialEq, clap::Parser)]
+#[command(
+    name = "tunes",
+    about = "Mobile-friendly MPD client",
+    long_about = None,
+    override_usage = "tunes [OPTION…] [FILE…]",
+    after_help = "Files given are added to the queue.",
+    args_override_self = true
+)]
+struct Arguments {
+    /// Connect to HOST instead of the server in the preferences
+    #[arg(long, value_name = "HOST", help_heading = "Connection")]
+    host: Option<String>,
+    /// Connect on PORT
+    #[arg(long, value_name = "PORT", value_parser = clap::value_parser!(u16).range(1..), help_heading = "Connection")]
+    port: Option<u16>,
+    /// Log in with PASSWORD
+    #[arg(long, value_name = "PASSWORD", help_heading = "Connection")]
+    password: Option<String>,
+    /// Use the MPD partition NAME
+    #[arg(long, value_name = "NAME", help_heading = "Connection")]
+    partition: Option<String>,
+    /// Show what HOST is playing, without changing anything
+    // Following another server (say, the living room's) is meant for a
+    // dashboard, so nothing can be changed by accident.
+    #[arg(long, value_name = "HOST", conflicts_with = "host", help_heading = "Connection")]
+    follow: Option<String>,
+    /// Keep settings separately, under NAME
+    #[arg(long, value_name = "NAME", value_parser = profile_name, help_heading = "Setup")]
+    profile: Option<String>,
+    /// Run fullscreen and read-only, waiting for the server
+    #[arg(long, help_heading = "Setup")]
+    kiosk: bool,
+    /// Run the macro NAME, in the running copy if there is one
+    #[arg(long = "macro", value_name = "NAME", help_heading = "Setup")]
+    run_macro: Option<String>,
+    /// Report on what's going on to stderr
+    // Everything there is to say, which is what diagnostics are.
+    #[arg(long, visible_alias = "verbose", help_heading = "Setup")]
+    diagnostics: bool,
+    /// Let time pass only when told to, for integration tests
+    #[arg(long, help_heading = "Setup")]
+    fake_clock: bool,
+    /// Everything we don't recognize, program name included, which is left
+    /// for GTK. It has options of its own, so unknown ones aren't an error.
+    #[arg(skip)]
+    rest: Vec<String>,
+}
+
+/// Profile names end up in file names and the application ID, whose parts
+/// can't start with a number.
+fn profile_name(name: &str) -> Result<String, String> {
+    let allowed = name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    match name.chars().next() {
+        Some(first) if allowed && !first.is_ascii_digit() => Ok(name.to_string()),
+        _ => Err(
+            "Profile names can only have letters, numbers, dashes and underscores, and can't start with a number".into(),
+        ),
+    }
+}
+
+impl Arguments {
+    /// Pick our options out of `args` for clap to make sense of, and leave
+    /// everything else in `rest`.
+    fn from_args(args: impl IntoIterator<Item = String>) -> Result<Self, clap::Error> {
+        let mut command = <Self as clap::CommandFactory>::command();
+        command.build();
+        let mut args = args.into_iter();
+        let mut rest: Vec<String> = args.next().into_iter().collect();
+        let mut ours = vec!["tunes".to_string()];
+        while let Some(arg) = args.next() {
+            let (name, inline_value) = match arg.strip_prefix("--") {
+                Some(arg) => match arg.split_once('=') {
+                    Some((name, _)) => (name, true),
+                    None => (arg, false),
+                },
+                None => {
+                    rest.push(arg);
+                    continue;
+                }
+            };
+            let known = command.get_arguments().find(|known| {
+                known.get_long() == Some(name) || known.get_all_aliases().map_or(false, |x| x.contains(&name))
+            });
+            match known {
+                Some(known) => {
+                    let takes_value = known.get_num_args().map_or(false, |x| x.takes_values());
+                    ours.push(arg);
+                    if takes_value && !inline_value {
+                        ours.extend(args.next());
+                    }
+                }
+                None => rest.push(arg),
+            }
+        }
+        let mut parsed = <Self as clap::Parser>::try_parse_from(ours)?;
+        parsed.rest = rest;
+        Ok(parsed)
+    }
+}
+
+impl Options {
+    /// Pick our options out of the command line, returning them along with the
+    /// arguments we didn't recognize.
+    pub(crate) fn parse() -> (Self, Vec<String>) {
+        // Help goes to stdout, and anything wrong to stderr.
+        let args = Arguments::from_args(std::env::args()).unwrap_or_else(|error| error.exit());
+        let mut options = Options {
+            server: Default::default(),
+            server_changes: Default::default(),
+            waiting: Default::default(),
+            tunnel: Default::default(),
+            pinned_server: false,
+            profile: args.profile,
+            partition: args.partition,
+            follow: args.follow.is_some(),
+            kiosk: args.kiosk,
+            diagnostics: args.diagnostics,
+            fake_clock: args.fake_clock,
+            run_macro: args.run_macro,
+        };
+        let rest = args.rest;
+        // The server to use is the one in the preferences, unless told
+        // otherwise, but which preferences depends on the profile, so it's
+        // worked out once we've seen everything.
+        let (mut host, port, password) = (args.follow.or(args.host), args.port.map(|x| x.to_string()), args.password);
+
+        if let Some(profile) = &options.profile {
+            PROFILE.set(profile.clone()).expect("Couldn't set profile");
+        }
+        let configured = Server::from_config(&Config::load(config_path()));
+        let mut server = configured.clone();
+        if options.follow {
+            // Followed as given, and the password's for our own server, not
+            // theirs.
+            server.host = host.take().unwrap_or_default();
+            server.password = None;
+            server.ssh = None;
+        }
+        if let Some(password) = password {
+            server.password = Some(password);
+        }
+        if host.is_some() || port.is_some() {
+            let (default_host, default_port) = match server.host.starts_with('/') {
+                true => ("127.0.0.1", "6600"),
+                false => server.host.rsplit_once(':').unwrap_or((&server.host, "6600")),
+            };
+            let host = host.unwrap_or_else(|| default_host.trim_start_matches('[').trim_end_matches(']').to_string());
+            server.host = Server::address(host, port.as_deref().unwrap_or(default_port));
+            // The tunnel's for the server in the preferences.
+            server.ssh = None;
+        }
+        options.pinned_server = options.follow || server != configured;
+        options.server = Arc::new(Mutex::new(server));
+
+        (options, rest)
+    }
+
+    /// Whether to leave out everything that changes the queue or playback.
+    pub(crate) fn read_only(&self) -> bool {
+        self.follow || self.kiosk
+    }
+
+    pub(crate) fn server(&self) -> Server {
+        self.server.lock().expect("Couldn't read server").clone()
+    }
+
+    /// Address of the server, for telling the user about.
+    pub(crate) fn host(&self) -> String {
+        self.server().host
+    }
+
+    /// How many times the server's been changed, to check connections
+    /// against.
+    pub(crate) fn server_changes(&self) -> u64 {
+        self.server_changes.load(Ordering::Relaxed)
+    }
+
+    /// Switch to `server`, cutting off connections waiting on the old one.
+    pub(crate) fn set_server(&self, server: Server) {
+        *self.server.lock().expect("Couldn't change server") = server;
+        self.server_changes.fetch_add(1, Ordering::Relaxed);
+        for (_, stream) in std::mem::take(&mut *self.waiting.lock().expect("Couldn't take connections")) {
+            let _ = stream.shutdown();
+        }
+    }
+
+    /// Where to connect to reach `server` through SSH, starting a tunnel if
+    /// there isn't one to it already, or the last one's gone.
+    pub(crate) fn tunnel_to(&self, server: &Server, ssh: &str) -> anyhow::Result<String> {
+        let mut tunnel = self.tunnel.lock().expect("Couldn't take tunnel");
+        if let Some(existing) = tunnel.as_mut() {
+            if existing.ssh == ssh && existing.target == server.host && existing.is_running() {
+                return Ok(existing.local.clone());
+            }
+        }
+        // Dropping the old one closes it.
+        *tunnel = None;
+        let new = SshTunnel::open(ssh, &server.host)?;
+        let local = new.local.clone();
+        *tunnel = Some(new);
+        Ok(local)
+    }
+
+    /// Close the SSH tunnel, if there's one open.
+    pub(crate) fn close_tunnel(&self) {
+        self.tunnel.lock().expect("Couldn't take tunnel").take();
+    }
+
+    /// Note that the thread `name` is about to wait on `conn`, so it's cut
+    /// off if the server changes.
+    pub(crate) fn note_waiting(&self, name: &'static str, conn: &Client) {
+        if let Ok(stream) = conn.get_ref().try_clone() {
+            self.waiting.lock().expect("Couldn't note connection").insert(name, stream);
+        }
+    }
+}
+
+/// Where the server's music is on this computer: the Music Folder set for
+/// the server in the preferences, or failing that, wherever MPD says, which
+/// it only tells clients on its own socket.
+pub(crate) fn music_directory(config: &Config, conn: &mut Client) -> Option<PathBuf> {
+    config
+        .get(CONNECTION_SECTION, "music-directory")
+        .filter(|x| !x.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| conn.music_directory().ok().map(PathBuf::from))
+}
+
+/// Where `uri`, opened with us, is in MPD's database. Local files have to be
+/// inside `music_directory`, and `mpd:` URIs name a path in the database
+/// directly. Streams are handed over as they are.
+pub(crate) fn database_uri(uri: &str, music_directory: Option<&std::path::Path>) -> Option<String> {
+    if let Some(path) = uri.strip_prefix("mpd:") {
+        let path = glib::uri_unescape_string(path.trim_start_matches('/'), None)?;
+        return (!path.is_empty()).then(|| path.to_string());
+    }
+    if uri.starts_with("http://") || uri.starts_with("https://") {
+        return Some(uri.to_string());
+    }
+    let path = gio::File::for_uri(uri).path()?;
+    let relative = path.strip_prefix(music_directory?).ok()?;
+    // The music directory itself would be everything, which isn't likely
+    // what anyone meant.
+    let relative = relative.to_str().filter(|x| !x.is_empty())?;
+    Some(relative.to_string())
+}
+
+/// MPD hangs up on clients that have said nothing for a minute, by default,
+/// so a connection left alone for this long is checked before it's used, and
+/// pinged every so often to keep it from being left alone that long at all.
+pub(crate) const IDLE_CONNECTION_CHECK: Duration = Duration::from_secs(30);
+
+/// An `ssh` forwarding a local port to the server, for servers that are only
+/// reachable through another machine. It's closed when dropped.
+#[derive(Debug)]
+pub(crate) struct SshTunnel {
+    pub(crate) child: std::process::Child,
+    /// Where the tunnel goes through, as given to `ssh`.
+    ssh: String,
+    /// The server's address, as the far end sees it.
+    target: String,
+    /// Where to connect to at this end.
+    local: String,
+}
+
+impl SshTunnel {
+    /// Start `ssh` through `ssh` to `target`, and wait for it to be ready.
+    /// There's no terminal to type a password into, so it needs a key (or
+    /// an agent) to log in with.
+    pub(crate) fn open(ssh: &str, target: &str) -> anyhow::Result<Self> {
+        // Asking for any free port and letting it go again leaves a moment
+        // for something else to take it, but nothing's likely to.
+        let port = std::net::TcpListener::bind("127.0.0.1:0")?.local_addr()?.port();
+        let local = format!("127.0.0.1:{}", port);
+        let child = std::process::Command::new("ssh")
+            .args(["-N", "-o", "BatchMode=yes", "-o", "ExitOnForwardFailure=yes"])
+            .args(["-o", "ServerAliveInterval=15"])
+            .arg("-L")
+            .arg(format!("{}:{}", local, target))
+            // Whatever's in the preferences is where to go, never more
+            // options for ssh.
+            .arg("--")
+            .arg(ssh)
+            .stdin(std::process::Stdio::null())
+            .spawn()
+            .context("Couldn't run ssh")?;
+        let mut tunnel = SshTunnel {
+            child,
+            ssh: ssh.to_string(),
+            target: target.to_string(),
+            local,
+        };
+        let until = Instant::now() + COMMAND_TIMEOUT;
+        loop {
+            if std::net::TcpStream::connect(&tunnel.local).is_ok() {
+                return Ok(tunnel);
+            }
+            if let Some(status) = tunnel.child.try_wait()? {
+                anyhow::bail!("Couldn't open a tunnel through {}: ssh {}", ssh, status);
+            }
+            if Instant::now() > until {
+                anyhow::bail!("Timed out opening a tunnel through {}", ssh);
+            }
+            std::thread::sleep(Duration::from_millis(100));
+        }
+    }
+
+    pub(crate) fn is_running(&mut self) -> bool {
+        matches!(self.child.try_wait(), Ok(None))
+    }
+}
+
+impl Drop for SshTunnel {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// How long to wait between attempts to reach a server that's gone away.
+/// It starts short, since most outages are a server restarting or Wi-Fi
+/// blinking, and doubles each time up to a limit, so a server that's off for
+/// the night isn't pestered. Changing servers starts it over.
+pub(crate) struct Backoff {
+    delay: Duration,
+}
+
+impl Backoff {
+    pub(crate) const FIRST: Duration = Duration::from_secs(1);
+    pub(crate) const LONGEST: Duration = Duration::from_secs(60);
+
+    /// Sleep for the current delay, or until the server changes, and make
+    /// the next delay longer.
+    pub(crate) fn wait(&mut self, options: &Options) {
+        let server_changes = options.server_changes();
+        let until = Instant::now() + self.delay;
+        while Instant::now() < until {
+            if options.server_changes() != server_changes {
+                *self = Backoff::default();
+                return;
+            }
+            std::thread::sleep(Duration::from_millis(250));
+        }
+        self.delay = (self.delay * 2).min(Self::LONGEST);
+    }
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Backoff { delay: Self::FIRST }
+    }
+}
+
+/// Like `connect`, but a kiosk keeps trying until the server shows up, since
+/// it may well have booted before the server did.
+pub(crate) fn connect_patiently(options: &Options) -> anyhow::Result<Client> {
+    let mut backoff = Backoff::default();
+    loop {
+        match connect(options) {
+            Err(e) if options.kiosk => {
+                eprintln!("Couldn't connect to {}, retrying: {}", options.host(), e);
+                backoff.wait(options);
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Like `connect`, but on a thread of its own, so the main loop can wait for
+/// it without the window freezing. Opening an SSH tunnel takes a while.
+pub(crate) async fn connect_in_background(options: &Options) -> anyhow::Result<Client> {
+    let (sender, receiver) = futures::channel::oneshot::channel();
+    let options = options.clone();
+    std::thread::spawn(move || {
+        let _ = sender.send(connect(&options));
+    });
+    receiver.await.context("Couldn't connect")?
+}
+
+/// Connect to MPD, logging in and switching to the partition we were asked
+/// to use, if any.
+pub(crate) fn connect(options: &Options) -> anyhow::Result<Client> {
+    let server = options.server();
+    if options.diagnostics {
+        eprintln!("Connecting to {}", server.host);
+    }
+    let stream = if let Some(ssh) = &server.ssh {
+        Stream::Tcp(connect_tcp(&options.tunnel_to(&server, ssh)?)?)
+    } else if server.is_socket() {
+        options.close_tunnel();
+        Stream::Unix(std::os::unix::net::UnixStream::connect(&server.host)?)
+    } else {
+        options.close_tunnel();
+        Stream::Tcp(connect_tcp(&server.host)?)
+    };
+    // A wedged server shouldn't be able to freeze the whole app by never
+    // answering. Connections that sit waiting turn this off.
+    stream.set_timeout(Some(COMMAND_TIMEOUT))?;
+    let mut conn = Client::new(stream)?;
+    if let Some(password) = &server.password {
+        conn.login(password)?;
+    }
+    if let Some(partition) = &options.partition {
+        conn.switch_partition(partition)?;
+    }
+    Ok(conn)
+}
+
+/// Log in to the server with `password` from now on, keeping it in the
+/// keyring too if the user wants to `remember` it. The idle thread gets cut
+/// off, and comes back logged in as well.
+pub(crate) fn use_password(
+    options: &Options,
+    config: &RefCell<Config>,
+    password: String,
+    remember: bool,
+) -> anyhow::Result<()> {
+    options.set_server(Server {
+        password: Some(password.clone()),
+        ..options.server()
+    });
+    // A server from the command line isn't the one in the preferences.
+    if remember && !options.pinned_server {
+        save_password(config, &password)?;
+    }
+    Ok(())
+}
+
+/// Keep `password` for the server in the preferences in the keyring, or
+/// forget it if it's empty. Whatever was left in the settings file goes.
+pub(crate) fn save_password(config: &RefCell<Config>, password: &str) -> anyhow::Result<()> {
+    store_password(&Server::configured_address(&config.borrow()), password)?;
+    config.borrow_mut().unset(CONNECTION_SECTION, "password");
+    Ok(())
+}
+
+/// Move any passwords older versions left in the settings file, for the
+/// server in the preferences and the saved ones, into the keyring.
+pub(crate) fn move_passwords_to_keyring(config: &RefCell<Config>) {
+    let sections = std::iter::once(CONNECTION_SECTION.to_string())
+        .chain(config.borrow().section_names(ServerProfile::SECTION_PREFIX).map(String::from))
+        .collect::<Vec<_>>();
+    for section in sections {
+        let (address, password) = {
+            let config = config.borrow();
+            let password = match config.get(&section, "password") {
+                Some(password) => password.to_string(),
+                None => continue,
+            };
+            let address = match section == CONNECTION_SECTION {
+                true => Server::configured_address(&config),
+                false => Server::address(
+                    config.get(&section, "host").unwrap_or("127.0.0.1").to_string(),
+                    config.get(&section, "port").unwrap_or("6600"),
+                ),
+            };
+            (address, password)
+        };
+        // An empty one only ever meant there wasn't a password.
+        if !password.is_empty() {
+            if let Err(e) = store_password(&address, &password) {
+                eprintln!("Couldn't move the password for {} into the keyring: {:#}", address, e);
+                continue;
+            }
+        }
+        config.borrow_mut().unset(&section, "password");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Arguments, clap::Error> {
+        Arguments::from_args(args.iter().map(|x| x.to_string()))
+    }
+
+    #[test]
+    fn picks_out_the_server() {
+        let args = parse(&["tunes", "--host", "music.local", "--port", "6601", "--password", "hunter2"]).unwrap();
+        assert_eq!(args.host.as_deref(), Some("music.local"));
+        assert_eq!(args.port, Some(6601));
+        assert_eq!(args.password.as_deref(), Some("hunter2"));
+        assert!(args.follow.is_none());
+        assert_eq!(args.rest, ["tunes"]);
+    }
+
+    #[test]
+    fn takes_values_after_equals() {
+        let args = parse(&["tunes", "--host=music.local", "--port=6601", "--profile=work"]).unwrap();
+        assert_eq!(args.host.as_deref(), Some("music.local"));
+        assert_eq!(args.port, Some(6601));
+        assert_eq!(args.profile.as_deref(), Some("work"));
+        assert_eq!(args.rest, ["tunes"]);
+    }
+
+    #[test]
+    fn checks_ports() {
+        assert!(parse(&["tunes", "--port", "abc"]).is_err());
+        assert!(parse(&["tunes", "--port", "0"]).is_err());
+        assert!(parse(&["tunes", "--port=70000"]).is_err());
+    }
+
+    #[test]
+    fn follows_a_host() {
+        let args = parse(&["tunes", "--follow", "living-room"]).unwrap();
+        assert_eq!(args.follow.as_deref(), Some("living-room"));
+        assert!(parse(&["tunes", "--follow", "living-room", "--host", "music.local"]).is_err());
+    }
+
+    #[test]
+    fn checks_profile_names() {
+        assert_eq!(parse(&["tunes", "--profile", "work_2"]).unwrap().profile.as_deref(), Some("work_2"));
+        assert!(parse(&["tunes", "--profile", "../work"]).is_err());
+        assert!(parse(&["tunes", "--profile", ""]).is_err());
+        // It'd make for an application ID GLib won't take.
+        assert!(parse(&["tunes", "--profile", "2nd"]).is_err());
+    }
+
+    #[test]
+    fn needs_values() {
+        assert!(parse(&["tunes", "--host"]).is_err());
+        assert!(parse(&["tunes", "--kiosk", "--profile"]).is_err());
+    }
+
+    #[test]
+    fn leaves_the_rest_for_gtk() {
+        let args = parse(&["tunes", "--display", ":1", "--kiosk", "song.flac", "--unknown"]).unwrap();
+        assert!(args.kiosk);
+        assert_eq!(args.rest, ["tunes", "--display", ":1", "song.flac", "--unknown"]);
+    }
+
+    #[test]
+    fn verbose_means_diagnostics() {
+        assert!(parse(&["tunes", "--verbose"]).unwrap().diagnostics);
+        assert!(parse(&["tunes", "--diagnostics"]).unwrap().diagnostics);
+        assert!(!parse(&["tunes"]).unwrap().diagnostics);
+    }
+
+    #[test]
+    fn asks_for_help() {
+        let error = parse(&["tunes", "--help"]).unwrap_err();
+        assert_eq!(error.kind(), clap::error::ErrorKind::DisplayHelp);
+        assert!(error.to_string().contains("--fake-clock"));
+    }
+}
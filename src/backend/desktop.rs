@@ -0,0 +1,477 @@
+// Copyright © 2021-2022 Jakob L. Kreuze <zerodaysfordays@sdf.org>
+//
+// This file is part of Tunes.
+//
+// Tunes is free software; you can redistribute it and/or modify it
+// under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation; either version 3 of the
+// License, or (at your option) any later version.
+//
+// Tunes is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY
+// or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Affero General
+// Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public
+// License along with Tunes. If not, see <http://www.gnu.org/licenses/>.
+
+use futures::channel::mpsc;
+use glib::clone;
+use gtk::prelude::*;
+use gtk::{gio, glib};
+use libhandy::ApplicationWindow;
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+use tunes_core::{is_stream, Client, Config, PlaybackStateChange, StateUpdateKind};
+
+use crate::backend::AlbumArtCache;
+use crate::models::Shortcut;
+
+/// Let the desktop know we'd like to keep running with the window closed.
+/// Sandboxed apps need the background portal's say-so, and it's harmless
+/// elsewhere. The answer comes back later, and there's nothing to do about a
+/// no but carry on.
+pub(crate) fn request_background() {
+    let bus = match gio::bus_get_sync(gio::BusType::Session, gio::Cancellable::NONE) {
+        Ok(bus) => bus,
+        Err(_) => return,
+    };
+    let mut options: std::collections::HashMap<String, glib::Variant> = Default::default();
+    options.insert("reason".into(), "Keep media controls working with the window closed".to_variant());
+    options.insert("autostart".into(), false.to_variant());
+    // No parent window, so the portal's dialog goes wherever it likes.
+    bus.call(
+        Some("org.freedesktop.portal.Desktop"),
+        "/org/freedesktop/portal/desktop",
+        "org.freedesktop.portal.Background",
+        "RequestBackground",
+        Some(&("", options).to_variant()),
+        None,
+        gio::DBusCallFlags::NONE,
+        -1,
+        gio::Cancellable::NONE,
+        |_| {},
+    );
+}
+
+/// Media controls over MPRIS, for the desktop's media keys and widgets, and
+/// `playerctl`. What's playing comes from MPD, and controls go back to it by
+/// way of the main loop.
+pub(crate) struct Mpris {
+    pub(crate) bus: gio::DBusConnection,
+    pub(crate) state: Rc<RefCell<MprisState>>,
+}
+
+/// What MPRIS has last been told, to work out what's changed.
+#[derive(Default)]
+pub(crate) struct MprisState {
+    playback_status: &'static str,
+    pub(crate) metadata: Option<glib::Variant>,
+    pub(crate) volume: f64,
+    shuffle: bool,
+    /// The current song's queue ID and length, for checking `SetPosition`
+    /// calls against.
+    pub(crate) song: Option<(u32, Duration)>,
+    /// How far into the song we were as of the last update, and when that
+    /// was if it's still playing.
+    pub(crate) position: Duration,
+    playing_since: Option<Instant>,
+}
+
+impl MprisState {
+    /// How far into the song we are now.
+    pub(crate) fn position(&self) -> Duration {
+        self.position + self.playing_since.map(|x| x.elapsed()).unwrap_or_default()
+    }
+}
+
+impl Mpris {
+    pub(crate) const NAME: &'static str = "org.mpris.MediaPlayer2.tunes";
+    pub(crate) const PATH: &'static str = "/org/mpris/MediaPlayer2";
+    pub(crate) const ROOT_INTERFACE: &'static str = "org.mpris.MediaPlayer2";
+    pub(crate) const PLAYER_INTERFACE: &'static str = "org.mpris.MediaPlayer2.Player";
+    pub(crate) const XML: &'static str = r#"
+        <node>
+          <interface name="org.mpris.MediaPlayer2">
+            <method name="Raise"/>
+            <method name="Quit"/>
+            <property name="CanQuit" type="b" access="read"/>
+            <property name="CanRaise" type="b" access="read"/>
+            <property name="HasTrackList" type="b" access="read"/>
+            <property name="Identity" type="s" access="read"/>
+            <property name="DesktopEntry" type="s" access="read"/>
+            <property name="SupportedUriSchemes" type="as" access="read"/>
+            <property name="SupportedMimeTypes" type="as" access="read"/>
+          </interface>
+          <interface name="org.mpris.MediaPlayer2.Player">
+            <method name="Next"/>
+            <method name="Previous"/>
+            <method name="Pause"/>
+            <method name="PlayPause"/>
+            <method name="Stop"/>
+            <method name="Play"/>
+            <method name="Seek"><arg name="Offset" type="x" direction="in"/></method>
+            <method name="SetPosition"><arg name="TrackId" type="o" direction="in"/><arg name="Position" type="x" direction="in"/></method>
+            <method name="OpenUri"><arg name="Uri" type="s" direction="in"/></method>
+            <signal name="Seeked"><arg name="Position" type="x"/></signal>
+            <property name="PlaybackStatus" type="s" access="read"/>
+            <property name="Rate" type="d" access="readwrite"/>
+            <property name="Shuffle" type="b" access="readwrite"/>
+            <property name="Metadata" type="a{sv}" access="read"/>
+            <property name="Volume" type="d" access="readwrite"/>
+            <property name="Position" type="x" access="read"/>
+            <property name="MinimumRate" type="d" access="read"/>
+            <property name="MaximumRate" type="d" access="read"/>
+            <property name="CanGoNext" type="b" access="read"/>
+            <property name="CanGoPrevious" type="b" access="read"/>
+            <property name="CanPlay" type="b" access="read"/>
+            <property name="CanPause" type="b" access="read"/>
+            <property name="CanSeek" type="b" access="read"/>
+            <property name="CanControl" type="b" access="read"/>
+          </interface>
+        </node>"#;
+
+    /// Take our place on the session bus. A `read_only` player still says
+    /// what's playing, but can't be controlled.
+    pub(crate) fn new(
+        app: &gtk::Application,
+        window: &ApplicationWindow,
+        sender: &mpsc::Sender<StateUpdateKind>,
+        read_only: bool,
+    ) -> Option<Self> {
+        let bus = gio::bus_get_sync(gio::BusType::Session, gio::Cancellable::NONE).ok()?;
+        let node = gio::DBusNodeInfo::for_xml(Self::XML).expect("Couldn't parse MPRIS interfaces");
+        let state = Rc::new(RefCell::new(MprisState {
+            playback_status: "Stopped",
+            ..Default::default()
+        }));
+
+        let send = clone!(@strong sender => move |action| {
+            if read_only {
+                return;
+            }
+            let mut sender = sender.clone();
+            sender
+                .try_send(StateUpdateKind::PlaybackStateChange(action))
+                .expect("Couldn't notify thread");
+        });
+
+        bus.register_object(
+            Self::PATH,
+            &node.lookup_interface(Self::ROOT_INTERFACE).expect("Couldn't find MPRIS interface"),
+            clone!(@weak app, @weak window => move |_, _, _, _, method, _, invocation| {
+                match method {
+                    "Raise" => window.present(),
+                    "Quit" => app.quit(),
+                    _ => {}
+                }
+                invocation.return_value(None);
+            }),
+            |_, _, _, _, property| match property {
+                "CanQuit" | "CanRaise" => true.to_variant(),
+                "HasTrackList" => false.to_variant(),
+                "Identity" => "Tunes".to_variant(),
+                "DesktopEntry" => "tunes".to_variant(),
+                _ => Vec::<String>::new().to_variant(),
+            },
+            |_, _, _, _, _, _| false,
+        )
+        .map_err(|e| eprintln!("Couldn't register media controls: {}", e))
+        .ok()?;
+
+        bus.register_object(
+            Self::PATH,
+            &node.lookup_interface(Self::PLAYER_INTERFACE).expect("Couldn't find MPRIS interface"),
+            clone!(@strong state, @strong send => move |_, _, _, _, method, parameters, invocation| {
+                let micros = |i: usize| parameters.child_value(i).get::<i64>().unwrap_or(0);
+                match method {
+                    "Next" => send(PlaybackStateChange::SkipForwards),
+                    "Previous" => send(PlaybackStateChange::SkipBackwards),
+                    "Pause" => send(PlaybackStateChange::Pause),
+                    "PlayPause" => send(PlaybackStateChange::TogglePause),
+                    "Stop" => send(PlaybackStateChange::Stop),
+                    "Play" => send(PlaybackStateChange::Start),
+                    "Seek" => {
+                        let state = state.borrow();
+                        if let Some((_, length)) = state.song {
+                            // Seeking past the end goes on to the next song,
+                            // and before the start goes to the start.
+                            let position = state.position().as_micros() as i64 + micros(0);
+                            if position > length.as_micros() as i64 {
+                                send(PlaybackStateChange::SkipForwards);
+                            } else {
+                                send(PlaybackStateChange::SeekTo(Duration::from_micros(position.max(0) as u64)));
+                            }
+                        }
+                    }
+                    "SetPosition" => {
+                        // Only if the song's still the one the caller meant.
+                        let track = parameters.child_value(0);
+                        let position = micros(1);
+                        if let Some((id, length)) = state.borrow().song {
+                            if track.str() == Some(Self::track_id(id).as_str())
+                                && (0..=length.as_micros() as i64).contains(&position)
+                            {
+                                send(PlaybackStateChange::SeekTo(Duration::from_micros(position as u64)));
+                            }
+                        }
+                    }
+                    _ => {}
+                }
+                invocation.return_value(None);
+            }),
+            clone!(@strong state => move |_, _, _, _, property| {
+                let state = state.borrow();
+                match property {
+                    "PlaybackStatus" => state.playback_status.to_variant(),
+                    "Rate" | "MinimumRate" | "MaximumRate" => 1.0f64.to_variant(),
+                    "Shuffle" => state.shuffle.to_variant(),
+                    "Metadata" => state
+                        .metadata
+                        .clone()
+                        .unwrap_or_else(|| Self::metadata(None, None)),
+                    "Volume" => state.volume.to_variant(),
+                    "Position" => (state.position().as_micros() as i64).to_variant(),
+                    "CanGoNext" | "CanGoPrevious" | "CanPlay" | "CanPause" | "CanSeek" | "CanControl" => {
+                        (!read_only).to_variant()
+                    }
+                    _ => "".to_variant(),
+                }
+            }),
+            move |_, _, _, _, property, value| {
+                match property {
+                    "Volume" => match value.get::<f64>() {
+                        Some(volume) => send(PlaybackStateChange::SetVolume((volume.clamp(0.0, 1.0) * 100.0).round() as i8)),
+                        None => return false,
+                    },
+                    "Shuffle" => match value.get::<bool>() {
+                        Some(shuffle) => send(PlaybackStateChange::SetRandom(shuffle)),
+                        None => return false,
+                    },
+                    // MPD only plays at the one speed.
+                    "Rate" => {}
+                    _ => return false,
+                }
+                true
+            },
+        )
+        .map_err(|e| eprintln!("Couldn't register media controls: {}", e))
+        .ok()?;
+
+        bus.call_sync(
+            Some("org.freedesktop.DBus"),
+            "/org/freedesktop/DBus",
+            "org.freedesktop.DBus",
+            "RequestName",
+            // Don't queue for the name if someone else has it.
+            Some(&(Self::NAME, 4u32).to_variant()),
+            None,
+            gio::DBusCallFlags::NONE,
+            1000,
+            gio::Cancellable::NONE,
+        )
+        .map_err(|e| eprintln!("Couldn't name media controls: {}", e))
+        .ok()?;
+
+        Some(Mpris { bus, state })
+    }
+
+    /// The object path MPRIS knows the song with queue ID `id` by.
+    pub(crate) fn track_id(id: u32) -> String {
+        format!("/space/jakob/Tunes/Track/{}", id)
+    }
+
+    /// The `Metadata` property for `song`, with its cover from `art`, if it's
+    /// been cached yet.
+    pub(crate) fn metadata(song: Option<&mpd::Song>, art: Option<&std::path::Path>) -> glib::Variant {
+        let mut metadata: std::collections::HashMap<String, glib::Variant> = Default::default();
+        let track_id = match song.and_then(|song| song.place) {
+            Some(place) => Self::track_id(place.id.0),
+            None => "/org/mpris/MediaPlayer2/TrackList/NoTrack".to_string(),
+        };
+        if let Ok(track_id) = glib::variant::ObjectPath::try_from(track_id) {
+            metadata.insert("mpris:trackid".into(), track_id.to_variant());
+        }
+        if let Some(song) = song {
+            if let Some(title) = &song.title {
+                metadata.insert("xesam:title".into(), title.to_variant());
+            }
+            if let Some(artist) = &song.artist {
+                metadata.insert("xesam:artist".into(), vec![artist.clone()].to_variant());
+            }
+            if let Some(album) = song.tags.get("Album") {
+                metadata.insert("xesam:album".into(), album.to_variant());
+            }
+            if let Some(album_artist) = song.tags.get("AlbumArtist") {
+                metadata.insert("xesam:albumArtist".into(), vec![album_artist.clone()].to_variant());
+            }
+            if let Some(duration) = song.duration {
+                metadata.insert("mpris:length".into(), (duration.as_micros() as i64).to_variant());
+            }
+            if is_stream(song) {
+                metadata.insert("xesam:url".into(), song.file.to_variant());
+            }
+        }
+        if let Some(uri) = art.and_then(|art| glib::filename_to_uri(art, None).ok()) {
+            metadata.insert("mpris:artUrl".into(), uri.as_str().to_variant());
+        }
+        metadata.to_variant()
+    }
+
+    /// Catch up with MPD, letting listeners know what's changed.
+    pub(crate) fn update(&self, conn: &mut Client) -> anyhow::Result<()> {
+        let status = conn.status()?;
+        let song = conn.currentsong()?;
+        let art = song
+            .as_ref()
+            .map(AlbumArtCache::path)
+            .filter(|path| path.exists());
+        let mut state = self.state.borrow_mut();
+        let mut changed: std::collections::HashMap<String, glib::Variant> = Default::default();
+
+        let playback_status = match status.state {
+            mpd::status::State::Play => "Playing",
+            mpd::status::State::Pause => "Paused",
+            mpd::status::State::Stop => "Stopped",
+        };
+        if state.playback_status != playback_status {
+            state.playback_status = playback_status;
+            changed.insert("PlaybackStatus".into(), playback_status.to_variant());
+        }
+        let metadata = Self::metadata(song.as_ref(), art.as_deref());
+        if state.metadata.as_ref() != Some(&metadata) {
+            changed.insert("Metadata".into(), metadata.clone());
+            state.metadata = Some(metadata);
+        }
+        // Without a mixer, the server says the volume is -1.
+        let volume = f64::from(status.volume.max(0)) / 100.0;
+        if state.volume != volume {
+            state.volume = volume;
+            changed.insert("Volume".into(), volume.to_variant());
+        }
+        if state.shuffle != status.random {
+            state.shuffle = status.random;
+            changed.insert("Shuffle".into(), status.random.to_variant());
+        }
+
+        // Position isn't announced as it goes, but a jump in it is someone
+        // seeking, which gets a signal of its own.
+        let song_key = song
+            .as_ref()
+            .and_then(|song| Some((song.place?.id.0, song.duration.unwrap_or_default())));
+        let position = status.elapsed.unwrap_or_default();
+        let seeked = state.song == song_key
+            && (position.as_secs_f64() - state.position().as_secs_f64()).abs() > 1.5;
+        state.song = song_key;
+        state.position = position;
+        state.playing_since = (status.state == mpd::status::State::Play).then(Instant::now);
+
+        if !changed.is_empty() {
+            let _ = self.bus.emit_signal(
+                None,
+                Self::PATH,
+                "org.freedesktop.DBus.Properties",
+                "PropertiesChanged",
+                Some(&(Self::PLAYER_INTERFACE, changed, Vec::<String>::new()).to_variant()),
+            );
+        }
+        if seeked {
+            let _ = self.bus.emit_signal(
+                None,
+                Self::PATH,
+                Self::PLAYER_INTERFACE,
+                "Seeked",
+                Some(&(position.as_micros() as i64,).to_variant()),
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Ask GNOME's settings daemon to send media keys our way, so they work
+/// while another window has focus. Whoever asked last gets them, so we ask
+/// again whenever `window` is focused.
+pub(crate) fn grab_media_keys(app: &gtk::Application, window: &ApplicationWindow) {
+    const NAME: &str = "org.gnome.SettingsDaemon.MediaKeys";
+    const PATH: &str = "/org/gnome/SettingsDaemon/MediaKeys";
+    let bus = match gio::bus_get_sync(gio::BusType::Session, gio::Cancellable::NONE) {
+        Ok(bus) => bus,
+        Err(e) => {
+            eprintln!("Couldn't listen for media keys: {}", e);
+            return;
+        }
+    };
+    bus.signal_subscribe(
+        Some(NAME),
+        Some(NAME),
+        Some("MediaPlayerKeyPressed"),
+        Some(PATH),
+        None,
+        gio::DBusSignalFlags::NONE,
+        clone!(@weak app => move |_, _, _, _, _, parameters| {
+            let shortcut = match parameters.get::<(String, String)>() {
+                Some((application, key)) if application == "Tunes" => match key.as_str() {
+                    "Play" | "Pause" => Shortcut::PlayPause,
+                    "Next" => Shortcut::Next,
+                    "Previous" => Shortcut::Previous,
+                    "Stop" => Shortcut::Stop,
+                    _ => return,
+                },
+                _ => return,
+            };
+            // Read-only modes don't have playback actions to take.
+            if app.lookup_action(shortcut.key()).is_some() {
+                app.activate_action(shortcut.key(), None);
+            }
+        }),
+    );
+
+    // Other desktops don't have the daemon, which is fine.
+    let grab = move || {
+        bus.call(
+            Some(NAME),
+            PATH,
+            NAME,
+            "GrabMediaPlayerKeys",
+            Some(&("Tunes", 0u32).to_variant()),
+            None,
+            gio::DBusCallFlags::NONE,
+            -1,
+            gio::Cancellable::NONE,
+            |_| {},
+        );
+    };
+    grab();
+    window.connect_is_active_notify(move |window| {
+        if window.is_active() {
+            grab();
+        }
+    });
+}
+
+/// Keep the screen on and the system awake while playing, if the user's
+/// asked for it. `cookie` holds on to the inhibitor while there is one.
+pub(crate) fn update_idle_inhibit(window: &ApplicationWindow, config: &Config, playing: bool, cookie: &mut Option<u32>) {
+    let wanted = playing && config.get("playback", "inhibit-idle") == Some("true");
+    let app = match window.application() {
+        Some(app) => app,
+        None => return,
+    };
+    match (wanted, *cookie) {
+        (true, None) => {
+            // Zero means the desktop wouldn't have it.
+            let new_cookie = app.inhibit(
+                Some(window),
+                gtk::ApplicationInhibitFlags::IDLE | gtk::ApplicationInhibitFlags::SUSPEND,
+                Some("Playing music"),
+            );
+            *cookie = (new_cookie != 0).then(|| new_cookie);
+        }
+        (false, Some(old_cookie)) => {
+            app.uninhibit(old_cookie);
+            *cookie = None;
+        }
+        _ => {}
+    }
+}
@@ -0,0 +1,253 @@
+// Copyright © 2021-2022 Jakob L. Kreuze <zerodaysfordays@sdf.org>
+//
+// This file is part of Tunes.
+//
+// Tunes is free software; you can redistribute it and/or modify it
+// under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation; either version 3 of the
+// License, or (at your option) any later version.
+//
+// Tunes is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY
+// or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Affero General
+// Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public
+// License along with Tunes. If not, see <http://www.gnu.org/licenses/>.
+
+use futures::channel::mpsc;
+use gtk::glib;
+use rusqlite::OptionalExtension;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tunes_core::{Client, FuzzyIndex, SearchRequest, StateUpdateKind};
+
+use crate::backend::{connect, Options};
+
+/// Look for near misses to `request` on a thread of its own, and send their
+/// songs back once they're found. Scoring every artist, album and title in a
+/// big library takes a while, and the window shouldn't freeze meanwhile.
+pub(crate) fn find_near_misses(
+    options: &Options,
+    index: &Arc<Mutex<Option<FuzzyIndex>>>,
+    sender: &mpsc::Sender<StateUpdateKind>,
+    request: SearchRequest,
+    page_size: u32,
+) {
+    let (options, index, mut sender) = (options.clone(), index.clone(), sender.clone());
+    std::thread::spawn(move || {
+        let mut conn = match connect(&options) {
+            Ok(conn) => conn,
+            Err(_) => return,
+        };
+        let near_misses = {
+            let mut index = index.lock().unwrap();
+            FuzzyIndex::refresh(&mut index, &mut conn);
+            match &*index {
+                Some(index) => index.near_misses(&request.text),
+                None => return,
+            }
+        };
+        let mut songs = Vec::new();
+        for (tag, value) in near_misses {
+            let mut query = mpd::Query::new();
+            query.and(mpd::Term::Tag(tag.into()), value.as_str());
+            songs.extend(conn.find(&query, (0, page_size)).unwrap_or_default());
+        }
+        // The window may have closed by now, and that's fine.
+        let _ = sender.try_send(StateUpdateKind::NearMissesFound(request, songs));
+    });
+}
+
+/// A copy of the MPD database's tags in SQLite, so searches don't have to wait
+/// on the server. There's one per server, in the user's cache directory.
+pub(crate) struct TagIndex {
+    db: rusqlite::Connection,
+}
+
+impl TagIndex {
+    pub(crate) fn path(options: &Options) -> PathBuf {
+        let name: String = options
+            .host()
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+            .collect();
+        glib::user_cache_dir()
+            .join("tunes")
+            .join(format!("index-{}.sqlite", name))
+    }
+
+    /// Open the index for keeping up to date, creating it if need be.
+    pub(crate) fn create(options: &Options) -> anyhow::Result<Self> {
+        let path = Self::path(options);
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let db = rusqlite::Connection::open(&path)?;
+        // Write-ahead logging lets searches go on while the index is updated.
+        db.query_row("PRAGMA journal_mode = WAL", [], |_| Ok(()))?;
+        db.execute_batch(
+            "CREATE TABLE IF NOT EXISTS meta (key TEXT PRIMARY KEY, value TEXT);
+             CREATE TABLE IF NOT EXISTS songs (file TEXT PRIMARY KEY, last_mod INTEGER, duration INTEGER);
+             CREATE TABLE IF NOT EXISTS tags (file TEXT, tag TEXT, value TEXT);
+             CREATE INDEX IF NOT EXISTS tags_by_file ON tags (file);",
+        )?;
+        Ok(TagIndex { db })
+    }
+
+    /// Open the index for searching.
+    pub(crate) fn open(options: &Options) -> anyhow::Result<Self> {
+        let db = rusqlite::Connection::open_with_flags(
+            Self::path(options),
+            rusqlite::OpenFlags::SQLITE_OPEN_READ_ONLY,
+        )?;
+        Ok(TagIndex { db })
+    }
+
+    /// Bring the index up to date with the MPD database, if it's changed since
+    /// last time, returning whether it had. Only songs that were added,
+    /// modified or removed since are touched.
+    pub(crate) fn refresh(&mut self, conn: &mut Client) -> anyhow::Result<bool> {
+        let db_update = conn.stats()?.db_update.as_secs().to_string();
+        let indexed: Option<String> = self
+            .db
+            .query_row("SELECT value FROM meta WHERE key = 'db_update'", [], |row| row.get(0))
+            .optional()?;
+        if indexed.as_deref() == Some(db_update.as_str()) {
+            return Ok(false);
+        }
+
+        let songs = conn.listallinfo()?;
+        let transaction = self.db.transaction()?;
+        {
+            let mut known: std::collections::HashMap<String, i64> = transaction
+                .prepare("SELECT file, last_mod FROM songs")?
+                .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+                .collect::<Result<_, _>>()?;
+            let mut insert_song = transaction
+                .prepare("INSERT OR REPLACE INTO songs (file, last_mod, duration) VALUES (?, ?, ?)")?;
+            let mut delete_song = transaction.prepare("DELETE FROM songs WHERE file = ?")?;
+            let mut insert_tag =
+                transaction.prepare("INSERT INTO tags (file, tag, value) VALUES (?, ?, ?)")?;
+            let mut delete_tags = transaction.prepare("DELETE FROM tags WHERE file = ?")?;
+
+            for song in &songs {
+                let last_mod = song.last_mod.map_or(0, |x| x.as_secs() as i64);
+                if known.remove(&song.file) == Some(last_mod) {
+                    continue;
+                }
+                let duration = song.duration.map(|x| x.as_secs() as i64);
+                insert_song.execute(rusqlite::params![song.file, last_mod, duration])?;
+                delete_tags.execute([&song.file])?;
+                // MPD keeps the title and artist apart from the other tags,
+                // but they're all the same to a search.
+                let tags = song
+                    .title
+                    .iter()
+                    .map(|x| ("Title", x))
+                    .chain(song.artist.iter().map(|x| ("Artist", x)))
+                    .chain(song.tags.iter().map(|(tag, x)| (tag.as_str(), x)));
+                for (tag, value) in tags {
+                    insert_tag.execute(rusqlite::params![song.file, tag, value])?;
+                }
+            }
+
+            // Whatever we didn't come across is gone from the database.
+            for file in known.keys() {
+                delete_song.execute([file])?;
+                delete_tags.execute([file])?;
+            }
+            transaction.execute(
+                "INSERT OR REPLACE INTO meta (key, value) VALUES ('db_update', ?)",
+                [&db_update],
+            )?;
+        }
+        transaction.commit()?;
+        Ok(true)
+    }
+
+    /// Find a page of songs for a plain search, matching the way MPD's own
+    /// search does: case-insensitively, anywhere in the tag.
+    pub(crate) fn search(&self, request: &SearchRequest, (start, end): (u32, u32)) -> anyhow::Result<Vec<mpd::Song>> {
+        let mut terms = Vec::new();
+        if !request.text.is_empty() {
+            terms.push((request.tag.as_deref(), request.text.as_str()));
+        }
+        terms.extend(
+            request
+                .fields
+                .iter()
+                .map(|(tag, value)| (Some(tag.as_str()), value.as_str())),
+        );
+
+        let mut sql = String::from("SELECT file, last_mod, duration FROM songs WHERE 1");
+        let mut params = Vec::new();
+        for (tag, value) in terms {
+            if let Some(tag) = tag {
+                sql.push_str(" AND file IN (SELECT file FROM tags WHERE tag = ? AND value LIKE ? ESCAPE '\\')");
+                params.push(tag.to_string());
+            } else {
+                sql.push_str(" AND file IN (SELECT file FROM tags WHERE value LIKE ? ESCAPE '\\')");
+            }
+            let escaped = value
+                .replace('\\', "\\\\")
+                .replace('%', "\\%")
+                .replace('_', "\\_");
+            params.push(format!("%{}%", escaped));
+        }
+        sql.push_str(&format!(" ORDER BY rowid LIMIT {} OFFSET {}", end.saturating_sub(start), start));
+
+        let rows = self
+            .db
+            .prepare(&sql)?
+            .query_map(rusqlite::params_from_iter(params.iter()), |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, Option<i64>>(1)?,
+                    row.get::<_, Option<i64>>(2)?,
+                ))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut tags = self.db.prepare("SELECT tag, value FROM tags WHERE file = ?")?;
+        rows.into_iter()
+            .map(|(file, last_mod, duration)| {
+                let mut song = mpd::Song {
+                    last_mod: last_mod.map(|x| Duration::from_secs(x as u64)),
+                    duration: duration.map(|x| Duration::from_secs(x as u64)),
+                    ..Default::default()
+                };
+                for pair in tags.query_map([&file], |row| Ok((row.get(0)?, row.get(1)?)))? {
+                    let (tag, value): (String, String) = pair?;
+                    match tag.as_str() {
+                        "Title" => song.title = Some(value),
+                        "Artist" => song.artist = Some(value),
+                        _ => {
+                            song.tags.insert(tag, value);
+                        }
+                    }
+                }
+                song.file = file;
+                Ok(song)
+            })
+            .collect()
+    }
+}
+
+/// Fetch a page of results for a plain search, from the local tag index if
+/// it's there, and from MPD otherwise.
+pub(crate) fn search_page(
+    conn: &mut Client,
+    tag_index: Option<&TagIndex>,
+    request: &SearchRequest,
+    window: (u32, u32),
+) -> anyhow::Result<Vec<mpd::Song>> {
+    if let Some(index) = tag_index {
+        match index.search(request, window) {
+            Ok(songs) => return Ok(songs),
+            Err(e) => eprintln!("Couldn't search tag index, asking MPD: {}", e),
+        }
+    }
+    Ok(conn.search(&request.query(), window)?)
+}
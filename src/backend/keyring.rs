@@ -0,0 +1,224 @@
+// Copyright © 2021-2022 Jakob L. Kreuze <zerodaysfordays@sdf.org>
+//
+// This file is part of Tunes.
+//
+// Tunes is free software; you can redistribute it and/or modify it
+// under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation; either version 3 of the
+// License, or (at your option) any later version.
+//
+// Tunes is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY
+// or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Affero General
+// Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public
+// License along with Tunes. If not, see <http://www.gnu.org/licenses/>.
+
+// Server passwords, kept in the desktop's keyring through the Secret Service
+// rather than in the settings file. Each is filed under the address of the
+// server it's for, so saved servers that point at the same place share one.
+
+use anyhow::{anyhow, Context};
+use gtk::prelude::*;
+use gtk::{gio, glib};
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+const NAME: &str = "org.freedesktop.secrets";
+const PATH: &str = "/org/freedesktop/secrets";
+const SERVICE: &str = "org.freedesktop.Secret.Service";
+const COLLECTION: &str = "org.freedesktop.Secret.Collection";
+const ITEM: &str = "org.freedesktop.Secret.Item";
+const PROMPT: &str = "org.freedesktop.Secret.Prompt";
+const SESSION: &str = "org.freedesktop.Secret.Session";
+
+/// Where new passwords go: whichever collection the user unlocks at login.
+const DEFAULT_COLLECTION: &str = "/org/freedesktop/secrets/aliases/default";
+
+/// A conversation with the Secret Service. Secrets are passed in the clear,
+/// which is fine since they never leave the session bus.
+struct Keyring {
+    bus: gio::DBusConnection,
+    session: String,
+}
+
+impl Keyring {
+    fn open() -> anyhow::Result<Self> {
+        let bus = gio::bus_get_sync(gio::BusType::Session, gio::Cancellable::NONE)
+            .context("Couldn't reach the session bus")?;
+        let reply = call(&bus, PATH, SERVICE, "OpenSession", ("plain", "".to_variant()).to_variant())
+            .context("Couldn't open the keyring")?;
+        let session = object_path(&reply.child_value(1))?;
+        Ok(Keyring { bus, session })
+    }
+
+    /// Items filed under `server`, unlocking them if they need it.
+    fn items(&self, server: &str) -> anyhow::Result<Vec<String>> {
+        let reply = call(&self.bus, PATH, SERVICE, "SearchItems", (attributes(server),).to_variant())?;
+        let mut items = object_paths(&reply.child_value(0));
+        let locked = object_paths(&reply.child_value(1));
+        if !locked.is_empty() {
+            let paths = locked.iter().map(|x| to_object_path(x)).collect::<anyhow::Result<Vec<_>>>()?;
+            let reply = call(&self.bus, PATH, SERVICE, "Unlock", (paths,).to_variant())
+                .context("Couldn't unlock the keyring")?;
+            items.extend(object_paths(&reply.child_value(0)));
+            if let Some(result) = self.prompt(&object_path(&reply.child_value(1))?)? {
+                items.extend(object_paths(&result));
+            }
+        }
+        Ok(items)
+    }
+
+    /// Let the user answer `prompt`, if the keyring asked for one, and wait
+    /// for them to. Nothing comes back if they dismissed it.
+    fn prompt(&self, prompt: &str) -> anyhow::Result<Option<glib::Variant>> {
+        if prompt == "/" {
+            return Ok(None);
+        }
+        // The keyring's own dialog takes over from here, so we wait the way
+        // a modal dialog would.
+        let main_loop = glib::MainLoop::new(None, false);
+        let result = Rc::new(Cell::new(None));
+        let subscription = self.bus.signal_subscribe(
+            Some(NAME),
+            Some(PROMPT),
+            Some("Completed"),
+            Some(prompt),
+            None,
+            gio::DBusSignalFlags::NONE,
+            glib::clone!(@strong main_loop, @strong result => move |_, _, _, _, _, parameters| {
+                let dismissed = parameters.child_value(0).get::<bool>().unwrap_or(true);
+                if !dismissed {
+                    result.set(parameters.child_value(1).as_variant());
+                }
+                main_loop.quit();
+            }),
+        );
+        // No parent window to give it, so it goes wherever it likes.
+        let shown = call(&self.bus, prompt, PROMPT, "Prompt", ("",).to_variant());
+        if shown.is_ok() {
+            main_loop.run();
+        }
+        self.bus.signal_unsubscribe(subscription);
+        shown.context("Couldn't ask to unlock the keyring")?;
+        Ok(result.take())
+    }
+
+    fn lookup(&self, server: &str) -> anyhow::Result<Option<String>> {
+        let session = to_object_path(&self.session)?;
+        for item in self.items(server)? {
+            let reply = call(&self.bus, &item, ITEM, "GetSecret", (session.clone(),).to_variant())?;
+            // The secret is (session, parameters, value, content type).
+            let value = reply.child_value(0).child_value(2).get::<Vec<u8>>().unwrap_or_default();
+            if let Ok(password) = String::from_utf8(value) {
+                return Ok(Some(password));
+            }
+        }
+        Ok(None)
+    }
+
+    fn store(&self, server: &str, password: &str) -> anyhow::Result<()> {
+        let mut properties: HashMap<String, glib::Variant> = HashMap::new();
+        properties.insert(
+            format!("{}.Label", ITEM),
+            format!("Password for the music server at {}", server).to_variant(),
+        );
+        properties.insert(format!("{}.Attributes", ITEM), attributes(server).to_variant());
+        let secret = (
+            to_object_path(&self.session)?,
+            Vec::<u8>::new(),
+            password.as_bytes().to_vec(),
+            "text/plain",
+        );
+        let reply = call(
+            &self.bus,
+            DEFAULT_COLLECTION,
+            COLLECTION,
+            "CreateItem",
+            (properties, secret, true).to_variant(),
+        )
+        .context("Couldn't save the password in the keyring")?;
+        // A locked keyring has to be unlocked before anything goes in it.
+        if object_path(&reply.child_value(0))? == "/" && self.prompt(&object_path(&reply.child_value(1))?)?.is_none() {
+            return Err(anyhow!("The keyring stayed locked, so the password wasn't saved"));
+        }
+        Ok(())
+    }
+
+    fn forget(&self, server: &str) -> anyhow::Result<()> {
+        for item in self.items(server)? {
+            let reply = call(&self.bus, &item, ITEM, "Delete", ().to_variant())
+                .context("Couldn't remove the password from the keyring")?;
+            self.prompt(&object_path(&reply.child_value(0))?)?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Keyring {
+    fn drop(&mut self) {
+        let _ = call(&self.bus, &self.session, SESSION, "Close", ().to_variant());
+    }
+}
+
+fn call(
+    bus: &gio::DBusConnection,
+    path: &str,
+    interface: &str,
+    method: &str,
+    parameters: glib::Variant,
+) -> anyhow::Result<glib::Variant> {
+    Ok(bus.call_sync(
+        Some(NAME),
+        path,
+        interface,
+        method,
+        Some(&parameters),
+        None,
+        gio::DBusCallFlags::NONE,
+        -1,
+        gio::Cancellable::NONE,
+    )?)
+}
+
+/// What a server's password is filed under.
+fn attributes(server: &str) -> HashMap<String, String> {
+    let mut attributes = HashMap::new();
+    attributes.insert("application".to_string(), "space.jakob.Tunes".to_string());
+    attributes.insert("server".to_string(), server.to_string());
+    attributes
+}
+
+fn to_object_path(path: &str) -> anyhow::Result<glib::variant::ObjectPath> {
+    glib::variant::ObjectPath::try_from(path.to_string()).map_err(|_| anyhow!("{} isn't an object path", path))
+}
+
+fn object_path(value: &glib::Variant) -> anyhow::Result<String> {
+    value
+        .str()
+        .map(String::from)
+        .ok_or_else(|| anyhow!("The keyring answered with {} for an object path", value.type_()))
+}
+
+fn object_paths(value: &glib::Variant) -> Vec<String> {
+    (0..value.n_children())
+        .filter_map(|i| value.child_value(i).str().map(String::from))
+        .collect()
+}
+
+/// The password saved for `server`, if there is one.
+pub(crate) fn lookup_password(server: &str) -> anyhow::Result<Option<String>> {
+    Keyring::open()?.lookup(server)
+}
+
+/// Save `password` for `server`, in place of any that was saved for it
+/// before, or forget the one there is if `password` is empty.
+pub(crate) fn store_password(server: &str, password: &str) -> anyhow::Result<()> {
+    let keyring = Keyring::open()?;
+    match password.is_empty() {
+        true => keyring.forget(server),
+        false => keyring.store(server, password),
+    }
+}
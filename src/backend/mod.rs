@@ -0,0 +1,41 @@
+// Copyright © 2021-2022 Jakob L. Kreuze <zerodaysfordays@sdf.org>
+//
+// This file is part of Tunes.
+//
+// Tunes is free software; you can redistribute it and/or modify it
+// under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation; either version 3 of the
+// License, or (at your option) any later version.
+//
+// Tunes is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY
+// or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Affero General
+// Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public
+// License along with Tunes. If not, see <http://www.gnu.org/licenses/>.
+
+// Talking to MPD, and to the rest of the desktop: connections, the queue,
+// album art, search indexes, the keyring, MPRIS and media keys.
+
+mod art;
+mod cleanup;
+mod connection;
+mod desktop;
+mod index;
+mod keyring;
+mod queue;
+
+pub(crate) use art::{
+    placeholder_art, spawn_album_art_loader, spawn_thumbnail_loaders, AlbumArtCache, AlbumArtRequest, CollageCache,
+    ThumbnailCache, ThumbnailRequest, COLLAGE_SIZE, ONLINE_ART, ONLINE_ART_KEY, ONLINE_ART_SECTION, THUMBNAIL_SIZE,
+};
+pub(crate) use cleanup::clean_up_in_background;
+pub(crate) use connection::{
+    connect, connect_in_background, connect_patiently, database_uri, move_passwords_to_keyring, music_directory,
+    save_password, use_password, Backoff, Options, Server, IDLE_CONNECTION_CHECK,
+};
+pub(crate) use desktop::{grab_media_keys, request_background, update_idle_inhibit, Mpris};
+pub(crate) use index::{find_near_misses, search_page, TagIndex};
+pub(crate) use keyring::{lookup_password, store_password};
+pub(crate) use queue::{dispatch_playback_state_change, enqueue, shuffle, PauseBehavior};
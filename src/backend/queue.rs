@@ -0,0 +1,197 @@
+// Copyright © 2021-2022 Jakob L. Kreuze <zerodaysfordays@sdf.org>
+//
+// This file is part of Tunes.
+//
+// Tunes is free software; you can redistribute it and/or modify it
+// under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation; either version 3 of the
+// License, or (at your option) any later version.
+//
+// Tunes is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY
+// or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Affero General
+// Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public
+// License along with Tunes. If not, see <http://www.gnu.org/licenses/>.
+
+use gtk::glib;
+use std::time::{Duration, Instant};
+use tunes_core::{is_stream, next_toggle, Client, Config, PlaybackStateChange};
+
+use crate::models::Clock;
+
+/// If the same song is added twice within this long, we assume the second
+/// time was an accidental double tap.
+const DUPLICATE_ADD_WINDOW: Duration = Duration::from_secs(2);
+
+/// Append `filename` to the queue, unless it's the same song as the last one
+/// added (tracked in `last_added`) and that was only a moment ago. Passing
+/// `force` skips that check. Returns whether the song was actually added.
+pub(crate) fn enqueue(
+    conn: &mut Client,
+    last_added: &mut Option<(String, Instant)>,
+    filename: &str,
+    force: bool,
+) -> anyhow::Result<bool> {
+    let now = Clock::get().now();
+    if let Some((last_filename, when)) = last_added {
+        if !force && last_filename == filename && now.duration_since(*when) < DUPLICATE_ADD_WINDOW {
+            return Ok(false);
+        }
+    }
+
+    conn.push_str(filename.into())?;
+    *last_added = Some((filename.into(), now));
+    Ok(true)
+}
+
+/// Put `items` in a random order.
+pub(crate) fn shuffle<T>(items: &mut [T]) {
+    for i in (1..items.len()).rev() {
+        let j = glib::random_int_range(0, i as i32 + 1) as usize;
+        items.swap(i, j);
+    }
+}
+
+/// Take action on `conn` based on a `PlaybackStateChange` notification
+pub(crate) fn dispatch_playback_state_change(
+    conn: &mut Client,
+    config: &Config,
+    action: PlaybackStateChange,
+) -> anyhow::Result<()> {
+    use PlaybackStateChange::*;
+    match action {
+        SkipBackwards => conn.prev()?,
+        SkipForwards => conn.next()?,
+        Start => conn.play()?,
+        Stop => conn.stop()?,
+        Pause => {
+            let stream = conn.currentsong()?.map_or(false, |song| is_stream(&song));
+            match PauseBehavior::load(config, stream) {
+                PauseBehavior::Pause => conn.pause(true)?,
+                PauseBehavior::Stop => conn.stop()?,
+            }
+        }
+        TogglePause => {
+            if conn.status()?.state == mpd::status::State::Play {
+                return dispatch_playback_state_change(conn, config, Pause);
+            }
+            conn.play()?
+        }
+        ChangeVolume(by) => {
+            // Without a mixer, the server says the volume is -1 and there's
+            // nothing to change.
+            let volume = conn.status()?.volume;
+            if volume >= 0 {
+                conn.volume((volume + by).clamp(0, 100))?
+            }
+        }
+        SetVolume(volume) => {
+            if conn.status()?.volume >= 0 {
+                conn.volume(volume.clamp(0, 100))?
+            }
+        }
+        SeekTo(position) => conn.rewind(position)?,
+        SetRandom(random) => conn.random(random)?,
+        // Older servers only know on and off.
+        CycleSingle => {
+            let oneshot = conn.version >= mpd::Version(0, 21, 0);
+            let mode = conn.status()?.single_mode;
+            conn.set_single(next_toggle(mode, oneshot))?
+        }
+        CycleConsume => {
+            let oneshot = conn.version >= mpd::Version(0, 24, 0);
+            let mode = conn.status()?.consume_mode;
+            conn.set_consume(next_toggle(mode, oneshot))?
+        }
+    }
+    Ok(())
+}
+
+/// What pausing does, which can be set separately for streams and files.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum PauseBehavior {
+    Pause,
+    Stop,
+}
+
+impl PauseBehavior {
+    pub(crate) const SECTION: &'static str = "playback";
+
+    /// Choices for streams, default first. A paused stream goes stale, and
+    /// picks up from wherever the station's got to anyway, so stopping is
+    /// the better default.
+    pub(crate) const STREAM_OPTIONS: [(&'static str, &'static str); 2] = [("stop", "Stop"), ("pause", "Pause")];
+    pub(crate) const FILE_OPTIONS: [(&'static str, &'static str); 2] = [("pause", "Pause"), ("stop", "Stop")];
+
+    pub(crate) fn key(stream: bool) -> &'static str {
+        if stream {
+            "pause-streams"
+        } else {
+            "pause-files"
+        }
+    }
+
+    pub(crate) fn load(config: &Config, stream: bool) -> Self {
+        match config.get(Self::SECTION, Self::key(stream)) {
+            Some("pause") => PauseBehavior::Pause,
+            Some("stop") => PauseBehavior::Stop,
+            _ if stream => PauseBehavior::Stop,
+            _ => PauseBehavior::Pause,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+    use tunes_core::Stream;
+
+    /// A client talking to a "server" that's already said everything it's
+    /// going to, which is `replies`, and that can be asked afterwards what it
+    /// was sent.
+    fn fake_server(replies: &str) -> (Client, std::os::unix::net::UnixStream) {
+        let (ours, mut theirs) = std::os::unix::net::UnixStream::pair().unwrap();
+        theirs.write_all(format!("OK MPD 0.23.0\n{}", replies).as_bytes()).unwrap();
+        (Client::new(Stream::Unix(ours)).unwrap(), theirs)
+    }
+
+    #[test]
+    fn skips_double_taps() {
+        let clock = Clock::use_fake();
+        let (mut conn, mut server) = fake_server(&"Id: 1\nOK\n".repeat(4));
+        let mut last_added = None;
+
+        assert!(enqueue(&mut conn, &mut last_added, "a.flac", false).unwrap());
+        // The same song again a moment later was surely an accident...
+        clock.advance(DUPLICATE_ADD_WINDOW / 4);
+        assert!(!enqueue(&mut conn, &mut last_added, "a.flac", false).unwrap());
+        // ...unless the user insists...
+        assert!(enqueue(&mut conn, &mut last_added, "a.flac", true).unwrap());
+        // ...or a different song is wanted...
+        assert!(enqueue(&mut conn, &mut last_added, "b.flac", false).unwrap());
+        // ...or enough time has passed.
+        clock.advance(DUPLICATE_ADD_WINDOW);
+        assert!(enqueue(&mut conn, &mut last_added, "b.flac", false).unwrap());
+
+        drop(conn);
+        let mut sent = String::new();
+        server.read_to_string(&mut sent).unwrap();
+        assert_eq!(sent.matches("addid").count(), 4);
+    }
+
+    #[test]
+    fn skips_double_taps_only_within_the_window() {
+        let clock = Clock::use_fake();
+        let (mut conn, _server) = fake_server(&"Id: 1\nOK\n".repeat(2));
+        let mut last_added = None;
+
+        assert!(enqueue(&mut conn, &mut last_added, "a.flac", false).unwrap());
+        clock.advance(DUPLICATE_ADD_WINDOW - Duration::from_millis(1));
+        assert!(!enqueue(&mut conn, &mut last_added, "a.flac", false).unwrap());
+        clock.advance(Duration::from_millis(1));
+        assert!(enqueue(&mut conn, &mut last_added, "a.flac", false).unwrap());
+    }
+}
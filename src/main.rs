@@ -15,26 +15,143 @@
 // You should have received a copy of the GNU Affero General Public
 // License along with Tunes. If not, see <http://www.gnu.org/licenses/>.
 
-use futures::{channel::mpsc, StreamExt};
+// The app's split three ways: `models` for what it keeps track of, `backend`
+// for talking to MPD and the rest of the desktop, and `ui` for the widgets.
+// What doesn't need GTK at all lives in the `tunes-core` crate alongside.
+// Each of the three lists what it offers the others in its `mod.rs`, and
+// every module imports just what it uses from them.
+mod backend;
+mod models;
+mod ui;
+
+use anyhow::Context;
+use futures::StreamExt;
+use futures::channel::mpsc;
 use glib::clone;
 use gtk::prelude::*;
-use gtk::subclass::prelude::ObjectSubclassExt;
-use gtk::{gdk_pixbuf, gio, glib, pango};
+use gtk::{gio, glib};
 use libhandy::prelude::*;
 use libhandy::{ApplicationWindow, HeaderBar};
 use mpd::idle::Idle;
-use mpd::Client;
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
+use std::rc::Rc;
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tunes_core::{
+    needs_password, next_event, timed_out, Config, ConnectionState, FormFactor, Macro, PlaybackStateChange,
+    StateUpdateKind, GENERAL_SECTION, WINDOW_SECTION,
+};
+
+use backend::{
+    connect, connect_in_background, connect_patiently, grab_media_keys, move_passwords_to_keyring, request_background,
+    spawn_album_art_loader, spawn_thumbnail_loaders, use_password, Backoff, Mpris, Options, TagIndex,
+    IDLE_CONNECTION_CHECK, ONLINE_ART, ONLINE_ART_KEY, ONLINE_ART_SECTION,
+};
+use models::{config_path, Clock, Shortcut};
+use ui::{
+    apply_theme, confirm_offline_adds, fill_macro_menu, fill_server_menu, handle_event, load_user_stylesheet,
+    password_dialog, save_offline_add, show_page_hint, show_preferences, show_whats_new, BrowseInfo,
+    ConnectionIndicator, GenreColors, HeaderTitle, ListColumns, QueryInfo, Session, SongInfo, Toast, TrayIcon, Ui,
+    STYLESHEET, TRAY_SECTION,
+};
+
+/// Windows shorter than this (in pixels) only have room for the mini player.
+const MINI_PLAYER_HEIGHT: i32 = 300;
+
+/// Size (in pixels) of the cover in the mini player.
+const MINI_PLAYER_ART_SIZE: i32 = 64;
+
+/// Whether the window's down to just the cover, the song and the playback
+/// buttons, which is handy for keeping it in a corner of the screen.
+#[derive(Default)]
+struct MiniPlayer {
+    /// The user's asked for it.
+    requested: Cell<bool>,
+    /// The window's too short for anything more.
+    cramped: Cell<bool>,
+    /// Whether it's showing now.
+    shown: Cell<bool>,
+    /// How big the window was before the user asked for the mini player, to
+    /// go back to afterwards.
+    full_size: Cell<Option<(i32, i32)>>,
+}
 
-const MPD_HOST: &str = "127.0.0.1:6600";
+impl MiniPlayer {
+    fn wanted(&self) -> bool {
+        self.requested.get() || self.cramped.get()
+    }
+}
 
 fn main() {
+    // GTK has command-line options of its own, so it gets whatever's left over
+    // after we've picked out ours.
+    let (options, gtk_args) = Options::parse();
+
+    // Each profile runs as an app of its own, and so does a copy pointed at
+    // a server of its own on the command line, rather than handing over to
+    // whichever copy is running already.
+    let application_id = match &options.profile {
+        Some(profile) => format!("space.jakob.Tunes.Profile.{}", profile.replace('-', "_")),
+        None => "space.jakob.Tunes".to_string(),
+    };
+    let mut flags = gio::ApplicationFlags::HANDLES_OPEN;
+    if options.pinned_server && !options.follow {
+        flags |= gio::ApplicationFlags::NON_UNIQUE;
+    }
     let application = gtk::Application::builder()
-        .application_id("space.jakob.Tunes")
+        .application_id(&application_id)
+        .flags(flags)
         .build();
+    // Files can be opened with us from a file manager, or given on the
+    // command line.
+    application.connect_open(|app, files, _| {
+        // Opening files is how we'd get started, if we hadn't already.
+        if app.active_window().is_none() {
+            app.activate();
+        }
+        let uris: Vec<String> = files.iter().map(|file| file.uri().to_string()).collect();
+        app.activate_action("open-uris", Some(&uris.to_variant()));
+    });
+
+    // Tests move a fake clock on by activating this action over D-Bus with
+    // a number of milliseconds, e.g. with `gdbus call ... org.gtk.Actions.Activate
+    // advance-clock "[<uint32 1000>]" {}`.
+    if options.fake_clock {
+        let clock = Clock::use_fake();
+        let advance = gio::SimpleAction::new("advance-clock", Some(glib::VariantTy::UINT32));
+        advance.connect_activate(move |_, parameter| {
+            if let Some(millis) = parameter.and_then(|p| p.get::<u32>()) {
+                clock.advance(Duration::from_millis(millis.into()));
+            }
+        });
+        application.add_action(&advance);
+    }
+
+    // If we're already running, a macro from the command line goes to the
+    // copy that's running rather than starting another.
+    if let Some(name) = &options.run_macro {
+        if application.register(gio::Cancellable::NONE).is_ok() && application.is_remote() {
+            application.activate_action("run-macro", Some(&name.to_variant()));
+            return;
+        }
+    }
+
+    // The tunnel, if there is one, shouldn't outlive us.
+    application.connect_shutdown(clone!(@strong options => move |_| options.close_tunnel()));
 
     // We have to wait until the `activate` signal is fired before we can do our
     // setup.
-    application.connect_activate(|app| {
+    application.connect_activate(move |app| {
+        // Running in the background or in the tray keeps the window around,
+        // hidden, and starting Tunes again brings it back.
+        if let Some(window) = app.windows().first() {
+            window.present();
+            return;
+        }
+        let options = options.clone();
+
         // Our event-handling code will look a bit like what's common in SDL
         // with their `SDLPollEvent` interface, in the sense that we'll have all
         // of the different sub-systems of this application notify the main
@@ -44,52 +161,393 @@ fn main() {
         // Load all of the mobile UI support code from `libhandy`.
         libhandy::init();
 
+        let config = Rc::new(RefCell::new(Config::load(config_path())));
+        move_passwords_to_keyring(&config);
+        let online_art = config.borrow().get(ONLINE_ART_SECTION, ONLINE_ART_KEY) == Some("true");
+        ONLINE_ART.store(online_art, Ordering::Relaxed);
+        // We don't know how wide the window is until it's allocated.
+        let form_factor = Rc::new(Cell::new(None));
+        let genre_colors = GenreColors {
+            config: config.clone(),
+            form_factor: form_factor.clone(),
+        };
+        let list_columns = ListColumns {
+            config: config.clone(),
+            form_factor: form_factor.clone(),
+        };
+
+        let provider = gtk::CssProvider::new();
+        provider
+            .load_from_data(STYLESHEET.as_bytes())
+            .expect("Couldn't load stylesheet");
+        gtk::StyleContext::add_provider_for_screen(
+            &gtk::gdk::Screen::default().expect("Couldn't get default screen"),
+            &provider,
+            gtk::STYLE_PROVIDER_PRIORITY_APPLICATION,
+        );
+        let user_style_monitor = load_user_stylesheet();
+
         // `mpd` will notify us of events. Let's spin up a thread to listen for
         // those notifications, and shuttle them through a channel as they
         // arrive.
-        std::thread::spawn(clone!(@strong sender => move || {
-            let mut conn = Client::connect(MPD_HOST).unwrap();
-            while let Ok(_subsystems) = conn.wait(&[mpd::idle::Subsystem::Player]) {
-                let mut sender = sender.clone();
+        // This is also how we find out the server's gone, or come back, so it
+        // keeps the header's connection indicator up to date.
+        std::thread::spawn(clone!(@strong sender, @strong options => move || {
+            let mut sender = sender.clone();
+            let mut backoff = Backoff::default();
+            loop {
+                let mut conn = match connect(&options) {
+                    Ok(conn) => conn,
+                    Err(e) => {
+                        eprintln!("Couldn't connect to {}, retrying: {}", options.host(), e);
+                        sender
+                            .try_send(StateUpdateKind::ConnectionChange(ConnectionState::Offline))
+                            .expect("Couldn't notify thread");
+                        backoff.wait(&options);
+                        continue;
+                    }
+                };
+                backoff = Backoff::default();
+                // Whatever happened while we were away, the UI should catch up.
+                sender
+                    .try_send(StateUpdateKind::ConnectionChange(ConnectionState::Connected))
+                    .expect("Couldn't notify thread");
                 sender
                     .try_send(StateUpdateKind::MpdEvent)
                     .expect("Couldn't notify thread");
+                // Waiting is all this connection does, so it can't time out.
+                let _ = conn.get_ref().set_timeout(None);
+                // Options covers single and consume modes, which are shown too.
+                options.note_waiting("idle", &conn);
+                while let Ok(_subsystems) =
+                    conn.wait(&[mpd::idle::Subsystem::Player, mpd::idle::Subsystem::Options])
+                {
+                    sender
+                        .try_send(StateUpdateKind::MpdEvent)
+                        .expect("Couldn't notify thread");
+                }
+                // The connection can drop out from under us when the server
+                // goes away, or just as often when a phone suspends us and the
+                // server gives up on us meanwhile, so we wait to get it back.
+                sender
+                    .try_send(StateUpdateKind::ConnectionChange(ConnectionState::Connecting))
+                    .expect("Couldn't notify thread");
             }
         }));
 
-        // We'll connect to the MPD daemon here so we can populate the UI with
-        // some information from the current state.
-        let mut conn = Client::connect(MPD_HOST).unwrap();
+        // Phones suspend whenever the screen goes off, and any connection
+        // could be long dead by the time they wake up, so logind's heads-up
+        // that we've resumed gets us to check.
+        match gio::bus_get_sync(gio::BusType::System, gio::Cancellable::NONE) {
+            Ok(bus) => {
+                bus.signal_subscribe(
+                    Some("org.freedesktop.login1"),
+                    Some("org.freedesktop.login1.Manager"),
+                    Some("PrepareForSleep"),
+                    Some("/org/freedesktop/login1"),
+                    None,
+                    gio::DBusSignalFlags::NONE,
+                    clone!(@strong sender => move |_, _, _, _, _, parameters| {
+                        // This goes off with `true` going to sleep, and with
+                        // `false` on the way back.
+                        if let Some((false,)) = parameters.get::<(bool,)>() {
+                            let mut sender = sender.clone();
+                            sender
+                                .try_send(StateUpdateKind::Resumed)
+                                .expect("Couldn't notify thread");
+                        }
+                    }),
+                );
+            }
+            Err(e) => eprintln!("Couldn't watch for resuming from suspend: {}", e),
+        }
 
-        // We'll have two "views" in our application: one for viewing and
-        // manipulating the current `mpd` queue, and another for searching for
-        // songs to add to the queue. In GTK, we can handle switching between
-        // these different views using a Stack.
+        // Searching goes much faster against a local copy of the database's
+        // tags, which another thread keeps up to date as the database changes.
+        // Nothing searches in read-only modes, so there's no need for it there.
+        if !options.read_only() {
+            std::thread::spawn(clone!(@strong sender, @strong options => move || {
+                let mut sender = sender.clone();
+                // Like the idle thread, this outlives any one connection, and
+                // catches up on whatever changed once it's back. Each server
+                // has an index of its own, so it's opened afresh each time in
+                // case the server's been changed.
+                let mut backoff = Backoff::default();
+                loop {
+                    let mut conn = match connect(&options) {
+                        Ok(conn) => conn,
+                        Err(_) => {
+                            backoff.wait(&options);
+                            continue;
+                        }
+                    };
+                    backoff = Backoff::default();
+                    let mut index = match TagIndex::create(&options) {
+                        Ok(index) => index,
+                        Err(e) => {
+                            eprintln!("Couldn't create tag index: {}", e);
+                            return;
+                        }
+                    };
+                    // Reading a big database takes a while, and nobody's
+                    // waiting on it, so this one doesn't time out either.
+                    let _ = conn.get_ref().set_timeout(None);
+                    options.note_waiting("tag-index", &conn);
+                    loop {
+                        match index.refresh(&mut conn) {
+                            Ok(_) => sender
+                                .try_send(StateUpdateKind::TagIndexUpdated)
+                                .expect("Couldn't notify thread"),
+                            Err(e) => eprintln!("Couldn't update tag index: {}", e),
+                        }
+                        if conn.wait(&[mpd::idle::Subsystem::Database]).is_err() {
+                            break;
+                        }
+                    }
+                }
+            }));
+        }
+
+        // Servers can be slow to answer, or not be there yet at all, so the
+        // window goes up straight away and gets filled in once a connection
+        // comes through. Connecting blocks, so it happens off to the side.
+        let (conn_sender, conn_receiver) = futures::channel::oneshot::channel();
+        std::thread::spawn(clone!(@strong options => move || {
+            let _ = conn_sender.send(connect_patiently(&options));
+        }));
+
+        // We'll have a few "views" in our application: one for viewing and
+        // manipulating the current `mpd` queue, and others for searching and
+        // browsing for songs to add to the queue. In GTK, we can handle
+        // switching between the library views using a Stack.
         let stack = gtk::Stack::new();
         stack.set_expand(true);
 
-        let song_info = SongInfo::new(sender.clone());
-        stack.add_named(song_info.as_ref(), "current_song");
-        stack.set_child_title(song_info.as_ref(), Some("Now Playing"));
-        stack.set_child_icon_name(song_info.as_ref(), Some("audio-speakers-symbolic"));
+        let album_art_requests = spawn_album_art_loader(&options, sender.clone());
+        let song_info = SongInfo::new(
+            sender.clone(),
+            album_art_requests,
+            genre_colors.clone(),
+            list_columns.clone(),
+            options.read_only(),
+        );
 
-        let query_info = QueryInfo::new(sender.clone());
-        stack.add_named(query_info.as_ref(), "query_songs");
-        stack.set_child_title(query_info.as_ref(), Some("Search Database"));
-        stack.set_child_icon_name(query_info.as_ref(), Some("system-search-symbolic"));
+        // Searching and browsing are only good for changing the queue, so
+        // there's no point showing them when read-only.
+        let query_info = QueryInfo::new(sender.clone(), genre_colors.clone(), list_columns.clone(), config.clone());
+        let thumbnail_requests = spawn_thumbnail_loaders(&options, sender.clone());
+        let browse_info = BrowseInfo::new(sender.clone(), thumbnail_requests, genre_colors, list_columns);
+        // Names for user stylesheets to pick these out by.
+        song_info.as_ref().set_widget_name("now-playing");
+        query_info.as_ref().set_widget_name("search");
+        browse_info.as_ref().set_widget_name("browse");
+        if !options.read_only() {
+            stack.add_named(query_info.as_ref(), "query_songs");
+            stack.set_child_title(query_info.as_ref(), Some("Search Database"));
+            stack.set_child_icon_name(query_info.as_ref(), Some("system-search-symbolic"));
+
+            stack.add_named(browse_info.as_ref(), "browse");
+            stack.set_child_title(browse_info.as_ref(), Some("Browse"));
+            stack.set_child_icon_name(browse_info.as_ref(), Some("folder-music-symbolic"));
+        }
+
+        // Now Playing sits beside the library when there's room. On a phone,
+        // the leaflet folds them into pages of their own, with buttons in the
+        // header to get between them, or a swipe either way, as Phosh users
+        // expect.
+        let leaflet = libhandy::Leaflet::builder()
+            .can_swipe_back(true)
+            .can_swipe_forward(true)
+            .transition_type(libhandy::LeafletTransitionType::Slide)
+            .build();
+        leaflet.add(song_info.as_ref());
+        leaflet.child_set_property(song_info.as_ref(), "name", &"current_song");
+        let separator = gtk::Separator::new(gtk::Orientation::Vertical);
+        if !options.read_only() {
+            separator.style_context().add_class("sidebar");
+            leaflet.add(&separator);
+            leaflet.child_set_property(&separator, "navigatable", &false);
+            leaflet.add(&stack);
+            leaflet.child_set_property(&stack, "name", &"library");
+        }
+        let now_playing: gtk::Widget = song_info.as_ref().clone();
 
         // The `HeaderBar` is a GTK concept that libhandy plays nicely with. On
         // desktop, the elements for switching stack views will show up there.
         // On mobile, it will show up in a `ViewSwitcherBar` at the bottom.
+        let current_title = Rc::new(RefCell::new(HeaderTitle::connecting()));
         let header_bar = HeaderBar::builder()
             .show_close_button(true)
-            .title(&header_title(&mut conn).unwrap())
+            .title(&current_title.borrow().render(350))
             .build();
+        header_bar.set_widget_name("header");
+
+        // Whenever the header bar is resized, re-render the status line so it
+        // fits. Changing the title during allocation would just trigger another
+        // one, so it's deferred until GTK is idle.
+        header_bar.connect_size_allocate(clone!(@strong current_title => move |header_bar, _| {
+            let rendered = current_title.borrow().render(header_bar.allocated_width());
+            if header_bar.title().as_deref() != Some(rendered.as_str()) {
+                let header_bar = header_bar.clone();
+                glib::idle_add_local_once(move || {
+                    header_bar.set_title(Some(&rendered));
+                });
+            }
+        }));
         let view_switcher_title = libhandy::ViewSwitcherTitle::builder()
             .title("Tunes")
             .stack(&stack)
             .build();
+        if options.follow {
+            view_switcher_title.set_subtitle(Some(&format!("Following {}", options.host())));
+        }
         header_bar.add(&view_switcher_title);
+        let connection = ConnectionIndicator::new(&sender, &options);
+        header_bar.pack_start(connection.as_ref());
+        // While the server's away, the last we heard of the queue stays up to
+        // look at, but there's no playing any of it.
+        connection.disable_while_offline(&song_info.controls);
+        connection.disable_while_offline(&song_info.options_bar);
+        connection.disable_while_offline(&song_info.queue_list);
+
+        // When folded, only one of the leaflet's pages shows at a time, so the
+        // header gets buttons for going between them.
+        let back_button = gtk::Button::from_icon_name(Some("go-previous-symbolic"), gtk::IconSize::SmallToolbar);
+        back_button.set_tooltip_text(Some("Now Playing"));
+        back_button.connect_clicked(clone!(@weak leaflet => move |_| {
+            leaflet.navigate(libhandy::NavigationDirection::Back);
+        }));
+        let library_button = gtk::Button::from_icon_name(Some("folder-music-symbolic"), gtk::IconSize::SmallToolbar);
+        library_button.set_tooltip_text(Some("Library"));
+        library_button.connect_clicked(clone!(@weak leaflet => move |_| {
+            leaflet.navigate(libhandy::NavigationDirection::Forward);
+        }));
+        if !options.read_only() {
+            header_bar.pack_start(&back_button);
+            header_bar.pack_start(&library_button);
+        }
+        let preferences_button = gtk::Button::from_icon_name(
+            Some("preferences-system-symbolic"),
+            gtk::IconSize::SmallToolbar,
+        );
+        preferences_button.set_tooltip_text(Some("Preferences"));
+
+        // Odds and ends that don't deserve a button of their own go in a menu.
+        // Everything in it is an app action, so it can be reached from outside
+        // too, and new features have somewhere to go. Whatever changes the
+        // queue or the database is left out when read-only.
+        for (name, event, changes) in [
+            ("random-album", StateUpdateKind::QueueRandomAlbumRequest, true),
+            ("timed-queue", StateUpdateKind::QueueTimedRequest, true),
+            ("save-queue", StateUpdateKind::QueueSaveRequest, true),
+            ("update-database", StateUpdateKind::DatabaseUpdateRequest, true),
+            ("clean-up", StateUpdateKind::CleanupRequest, true),
+            ("new-macro", StateUpdateKind::MacroEditRequest(None), true),
+            ("save-server", StateUpdateKind::ServerSaveRequest, false),
+            ("about", StateUpdateKind::AboutRequest, false),
+        ] {
+            if changes && options.read_only() {
+                continue;
+            }
+            let action = gio::SimpleAction::new(name, None);
+            action.connect_activate(clone!(@strong sender => move |_, _| {
+                let mut sender = sender.clone();
+                sender.try_send(event.clone()).expect("Couldn't notify thread");
+                // Whatever it did to the queue should show straight away.
+                sender
+                    .try_send(StateUpdateKind::MpdEvent)
+                    .expect("Couldn't notify thread");
+            }));
+            app.add_action(&action);
+        }
+        let menu_item = |text: &str, action: &str| {
+            gtk::ModelButton::builder()
+                .text(text)
+                .action_name(action)
+                .build()
+        };
+        let menu_box = gtk::Box::new(gtk::Orientation::Vertical, 0);
+        menu_box.set_border_width(6);
+        // Macros get a section of their own, which is filled in once the
+        // config's been read and again whenever they change.
+        let macro_menu = gtk::Box::new(gtk::Orientation::Vertical, 0);
+        fill_macro_menu(&macro_menu, &sender, &Macro::load_all(&config.borrow()));
+        // So do saved servers, to hop between. A server given on the command
+        // line isn't for switching away from.
+        let server_menu = gtk::Box::new(gtk::Orientation::Vertical, 0);
+        fill_server_menu(&server_menu, &sender, &config.borrow());
+        if !options.pinned_server {
+            menu_box.add(&server_menu);
+            menu_box.add(&menu_item("Save Server…", "app.save-server"));
+            menu_box.add(&gtk::Separator::new(gtk::Orientation::Horizontal));
+        }
+        if !options.read_only() {
+            let random_album = menu_item("Surprise Me", "app.random-album");
+            random_album.set_tooltip_text(Some("Queue a random album"));
+            menu_box.add(&random_album);
+            let timed_queue = menu_item("Timed Queue…", "app.timed-queue");
+            timed_queue.set_tooltip_text(Some("Queue music to fill a length of time"));
+            menu_box.add(&timed_queue);
+            menu_box.add(&menu_item("Save Queue…", "app.save-queue"));
+            menu_box.add(&menu_item("Update Database", "app.update-database"));
+            let clean_up = menu_item("Clean Up…", "app.clean-up");
+            clean_up.set_tooltip_text(Some("Clear out what's kept for music that's left the library"));
+            menu_box.add(&clean_up);
+            menu_box.add(&gtk::Separator::new(gtk::Orientation::Horizontal));
+            menu_box.add(&macro_menu);
+            menu_box.add(&menu_item("New Macro…", "app.new-macro"));
+            menu_box.add(&gtk::Separator::new(gtk::Orientation::Horizontal));
+        }
+        menu_box.add(&menu_item("Mini Player", "app.mini-player"));
+        menu_box.add(&menu_item("Preferences", "app.preferences"));
+        menu_box.add(&menu_item("Keyboard Shortcuts", "app.shortcuts"));
+        menu_box.add(&menu_item("About Tunes", "app.about"));
+        menu_box.show_all();
+
+        // Macros can be run from outside too, by name, like
+        // `gapplication action space.jakob.Tunes run-macro "'Dinner'"`.
+        let run_macro = gio::SimpleAction::new("run-macro", Some(glib::VariantTy::STRING));
+        run_macro.connect_activate(clone!(@strong sender => move |_, parameter| {
+            if let Some(name) = parameter.and_then(|x| x.get::<String>()) {
+                let mut sender = sender.clone();
+                sender
+                    .try_send(StateUpdateKind::MacroRunRequest(name))
+                    .expect("Couldn't notify thread");
+                sender
+                    .try_send(StateUpdateKind::MpdEvent)
+                    .expect("Couldn't notify thread");
+            }
+        }));
+        if !options.read_only() {
+            app.add_action(&run_macro);
+        }
+        let open_uris = gio::SimpleAction::new("open-uris", Some(glib::VariantTy::STRING_ARRAY));
+        open_uris.connect_activate(clone!(@strong sender => move |_, parameter| {
+            if let Some(uris) = parameter.and_then(|x| x.get::<Vec<String>>()) {
+                let mut sender = sender.clone();
+                sender
+                    .try_send(StateUpdateKind::OpenRequest(uris))
+                    .expect("Couldn't notify thread");
+                sender
+                    .try_send(StateUpdateKind::MpdEvent)
+                    .expect("Couldn't notify thread");
+            }
+        }));
+        if !options.read_only() {
+            app.add_action(&open_uris);
+        }
+        let menu_button = gtk::MenuButton::builder()
+            .image(&gtk::Image::from_icon_name(
+                Some("open-menu-symbolic"),
+                gtk::IconSize::SmallToolbar,
+            ))
+            .popover(&gtk::Popover::builder().child(&menu_box).build())
+            .build();
+
+        if !options.kiosk {
+            header_bar.pack_end(&menu_button);
+            header_bar.pack_end(&preferences_button);
+        }
         let view_switcher_bar = libhandy::ViewSwitcherBar::builder()
             .visible(true)
             .can_focus(false)
@@ -97,24 +555,284 @@ fn main() {
             .reveal(true)
             .build();
 
+        // The view switcher only makes sense alongside the library, and the
+        // header's buttons only when the leaflet's folded.
+        let mini_player = Rc::new(MiniPlayer::default());
+        let update_navigation = Rc::new(
+            clone!(@weak back_button, @weak library_button, @weak view_switcher_bar, @strong mini_player => move |leaflet: &libhandy::Leaflet| {
+                // The mini player has no room for the library at all.
+                let mini = mini_player.shown.get();
+                let folded = leaflet.is_folded();
+                let library = leaflet.visible_child_name().as_deref() == Some("library");
+                back_button.set_visible(folded && library && !mini);
+                library_button.set_visible(folded && !library && !mini);
+                view_switcher_bar.set_reveal((!folded || library) && !mini);
+                leaflet.set_can_swipe_forward(!mini);
+            }),
+        );
+        leaflet.connect_folded_notify(clone!(@strong update_navigation => move |leaflet| update_navigation(leaflet)));
+        leaflet.connect_visible_child_name_notify(
+            clone!(@strong update_navigation => move |leaflet| update_navigation(leaflet)),
+        );
+
         // The window needs a single child, so we'll join the header bar, the
-        // stack, and the view switcher into a single box.
+        // leaflet, and the view switcher into a single box.
         let content = gtk::Box::new(gtk::Orientation::Vertical, 0);
         content.set_vexpand(true);
         content.add(&header_bar);
-        content.add(&stack);
+        // Toasts float over the bottom of whichever view is showing.
+        let toast = Toast::new();
+        let overlay = gtk::Overlay::new();
+        overlay.add(&leaflet);
+        overlay.add_overlay(toast.as_ref());
+        content.add(&overlay);
         content.add(&view_switcher_bar);
 
         // Finally, the window. It's tied to a child, which we made above, and
         // the GtkApplication that we declared at the beginning of `main`.
+        // It comes back the size it was last closed at.
+        let (width, height) = {
+            let config = config.borrow();
+            let get = |key, default| {
+                config
+                    .get(WINDOW_SECTION, key)
+                    .and_then(|x| x.parse().ok())
+                    .unwrap_or(default)
+            };
+            (get("width", 350), get("height", 600))
+        };
         let window = ApplicationWindow::builder()
-            .default_width(350)
-            .default_height(70)
-            .modal(true)
+            .default_width(width)
+            .default_height(height)
             .child(&content)
             .build();
         window.set_application(Some(app));
+        if config.borrow().get(WINDOW_SECTION, "maximized") == Some("true") && !options.kiosk {
+            window.maximize();
+        }
         window.show_all();
+        update_navigation(&leaflet);
+        apply_theme(&config.borrow());
+        // Following the system means keeping up when it changes its mind,
+        // like at sunset.
+        if let Ok(bus) = gio::bus_get_sync(gio::BusType::Session, gio::Cancellable::NONE) {
+            bus.signal_subscribe(
+                Some("org.freedesktop.portal.Desktop"),
+                Some("org.freedesktop.portal.Settings"),
+                Some("SettingChanged"),
+                Some("/org/freedesktop/portal/desktop"),
+                Some("org.freedesktop.appearance"),
+                gio::DBusSignalFlags::NONE,
+                clone!(@strong config => move |_, _, _, _, _, _| {
+                    apply_theme(&config.borrow());
+                }),
+            );
+        }
+        // Searching and browsing aren't there at all when read-only.
+        let startup_view = {
+            let config = config.borrow();
+            match config.get(GENERAL_SECTION, "startup-view") {
+                Some("last") | None => config.get(GENERAL_SECTION, "last-view").map(String::from),
+                view => view.map(String::from),
+            }
+        };
+        if let Some(view) = startup_view.filter(|view| view != "current_song" && !options.read_only()) {
+            stack.set_visible_child_name(&view);
+            leaflet.set_visible_child_name("library");
+        }
+
+        // With an icon in the tray, closing the window only hides it, and
+        // playback can be kept an eye on from there.
+        let tray = (config.borrow().get(TRAY_SECTION, "enabled") == Some("true") && !options.kiosk)
+            .then(|| TrayIcon::new(app, &window, &sender))
+            .flatten();
+        let close_to_tray = tray.is_some();
+        if config.borrow().get(GENERAL_SECTION, "run-in-background") == Some("true") && !options.kiosk {
+            request_background();
+        }
+
+        // Desktop media controls, and `playerctl`, talk to us over MPRIS.
+        let mpris = Mpris::new(app, &window, &sender, options.read_only());
+
+        // Remember where the window was left for next time. A kiosk is always
+        // fullscreen on the same page, so there's nothing to remember.
+        if !options.kiosk {
+            window.connect_delete_event(
+                clone!(@strong config, @weak stack, @weak leaflet, @strong mini_player => @default-return gtk::Inhibit(false), move |window, _| {
+                    let mut config = config.borrow_mut();
+                    let maximized = window.is_maximized();
+                    // A maximized window's size is the screen's, which isn't
+                    // what it should go back to when unmaximized, and the mini
+                    // player's size is only for the mini player.
+                    if !maximized {
+                        let (width, height) = mini_player.full_size.get().unwrap_or_else(|| window.size());
+                        config.set(WINDOW_SECTION, "width", width);
+                        config.set(WINDOW_SECTION, "height", height);
+                    }
+                    config.set(WINDOW_SECTION, "maximized", maximized);
+                    let view = if leaflet.is_folded() && leaflet.visible_child_name().as_deref() != Some("library") {
+                        Some("current_song".into())
+                    } else {
+                        stack.visible_child_name()
+                    };
+                    if let Some(view) = view {
+                        config.set(GENERAL_SECTION, "last-view", view);
+                    }
+                    let keep_running = close_to_tray || config.get(GENERAL_SECTION, "run-in-background") == Some("true");
+                    if keep_running {
+                        window.hide();
+                    }
+                    gtk::Inhibit(keep_running)
+                }),
+            );
+        }
+        if options.kiosk {
+            window.style_context().add_class("kiosk");
+            window.fullscreen();
+        }
+
+        // Nobody's around to read any of this on a kiosk.
+        if !options.kiosk {
+            show_whats_new(&window, &config);
+            show_page_hint("current_song", &now_playing, &config);
+            stack.connect_visible_child_name_notify(clone!(@strong config => move |stack| {
+                if let (Some(page), Some(child)) = (stack.visible_child_name(), stack.visible_child()) {
+                    show_page_hint(&page, &child, &config);
+                }
+            }));
+            leaflet.connect_visible_child_name_notify(
+                clone!(@strong config, @weak stack, @strong now_playing => move |leaflet| {
+                    if leaflet.visible_child_name().as_deref() == Some("library") {
+                        if let (Some(page), Some(child)) = (stack.visible_child_name(), stack.visible_child()) {
+                            show_page_hint(&page, &child, &config);
+                        }
+                    } else {
+                        show_page_hint("current_song", &now_playing, &config);
+                    }
+                }),
+            );
+        }
+
+        preferences_button.connect_clicked(
+            clone!(@weak window, @strong config, @strong sender => move |_| {
+                show_preferences(&window, &config, &sender);
+            }),
+        );
+
+        // Keyboard shortcuts are app actions, so they can be activated from
+        // outside too. Anything that changes playback is left out when
+        // read-only, as is searching, which isn't there.
+        for shortcut in Shortcut::ALL {
+            let event = match shortcut {
+                Shortcut::PlayPause => Some(StateUpdateKind::PlaybackStateChange(PlaybackStateChange::TogglePause)),
+                Shortcut::Next => Some(StateUpdateKind::PlaybackStateChange(PlaybackStateChange::SkipForwards)),
+                Shortcut::Previous => Some(StateUpdateKind::PlaybackStateChange(PlaybackStateChange::SkipBackwards)),
+                Shortcut::Stop => Some(StateUpdateKind::PlaybackStateChange(PlaybackStateChange::Stop)),
+                Shortcut::VolumeUp => Some(StateUpdateKind::PlaybackStateChange(PlaybackStateChange::ChangeVolume(5))),
+                Shortcut::VolumeDown => Some(StateUpdateKind::PlaybackStateChange(PlaybackStateChange::ChangeVolume(-5))),
+                Shortcut::Search => Some(StateUpdateKind::FocusSearchRequest),
+                Shortcut::Preferences => Some(StateUpdateKind::PreferencesRequest),
+                Shortcut::ShowShortcuts => Some(StateUpdateKind::ShortcutsRequest),
+                Shortcut::FocusNext | Shortcut::FocusPrevious => None,
+            };
+            let available = match shortcut {
+                // A kiosk has no preferences button, so it has no shortcut.
+                Shortcut::Preferences => !options.kiosk,
+                Shortcut::ShowShortcuts | Shortcut::FocusNext | Shortcut::FocusPrevious => true,
+                _ => !options.read_only(),
+            };
+            if !available {
+                continue;
+            }
+            let action = gio::SimpleAction::new(shortcut.key(), None);
+            action.connect_activate(clone!(@strong sender, @weak window => move |_, _| {
+                let direction = match (&event, shortcut) {
+                    (Some(event), _) => {
+                        let mut sender = sender.clone();
+                        sender.try_send(event.clone()).expect("Couldn't notify thread");
+                        return;
+                    }
+                    (None, Shortcut::FocusPrevious) => gtk::DirectionType::Up,
+                    (None, _) => gtk::DirectionType::Down,
+                };
+                window.emit_move_focus(direction);
+            }));
+            app.add_action(&action);
+        }
+        window.connect_key_press_event(
+            clone!(@strong config, @weak app => @default-return gtk::Inhibit(false), move |window, event| {
+                // Media keys do what they say whatever the shortcuts are set
+                // to, and aren't for typing.
+                if let Some(shortcut) = Shortcut::find_media_key(event) {
+                    if app.lookup_action(shortcut.key()).is_some() {
+                        app.activate_action(shortcut.key(), None);
+                        return gtk::Inhibit(true);
+                    }
+                }
+                // Keys without Ctrl or Alt are for typing when there's
+                // somewhere to type.
+                let typing = window.focus().map_or(false, |widget| widget.is::<gtk::Editable>());
+                let modified = event
+                    .state()
+                    .intersects(gtk::gdk::ModifierType::CONTROL_MASK | gtk::gdk::ModifierType::MOD1_MASK);
+                if typing && !modified {
+                    return gtk::Inhibit(false);
+                }
+                match Shortcut::find(&config.borrow(), event) {
+                    Some(shortcut) if app.lookup_action(shortcut.key()).is_some() => {
+                        app.activate_action(shortcut.key(), None);
+                        gtk::Inhibit(true)
+                    }
+                    _ => gtk::Inhibit(false),
+                }
+            }),
+        );
+
+        // Media keys still work with the window in the background, on GNOME.
+        grab_media_keys(app, &window);
+
+        window.connect_is_active_notify(clone!(@strong sender => move |_| {
+            let mut sender = sender.clone();
+            sender
+                .try_send(StateUpdateKind::WindowFocusChange)
+                .expect("Couldn't notify thread");
+        }));
+
+        // Keep an eye on the window width so we can switch display profiles as
+        // it crosses between phone and desktop sizes. Squashing it down short
+        // enough switches to the mini player, too.
+        window.connect_size_allocate(clone!(@strong sender, @strong form_factor, @strong mini_player => move |window, _| {
+            let current = FormFactor::from_width(window.allocated_width());
+            if form_factor.replace(Some(current)) != Some(current) {
+                let mut sender = sender.clone();
+                sender
+                    .try_send(StateUpdateKind::FormFactorChange(current))
+                    .expect("Couldn't notify thread");
+            }
+            let cramped = window.allocated_height() < MINI_PLAYER_HEIGHT;
+            if mini_player.cramped.replace(cramped) != cramped {
+                let mut sender = sender.clone();
+                sender
+                    .try_send(StateUpdateKind::MiniPlayerChange)
+                    .expect("Couldn't notify thread");
+            }
+        }));
+
+        // The mini player can be turned on from the menu, which shows it as
+        // a check box, or from outside.
+        let mini_player_action = gio::SimpleAction::new_stateful("mini-player", None, &false.to_variant());
+        mini_player_action.connect_activate(clone!(@strong sender, @strong mini_player => move |action, _| {
+            let requested = !mini_player.requested.get();
+            mini_player.requested.set(requested);
+            action.set_state(&requested.to_variant());
+            let mut sender = sender.clone();
+            sender
+                .try_send(StateUpdateKind::MiniPlayerChange)
+                .expect("Couldn't notify thread");
+        }));
+        if !options.kiosk {
+            app.add_action(&mini_player_action);
+        }
 
         // This isn't perfect (it won't run when the window gets its initial
         // size), but this is how we notify that the album art display should be
@@ -127,11 +845,7 @@ fn main() {
             false
         }));
 
-        // Now that everything's been allocated a window, let's go ahead and
-        // update the widgets.
-        song_info
-            .update(&mut conn)
-            .expect("Couldn't update song info");
+        song_info.show_placeholder(&format!("Connecting to {}…", options.host()));
 
         // The following code will fill the search view with every song in the
         // database. If you have a music library as big as mine, it will
@@ -142,606 +856,210 @@ fn main() {
         // let mut query = mpd::Query::new();
         // query.and(mpd::Term::Any, "");
         // let songs = conn.search(&query, (0, 65535));
-        // for song in songs.unwrap() {
-        //     query_info.model.insert(0, &SongObject::new(&song));
-        // }
-
+        // query_info.results.set_songs(songs.unwrap().iter());
+
+        // What the event loop works with, passed along to `handle_event`.
+        let ui = Ui {
+            window,
+            header_bar,
+            current_title,
+            song_info,
+            query_info,
+            browse_info,
+            stack,
+            leaflet,
+            separator,
+            macro_menu,
+            server_menu,
+            mini_player,
+            toast,
+            connection,
+            tray,
+            mpris,
+            config,
+            form_factor,
+            options,
+            sender,
+        };
         // Finally, we'll start the "main event loop" we've been talking about
         // in the main context of the application.
         let main_context = gtk::glib::MainContext::default();
         main_context.spawn_local(async move {
-            let mut conn = Client::connect(MPD_HOST).unwrap();
-            while let Some(event_type) = receiver.next().await {
-                match event_type {
-                    StateUpdateKind::MpdEvent => {
-                        if let Ok(title) = header_title(&mut conn) {
-                            header_bar.set_title(Some(&title));
-                            song_info
-                                .update(&mut conn)
-                                .expect("Couldn't update song info");
+            // This runs as long as the app does, and so does the stylesheet's
+            // monitor with it.
+            let _user_style_monitor = user_style_monitor;
+            let Ui {
+                window,
+                song_info,
+                query_info,
+                toast,
+                connection,
+                config,
+                options,
+                sender,
+                ..
+            } = &ui;
+            let mut pending = VecDeque::new();
+            // Songs asked for while offline, to add once the server's back.
+            let mut offline_adds = Vec::new();
+            // Without a server there's nothing to do, but the window stays up
+            // to say so until it turns up, or the user tries again.
+            let conn = match conn_receiver.await.expect("Couldn't notify thread") {
+                Ok(conn) => conn,
+                Err(e) => {
+                    song_info.show_placeholder(&format!("Couldn't connect to {}", options.host()));
+                    toast.show_error(&format!("{:#}", e));
+                    connection.set_state(ConnectionState::Offline);
+                    // The idle thread tries again with the password, and
+                    // lets us know once it's through.
+                    if needs_password(&e) {
+                        if let Some((password, remember)) = password_dialog(window, &options.host()).await {
+                            if let Err(e) = use_password(options, config, password, remember) {
+                                toast.show_error(&format!("{:#}", e));
+                            }
                         }
                     }
-                    StateUpdateKind::WindowResizeEvent => {
-                        song_info
-                            .update_album_art(&mut conn)
-                            .expect("Couldn't update album art");
-                    }
-                    StateUpdateKind::QueryUpdateEvent(query_string) => {
-                        // Let's not produce massive queries while the user is typing :)
-                        if query_string.len() <= 2 {
-                            continue;
+                    loop {
+                        match receiver.next().await {
+                            Some(StateUpdateKind::ConnectionChange(ConnectionState::Connected))
+                            | Some(StateUpdateKind::ReconnectRequest) => {
+                                if let Ok(conn) = connect_in_background(options).await {
+                                    break conn;
+                                }
+                            }
+                            Some(event) if event.is_queue_add() => save_offline_add(toast, &mut offline_adds, event),
+                            Some(_) => {}
+                            None => return,
                         }
-
-                        // Start from a blank slate.
-                        query_info.model.remove_all();
-
-                        // Query on all fields, case-insensitively, for the text
-                        // that the user input.
-                        let mut query = mpd::Query::new();
-                        query.and(mpd::Term::Any, &query_string);
-                        let songs = conn.search(&query, (0, 65535));
-
-                        // Insert them all into the model. This is reversed,
-                        // which I don't consider to be a big deal. It's far
-                        // less complex than adding it in order, which you will
-                        // see below in the code that handles the queue.
-                        for song in songs.unwrap() {
-                            query_info.model.insert(0, &SongObject::new(&song));
-                        }
-                    }
-                    StateUpdateKind::QueueDeleteRequest(index) => {
-                        conn.delete(index).expect("Couldn't dequeue song");
-                    }
-                    StateUpdateKind::QueueAddRequest(filename) => {
-                        conn.push_str(filename).expect("Couldn't queue song");
-                    }
-                    StateUpdateKind::PlaybackStateChange(action) => {
-                        dispatch_playback_state_change(&mut conn, action)
-                            .expect("Couldn't queue action");
                     }
                 }
+            };
+            connection.set_state(ConnectionState::Connected);
+            if !offline_adds.is_empty() {
+                let adds = std::mem::take(&mut offline_adds);
+                if confirm_offline_adds(window, adds.len()).await {
+                    pending.extend(adds);
+                }
             }
-        });
-    });
-
-    application.run();
-}
-
-/// Take action on `conn` based on a `PlaybackStateChange` notification
-fn dispatch_playback_state_change(
-    conn: &mut mpd::Client,
-    action: PlaybackStateChange,
-) -> anyhow::Result<()> {
-    use PlaybackStateChange::*;
-    match action {
-        SkipBackwards => conn.prev()?,
-        SkipForwards => conn.next()?,
-        Start => conn.play()?,
-        Stop => conn.stop()?,
-        Pause => conn.pause(true)?,
-    }
-    Ok(())
-}
-
-/// Kind of event we can notify the UI future about
-#[derive(Debug)]
-enum StateUpdateKind {
-    MpdEvent,
-    WindowResizeEvent,
-    QueryUpdateEvent(String),
-    QueueAddRequest(String),
-    QueueDeleteRequest(u32),
-    PlaybackStateChange(PlaybackStateChange),
-}
-
-/// A simple action that affects playback state.
-#[derive(Debug)]
-enum PlaybackStateChange {
-    Start,
-    Stop,
-    Pause,
-    SkipBackwards,
-    SkipForwards,
-}
-
-/// Produce a short status line for the current state of `conn`.
-fn header_title(conn: &mut mpd::client::Client) -> anyhow::Result<String> {
-    let status = conn.status();
-    let state_descriptor = match status?.state {
-        mpd::status::State::Stop => "[STOPPED]",
-        mpd::status::State::Pause => "[PAUSED]",
-        mpd::status::State::Play => "[PLAYING]",
-    };
-    if let Some(song) = conn.currentsong()? {
-        Ok(format!(
-            "{} {} - {}",
-            state_descriptor,
-            song.title.unwrap_or_else(|| "Untitled".into()),
-            song.artist.unwrap_or_else(|| "Untitled".into()),
-        ))
-    } else {
-        Ok("Tunes: No Song".into())
-    }
-}
-
-/// View for information about the currently playing song.
-struct SongInfo {
-    container: gtk::Box,
-    album_art: gtk::Image,
-    song_text: gtk::Label,
-    model: gio::ListStore,
-}
-
-impl SongInfo {
-    fn new(sender: mpsc::Sender<StateUpdateKind>) -> Self {
-        let container = gtk::Box::new(gtk::Orientation::Vertical, 16);
-        let album_art = gtk::Image::new();
-        let song_text = gtk::Label::new(None);
-        song_text.set_justify(gtk::Justification::Center);
-        song_text.set_line_wrap(true);
-        song_text.set_line_wrap_mode(pango::WrapMode::WordChar);
-        container.add(&album_art);
-        container.add(&song_text);
-
-        let action_bar = gtk::Box::new(gtk::Orientation::Horizontal, 16);
-        action_bar.set_halign(gtk::Align::Center);
-
-        let control_previous_song = gtk::Button::from_icon_name(
-            Some("media-skip-backward-symbolic"),
-            gtk::IconSize::SmallToolbar,
-        );
-        action_bar.add(&control_previous_song);
-        control_previous_song.connect_clicked(clone!(@strong sender => move |_| {
-            let mut sender = sender.clone();
-            sender
-                .try_send(StateUpdateKind::PlaybackStateChange(
-                    PlaybackStateChange::SkipBackwards,
-                ))
+            // Now that there's a server to ask, let's go ahead and fill in
+            // the widgets.
+            let mut initial_sender = sender.clone();
+            initial_sender
+                .try_send(StateUpdateKind::MpdEvent)
                 .expect("Couldn't notify thread");
-        }));
-
-        let control_start_song = gtk::Button::from_icon_name(
-            Some("media-playback-start-symbolic"),
-            gtk::IconSize::SmallToolbar,
-        );
-        action_bar.add(&control_start_song);
-        control_start_song.connect_clicked(clone!(@strong sender => move |_| {
-            let mut sender = sender.clone();
-            sender
-                .try_send(StateUpdateKind::PlaybackStateChange(
-                    PlaybackStateChange::Start,
-                ))
-                .expect("Couldn't notify thread");
-        }));
-
-        let control_pause_song = gtk::Button::from_icon_name(
-            Some("media-playback-pause-symbolic"),
-            gtk::IconSize::SmallToolbar,
-        );
-        action_bar.add(&control_pause_song);
-        control_pause_song.connect_clicked(clone!(@strong sender => move |_| {
-            let mut sender = sender.clone();
-            sender
-                .try_send(StateUpdateKind::PlaybackStateChange(
-                    PlaybackStateChange::Pause,
-                ))
-                .expect("Couldn't notify thread");
-        }));
-
-        let control_stop_song = gtk::Button::from_icon_name(
-            Some("media-playback-stop-symbolic"),
-            gtk::IconSize::SmallToolbar,
-        );
-        action_bar.add(&control_stop_song);
-        control_stop_song.connect_clicked(clone!(@strong sender => move |_| {
-            let mut sender = sender.clone();
-            sender
-                .try_send(StateUpdateKind::PlaybackStateChange(
-                    PlaybackStateChange::Stop,
-                ))
-                .expect("Couldn't notify thread");
-        }));
-
-        let control_next_song = gtk::Button::from_icon_name(
-            Some("media-skip-forward-symbolic"),
-            gtk::IconSize::SmallToolbar,
-        );
-        action_bar.add(&control_next_song);
-        control_next_song.connect_clicked(clone!(@strong sender => move |_| {
-            let mut sender = sender.clone();
-            sender
-                .try_send(StateUpdateKind::PlaybackStateChange(
-                    PlaybackStateChange::SkipForwards,
-                ))
-                .expect("Couldn't notify thread");
-        }));
-
-        let model = gio::ListStore::new(SongObject::static_type());
-        let listbox = gtk::ListBox::new();
-        listbox.bind_model(
-            Some(&model),
-            clone!(@strong sender => move |item| {
-                let sender = sender.clone();
-
-                let box_ = gtk::ListBoxRow::new();
-                let item = item
-                    .downcast_ref::<SongObject>()
-                    .expect("Row data is of wrong type");
-
-                let grid = gtk::Grid::builder().column_homogeneous(true).build();
-
-                let remove_individual_song = gtk::Button::from_icon_name(
-                    Some("list-remove-symbolic"),
-                    gtk::IconSize::SmallToolbar,
-                );
-                let index = item.property::<u32>("index");
-                remove_individual_song.connect_clicked(move |_| {
+            if let Some(name) = options.run_macro.clone() {
+                initial_sender
+                    .try_send(StateUpdateKind::MacroRunRequest(name))
+                    .expect("Couldn't notify thread");
+            }
+            let mut session = Session {
+                conn,
+                conn_server_changes: options.server_changes(),
+                last_added: None,
+                last_played: None,
+                last_warmed: None,
+                idle_inhibit: None,
+                fuzzy_index: Arc::new(Mutex::new(None)),
+                search_results: None,
+                tag_index: None,
+                pending,
+                offline_adds,
+            };
+            let mut last_used = Instant::now();
+            // Nudge the loop now and then, so the check below keeps the
+            // connection from going quiet, and finds out when it's gone.
+            let _keep_alive = Clock::get().timeout_seconds(
+                IDLE_CONNECTION_CHECK.as_secs() as u32,
+                clone!(@strong sender => move || {
                     let mut sender = sender.clone();
                     sender
-                        .try_send(StateUpdateKind::QueueDeleteRequest(index))
+                        .try_send(StateUpdateKind::KeepAlive)
                         .expect("Couldn't notify thread");
-                    sender
-                        .try_send(StateUpdateKind::MpdEvent)
-                        .expect("Couldn't notify thread");
-                });
-                grid.attach(&remove_individual_song, 0, 0, 1, 1);
-
-                let title_label = gtk::Label::new(None);
-                title_label.set_line_wrap(true);
-                title_label.set_line_wrap_mode(pango::WrapMode::WordChar);
-                item.bind_property("title", &title_label, "label")
-                    .flags(glib::BindingFlags::DEFAULT | glib::BindingFlags::SYNC_CREATE)
-                    .build();
-                grid.attach(&title_label, 1, 0, 1, 1);
-
-
-                let album_label = gtk::Label::new(None);
-                album_label.set_line_wrap(true);
-                album_label.set_line_wrap_mode(pango::WrapMode::WordChar);
-                item.bind_property("album", &album_label, "label")
-                    .flags(glib::BindingFlags::DEFAULT | glib::BindingFlags::SYNC_CREATE)
-                    .build();
-                grid.attach(&album_label, 2, 0, 1, 1);
-
-
-                let artist_label = gtk::Label::new(None);
-                artist_label.set_line_wrap(true);
-                artist_label.set_line_wrap_mode(pango::WrapMode::WordChar);
-                item.bind_property("artist", &artist_label, "label")
-                    .flags(glib::BindingFlags::DEFAULT | glib::BindingFlags::SYNC_CREATE)
-                    .build();
-                grid.attach(&artist_label, 3, 0, 1, 1);
-
-                grid.show_all();
-                box_.add(&grid);
-                box_.upcast::<gtk::Widget>()
-            }),
-        );
-
-        let scrolled_window =
-            gtk::ScrolledWindow::new(gtk::Adjustment::NONE, gtk::Adjustment::NONE);
-        scrolled_window.add(&listbox);
-        scrolled_window.set_vexpand(true);
-
-        container.add(&action_bar);
-        container.add(&scrolled_window);
-        container.show_all();
-
-        SongInfo {
-            container,
-            album_art,
-            song_text,
-            model,
-        }
-    }
-
-    fn update_album_art(&self, conn: &mut mpd::Client) -> anyhow::Result<()> {
-        if let Some(song) = conn.currentsong()? {
-            // If we've been allocated a window, pick the least dimension (width
-            // or height) and divide that dimension by two to get the size (in
-            // pixels) that we'll scale the album art to. Otherwise, we default
-            // to 128.
-            let album_art_size = std::cmp::min(
-                self.container
-                    .window()
-                    .map(|x| x.width() / 2)
-                    .unwrap_or(128),
-                self.container
-                    .window()
-                    .map(|x| x.height() / 2)
-                    .unwrap_or(128),
+                    glib::Continue(true)
+                }),
             );
-
-            let image_data = conn.albumart(&song)?;
-            let image_pixbuf = gdk_pixbuf::Pixbuf::from_stream(
-                &gio::MemoryInputStream::from_bytes(&glib::Bytes::from(&image_data)),
-                gio::Cancellable::NONE,
-            )
-            .ok()
-            .and_then(|x| {
-                x.scale_simple(
-                    album_art_size,
-                    album_art_size,
-                    gtk::gdk_pixbuf::InterpType::Hyper,
-                )
-            });
-            self.album_art.set_pixbuf(image_pixbuf.as_ref());
-        }
-        Ok(())
-    }
-
-    fn update(&self, conn: &mut mpd::Client) -> anyhow::Result<()> {
-        self.update_album_art(conn)?;
-
-        if let Some(song) = conn.currentsong()? {
-            let title = song.title.as_deref().unwrap_or("[Unknown]");
-            let artist = song.artist.as_deref().unwrap_or("[Unknown]");
-            let album = song
-                .tags
-                .get("Album")
-                .map(|x| x.as_str())
-                .unwrap_or("[Unknown]");
-            let text = format!("{}\n{} - {}", title, artist, album);
-            self.song_text.set_text(&text);
-
-            // We'll use `pango` attributes to make the display look nice and
-            // pretty. Scale the title of the song the most, and still make the
-            // other info reasonably large.
-            let attr_list = gtk::pango::AttrList::new();
-            let mut attr = gtk::pango::AttrFloat::new_scale(2.0);
-            attr.set_start_index(0);
-            attr.set_end_index(title.len() as u32);
-            attr_list.insert(attr);
-            let mut attr = gtk::pango::AttrFloat::new_scale(1.5);
-            attr.set_start_index(title.len() as u32 + 1);
-            attr_list.insert(attr);
-
-            self.song_text.set_attributes(Some(&attr_list));
-        }
-
-        self.model.remove_all();
-        for (i, song) in conn.queue()?.iter().enumerate() {
-            let index = i.try_into().unwrap();
-            let object = SongObject::new(song);
-            object.set_index(index);
-            self.model.insert(index, &object)
-        }
-
-        Ok(())
-    }
-}
-
-impl AsRef<gtk::Widget> for SongInfo {
-    fn as_ref(&self) -> &gtk::Widget {
-        self.container.upcast_ref()
-    }
-}
-
-/// View for selecting songs to add to the queue.
-struct QueryInfo {
-    container: gtk::Box,
-    model: gio::ListStore,
-}
-
-impl QueryInfo {
-    fn new(sender: mpsc::Sender<StateUpdateKind>) -> Self {
-        let container = gtk::Box::new(gtk::Orientation::Vertical, 2);
-
-        let query_input = gtk::Entry::builder().visible(true).build();
-        query_input.connect_key_press_event(clone!(@strong sender => move |widget, _| {
-            let mut sender = sender.clone();
-            sender
-                .try_send(StateUpdateKind::QueryUpdateEvent(widget.text().into()))
-                .expect("Couldn't notify thread");
-            gtk::Inhibit(false)
-        }));
-
-        let model = gio::ListStore::new(SongObject::static_type());
-        let listbox = gtk::ListBox::new();
-        listbox.bind_model(Some(&model), clone!(@strong sender => move |item| {
-            let sender = sender.clone();
-
-            let box_ = gtk::ListBoxRow::new();
-            let item = item
-                .downcast_ref::<SongObject>()
-                .expect("Row data is of wrong type");
-
-            let grid = gtk::Grid::builder().column_homogeneous(true).build();
-
-            let add_individual_song =
-                gtk::Button::from_icon_name(Some("list-add-symbolic"), gtk::IconSize::SmallToolbar);
-            add_individual_song.set_visible(true);
-            let filename = item.property::<String>("filename");
-            add_individual_song.connect_clicked(move |_| {
-                let filename = filename.clone();
-                let mut sender = sender.clone();
-                sender
-                    .try_send(StateUpdateKind::QueueAddRequest(filename))
-                    .expect("Couldn't notify thread");
-                sender
-                    .try_send(StateUpdateKind::MpdEvent)
-                    .expect("Couldn't notify thread");
-            });
-            grid.attach(&add_individual_song, 0, 0, 1, 1);
-
-            let title_label = gtk::Label::new(None);
-            title_label.set_line_wrap(true);
-            title_label.set_line_wrap_mode(pango::WrapMode::WordChar);
-            item.bind_property("title", &title_label, "label")
-                .flags(glib::BindingFlags::DEFAULT | glib::BindingFlags::SYNC_CREATE)
-                .build();
-            grid.attach(&title_label, 1, 0, 1, 1);
-
-            let album_label = gtk::Label::new(None);
-            album_label.set_line_wrap(true);
-            album_label.set_line_wrap_mode(pango::WrapMode::WordChar);
-            item.bind_property("album", &album_label, "label")
-                .flags(glib::BindingFlags::DEFAULT | glib::BindingFlags::SYNC_CREATE)
-                .build();
-            grid.attach(&album_label, 2, 0, 1, 1);
-
-            let artist_label = gtk::Label::new(None);
-            artist_label.set_line_wrap(true);
-            artist_label.set_line_wrap_mode(pango::WrapMode::WordChar);
-            item.bind_property("artist", &artist_label, "label")
-                .flags(glib::BindingFlags::DEFAULT | glib::BindingFlags::SYNC_CREATE)
-                .build();
-            grid.attach(&artist_label, 3, 0, 1, 1);
-
-            grid.show_all();
-            box_.add(&grid);
-            box_.upcast::<gtk::Widget>()
-        }));
-
-        let scrolled_window =
-            gtk::ScrolledWindow::new(gtk::Adjustment::NONE, gtk::Adjustment::NONE);
-        scrolled_window.add(&listbox);
-        scrolled_window.set_vexpand(true);
-
-        container.add(&query_input);
-        container.add(&scrolled_window);
-
-        QueryInfo { container, model }
-    }
-}
-
-impl AsRef<gtk::Widget> for QueryInfo {
-    fn as_ref(&self) -> &gtk::Widget {
-        self.container.upcast_ref()
-    }
-}
-
-// Unfortunately, to use the `ListStore` interface, we'll need to represent our
-// data as an actual `glib` object. This is a little hairy in Rust, involving a
-// fair bit of boilerplate, but not too terrible.
-glib::wrapper! {
-    pub struct SongObject(ObjectSubclass<imp::SongObject>);
-}
-
-impl SongObject {
-    pub fn new(song: &mpd::song::Song) -> Self {
-        glib::Object::new(&[
-            ("filename", &song.file.clone()),
-            (
-                "title",
-                &song
-                    .title
-                    .as_ref()
-                    .cloned()
-                    .unwrap_or_else(|| "[Untitled]".into()),
-            ),
-            (
-                "artist",
-                &song
-                    .artist
-                    .as_ref()
-                    .cloned()
-                    .unwrap_or_else(|| "[No Artist]".into()),
-            ),
-            (
-                "album",
-                &song
-                    .tags
-                    .get("Album")
-                    .cloned()
-                    .unwrap_or_else(|| "[Untitled]".into()),
-            ),
-        ])
-        .expect("Failed to create `SongObject`.")
-    }
-
-    pub fn set_index(&self, idx: u32) {
-        let private = imp::SongObject::from_instance(self);
-        private.index.set(idx);
-    }
-}
-
-// These class "implementations" are typically done in a separate
-// file/directory. I wanted to keep the example self-contained.
-mod imp {
-    use std::cell::{Cell, RefCell};
-
-    use glib::{ParamSpec, ParamSpecString, Value};
-    use gtk::glib;
-    use gtk::prelude::*;
-    use gtk::subclass::prelude::*;
-    use once_cell::sync::Lazy;
-
-    // Object holding the state
-    #[derive(Default)]
-    pub struct SongObject {
-        filename: RefCell<String>,
-        title: RefCell<String>,
-        artist: RefCell<String>,
-        album: RefCell<String>,
-        pub(crate) index: Cell<u32>,
-    }
-
-    // The central trait for subclassing a GObject
-    #[glib::object_subclass]
-    impl ObjectSubclass for SongObject {
-        const NAME: &'static str = "TunesSongObject";
-        type Type = super::SongObject;
-    }
-
-    // Trait shared by all GObjects
-    impl ObjectImpl for SongObject {
-        fn properties() -> &'static [ParamSpec] {
-            static PROPERTIES: Lazy<Vec<ParamSpec>> = Lazy::new(|| {
-                vec![
-                    ParamSpecString::builder("filename").build(),
-                    ParamSpecString::builder("title").build(),
-                    ParamSpecString::builder("artist").build(),
-                    ParamSpecString::builder("album").build(),
-                    ParamSpecString::builder("index").build(),
-                ]
-            });
-            PROPERTIES.as_ref()
-        }
-
-        fn set_property(&self, _obj: &Self::Type, _id: usize, value: &Value, pspec: &ParamSpec) {
-            match pspec.name() {
-                "filename" => {
-                    let input = value
-                        .get()
-                        .expect("The value needs to be of type `String`.");
-                    self.filename.replace(input);
-                }
-                "title" => {
-                    let input = value
-                        .get()
-                        .expect("The value needs to be of type `String`.");
-                    self.title.replace(input);
-                }
-                "artist" => {
-                    let input = value
-                        .get()
-                        .expect("The value needs to be of type `String`.");
-                    self.artist.replace(input);
+            while let Some(event_type) = next_event(&mut receiver, &mut session.pending).await {
+                // Check a kiosk's connection is still good before each event,
+                // and sit tight while the server's away. The idle thread will
+                // let us know once it's back. Anyone's connection may have
+                // gone while the device was suspended, or been dropped by
+                // the server after sitting unused for a while.
+                let check = options.kiosk
+                    || matches!(event_type, StateUpdateKind::Resumed)
+                    || last_used.elapsed() > IDLE_CONNECTION_CHECK;
+                last_used = Instant::now();
+                if check && session.conn.ping().is_err() {
+                    match connect_in_background(options).await {
+                        Ok(new_conn) => {
+                            session.conn = new_conn;
+                            connection.set_state(ConnectionState::Connected);
+                        }
+                        // Adds get saved for later, just below.
+                        Err(_) => {
+                            connection.set_state(ConnectionState::Offline);
+                            if !event_type.is_queue_add() {
+                                continue;
+                            }
+                        }
+                    }
                 }
-                "album" => {
-                    let input = value
-                        .get()
-                        .expect("The value needs to be of type `String`.");
-                    self.album.replace(input);
+                // Adding to the queue can wait until the server's back, and
+                // the user gets a say then. Anything else goes ahead, to fail
+                // and say so as usual.
+                if connection.state() == ConnectionState::Offline && event_type.is_queue_add() {
+                    save_offline_add(toast, &mut session.offline_adds, event_type);
+                    continue;
                 }
-                "index" => {
-                    let input = value.get().expect("The value needs to be of type `u32`.");
-                    self.index.replace(input);
+
+                // Anything going wrong is shown rather than taking the whole
+                // app down with it, since it's most likely the server having
+                // a moment.
+                let result = handle_event(&ui, &mut session, event_type).await;
+                if let Err(e) = result {
+                    // Whatever it was in the middle of has stopped.
+                    query_info.set_busy(false);
+                    // The server wants a password we don't have, or have
+                    // wrong, so ask for one rather than just failing.
+                    if needs_password(&e) {
+                        let host = options.host();
+                        if let Some((password, remember)) = password_dialog(window, &host).await {
+                            if let Err(e) = use_password(options, config, password, remember) {
+                                toast.show_error(&format!("{:#}", e));
+                            }
+                            session.conn_server_changes = options.server_changes();
+                            match connect_in_background(options).await {
+                                Ok(new_conn) => {
+                                    session.conn = new_conn;
+                                    connection.set_state(ConnectionState::Connected);
+                                    toast.show(&format!("Logged in to {}", host));
+                                }
+                                Err(e) => toast.show_error(&format!("{:#}", e.context("Couldn't log in"))),
+                            }
+                            continue;
+                        }
+                    }
+                    // A command left half-answered has the connection out of
+                    // step, so there's no asking it anything else.
+                    if timed_out(&e) {
+                        toast.show_error(&format!("Lost the connection to {}", options.host()));
+                        connection.set_state(ConnectionState::Connecting);
+                    } else {
+                        toast.show_error(&format!("{:#}", e));
+                    }
+                    // The connection may well have gone with it.
+                    if timed_out(&e) || session.conn.ping().is_err() {
+                        match connect_in_background(options).await {
+                            Ok(new_conn) => {
+                                session.conn = new_conn;
+                                connection.set_state(ConnectionState::Connected);
+                            }
+                            Err(_) => connection.set_state(ConnectionState::Offline),
+                        }
+                    }
                 }
-                _ => unimplemented!(),
             }
-        }
+        });
+    });
 
-        fn property(&self, _obj: &Self::Type, _id: usize, pspec: &ParamSpec) -> Value {
-            match pspec.name() {
-                "filename" => self.filename.borrow().to_value(),
-                "title" => self.title.borrow().to_value(),
-                "artist" => self.artist.borrow().to_value(),
-                "album" => self.album.borrow().to_value(),
-                "index" => self.index.get().to_value(),
-                _ => unimplemented!(),
-            }
-        }
-    }
+    application.run_with_args(&gtk_args);
 }
@@ -0,0 +1,53 @@
+// Copyright © 2021-2022 Jakob L. Kreuze <zerodaysfordays@sdf.org>
+//
+// This file is part of Tunes.
+//
+// Tunes is free software; you can redistribute it and/or modify it
+// under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation; either version 3 of the
+// License, or (at your option) any later version.
+//
+// Tunes is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY
+// or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Affero General
+// Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public
+// License along with Tunes. If not, see <http://www.gnu.org/licenses/>.
+
+use gtk::{gdk_pixbuf, gio, glib};
+use tunes_core::DecodedArt;
+
+/// Decode a picture and scale it to `size` pixels square.
+pub(crate) fn decode_art(data: &[u8], size: i32) -> Option<DecodedArt> {
+    let pixbuf = gdk_pixbuf::Pixbuf::from_stream(
+        &gio::MemoryInputStream::from_bytes(&glib::Bytes::from(data)),
+        gio::Cancellable::NONE,
+    )
+    .ok()?
+    .scale_simple(size, size, gdk_pixbuf::InterpType::Hyper)?;
+    Some(art_from_pixbuf(&pixbuf))
+}
+
+pub(crate) fn art_from_pixbuf(pixbuf: &gdk_pixbuf::Pixbuf) -> DecodedArt {
+    DecodedArt::new(
+        pixbuf.read_pixel_bytes().to_vec(),
+        pixbuf.width(),
+        pixbuf.height(),
+        pixbuf.rowstride(),
+        pixbuf.has_alpha(),
+    )
+}
+
+/// Art that came over from another thread, as a pixbuf to show.
+pub(crate) fn art_pixbuf(art: &DecodedArt) -> gdk_pixbuf::Pixbuf {
+    gdk_pixbuf::Pixbuf::from_bytes(
+        &glib::Bytes::from(&art.pixels),
+        gdk_pixbuf::Colorspace::Rgb,
+        art.has_alpha,
+        8,
+        art.width,
+        art.height,
+        art.rowstride,
+    )
+}
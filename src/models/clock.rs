@@ -0,0 +1,291 @@
+// Copyright © 2021-2022 Jakob L. Kreuze <zerodaysfordays@sdf.org>
+//
+// This file is part of Tunes.
+//
+// Tunes is free software; you can redistribute it and/or modify it
+// under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation; either version 3 of the
+// License, or (at your option) any later version.
+//
+// Tunes is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY
+// or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Affero General
+// Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public
+// License along with Tunes. If not, see <http://www.gnu.org/licenses/>.
+
+use gtk::glib;
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+use std::sync::atomic::AtomicU64;
+use std::time::{Duration, Instant};
+
+/// Where time comes from. That's normally the system, but integration tests
+/// can run us with `--fake-clock` so that time only moves when they say so,
+/// and everything that waits on it happens exactly when they expect.
+#[derive(Clone)]
+pub(crate) enum Clock {
+    System,
+    Fake(Rc<FakeClock>),
+}
+
+pub(crate) struct FakeClock {
+    /// The real instant we started at, which fake time counts on from.
+    pub(crate) start: Instant,
+    pub(crate) elapsed: Cell<Duration>,
+    next_id: Cell<u64>,
+    timers: RefCell<Vec<FakeTimer>>,
+    /// The timer whose callback is running, unless it was removed meanwhile.
+    running: Cell<Option<u64>>,
+}
+
+/// A timer waiting for fake time to reach it.
+pub(crate) struct FakeTimer {
+    pub(crate) id: u64,
+    due: Duration,
+    /// How often it goes off again, for timers that repeat.
+    interval: Option<Duration>,
+    callback: Box<dyn FnMut() -> glib::Continue>,
+}
+
+thread_local! {
+    pub(crate) static CLOCK: RefCell<Clock> = RefCell::new(Clock::System);
+}
+
+impl Clock {
+    /// The clock everything on this thread should go by.
+    pub(crate) fn get() -> Self {
+        CLOCK.with(|clock| clock.borrow().clone())
+    }
+
+    /// Switch this thread over to a fake clock, stopped at the current time.
+    pub(crate) fn use_fake() -> Self {
+        let clock = Clock::Fake(Rc::new(FakeClock {
+            start: Instant::now(),
+            elapsed: Cell::new(Duration::ZERO),
+            next_id: Cell::new(0),
+            timers: RefCell::new(Vec::new()),
+            running: Cell::new(None),
+        }));
+        CLOCK.with(|current| current.replace(clock.clone()));
+        clock
+    }
+
+    pub(crate) fn now(&self) -> Instant {
+        match self {
+            Clock::System => Instant::now(),
+            Clock::Fake(fake) => fake.start + fake.elapsed.get(),
+        }
+    }
+
+    /// Run `callback` once, `delay` from now.
+    pub(crate) fn timeout_once(&self, delay: Duration, callback: impl FnOnce() + 'static) -> ClockSource {
+        match self {
+            Clock::System => ClockSource::Glib(glib::timeout_add_local_once(delay, callback)),
+            Clock::Fake(fake) => {
+                let mut callback = Some(callback);
+                fake.add(delay, None, move || {
+                    if let Some(callback) = callback.take() {
+                        callback();
+                    }
+                    glib::Continue(false)
+                })
+            }
+        }
+    }
+
+    /// Run `callback` every `interval` seconds, for as long as it says to.
+    pub(crate) fn timeout_seconds(
+        &self,
+        interval: u32,
+        callback: impl FnMut() -> glib::Continue + 'static,
+    ) -> ClockSource {
+        match self {
+            Clock::System => ClockSource::Glib(glib::timeout_add_seconds_local(interval, callback)),
+            Clock::Fake(fake) => {
+                let interval = Duration::from_secs(interval.into());
+                fake.add(interval, Some(interval), callback)
+            }
+        }
+    }
+
+    /// Move a fake clock on by `by`, running every timer that comes due on
+    /// the way, in order. The system clock moves on by itself.
+    pub(crate) fn advance(&self, by: Duration) {
+        let fake = match self {
+            Clock::System => return,
+            Clock::Fake(fake) => fake,
+        };
+        let target = fake.elapsed.get() + by;
+        loop {
+            // The callback might well add or remove timers, so it can't run
+            // while we're holding on to the list.
+            let mut timer = {
+                let mut timers = fake.timers.borrow_mut();
+                let next = timers
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, timer)| timer.due <= target)
+                    .min_by_key(|(_, timer)| (timer.due, timer.id))
+                    .map(|(i, _)| i);
+                match next {
+                    Some(i) => timers.remove(i),
+                    None => break,
+                }
+            };
+            fake.elapsed.set(timer.due);
+            fake.running.set(Some(timer.id));
+            let again = (timer.callback)();
+            if let (glib::Continue(true), Some(interval), Some(_)) =
+                (again, timer.interval, fake.running.take())
+            {
+                timer.due += interval;
+                fake.timers.borrow_mut().push(timer);
+            }
+        }
+        fake.elapsed.set(target);
+    }
+}
+
+impl FakeClock {
+    pub(crate) fn add(
+        self: &Rc<Self>,
+        delay: Duration,
+        interval: Option<Duration>,
+        callback: impl FnMut() -> glib::Continue + 'static,
+    ) -> ClockSource {
+        let id = self.next_id.get();
+        self.next_id.set(id + 1);
+        self.timers.borrow_mut().push(FakeTimer {
+            id,
+            due: self.elapsed.get() + delay,
+            interval,
+            callback: Box::new(callback),
+        });
+        ClockSource::Fake(Rc::downgrade(self), id)
+    }
+}
+
+/// A timer set with a `Clock`, which can be removed before it goes off.
+pub(crate) enum ClockSource {
+    Glib(glib::SourceId),
+    Fake(std::rc::Weak<FakeClock>, u64),
+}
+
+impl ClockSource {
+    pub(crate) fn remove(self) {
+        match self {
+            ClockSource::Glib(source) => source.remove(),
+            ClockSource::Fake(fake, id) => {
+                if let Some(fake) = fake.upgrade() {
+                    fake.timers.borrow_mut().retain(|timer| timer.id != id);
+                    if fake.running.get() == Some(id) {
+                        fake.running.set(None);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// A timer that starts over whenever it's set again, so whatever it's for
+/// only happens once things have been quiet for a while.
+#[derive(Default)]
+pub(crate) struct Debounce {
+    pub(crate) pending: Rc<RefCell<Option<ClockSource>>>,
+}
+
+impl Debounce {
+    /// Run `callback` once it's been `delay` since this was last set, instead
+    /// of whatever was waiting.
+    pub(crate) fn set(&self, delay: Duration, callback: impl FnOnce() + 'static) {
+        self.cancel();
+        let pending = self.pending.clone();
+        let source = Clock::get().timeout_once(delay, move || {
+            pending.take();
+            callback();
+        });
+        self.pending.replace(Some(source));
+    }
+
+    /// Forget whatever's waiting, returning whether there was anything.
+    pub(crate) fn cancel(&self) -> bool {
+        match self.pending.take() {
+            Some(source) => {
+                source.remove();
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// Number of times a periodic timer has woken us up, so it's easy to check
+/// that we really are sitting still when there's nothing to do.
+pub(crate) static TIMER_WAKEUPS: AtomicU64 = AtomicU64::new(0);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use glib::clone;
+
+    /// About how long the search form waits for typing to stop.
+    const SEARCH_DEBOUNCE: Duration = Duration::from_millis(250);
+
+    #[test]
+    fn waits_for_a_pause() {
+        let clock = Clock::use_fake();
+        let debounce = Debounce::default();
+        let fired = Rc::new(Cell::new(0));
+        let set = |debounce: &Debounce| {
+            let fired = fired.clone();
+            debounce.set(SEARCH_DEBOUNCE, move || fired.set(fired.get() + 1));
+        };
+
+        // Typing faster than the delay keeps putting it off.
+        for _ in 0..3 {
+            set(&debounce);
+            clock.advance(SEARCH_DEBOUNCE / 2);
+        }
+        assert_eq!(fired.get(), 0);
+
+        // It goes off once, a whole delay after the last change.
+        clock.advance(SEARCH_DEBOUNCE / 2 - Duration::from_millis(1));
+        assert_eq!(fired.get(), 0);
+        clock.advance(Duration::from_millis(1));
+        assert_eq!(fired.get(), 1);
+        clock.advance(SEARCH_DEBOUNCE * 4);
+        assert_eq!(fired.get(), 1);
+        assert!(!debounce.cancel());
+    }
+
+    #[test]
+    fn can_be_called_off() {
+        let clock = Clock::use_fake();
+        let debounce = Debounce::default();
+        let fired = Rc::new(Cell::new(false));
+        debounce.set(SEARCH_DEBOUNCE, clone!(@strong fired => move || fired.set(true)));
+        assert!(debounce.cancel());
+        clock.advance(SEARCH_DEBOUNCE * 2);
+        assert!(!fired.get());
+    }
+
+    #[test]
+    fn repeats_on_schedule() {
+        let clock = Clock::use_fake();
+        let ticks = Rc::new(Cell::new(0));
+        let source = clock.timeout_seconds(
+            30,
+            clone!(@strong ticks => move || {
+                ticks.set(ticks.get() + 1);
+                glib::Continue(true)
+            }),
+        );
+        clock.advance(Duration::from_secs(95));
+        assert_eq!(ticks.get(), 3);
+        source.remove();
+        clock.advance(Duration::from_secs(60));
+        assert_eq!(ticks.get(), 3);
+    }
+}
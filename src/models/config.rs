@@ -0,0 +1,34 @@
+// Copyright © 2021-2022 Jakob L. Kreuze <zerodaysfordays@sdf.org>
+//
+// This file is part of Tunes.
+//
+// Tunes is free software; you can redistribute it and/or modify it
+// under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation; either version 3 of the
+// License, or (at your option) any later version.
+//
+// Tunes is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY
+// or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Affero General
+// Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public
+// License along with Tunes. If not, see <http://www.gnu.org/licenses/>.
+
+use gtk::glib;
+use std::path::PathBuf;
+
+/// Settings profile picked on the command line, if any. Each has a config
+/// file of its own, so, say, a kitchen tablet's setup can live alongside the
+/// everyday one.
+pub(crate) static PROFILE: once_cell::sync::OnceCell<String> = once_cell::sync::OnceCell::new();
+
+/// Where the settings for the profile in use are kept, in the user's config
+/// directory.
+pub(crate) fn config_path() -> PathBuf {
+    let dir = glib::user_config_dir().join("tunes");
+    match PROFILE.get() {
+        Some(profile) => dir.join("profiles").join(format!("{}.conf", profile)),
+        None => dir.join("tunes.conf"),
+    }
+}
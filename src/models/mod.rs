@@ -0,0 +1,34 @@
+// Copyright © 2021-2022 Jakob L. Kreuze <zerodaysfordays@sdf.org>
+//
+// This file is part of Tunes.
+//
+// Tunes is free software; you can redistribute it and/or modify it
+// under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation; either version 3 of the
+// License, or (at your option) any later version.
+//
+// Tunes is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY
+// or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Affero General
+// Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public
+// License along with Tunes. If not, see <http://www.gnu.org/licenses/>.
+
+// What the app keeps track of on top of tunes-core: where the settings live,
+// timers, shortcuts, album art as pixbufs and songs as list items. Settings
+// themselves, events, macros and smart playlists are in tunes-core.
+
+mod art;
+mod clock;
+mod config;
+mod search;
+mod shortcut;
+mod song_object;
+
+pub(crate) use art::{art_from_pixbuf, art_pixbuf, decode_art};
+pub(crate) use clock::{Clock, ClockSource, Debounce, TIMER_WAKEUPS};
+pub(crate) use config::{config_path, PROFILE};
+pub(crate) use search::{search_page_size, SEARCH_PAGE_SIZES};
+pub(crate) use shortcut::Shortcut;
+pub(crate) use song_object::{format_duration, format_total_duration, SongObject};
@@ -0,0 +1,30 @@
+// Copyright © 2021-2022 Jakob L. Kreuze <zerodaysfordays@sdf.org>
+//
+// This file is part of Tunes.
+//
+// Tunes is free software; you can redistribute it and/or modify it
+// under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation; either version 3 of the
+// License, or (at your option) any later version.
+//
+// Tunes is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY
+// or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Affero General
+// Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public
+// License along with Tunes. If not, see <http://www.gnu.org/licenses/>.
+
+use tunes_core::Config;
+
+/// How many search results to fetch at a time, as pairs of config value and
+/// label. The first is the default.
+pub(crate) const SEARCH_PAGE_SIZES: [(&str, &str); 4] = [("100", "100"), ("50", "50"), ("250", "250"), ("500", "500")];
+
+/// How many search results to fetch at a time, as set in the preferences.
+pub(crate) fn search_page_size(config: &Config) -> u32 {
+    config
+        .get("search", "page-size")
+        .and_then(|size| size.parse().ok())
+        .unwrap_or(100)
+}
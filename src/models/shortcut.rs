@@ -0,0 +1,150 @@
+// Copyright © 2021-2022 Jakob L. Kreuze <zerodaysfordays@sdf.org>
+//
+// This file is part of Tunes.
+//
+// Tunes is free software; you can redistribute it and/or modify it
+// under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation; either version 3 of the
+// License, or (at your option) any later version.
+//
+// Tunes is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY
+// or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Affero General
+// Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public
+// License along with Tunes. If not, see <http://www.gnu.org/licenses/>.
+
+use tunes_core::Config;
+
+/// Things that can be done from the keyboard. Each has a default shortcut
+/// (or none), which the user can change in the preferences.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Shortcut {
+    PlayPause,
+    Next,
+    Previous,
+    Stop,
+    VolumeUp,
+    VolumeDown,
+    Search,
+    FocusNext,
+    FocusPrevious,
+    Preferences,
+    ShowShortcuts,
+}
+
+impl Shortcut {
+    pub(crate) const SECTION: &'static str = "shortcuts";
+
+    pub(crate) const ALL: [Shortcut; 11] = [
+        Shortcut::PlayPause,
+        Shortcut::Next,
+        Shortcut::Previous,
+        Shortcut::Stop,
+        Shortcut::VolumeUp,
+        Shortcut::VolumeDown,
+        Shortcut::Search,
+        Shortcut::FocusNext,
+        Shortcut::FocusPrevious,
+        Shortcut::Preferences,
+        Shortcut::ShowShortcuts,
+    ];
+
+    /// The name of the app action, which is also its key in the config.
+    pub(crate) fn key(self) -> &'static str {
+        match self {
+            Shortcut::PlayPause => "play-pause",
+            Shortcut::Next => "next",
+            Shortcut::Previous => "previous",
+            Shortcut::Stop => "stop",
+            Shortcut::VolumeUp => "volume-up",
+            Shortcut::VolumeDown => "volume-down",
+            Shortcut::Search => "search",
+            Shortcut::FocusNext => "focus-next",
+            Shortcut::FocusPrevious => "focus-previous",
+            Shortcut::Preferences => "preferences",
+            Shortcut::ShowShortcuts => "shortcuts",
+        }
+    }
+
+    pub(crate) fn title(self) -> &'static str {
+        match self {
+            Shortcut::PlayPause => "Play or Pause",
+            Shortcut::Next => "Next Song",
+            Shortcut::Previous => "Previous Song",
+            Shortcut::Stop => "Stop",
+            Shortcut::VolumeUp => "Volume Up",
+            Shortcut::VolumeDown => "Volume Down",
+            Shortcut::Search => "Search",
+            Shortcut::FocusNext => "Move Down a Row",
+            Shortcut::FocusPrevious => "Move Up a Row",
+            Shortcut::Preferences => "Preferences",
+            Shortcut::ShowShortcuts => "Keyboard Shortcuts",
+        }
+    }
+
+    /// The shortcut out of the box, in the form `gtk::accelerator_parse`
+    /// reads. The arrow keys already move between rows, so that's left for
+    /// anyone who wants j and k.
+    pub(crate) fn default_accelerator(self) -> &'static str {
+        match self {
+            Shortcut::PlayPause => "<Primary>space",
+            Shortcut::Next => "<Primary>Right",
+            Shortcut::Previous => "<Primary>Left",
+            Shortcut::Stop => "",
+            Shortcut::VolumeUp => "<Primary>Up",
+            Shortcut::VolumeDown => "<Primary>Down",
+            Shortcut::Search => "<Primary>f",
+            Shortcut::FocusNext | Shortcut::FocusPrevious => "",
+            Shortcut::Preferences => "<Primary>comma",
+            Shortcut::ShowShortcuts => "<Primary>question",
+        }
+    }
+
+    /// The key and modifiers this is bound to, if any. An empty setting means
+    /// the user's turned it off.
+    pub(crate) fn binding(self, config: &Config) -> Option<(u32, gtk::gdk::ModifierType)> {
+        let accelerator = config
+            .get(Self::SECTION, self.key())
+            .unwrap_or_else(|| self.default_accelerator());
+        let (keyval, modifiers) = gtk::accelerator_parse(accelerator);
+        (keyval != 0).then(|| (keyval, modifiers))
+    }
+
+    /// How the shortcut is shown to the user, like "Ctrl+F".
+    pub(crate) fn label(self, config: &Config) -> String {
+        self.binding(config)
+            .and_then(|(keyval, modifiers)| gtk::accelerator_get_label(keyval, modifiers))
+            .map_or_else(|| "Disabled".to_string(), String::from)
+    }
+
+    /// The keyboard media keys that do the same, on top of whatever the
+    /// shortcut's bound to.
+    pub(crate) fn media_keys(self) -> &'static [&'static str] {
+        match self {
+            Shortcut::PlayPause => &["XF86AudioPlay", "XF86AudioPause"],
+            Shortcut::Next => &["XF86AudioNext"],
+            Shortcut::Previous => &["XF86AudioPrev"],
+            Shortcut::Stop => &["XF86AudioStop"],
+            _ => &[],
+        }
+    }
+
+    /// Which shortcut, if any, the media key press `event` is for.
+    pub(crate) fn find_media_key(event: &gtk::gdk::EventKey) -> Option<Shortcut> {
+        let name = event.keyval().name()?;
+        Self::ALL
+            .into_iter()
+            .find(|shortcut| shortcut.media_keys().contains(&name.as_str()))
+    }
+
+    /// Which shortcut, if any, the key press `event` is for.
+    pub(crate) fn find(config: &Config, event: &gtk::gdk::EventKey) -> Option<Shortcut> {
+        let keyval = *event.keyval().to_lower();
+        let modifiers = event.state() & gtk::accelerator_get_default_mod_mask();
+        Self::ALL
+            .into_iter()
+            .find(|shortcut| shortcut.binding(config) == Some((keyval, modifiers)))
+    }
+}
@@ -0,0 +1,296 @@
+// Copyright © 2021-2022 Jakob L. Kreuze <zerodaysfordays@sdf.org>
+//
+// This file is part of Tunes.
+//
+// Tunes is free software; you can redistribute it and/or modify it
+// under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation; either version 3 of the
+// License, or (at your option) any later version.
+//
+// Tunes is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY
+// or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Affero General
+// Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public
+// License along with Tunes. If not, see <http://www.gnu.org/licenses/>.
+
+use gtk::glib;
+use gtk::prelude::*;
+use gtk::subclass::prelude::ObjectSubclassExt;
+use std::time::Duration;
+use tunes_core::{album_artist, tag_number};
+
+// Unfortunately, to use the `ListStore` interface, we'll need to represent our
+// data as an actual `glib` object. This is a little hairy in Rust, involving a
+// fair bit of boilerplate, but not too terrible.
+glib::wrapper! {
+    pub struct SongObject(ObjectSubclass<imp::SongObject>);
+}
+
+impl SongObject {
+    pub fn new(song: &mpd::song::Song) -> Self {
+        glib::Object::new(&[
+            ("filename", &song.file.clone()),
+            (
+                "title",
+                &song
+                    .title
+                    .as_ref()
+                    .cloned()
+                    .unwrap_or_else(|| "[Untitled]".into()),
+            ),
+            (
+                "artist",
+                &song
+                    .artist
+                    .as_ref()
+                    .cloned()
+                    .unwrap_or_else(|| "[No Artist]".into()),
+            ),
+            (
+                "album",
+                &song
+                    .tags
+                    .get("Album")
+                    .cloned()
+                    .unwrap_or_else(|| "[Untitled]".into()),
+            ),
+            (
+                "composer",
+                &song.tags.get("Composer").cloned().unwrap_or_default(),
+            ),
+            (
+                "performer",
+                &song.tags.get("Performer").cloned().unwrap_or_default(),
+            ),
+            ("work", &song.tags.get("Work").cloned().unwrap_or_default()),
+            ("genre", &song.tags.get("Genre").cloned().unwrap_or_default()),
+            ("disc", &song.tags.get("Disc").cloned().unwrap_or_default()),
+            (
+                "track",
+                &Some(tag_number(song, "Track"))
+                    .filter(|&x| x > 0)
+                    .map(|x| x.to_string())
+                    .unwrap_or_default(),
+            ),
+            ("date", &song.tags.get("Date").cloned().unwrap_or_default()),
+            (
+                "duration",
+                &song.duration.map(format_duration).unwrap_or_default(),
+            ),
+            (
+                "album-artist",
+                &album_artist(song).unwrap_or_default().to_string(),
+            ),
+        ])
+        .expect("Failed to create `SongObject`.")
+    }
+
+    pub fn set_index(&self, idx: u32) {
+        let private = imp::SongObject::from_instance(self);
+        private.index.set(idx);
+    }
+
+    /// The song's ID in the queue, which unlike its index stays put as songs
+    /// around it come and go.
+    pub fn queue_id(&self) -> Option<u32> {
+        imp::SongObject::from_instance(self).queue_id.get()
+    }
+
+    pub fn set_queue_id(&self, id: Option<u32>) {
+        imp::SongObject::from_instance(self).queue_id.set(id);
+    }
+}
+
+/// Format a duration like "3:07", or "1:02:03" if it's over an hour.
+pub(crate) fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    if seconds >= 60 * 60 {
+        format!("{}:{:02}:{:02}", seconds / 3600, seconds / 60 % 60, seconds % 60)
+    } else {
+        format!("{}:{:02}", seconds / 60, seconds % 60)
+    }
+}
+
+/// A long stretch of time in hours and minutes, like "1h 40m".
+pub(crate) fn format_total_duration(duration: Duration) -> String {
+    let minutes = duration.as_secs() / 60;
+    if minutes >= 60 {
+        format!("{}h {}m", minutes / 60, minutes % 60)
+    } else {
+        format!("{}m", minutes)
+    }
+}
+
+// These class "implementations" are typically done in a separate
+// file/directory. I wanted to keep the example self-contained.
+mod imp {
+    use std::cell::{Cell, RefCell};
+
+    use glib::{ParamSpec, ParamSpecString, Value};
+    use gtk::glib;
+    use gtk::prelude::*;
+    use gtk::subclass::prelude::*;
+    use once_cell::sync::Lazy;
+
+    // Object holding the state
+    #[derive(Default)]
+    pub struct SongObject {
+        filename: RefCell<String>,
+        title: RefCell<String>,
+        artist: RefCell<String>,
+        album: RefCell<String>,
+        composer: RefCell<String>,
+        performer: RefCell<String>,
+        work: RefCell<String>,
+        genre: RefCell<String>,
+        disc: RefCell<String>,
+        album_artist: RefCell<String>,
+        track: RefCell<String>,
+        date: RefCell<String>,
+        duration: RefCell<String>,
+        pub(crate) index: Cell<u32>,
+        pub(crate) queue_id: Cell<Option<u32>>,
+    }
+
+    // The central trait for subclassing a GObject
+    #[glib::object_subclass]
+    impl ObjectSubclass for SongObject {
+        const NAME: &'static str = "TunesSongObject";
+        type Type = super::SongObject;
+    }
+
+    // Trait shared by all GObjects
+    impl ObjectImpl for SongObject {
+        fn properties() -> &'static [ParamSpec] {
+            static PROPERTIES: Lazy<Vec<ParamSpec>> = Lazy::new(|| {
+                vec![
+                    ParamSpecString::builder("filename").build(),
+                    ParamSpecString::builder("title").build(),
+                    ParamSpecString::builder("artist").build(),
+                    ParamSpecString::builder("album").build(),
+                    ParamSpecString::builder("composer").build(),
+                    ParamSpecString::builder("performer").build(),
+                    ParamSpecString::builder("work").build(),
+                    ParamSpecString::builder("genre").build(),
+                    ParamSpecString::builder("disc").build(),
+                    ParamSpecString::builder("album-artist").build(),
+                    ParamSpecString::builder("track").build(),
+                    ParamSpecString::builder("date").build(),
+                    ParamSpecString::builder("duration").build(),
+                    ParamSpecString::builder("index").build(),
+                ]
+            });
+            PROPERTIES.as_ref()
+        }
+
+        fn set_property(&self, _obj: &Self::Type, _id: usize, value: &Value, pspec: &ParamSpec) {
+            match pspec.name() {
+                "filename" => {
+                    let input = value
+                        .get()
+                        .expect("The value needs to be of type `String`.");
+                    self.filename.replace(input);
+                }
+                "title" => {
+                    let input = value
+                        .get()
+                        .expect("The value needs to be of type `String`.");
+                    self.title.replace(input);
+                }
+                "artist" => {
+                    let input = value
+                        .get()
+                        .expect("The value needs to be of type `String`.");
+                    self.artist.replace(input);
+                }
+                "album" => {
+                    let input = value
+                        .get()
+                        .expect("The value needs to be of type `String`.");
+                    self.album.replace(input);
+                }
+                "composer" => {
+                    let input = value
+                        .get()
+                        .expect("The value needs to be of type `String`.");
+                    self.composer.replace(input);
+                }
+                "performer" => {
+                    let input = value
+                        .get()
+                        .expect("The value needs to be of type `String`.");
+                    self.performer.replace(input);
+                }
+                "work" => {
+                    let input = value
+                        .get()
+                        .expect("The value needs to be of type `String`.");
+                    self.work.replace(input);
+                }
+                "genre" => {
+                    let input = value
+                        .get()
+                        .expect("The value needs to be of type `String`.");
+                    self.genre.replace(input);
+                }
+                "disc" => {
+                    let input = value
+                        .get()
+                        .expect("The value needs to be of type `String`.");
+                    self.disc.replace(input);
+                }
+                "album-artist" => {
+                    let input = value
+                        .get()
+                        .expect("The value needs to be of type `String`.");
+                    self.album_artist.replace(input);
+                }
+                "track" => {
+                    let input = value
+                        .get()
+                        .expect("The value needs to be of type `String`.");
+                    self.track.replace(input);
+                }
+                "date" => {
+                    let input = value
+                        .get()
+                        .expect("The value needs to be of type `String`.");
+                    self.date.replace(input);
+                }
+                "duration" => {
+                    let input = value
+                        .get()
+                        .expect("The value needs to be of type `String`.");
+                    self.duration.replace(input);
+                }
+                "index" => {
+                    let input = value.get().expect("The value needs to be of type `u32`.");
+                    self.index.replace(input);
+                }
+                _ => unimplemented!(),
+            }
+        }
+
+        fn property(&self, _obj: &Self::Type, _id: usize, pspec: &ParamSpec) -> Value {
+            match pspec.name() {
+                "filename" => self.filename.borrow().to_value(),
+                "title" => self.title.borrow().to_value(),
+                "artist" => self.artist.borrow().to_value(),
+                "album" => self.album.borrow().to_value(),
+                "composer" => self.composer.borrow().to_value(),
+                "performer" => self.performer.borrow().to_value(),
+                "work" => self.work.borrow().to_value(),
+                "genre" => self.genre.borrow().to_value(),
+                "disc" => self.disc.borrow().to_value(),
+                "album-artist" => self.album_artist.borrow().to_value(),
+                "track" => self.track.borrow().to_value(),
+                "date" => self.date.borrow().to_value(),
+                "duration" => self.duration.borrow().to_value(),
+                "index" => self.index.get().to_value(),
+                _ => unimplemented!(),
+            }
+        }
+    }
+}
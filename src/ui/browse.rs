@@ -0,0 +1,1354 @@
+// Copyright © 2021-2022 Jakob L. Kreuze <zerodaysfordays@sdf.org>
+//
+// This file is part of Tunes.
+//
+// Tunes is free software; you can redistribute it and/or modify it
+// under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation; either version 3 of the
+// License, or (at your option) any later version.
+//
+// Tunes is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY
+// or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Affero General
+// Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public
+// License along with Tunes. If not, see <http://www.gnu.org/licenses/>.
+
+use futures::channel::mpsc;
+use glib::clone;
+use gtk::prelude::*;
+use gtk::{glib, pango};
+use std::cell::{Cell, RefCell};
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::rc::Rc;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use tunes_core::{year_of, DecodedArt, SmartPlaylist, StateUpdateKind};
+
+use crate::backend::{ThumbnailCache, ThumbnailRequest, COLLAGE_SIZE, THUMBNAIL_SIZE};
+use crate::models::{art_pixbuf, SongObject};
+use crate::ui::{song_row, GenreColors, ListColumns, SongList};
+
+/// View for exploring the library by something other than a free-text search.
+/// Each way of browsing gets its own page in an inner stack.
+pub(crate) struct BrowseInfo {
+    pub(crate) container: gtk::Box,
+    pub(crate) stack: gtk::Stack,
+    tag_pages: Vec<TagBrowser>,
+    pub(crate) folders: FolderBrowser,
+    pub(crate) recent: RecentBrowser,
+    pub(crate) dates: DateBrowser,
+    pub(crate) smart_playlists: SmartPlaylistBrowser,
+    pub(crate) playlists: PlaylistBrowser,
+    pub(crate) albums: AlbumBrowser,
+    pub(crate) media: MediaBrowser,
+}
+
+impl BrowseInfo {
+    pub(crate) fn new(
+        sender: mpsc::Sender<StateUpdateKind>,
+        thumbnail_requests: (std::sync::mpsc::Sender<ThumbnailRequest>, Arc<AtomicU64>),
+        genre_colors: GenreColors,
+        list_columns: ListColumns,
+    ) -> Self {
+        let container = gtk::Box::new(gtk::Orientation::Vertical, 2);
+
+        let stack = gtk::Stack::new();
+        stack.set_vexpand(true);
+        stack.set_transition_type(gtk::StackTransitionType::Crossfade);
+
+        // There'll be more browse pages than fit across a phone screen, so
+        // the switcher gets to scroll sideways.
+        let switcher = gtk::StackSwitcher::builder()
+            .stack(&stack)
+            .halign(gtk::Align::Center)
+            .build();
+        let switcher_window =
+            gtk::ScrolledWindow::new(gtk::Adjustment::NONE, gtk::Adjustment::NONE);
+        switcher_window.set_policy(gtk::PolicyType::Automatic, gtk::PolicyType::Never);
+        switcher_window.add(&switcher);
+
+        container.add(&switcher_window);
+        container.add(&stack);
+
+        let folders = FolderBrowser::new(sender.clone(), genre_colors.clone(), list_columns.clone());
+        let recent = RecentBrowser::new(sender.clone(), genre_colors.clone(), list_columns.clone());
+        let dates = DateBrowser::new(sender.clone(), genre_colors.clone(), list_columns.clone());
+        let smart_playlists = SmartPlaylistBrowser::new(sender.clone());
+        let (requests, generation) = thumbnail_requests;
+        let playlists = PlaylistBrowser::new(sender.clone(), requests.clone());
+        let albums = AlbumBrowser::new(sender.clone(), requests, generation);
+        let media = MediaBrowser::new(sender.clone(), &stack);
+        let mut browse_info = BrowseInfo {
+            container,
+            stack,
+            tag_pages: Vec::new(),
+            folders,
+            recent,
+            dates,
+            smart_playlists,
+            playlists,
+            albums,
+            media,
+        };
+        browse_info
+            .stack
+            .add_titled(browse_info.recent.as_ref(), "recent", "Recently Added");
+        browse_info.add_tag_page(TagBrowser::new(sender.clone(), genre_colors.clone(), list_columns.clone(), "Genre"), "genres", "Genres");
+        // Classical listeners care far more about these than artist or album.
+        browse_info.add_tag_page(TagBrowser::new(sender.clone(), genre_colors.clone(), list_columns.clone(), "Composer"), "composers", "Composers");
+        browse_info.add_tag_page(TagBrowser::new(sender.clone(), genre_colors.clone(), list_columns.clone(), "Work"), "works", "Works");
+        browse_info.add_tag_page(TagBrowser::new(sender, genre_colors, list_columns, "Performer"), "performers", "Performers");
+        browse_info
+            .stack
+            .add_titled(browse_info.dates.as_ref(), "years", "Years");
+        browse_info.stack.add_titled(
+            browse_info.smart_playlists.as_ref(),
+            "smart-playlists",
+            "Smart Playlists",
+        );
+        browse_info
+            .stack
+            .add_titled(browse_info.playlists.as_ref(), "playlists", "Playlists");
+        browse_info
+            .stack
+            .add_titled(browse_info.albums.as_ref(), "albums", "Albums");
+        browse_info
+            .stack
+            .add_titled(browse_info.folders.as_ref(), "folders", "Folders");
+        browse_info
+            .stack
+            .add_titled(browse_info.media.as_ref(), "media", "External Media");
+        browse_info
+    }
+
+    pub(crate) fn add_tag_page(&mut self, page: TagBrowser, name: &str, title: &str) {
+        self.stack.add_titled(page.as_ref(), name, title);
+        self.tag_pages.push(page);
+    }
+
+    /// Find the page browsing by `tag`, if there is one.
+    pub(crate) fn tag_page(&self, tag: &str) -> Option<&TagBrowser> {
+        self.tag_pages.iter().find(|page| page.tag == tag)
+    }
+}
+
+impl AsRef<gtk::Widget> for BrowseInfo {
+    fn as_ref(&self) -> &gtk::Widget {
+        self.container.upcast_ref()
+    }
+}
+
+/// Browse page listing every value of one tag along with how many songs carry
+/// it. Picking a value lists those songs.
+pub(crate) struct TagBrowser {
+    pub(crate) tag: &'static str,
+    pub(crate) container: gtk::Stack,
+    pub(crate) values: gtk::ListBox,
+    value_names: Rc<RefCell<Vec<String>>>,
+    songs_title: gtk::Label,
+    songs_value: Rc<RefCell<String>>,
+    pub(crate) songs: SongList,
+    pub(crate) sender: mpsc::Sender<StateUpdateKind>,
+    genre_colors: GenreColors,
+}
+
+impl TagBrowser {
+    pub(crate) fn new(
+        sender: mpsc::Sender<StateUpdateKind>,
+        genre_colors: GenreColors,
+        list_columns: ListColumns,
+        tag: &'static str,
+    ) -> Self {
+        let container = gtk::Stack::new();
+        container.set_transition_type(gtk::StackTransitionType::SlideLeftRight);
+
+        let value_names = Rc::new(RefCell::new(Vec::<String>::new()));
+        let values = gtk::ListBox::new();
+        values.set_selection_mode(gtk::SelectionMode::None);
+        values.connect_row_activated(
+            clone!(@strong sender, @strong value_names => move |_, row| {
+                let value = usize::try_from(row.index())
+                    .ok()
+                    .and_then(|index| value_names.borrow().get(index).cloned());
+                if let Some(value) = value {
+                    let mut sender = sender.clone();
+                    sender
+                        .try_send(StateUpdateKind::BrowseTagSongsRequest(tag.into(), value))
+                        .expect("Couldn't notify thread");
+                }
+            }),
+        );
+        let values_window =
+            gtk::ScrolledWindow::new(gtk::Adjustment::NONE, gtk::Adjustment::NONE);
+        values_window.add(&values);
+        values_window.set_vexpand(true);
+        container.add_named(&values_window, "values");
+
+        let songs_value = Rc::new(RefCell::new(String::new()));
+        let songs_header = gtk::Box::new(gtk::Orientation::Horizontal, 4);
+        let back = gtk::Button::from_icon_name(
+            Some("go-previous-symbolic"),
+            gtk::IconSize::SmallToolbar,
+        );
+        back.connect_clicked(clone!(@weak container => move |_| {
+            container.set_visible_child_name("values");
+        }));
+        songs_header.add(&back);
+
+        let songs_title = gtk::Label::new(None);
+        songs_title.set_hexpand(true);
+        songs_title.set_ellipsize(pango::EllipsizeMode::End);
+        songs_header.add(&songs_title);
+
+        let add_all =
+            gtk::Button::from_icon_name(Some("list-add-symbolic"), gtk::IconSize::SmallToolbar);
+        add_all.set_tooltip_text(Some("Add All to Queue"));
+        add_all.connect_clicked(clone!(@strong sender, @strong songs_value => move |_| {
+            let mut sender = sender.clone();
+            sender
+                .try_send(StateUpdateKind::QueueAddTagRequest(
+                    tag.into(),
+                    songs_value.borrow().clone(),
+                ))
+                .expect("Couldn't notify thread");
+            sender
+                .try_send(StateUpdateKind::MpdEvent)
+                .expect("Couldn't notify thread");
+        }));
+        songs_header.add(&add_all);
+
+        let songs = SongList::new(sender.clone(), genre_colors.clone(), list_columns);
+        let songs_page = gtk::Box::new(gtk::Orientation::Vertical, 2);
+        songs_page.add(&songs_header);
+        songs_page.add(songs.as_ref());
+        container.add_named(&songs_page, "songs");
+
+        // The counts are cheap enough to fetch that we just refresh them every
+        // time the page is shown.
+        container.connect_map(clone!(@strong sender => move |_| {
+            let mut sender = sender.clone();
+            sender
+                .try_send(StateUpdateKind::BrowseTagRequest(tag.into()))
+                .expect("Couldn't notify thread");
+        }));
+        container.show_all();
+        container.set_visible_child_name("values");
+
+        TagBrowser {
+            tag,
+            container,
+            values,
+            value_names,
+            songs_title,
+            songs_value,
+            songs,
+            sender,
+            genre_colors,
+        }
+    }
+
+    /// What to show for a tag value, since songs without the tag are grouped
+    /// under an empty one.
+    pub(crate) fn display_value(&self, value: &str) -> String {
+        if value.is_empty() {
+            format!("[No {}]", self.tag)
+        } else {
+            value.into()
+        }
+    }
+
+    pub(crate) fn set_values(&self, counts: &[mpd::Count]) {
+        for child in self.values.children() {
+            self.values.remove(&child);
+        }
+
+        let mut value_names = self.value_names.borrow_mut();
+        value_names.clear();
+        for count in counts {
+            let row = gtk::Box::new(gtk::Orientation::Horizontal, 8);
+
+            let value_label = gtk::Label::new(Some(&self.display_value(&count.value)));
+            value_label.set_hexpand(true);
+            value_label.set_xalign(0.0);
+            value_label.set_ellipsize(pango::EllipsizeMode::End);
+            row.add(&value_label);
+
+            let count_label = gtk::Label::new(Some(&format!("{} songs", count.songs)));
+            count_label.style_context().add_class("dim-label");
+            row.add(&count_label);
+
+            // The genre page is where genre colors get picked.
+            if self.tag == "Genre" && !count.value.is_empty() {
+                let color = gtk::ColorButton::new();
+                if let Ok(rgba) = gtk::gdk::RGBA::parse(&self.genre_colors.color(&count.value)) {
+                    color.set_rgba(&rgba);
+                }
+                color.set_tooltip_text(Some("Genre Color"));
+                let genre = count.value.clone();
+                let genre_colors = self.genre_colors.clone();
+                let sender = self.sender.clone();
+                color.connect_color_set(move |color| {
+                    genre_colors.set_color(&genre, &color.rgba());
+                    let mut sender = sender.clone();
+                    sender
+                        .try_send(StateUpdateKind::DisplayProfileChange)
+                        .expect("Couldn't notify thread");
+                });
+                row.add(&color);
+            }
+
+            let add_all =
+                gtk::Button::from_icon_name(Some("list-add-symbolic"), gtk::IconSize::SmallToolbar);
+            add_all.set_tooltip_text(Some("Add All to Queue"));
+            let tag = self.tag;
+            let value = count.value.clone();
+            let sender = self.sender.clone();
+            add_all.connect_clicked(move |_| {
+                let mut sender = sender.clone();
+                sender
+                    .try_send(StateUpdateKind::QueueAddTagRequest(tag.into(), value.clone()))
+                    .expect("Couldn't notify thread");
+                sender
+                    .try_send(StateUpdateKind::MpdEvent)
+                    .expect("Couldn't notify thread");
+            });
+            row.add(&add_all);
+
+            row.show_all();
+            self.values.add(&row);
+            value_names.push(count.value.clone());
+        }
+    }
+
+    pub(crate) fn set_songs(&self, value: &str, songs: &[mpd::Song]) {
+        self.songs_title.set_text(&self.display_value(value));
+        self.songs_value.replace(value.into());
+        self.songs.set_songs(songs);
+        self.container.set_visible_child_name("songs");
+    }
+}
+
+impl AsRef<gtk::Widget> for TagBrowser {
+    fn as_ref(&self) -> &gtk::Widget {
+        self.container.upcast_ref()
+    }
+}
+
+/// Browse page for the `Date` tag, going from decades to years to the songs
+/// from that year.
+pub(crate) struct DateBrowser {
+    pub(crate) container: gtk::Stack,
+    decades: gtk::ListBox,
+    decade_starts: Rc<RefCell<Vec<u32>>>,
+    years: gtk::ListBox,
+    year_numbers: Rc<RefCell<Vec<u32>>>,
+    years_title: gtk::Label,
+    /// How many songs there are from each year.
+    song_counts: Rc<RefCell<BTreeMap<u32, u32>>>,
+    songs_title: gtk::Label,
+    songs_year: Rc<Cell<u32>>,
+    pub(crate) songs: SongList,
+    pub(crate) sender: mpsc::Sender<StateUpdateKind>,
+}
+
+impl DateBrowser {
+    pub(crate) fn new(sender: mpsc::Sender<StateUpdateKind>, genre_colors: GenreColors, list_columns: ListColumns) -> Self {
+        let container = gtk::Stack::new();
+        container.set_transition_type(gtk::StackTransitionType::SlideLeftRight);
+
+        let decade_starts = Rc::new(RefCell::new(Vec::<u32>::new()));
+        let decades = gtk::ListBox::new();
+        decades.set_selection_mode(gtk::SelectionMode::None);
+        let decades_window =
+            gtk::ScrolledWindow::new(gtk::Adjustment::NONE, gtk::Adjustment::NONE);
+        decades_window.add(&decades);
+        decades_window.set_vexpand(true);
+        container.add_named(&decades_window, "decades");
+
+        let (years_header, years_title) = Self::header(&container, "decades");
+        let year_numbers = Rc::new(RefCell::new(Vec::<u32>::new()));
+        let years = gtk::ListBox::new();
+        years.set_selection_mode(gtk::SelectionMode::None);
+        years.connect_row_activated(clone!(@strong sender, @strong year_numbers => move |_, row| {
+            let year = usize::try_from(row.index())
+                .ok()
+                .and_then(|index| year_numbers.borrow().get(index).copied());
+            if let Some(year) = year {
+                let mut sender = sender.clone();
+                sender
+                    .try_send(StateUpdateKind::BrowseYearSongsRequest(year))
+                    .expect("Couldn't notify thread");
+            }
+        }));
+        let years_window =
+            gtk::ScrolledWindow::new(gtk::Adjustment::NONE, gtk::Adjustment::NONE);
+        years_window.add(&years);
+        years_window.set_vexpand(true);
+        let years_page = gtk::Box::new(gtk::Orientation::Vertical, 2);
+        years_page.add(&years_header);
+        years_page.add(&years_window);
+        container.add_named(&years_page, "years");
+
+        // Picking a decade doesn't need to go back to MPD; we already know
+        // which years it has from the counts.
+        let song_counts = Rc::new(RefCell::new(BTreeMap::<u32, u32>::new()));
+        decades.connect_row_activated(clone!(
+            @strong sender,
+            @strong decade_starts,
+            @strong song_counts,
+            @strong year_numbers,
+            @weak container,
+            @weak years,
+            @weak years_title => move |_, row| {
+                let decade = usize::try_from(row.index())
+                    .ok()
+                    .and_then(|index| decade_starts.borrow().get(index).copied());
+                if let Some(decade) = decade {
+                    years_title.set_text(&format!("{}s", decade));
+                    for child in years.children() {
+                        years.remove(&child);
+                    }
+                    let mut year_numbers = year_numbers.borrow_mut();
+                    year_numbers.clear();
+                    for (&year, &count) in song_counts.borrow().range(decade..decade + 10) {
+                        years.add(&DateBrowser::period_row(&sender, &year.to_string(), count, year, year));
+                        year_numbers.push(year);
+                    }
+                    container.set_visible_child_name("years");
+                }
+            }
+        ));
+
+        let (songs_header, songs_title) = Self::header(&container, "years");
+        let songs_year = Rc::new(Cell::new(0));
+        let add_all =
+            gtk::Button::from_icon_name(Some("list-add-symbolic"), gtk::IconSize::SmallToolbar);
+        add_all.set_tooltip_text(Some("Add All to Queue"));
+        add_all.connect_clicked(clone!(@strong sender, @strong songs_year => move |_| {
+            let mut sender = sender.clone();
+            sender
+                .try_send(StateUpdateKind::QueueAddYearsRequest(songs_year.get(), songs_year.get()))
+                .expect("Couldn't notify thread");
+            sender
+                .try_send(StateUpdateKind::MpdEvent)
+                .expect("Couldn't notify thread");
+        }));
+        songs_header.add(&add_all);
+        let songs = SongList::new(sender.clone(), genre_colors, list_columns);
+        let songs_page = gtk::Box::new(gtk::Orientation::Vertical, 2);
+        songs_page.add(&songs_header);
+        songs_page.add(songs.as_ref());
+        container.add_named(&songs_page, "songs");
+
+        container.connect_map(clone!(@strong sender => move |_| {
+            let mut sender = sender.clone();
+            sender
+                .try_send(StateUpdateKind::BrowseDatesRequest)
+                .expect("Couldn't notify thread");
+        }));
+        container.show_all();
+        container.set_visible_child_name("decades");
+
+        DateBrowser {
+            container,
+            decades,
+            decade_starts,
+            years,
+            year_numbers,
+            years_title,
+            song_counts,
+            songs_title,
+            songs_year,
+            songs,
+            sender,
+        }
+    }
+
+    /// Header for a page below the top one, with a button going back to
+    /// `parent` and a title.
+    pub(crate) fn header(container: &gtk::Stack, parent: &'static str) -> (gtk::Box, gtk::Label) {
+        let header = gtk::Box::new(gtk::Orientation::Horizontal, 4);
+        let back = gtk::Button::from_icon_name(
+            Some("go-previous-symbolic"),
+            gtk::IconSize::SmallToolbar,
+        );
+        back.connect_clicked(clone!(@weak container => move |_| {
+            container.set_visible_child_name(parent);
+        }));
+        header.add(&back);
+
+        let title = gtk::Label::new(None);
+        title.set_hexpand(true);
+        title.set_ellipsize(pango::EllipsizeMode::End);
+        header.add(&title);
+        (header, title)
+    }
+
+    /// A row for a decade or year, with a button to queue all of its songs.
+    pub(crate) fn period_row(
+        sender: &mpsc::Sender<StateUpdateKind>,
+        label: &str,
+        songs: u32,
+        first: u32,
+        last: u32,
+    ) -> gtk::Widget {
+        let row = gtk::Box::new(gtk::Orientation::Horizontal, 8);
+
+        let label = gtk::Label::new(Some(label));
+        label.set_hexpand(true);
+        label.set_xalign(0.0);
+        row.add(&label);
+
+        let count_label = gtk::Label::new(Some(&format!("{} songs", songs)));
+        count_label.style_context().add_class("dim-label");
+        row.add(&count_label);
+
+        let add_all =
+            gtk::Button::from_icon_name(Some("list-add-symbolic"), gtk::IconSize::SmallToolbar);
+        add_all.set_tooltip_text(Some("Add All to Queue"));
+        let sender = sender.clone();
+        add_all.connect_clicked(move |_| {
+            let mut sender = sender.clone();
+            sender
+                .try_send(StateUpdateKind::QueueAddYearsRequest(first, last))
+                .expect("Couldn't notify thread");
+            sender
+                .try_send(StateUpdateKind::MpdEvent)
+                .expect("Couldn't notify thread");
+        });
+        row.add(&add_all);
+
+        row.show_all();
+        row.upcast::<gtk::Widget>()
+    }
+
+    /// Fill in the decades from song counts for each distinct `Date`. Dates
+    /// are usually just a year, but can be a full "1994-05-03".
+    pub(crate) fn set_dates(&self, counts: &[mpd::Count]) {
+        let mut song_counts = self.song_counts.borrow_mut();
+        song_counts.clear();
+        for count in counts {
+            if let Some(year) = year_of(&count.value) {
+                *song_counts.entry(year).or_default() += count.songs;
+            }
+        }
+
+        let mut decades: BTreeMap<u32, u32> = BTreeMap::new();
+        for (year, count) in song_counts.iter() {
+            *decades.entry(year - year % 10).or_default() += count;
+        }
+
+        for child in self.decades.children() {
+            self.decades.remove(&child);
+        }
+        let mut decade_starts = self.decade_starts.borrow_mut();
+        decade_starts.clear();
+        for (decade, count) in decades {
+            self.decades.add(&Self::period_row(
+                &self.sender,
+                &format!("{}s", decade),
+                count,
+                decade,
+                decade + 9,
+            ));
+            decade_starts.push(decade);
+        }
+    }
+
+    pub(crate) fn set_songs(&self, year: u32, songs: &[mpd::Song]) {
+        self.songs_title.set_text(&year.to_string());
+        self.songs_year.set(year);
+        self.songs.set_songs(songs);
+        self.container.set_visible_child_name("songs");
+    }
+}
+
+impl AsRef<gtk::Widget> for DateBrowser {
+    fn as_ref(&self) -> &gtk::Widget {
+        self.container.upcast_ref()
+    }
+}
+
+/// Browse page listing songs added to the library recently, newest first, so
+/// freshly ripped or downloaded albums are easy to find.
+pub(crate) struct RecentBrowser {
+    pub(crate) container: gtk::Box,
+    pub(crate) songs: SongList,
+}
+
+impl RecentBrowser {
+    /// How far back the page can look, as pairs of days and label.
+    pub(crate) const PERIODS: [(u64, &'static str); 4] = [
+        (7, "Past Week"),
+        (30, "Past Month"),
+        (90, "Past Three Months"),
+        (365, "Past Year"),
+    ];
+
+    pub(crate) fn new(sender: mpsc::Sender<StateUpdateKind>, genre_colors: GenreColors, list_columns: ListColumns) -> Self {
+        let container = gtk::Box::new(gtk::Orientation::Vertical, 2);
+
+        let period = gtk::ComboBoxText::new();
+        period.set_halign(gtk::Align::End);
+        for (days, label) in Self::PERIODS {
+            period.append(Some(&days.to_string()), label);
+        }
+        period.set_active(Some(1));
+        period.connect_changed(clone!(@strong sender => move |period| {
+            RecentBrowser::request(&sender, period);
+        }));
+
+        let songs = SongList::new(sender.clone(), genre_colors, list_columns);
+        container.add(&period);
+        container.add(songs.as_ref());
+
+        container.connect_map(clone!(@strong sender, @weak period => move |_| {
+            RecentBrowser::request(&sender, &period);
+        }));
+        container.show_all();
+
+        RecentBrowser { container, songs }
+    }
+
+    pub(crate) fn request(sender: &mpsc::Sender<StateUpdateKind>, period: &gtk::ComboBoxText) {
+        if let Some(days) = period.active_id().and_then(|x| x.parse::<u64>().ok()) {
+            let mut sender = sender.clone();
+            sender
+                .try_send(StateUpdateKind::BrowseRecentRequest(days))
+                .expect("Couldn't notify thread");
+        }
+    }
+}
+
+impl AsRef<gtk::Widget> for RecentBrowser {
+    fn as_ref(&self) -> &gtk::Widget {
+        self.container.upcast_ref()
+    }
+}
+
+/// Browse page that walks the music directory as it's laid out on disk, for
+/// libraries that are organized by folder rather than by tags.
+pub(crate) struct FolderBrowser {
+    pub(crate) container: gtk::Box,
+    pub(crate) path: Rc<RefCell<String>>,
+    path_label: gtk::Label,
+    up: gtk::Button,
+    pub(crate) entries: gtk::ListBox,
+    pub(crate) sender: mpsc::Sender<StateUpdateKind>,
+    genre_colors: GenreColors,
+    pub(crate) list_columns: ListColumns,
+}
+
+impl FolderBrowser {
+    pub(crate) fn new(sender: mpsc::Sender<StateUpdateKind>, genre_colors: GenreColors, list_columns: ListColumns) -> Self {
+        let container = gtk::Box::new(gtk::Orientation::Vertical, 2);
+        let path = Rc::new(RefCell::new(String::new()));
+
+        let header = gtk::Box::new(gtk::Orientation::Horizontal, 4);
+        let up = gtk::Button::from_icon_name(Some("go-up-symbolic"), gtk::IconSize::SmallToolbar);
+        up.set_tooltip_text(Some("Parent Folder"));
+        up.connect_clicked(clone!(@strong sender, @strong path => move |_| {
+            let parent = match path.borrow().rsplit_once('/') {
+                Some((parent, _)) => parent.to_string(),
+                None => String::new(),
+            };
+            let mut sender = sender.clone();
+            sender
+                .try_send(StateUpdateKind::BrowseDirectoryRequest(parent))
+                .expect("Couldn't notify thread");
+        }));
+        header.add(&up);
+
+        let path_label = gtk::Label::new(None);
+        path_label.set_hexpand(true);
+        path_label.set_ellipsize(pango::EllipsizeMode::Start);
+        header.add(&path_label);
+
+        let add_all =
+            gtk::Button::from_icon_name(Some("list-add-symbolic"), gtk::IconSize::SmallToolbar);
+        add_all.set_tooltip_text(Some("Add Folder to Queue"));
+        add_all.connect_clicked(clone!(@strong sender, @strong path => move |_| {
+            let mut sender = sender.clone();
+            sender
+                .try_send(StateUpdateKind::QueueAddDirectoryRequest(path.borrow().clone()))
+                .expect("Couldn't notify thread");
+            sender
+                .try_send(StateUpdateKind::MpdEvent)
+                .expect("Couldn't notify thread");
+        }));
+        header.add(&add_all);
+
+        let entries = gtk::ListBox::new();
+        entries.set_selection_mode(gtk::SelectionMode::None);
+        let scrolled_window =
+            gtk::ScrolledWindow::new(gtk::Adjustment::NONE, gtk::Adjustment::NONE);
+        scrolled_window.add(&entries);
+        scrolled_window.set_vexpand(true);
+
+        container.add(&header);
+        container.add(&scrolled_window);
+
+        // Like the tag pages, we just re-list whatever folder we're in each time
+        // the page is shown.
+        container.connect_map(clone!(@strong sender, @strong path => move |_| {
+            let mut sender = sender.clone();
+            sender
+                .try_send(StateUpdateKind::BrowseDirectoryRequest(path.borrow().clone()))
+                .expect("Couldn't notify thread");
+        }));
+        container.show_all();
+
+        FolderBrowser {
+            container,
+            path,
+            path_label,
+            up,
+            entries,
+            sender,
+            genre_colors,
+            list_columns,
+        }
+    }
+
+    pub(crate) fn set_entries(&self, path: &str, entries: &[mpd::song::Entry]) {
+        self.path.replace(path.into());
+        self.path_label
+            .set_text(if path.is_empty() { "Music" } else { path });
+        self.up.set_sensitive(!path.is_empty());
+
+        for child in self.entries.children() {
+            self.entries.remove(&child);
+        }
+
+        for entry in entries {
+            let row = match entry {
+                mpd::song::Entry::Directory(directory) => self.directory_row(directory),
+                mpd::song::Entry::Song(song) => {
+                    let item = SongObject::new(song);
+                    let add_individual_song = gtk::Button::from_icon_name(
+                        Some("list-add-symbolic"),
+                        gtk::IconSize::SmallToolbar,
+                    );
+                    let filename = song.file.clone();
+                    let sender = self.sender.clone();
+                    add_individual_song.connect_clicked(move |_| {
+                        let mut sender = sender.clone();
+                        sender
+                            .try_send(StateUpdateKind::QueueAddRequest(filename.clone()))
+                            .expect("Couldn't notify thread");
+                        sender
+                            .try_send(StateUpdateKind::MpdEvent)
+                            .expect("Couldn't notify thread");
+                    });
+                    song_row(&item, Some(add_individual_song.upcast_ref()), &[], &self.genre_colors, &self.list_columns)
+                }
+                // Stored playlists get their own page, so there's no need to
+                // show the files here.
+                mpd::song::Entry::Playlist(_) => continue,
+            };
+            self.entries.add(&row);
+        }
+    }
+
+    pub(crate) fn directory_row(&self, directory: &str) -> gtk::Widget {
+        let row = gtk::ListBoxRow::new();
+        let row_box = gtk::Box::new(gtk::Orientation::Horizontal, 8);
+
+        let open = gtk::Button::from_icon_name(Some("folder-symbolic"), gtk::IconSize::SmallToolbar);
+        open.set_tooltip_text(Some("Open Folder"));
+        let path = directory.to_string();
+        let sender = self.sender.clone();
+        open.connect_clicked(move |_| {
+            let mut sender = sender.clone();
+            sender
+                .try_send(StateUpdateKind::BrowseDirectoryRequest(path.clone()))
+                .expect("Couldn't notify thread");
+        });
+        row_box.add(&open);
+
+        let name = directory.rsplit('/').next().unwrap_or(directory);
+        let name_label = gtk::Label::new(Some(name));
+        name_label.set_hexpand(true);
+        name_label.set_xalign(0.0);
+        name_label.set_ellipsize(pango::EllipsizeMode::End);
+        row_box.add(&name_label);
+
+        let add_all =
+            gtk::Button::from_icon_name(Some("list-add-symbolic"), gtk::IconSize::SmallToolbar);
+        add_all.set_tooltip_text(Some("Add Folder to Queue"));
+        let path = directory.to_string();
+        let sender = self.sender.clone();
+        add_all.connect_clicked(move |_| {
+            let mut sender = sender.clone();
+            sender
+                .try_send(StateUpdateKind::QueueAddDirectoryRequest(path.clone()))
+                .expect("Couldn't notify thread");
+            sender
+                .try_send(StateUpdateKind::MpdEvent)
+                .expect("Couldn't notify thread");
+        });
+        row_box.add(&add_all);
+
+        row.add(&row_box);
+        row.show_all();
+        row.upcast::<gtk::Widget>()
+    }
+}
+
+impl AsRef<gtk::Widget> for FolderBrowser {
+    fn as_ref(&self) -> &gtk::Widget {
+        self.container.upcast_ref()
+    }
+}
+
+/// Browse page listing saved smart playlists, each of which can be queued,
+/// edited, or deleted.
+pub(crate) struct SmartPlaylistBrowser {
+    pub(crate) container: gtk::Box,
+    pub(crate) playlists: gtk::ListBox,
+    pub(crate) sender: mpsc::Sender<StateUpdateKind>,
+}
+
+impl SmartPlaylistBrowser {
+    pub(crate) fn new(sender: mpsc::Sender<StateUpdateKind>) -> Self {
+        let container = gtk::Box::new(gtk::Orientation::Vertical, 2);
+
+        let new_playlist = gtk::Button::with_label("New Smart Playlist");
+        new_playlist.set_halign(gtk::Align::End);
+        new_playlist.connect_clicked(clone!(@strong sender => move |_| {
+            let mut sender = sender.clone();
+            sender
+                .try_send(StateUpdateKind::SmartPlaylistEditRequest(None))
+                .expect("Couldn't notify thread");
+        }));
+
+        let playlists = gtk::ListBox::new();
+        playlists.set_selection_mode(gtk::SelectionMode::None);
+        let scrolled_window =
+            gtk::ScrolledWindow::new(gtk::Adjustment::NONE, gtk::Adjustment::NONE);
+        scrolled_window.add(&playlists);
+        scrolled_window.set_vexpand(true);
+
+        container.add(&new_playlist);
+        container.add(&scrolled_window);
+
+        container.connect_map(clone!(@strong sender => move |_| {
+            let mut sender = sender.clone();
+            sender
+                .try_send(StateUpdateKind::BrowseSmartPlaylistsRequest)
+                .expect("Couldn't notify thread");
+        }));
+        container.show_all();
+
+        SmartPlaylistBrowser {
+            container,
+            playlists,
+            sender,
+        }
+    }
+
+    pub(crate) fn set_playlists(&self, playlists: &[SmartPlaylist]) {
+        for child in self.playlists.children() {
+            self.playlists.remove(&child);
+        }
+
+        for playlist in playlists {
+            let row = gtk::Box::new(gtk::Orientation::Horizontal, 8);
+
+            let text = gtk::Box::new(gtk::Orientation::Vertical, 0);
+            text.set_hexpand(true);
+            let name_label = gtk::Label::new(Some(&playlist.name));
+            name_label.set_xalign(0.0);
+            name_label.set_ellipsize(pango::EllipsizeMode::End);
+            text.add(&name_label);
+            let rules: Vec<String> = playlist.rules.iter().map(|x| x.to_string()).collect();
+            let rules_label = gtk::Label::new(Some(&rules.join(", ")));
+            rules_label.set_xalign(0.0);
+            rules_label.set_ellipsize(pango::EllipsizeMode::End);
+            rules_label.style_context().add_class("dim-label");
+            text.add(&rules_label);
+            row.add(&text);
+
+            let actions: [(&str, &str, fn(String) -> StateUpdateKind); 3] = [
+                ("document-edit-symbolic", "Edit", |name| {
+                    StateUpdateKind::SmartPlaylistEditRequest(Some(name))
+                }),
+                ("user-trash-symbolic", "Delete", StateUpdateKind::SmartPlaylistDeleteRequest),
+                ("list-add-symbolic", "Add to Queue", StateUpdateKind::QueueSmartPlaylistRequest),
+            ];
+            for (icon, tooltip, event) in actions {
+                let button = gtk::Button::from_icon_name(Some(icon), gtk::IconSize::SmallToolbar);
+                button.set_tooltip_text(Some(tooltip));
+                let name = playlist.name.clone();
+                let sender = self.sender.clone();
+                button.connect_clicked(move |_| {
+                    let mut sender = sender.clone();
+                    sender
+                        .try_send(event(name.clone()))
+                        .expect("Couldn't notify thread");
+                    sender
+                        .try_send(StateUpdateKind::MpdEvent)
+                        .expect("Couldn't notify thread");
+                });
+                row.add(&button);
+            }
+
+            row.show_all();
+            self.playlists.add(&row);
+        }
+    }
+}
+
+impl AsRef<gtk::Widget> for SmartPlaylistBrowser {
+    fn as_ref(&self) -> &gtk::Widget {
+        self.container.upcast_ref()
+    }
+}
+
+/// Browse page with a tile for each stored playlist, showing a collage of its
+/// albums' covers. Picking one queues it.
+pub(crate) struct PlaylistBrowser {
+    pub(crate) container: gtk::ScrolledWindow,
+    tiles: gtk::FlowBox,
+    /// Each tile's playlist and collage, in order.
+    pub(crate) playlists: Rc<RefCell<Vec<(String, gtk::Image)>>>,
+    pub(crate) sender: mpsc::Sender<StateUpdateKind>,
+    requests: std::sync::mpsc::Sender<ThumbnailRequest>,
+}
+
+impl PlaylistBrowser {
+    pub(crate) fn new(
+        sender: mpsc::Sender<StateUpdateKind>,
+        requests: std::sync::mpsc::Sender<ThumbnailRequest>,
+    ) -> Self {
+        let playlists = Rc::new(RefCell::new(Vec::<(String, gtk::Image)>::new()));
+        let tiles = gtk::FlowBox::builder()
+            .selection_mode(gtk::SelectionMode::None)
+            .homogeneous(true)
+            .valign(gtk::Align::Start)
+            .column_spacing(8)
+            .row_spacing(8)
+            .border_width(8)
+            .build();
+        tiles.connect_child_activated(clone!(@strong sender, @strong playlists => move |_, child| {
+            let name = usize::try_from(child.index())
+                .ok()
+                .and_then(|index| playlists.borrow().get(index).map(|(name, _)| name.clone()));
+            if let Some(name) = name {
+                let mut sender = sender.clone();
+                sender
+                    .try_send(StateUpdateKind::QueuePlaylistRequest(name))
+                    .expect("Couldn't notify thread");
+                sender
+                    .try_send(StateUpdateKind::MpdEvent)
+                    .expect("Couldn't notify thread");
+            }
+        }));
+
+        let container = gtk::ScrolledWindow::new(gtk::Adjustment::NONE, gtk::Adjustment::NONE);
+        container.add(&tiles);
+        container.set_vexpand(true);
+        container.connect_map(clone!(@strong sender => move |_| {
+            let mut sender = sender.clone();
+            sender
+                .try_send(StateUpdateKind::BrowsePlaylistsRequest)
+                .expect("Couldn't notify thread");
+        }));
+        container.show_all();
+
+        PlaylistBrowser {
+            container,
+            tiles,
+            playlists,
+            sender,
+            requests,
+        }
+    }
+
+    /// Show `playlists`, and have their collages made. Each has an icon
+    /// until its collage comes in, and keeps it if there's no collage.
+    pub(crate) fn set_playlists(&self, playlists: Vec<mpd::Playlist>) {
+        for child in self.tiles.children() {
+            self.tiles.remove(&child);
+        }
+
+        let mut tiles = Vec::new();
+        for playlist in playlists {
+            let name = playlist.name.clone();
+            let tile = gtk::Box::new(gtk::Orientation::Vertical, 4);
+            let image = gtk::Image::from_icon_name(Some("audio-x-generic-symbolic"), gtk::IconSize::Dialog);
+            image.set_size_request(COLLAGE_SIZE, COLLAGE_SIZE);
+            tile.add(&image);
+            let caption = gtk::Box::new(gtk::Orientation::Horizontal, 4);
+            let label = gtk::Label::new(Some(&name));
+            label.set_ellipsize(pango::EllipsizeMode::End);
+            label.set_max_width_chars(16);
+            label.set_hexpand(true);
+            caption.add(&label);
+            let delete = gtk::Button::from_icon_name(Some("user-trash-symbolic"), gtk::IconSize::SmallToolbar);
+            delete.set_relief(gtk::ReliefStyle::None);
+            delete.set_tooltip_text(Some("Delete"));
+            let deleted = name.clone();
+            let sender = self.sender.clone();
+            delete.connect_clicked(move |_| {
+                let mut sender = sender.clone();
+                sender
+                    .try_send(StateUpdateKind::PlaylistDeleteRequest(deleted.clone()))
+                    .expect("Couldn't notify thread");
+            });
+            caption.add(&delete);
+            tile.add(&caption);
+            tile.set_tooltip_text(Some(&format!("Add “{}” to the queue", name)));
+            tile.show_all();
+            self.tiles.add(&tile);
+            tiles.push((name, image));
+            let _ = self.requests.send(ThumbnailRequest::Collage(playlist));
+        }
+        self.playlists.replace(tiles);
+    }
+
+    /// Put a freshly made collage on the tile for the playlist called `name`.
+    pub(crate) fn show_collage(&self, name: &str, art: Option<DecodedArt>) {
+        if let Some(art) = art {
+            if let Some((_, image)) = self.playlists.borrow().iter().find(|(x, _)| x == name) {
+                image.set_from_pixbuf(Some(&art_pixbuf(&art)));
+            }
+        }
+    }
+}
+
+impl AsRef<gtk::Widget> for PlaylistBrowser {
+    fn as_ref(&self) -> &gtk::Widget {
+        self.container.upcast_ref()
+    }
+}
+
+/// Browse page with a grid of every album's cover. Covers are only made for
+/// albums on (or near) the screen, and those scrolled well away go back to
+/// an icon, so a big library doesn't take all the memory there is.
+pub(crate) struct AlbumBrowser {
+    pub(crate) container: gtk::ScrolledWindow,
+    tiles: gtk::FlowBox,
+    /// Each tile's album and cover, in order.
+    pub(crate) albums: Rc<RefCell<Vec<(String, gtk::Image)>>>,
+    thumbnails: ThumbnailCache,
+    /// Albums whose thumbnails are on their way, as of the latest generation.
+    pub(crate) pending: RefCell<BTreeSet<String>>,
+    requests: std::sync::mpsc::Sender<ThumbnailRequest>,
+    pub(crate) generation: Arc<AtomicU64>,
+}
+
+impl AlbumBrowser {
+    pub(crate) fn new(
+        sender: mpsc::Sender<StateUpdateKind>,
+        requests: std::sync::mpsc::Sender<ThumbnailRequest>,
+        generation: Arc<AtomicU64>,
+    ) -> Self {
+        let albums = Rc::new(RefCell::new(Vec::<(String, gtk::Image)>::new()));
+        let tiles = gtk::FlowBox::builder()
+            .selection_mode(gtk::SelectionMode::None)
+            .homogeneous(true)
+            .valign(gtk::Align::Start)
+            .column_spacing(8)
+            .row_spacing(8)
+            .border_width(8)
+            .build();
+        tiles.connect_child_activated(clone!(@strong sender, @strong albums => move |_, child| {
+            let album = usize::try_from(child.index())
+                .ok()
+                .and_then(|index| albums.borrow().get(index).map(|(album, _)| album.clone()));
+            if let Some(album) = album {
+                let mut sender = sender.clone();
+                sender
+                    .try_send(StateUpdateKind::QueueAddTagRequest("Album".into(), album))
+                    .expect("Couldn't notify thread");
+                sender
+                    .try_send(StateUpdateKind::MpdEvent)
+                    .expect("Couldn't notify thread");
+            }
+        }));
+
+        let container = gtk::ScrolledWindow::new(gtk::Adjustment::NONE, gtk::Adjustment::NONE);
+        container.add(&tiles);
+        container.set_vexpand(true);
+        container.connect_map(clone!(@strong sender => move |_| {
+            let mut sender = sender.clone();
+            sender
+                .try_send(StateUpdateKind::BrowseAlbumsRequest)
+                .expect("Couldn't notify thread");
+        }));
+        // Which covers are wanted depends on what's on screen.
+        let wanted = clone!(@strong sender => move || {
+            let mut sender = sender.clone();
+            sender
+                .try_send(StateUpdateKind::ThumbnailsWanted)
+                .expect("Couldn't notify thread");
+        });
+        let adjustment = container.vadjustment();
+        adjustment.connect_value_changed(clone!(@strong wanted => move |_| wanted()));
+        // This covers the window being resized and the tiles being filled in.
+        adjustment.connect_changed(move |_| wanted());
+        container.show_all();
+
+        AlbumBrowser {
+            container,
+            tiles,
+            albums,
+            thumbnails: ThumbnailCache {
+                entries: RefCell::new(VecDeque::new()),
+            },
+            pending: RefCell::new(BTreeSet::new()),
+            requests,
+            generation,
+        }
+    }
+
+    /// Drop the covers made so far, which belong to another server's albums
+    /// once it's been switched away from. The tiles are redone next time the
+    /// grid's shown.
+    pub(crate) fn forget_thumbnails(&self) {
+        self.thumbnails.clear();
+        self.pending.borrow_mut().clear();
+        self.generation.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Show a tile for each of `albums`, with no covers until they're wanted.
+    pub(crate) fn set_albums(&self, albums: &[String]) {
+        for child in self.tiles.children() {
+            self.tiles.remove(&child);
+        }
+
+        let mut tiles = Vec::new();
+        for album in albums {
+            let tile = gtk::Box::new(gtk::Orientation::Vertical, 4);
+            let image = gtk::Image::from_icon_name(Some("media-optical-symbolic"), gtk::IconSize::Dialog);
+            image.set_size_request(THUMBNAIL_SIZE, THUMBNAIL_SIZE);
+            tile.add(&image);
+            let label = gtk::Label::new(Some(album));
+            label.set_ellipsize(pango::EllipsizeMode::End);
+            label.set_max_width_chars(14);
+            tile.add(&label);
+            tile.set_tooltip_text(Some(&format!("Add “{}” to the queue", album)));
+            tile.show_all();
+            self.tiles.add(&tile);
+            tiles.push((album.clone(), image));
+        }
+        self.albums.replace(tiles);
+    }
+
+    /// Show covers on the tiles that are on screen, or nearly, asking for
+    /// any that haven't been made yet. The rest go back to an icon so their
+    /// covers can be let go.
+    pub(crate) fn load_visible(&self) {
+        let adjustment = self.container.vadjustment();
+        let (top, bottom) = (
+            adjustment.value() - adjustment.page_size(),
+            adjustment.value() + 2.0 * adjustment.page_size(),
+        );
+
+        // Whatever was asked for before is for a different scroll position.
+        let generation = self.generation.fetch_add(1, Ordering::Relaxed) + 1;
+        self.pending.borrow_mut().clear();
+
+        for (i, (album, image)) in self.albums.borrow().iter().enumerate() {
+            let tile = match self.tiles.child_at_index(i as i32) {
+                Some(tile) => tile,
+                None => continue,
+            };
+            let allocation = tile.allocation();
+            let near = f64::from(allocation.y() + allocation.height()) >= top
+                && f64::from(allocation.y()) <= bottom;
+            if !near {
+                if image.storage_type() == gtk::ImageType::Pixbuf {
+                    image.set_from_icon_name(Some("media-optical-symbolic"), gtk::IconSize::Dialog);
+                }
+                continue;
+            }
+            if image.storage_type() == gtk::ImageType::Pixbuf {
+                continue;
+            }
+            match self.thumbnails.get(album) {
+                Some(pixbuf) => image.set_from_pixbuf(Some(&pixbuf)),
+                None => {
+                    self.pending.borrow_mut().insert(album.clone());
+                    let _ = self.requests.send(ThumbnailRequest::Album {
+                        album: album.clone(),
+                        generation,
+                    });
+                }
+            }
+        }
+    }
+
+    /// Put a freshly made thumbnail in the cache and on its tile.
+    pub(crate) fn show_thumbnail(&self, album: String, art: Option<DecodedArt>) {
+        let wanted = self.pending.borrow_mut().remove(&album);
+        let pixbuf = match art {
+            Some(art) => art_pixbuf(&art),
+            // Albums without a cover keep the icon.
+            None => return,
+        };
+        if wanted {
+            if let Some((_, image)) = self.albums.borrow().iter().find(|(x, _)| *x == album) {
+                image.set_from_pixbuf(Some(&pixbuf));
+            }
+        }
+        self.thumbnails.insert(album, pixbuf);
+    }
+}
+
+impl AsRef<gtk::Widget> for AlbumBrowser {
+    fn as_ref(&self) -> &gtk::Widget {
+        self.container.upcast_ref()
+    }
+}
+
+/// Browse page for storage MPD has mounted on top of the music directory, such
+/// as audio CDs and USB drives, along with neighbors that could be mounted.
+pub(crate) struct MediaBrowser {
+    pub(crate) container: gtk::Box,
+    sources: gtk::ListBox,
+    placeholder: gtk::Label,
+    pub(crate) stack: gtk::Stack,
+    pub(crate) sender: mpsc::Sender<StateUpdateKind>,
+}
+
+impl MediaBrowser {
+    pub(crate) fn new(sender: mpsc::Sender<StateUpdateKind>, stack: &gtk::Stack) -> Self {
+        let container = gtk::Box::new(gtk::Orientation::Vertical, 2);
+
+        let sources = gtk::ListBox::new();
+        sources.set_selection_mode(gtk::SelectionMode::None);
+        let placeholder = gtk::Label::new(Some("No external media"));
+        placeholder.set_vexpand(true);
+        let scrolled_window =
+            gtk::ScrolledWindow::new(gtk::Adjustment::NONE, gtk::Adjustment::NONE);
+        scrolled_window.add(&sources);
+        scrolled_window.set_vexpand(true);
+
+        container.add(&placeholder);
+        container.add(&scrolled_window);
+
+        // MPD doesn't tell idle clients about mounts coming and going, so we
+        // just ask again whenever the page is shown.
+        container.connect_map(clone!(@strong sender => move |_| {
+            let mut sender = sender.clone();
+            sender
+                .try_send(StateUpdateKind::BrowseMediaRequest)
+                .expect("Couldn't notify thread");
+        }));
+        container.show_all();
+
+        MediaBrowser {
+            container,
+            sources,
+            placeholder,
+            stack: stack.clone(),
+            sender,
+        }
+    }
+
+    pub(crate) fn set_sources(&self, mounts: &[mpd::Mount], neighbors: &[mpd::Neighbor]) {
+        for child in self.sources.children() {
+            self.sources.remove(&child);
+        }
+
+        // The music directory itself shows up as a mount with an empty name;
+        // that's what the Folders page is for.
+        let mounts: Vec<_> = mounts.iter().filter(|x| !x.name.is_empty()).collect();
+        let neighbors: Vec<_> = neighbors
+            .iter()
+            .filter(|neighbor| !mounts.iter().any(|x| x.storage == neighbor.storage))
+            .collect();
+
+        for mount in &mounts {
+            let open =
+                gtk::Button::from_icon_name(Some("folder-symbolic"), gtk::IconSize::SmallToolbar);
+            open.set_tooltip_text(Some("Browse"));
+            let name = mount.name.clone();
+            let stack = self.stack.clone();
+            let sender = self.sender.clone();
+            open.connect_clicked(move |_| {
+                // Switch pages first, since showing the Folders page reloads
+                // whatever it was looking at before.
+                stack.set_visible_child_name("folders");
+                let mut sender = sender.clone();
+                sender
+                    .try_send(StateUpdateKind::BrowseDirectoryRequest(name.clone()))
+                    .expect("Couldn't notify thread");
+            });
+
+            let unmount =
+                gtk::Button::from_icon_name(Some("media-eject-symbolic"), gtk::IconSize::SmallToolbar);
+            unmount.set_tooltip_text(Some("Unmount"));
+            let name = mount.name.clone();
+            let sender = self.sender.clone();
+            unmount.connect_clicked(move |_| {
+                let mut sender = sender.clone();
+                sender
+                    .try_send(StateUpdateKind::MediaUnmountRequest(name.clone()))
+                    .expect("Couldn't notify thread");
+            });
+
+            self.sources
+                .add(&Self::source_row(&mount.name, &mount.storage, &[&open, &unmount]));
+        }
+
+        for neighbor in &neighbors {
+            let mount =
+                gtk::Button::from_icon_name(Some("list-add-symbolic"), gtk::IconSize::SmallToolbar);
+            mount.set_tooltip_text(Some("Mount"));
+            // Mount points live at the top of the music directory, so they
+            // can't contain slashes.
+            let name = neighbor.name.replace('/', "-");
+            let storage = neighbor.storage.clone();
+            let sender = self.sender.clone();
+            mount.connect_clicked(move |_| {
+                let mut sender = sender.clone();
+                sender
+                    .try_send(StateUpdateKind::MediaMountRequest(name.clone(), storage.clone()))
+                    .expect("Couldn't notify thread");
+            });
+
+            self.sources
+                .add(&Self::source_row(&neighbor.name, &neighbor.storage, &[&mount]));
+        }
+
+        self.placeholder
+            .set_visible(mounts.is_empty() && neighbors.is_empty());
+    }
+
+    pub(crate) fn source_row(name: &str, storage: &str, buttons: &[&gtk::Button]) -> gtk::Widget {
+        let row = gtk::ListBoxRow::new();
+        let row_box = gtk::Box::new(gtk::Orientation::Horizontal, 8);
+
+        let text = gtk::Box::new(gtk::Orientation::Vertical, 0);
+        text.set_hexpand(true);
+        let name_label = gtk::Label::new(Some(name));
+        name_label.set_xalign(0.0);
+        name_label.set_ellipsize(pango::EllipsizeMode::End);
+        text.add(&name_label);
+        let storage_label = gtk::Label::new(Some(storage));
+        storage_label.set_xalign(0.0);
+        storage_label.set_ellipsize(pango::EllipsizeMode::Middle);
+        storage_label.style_context().add_class("dim-label");
+        text.add(&storage_label);
+        row_box.add(&text);
+
+        for button in buttons {
+            row_box.add(*button);
+        }
+
+        row.add(&row_box);
+        row.show_all();
+        row.upcast::<gtk::Widget>()
+    }
+}
+
+impl AsRef<gtk::Widget> for MediaBrowser {
+    fn as_ref(&self) -> &gtk::Widget {
+        self.container.upcast_ref()
+    }
+}
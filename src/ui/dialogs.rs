@@ -0,0 +1,753 @@
+// Copyright © 2021-2022 Jakob L. Kreuze <zerodaysfordays@sdf.org>
+//
+// This file is part of Tunes.
+//
+// Tunes is free software; you can redistribute it and/or modify it
+// under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation; either version 3 of the
+// License, or (at your option) any later version.
+//
+// Tunes is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY
+// or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Affero General
+// Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public
+// License along with Tunes. If not, see <http://www.gnu.org/licenses/>.
+
+use futures::channel::mpsc;
+use glib::clone;
+use gtk::prelude::*;
+use gtk::{gdk_pixbuf, glib, pango};
+use libhandy::ApplicationWindow;
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+use std::time::Duration;
+use tunes_core::{
+    parse_labels, Client, Config, Confirmation, DisplayProfile, FormFactor, Macro, MacroEdit, MacroStep, Rule,
+    SmartPlaylist, StateUpdateKind,
+};
+
+use crate::backend::Options;
+use crate::models::format_duration;
+use crate::ui::Toast;
+
+/// Ask for the password to `host`, and whether to keep it in the keyring.
+pub(crate) async fn password_dialog(window: &ApplicationWindow, host: &str) -> Option<(String, bool)> {
+    let dialog = gtk::Dialog::with_buttons(
+        Some("Password Needed"),
+        Some(window),
+        gtk::DialogFlags::MODAL | gtk::DialogFlags::DESTROY_WITH_PARENT,
+        &[
+            ("Cancel", gtk::ResponseType::Cancel),
+            ("Log In", gtk::ResponseType::Accept),
+        ],
+    );
+    dialog.set_default_response(gtk::ResponseType::Accept);
+
+    let label = gtk::Label::new(Some(&format!("{} needs a password for that.", host)));
+    label.set_line_wrap(true);
+    let password_input = gtk::Entry::new();
+    password_input.set_visibility(false);
+    password_input.set_input_purpose(gtk::InputPurpose::Password);
+    password_input.set_activates_default(true);
+    let remember = gtk::CheckButton::with_label("Remember this password");
+    let remember_note = gtk::Label::new(Some("It's kept in the keyring."));
+    remember_note.set_line_wrap(true);
+    remember_note.set_xalign(0.0);
+    remember_note.style_context().add_class("dim-label");
+
+    let content = dialog.content_area();
+    content.set_spacing(8);
+    content.set_border_width(8);
+    content.add(&label);
+    content.add(&password_input);
+    content.add(&remember);
+    content.add(&remember_note);
+    dialog.show_all();
+
+    let response = dialog.run_future().await;
+    let password = password_input.text().to_string();
+    dialog.close();
+
+    match response {
+        gtk::ResponseType::Accept if !password.is_empty() => Some((password, remember.is_active())),
+        _ => None,
+    }
+}
+
+/// Show the cover cached at `path` over the whole of `window`, as big as it'll
+/// fit. Pinching zooms in and out, and a tap or Escape goes back.
+pub(crate) fn show_art_overlay(window: &gtk::Window, path: &std::path::Path) {
+    // Songs without a cover don't have anything cached.
+    let full = match gdk_pixbuf::Pixbuf::from_file(path) {
+        Ok(pixbuf) => pixbuf,
+        Err(_) => return,
+    };
+
+    let overlay = gtk::Window::builder()
+        .transient_for(window)
+        .modal(true)
+        .decorated(false)
+        .build();
+    overlay.style_context().add_class("art-overlay");
+
+    let image = gtk::Image::new();
+    let scrolled_window = gtk::ScrolledWindow::new(gtk::Adjustment::NONE, gtk::Adjustment::NONE);
+    scrolled_window.add(&image);
+    let event_box = gtk::EventBox::new();
+    event_box.add(&scrolled_window);
+    overlay.add(&event_box);
+
+    // Fitting the screen is where zooming starts from.
+    let (width, height) = (window.allocated_width(), window.allocated_height());
+    let fitted = (f64::from(width) / f64::from(full.width()))
+        .min(f64::from(height) / f64::from(full.height()));
+    let show = clone!(@strong full, @weak image => move |zoom: f64| {
+        let scale = fitted * zoom;
+        let (width, height) = (
+            (f64::from(full.width()) * scale).max(1.0) as i32,
+            (f64::from(full.height()) * scale).max(1.0) as i32,
+        );
+        image.set_from_pixbuf(full.scale_simple(width, height, gdk_pixbuf::InterpType::Bilinear).as_ref());
+    });
+    show(1.0);
+
+    // Each pinch zooms from wherever the last one left off.
+    let zoom = Rc::new(Cell::new(1.0));
+    let pinched = Rc::new(Cell::new(1.0));
+    let gesture = gtk::GestureZoom::new(&event_box);
+    gesture.connect_scale_changed(clone!(@strong zoom, @strong pinched => move |_, scale| {
+        pinched.set((zoom.get() * scale).clamp(1.0, 8.0));
+        show(pinched.get());
+    }));
+    gesture.connect_end(move |_, _| zoom.set(pinched.get()));
+
+    // Pinching ends with fingers lifting too, so only a tap that wasn't part
+    // of one closes it.
+    event_box.connect_button_release_event(clone!(@weak overlay, @strong gesture => move |_, _| {
+        if !gesture.is_recognized() {
+            overlay.close();
+        }
+        gtk::Inhibit(true)
+    }));
+    overlay.connect_key_press_event(|overlay, event| {
+        if event.keyval() == gtk::gdk::keys::constants::Escape {
+            overlay.close();
+        }
+        gtk::Inhibit(false)
+    });
+
+    overlay.show_all();
+    overlay.fullscreen();
+}
+
+/// Ask the user for a smart playlist's name and rules, starting from
+/// `existing` if we're editing one.
+pub(crate) async fn smart_playlist_dialog(
+    window: &ApplicationWindow,
+    existing: Option<&SmartPlaylist>,
+) -> Option<SmartPlaylist> {
+    let dialog = gtk::Dialog::with_buttons(
+        Some("Smart Playlist"),
+        Some(window),
+        gtk::DialogFlags::MODAL | gtk::DialogFlags::DESTROY_WITH_PARENT,
+        &[
+            ("Cancel", gtk::ResponseType::Cancel),
+            ("Save", gtk::ResponseType::Accept),
+        ],
+    );
+    dialog.set_default_response(gtk::ResponseType::Accept);
+    dialog.set_default_size(360, 320);
+
+    let name_input = gtk::Entry::new();
+    name_input.set_placeholder_text(Some("Name"));
+    let rules_input = gtk::TextView::new();
+    rules_input.set_wrap_mode(gtk::WrapMode::WordChar);
+    if let Some(existing) = existing {
+        name_input.set_text(&existing.name);
+        let rules: Vec<String> = existing.rules.iter().map(|x| x.to_string()).collect();
+        if let Some(buffer) = rules_input.buffer() {
+            buffer.set_text(&rules.join("\n"));
+        }
+    }
+    let rules_window = gtk::ScrolledWindow::new(gtk::Adjustment::NONE, gtk::Adjustment::NONE);
+    rules_window.add(&rules_input);
+    rules_window.set_vexpand(true);
+
+    let help = gtk::Label::new(Some(
+        "One rule per line, for example:\nGenre is Jazz\nArtist contains Davis\nyear > 2010\nrating >= 4\nnot played in 30 days\nlabelled chill",
+    ));
+    help.set_xalign(0.0);
+    help.style_context().add_class("dim-label");
+    let error = gtk::Label::new(None);
+    error.set_xalign(0.0);
+    error.set_line_wrap(true);
+
+    let content = dialog.content_area();
+    content.set_spacing(8);
+    content.set_border_width(8);
+    content.add(&name_input);
+    content.add(&rules_window);
+    content.add(&help);
+    content.add(&error);
+    dialog.show_all();
+
+    // Keep the dialog up until the rules make sense or the user gives up.
+    let playlist = loop {
+        if dialog.run_future().await != gtk::ResponseType::Accept {
+            break None;
+        }
+
+        let name = name_input.text().trim().to_string();
+        let text = rules_input
+            .buffer()
+            .and_then(|buffer| buffer.text(&buffer.start_iter(), &buffer.end_iter(), false))
+            .map(|x| x.to_string())
+            .unwrap_or_default();
+        let rules: Result<Vec<Rule>, String> = text
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(Rule::parse)
+            .collect();
+        match rules {
+            _ if name.is_empty() => error.set_text("The playlist needs a name."),
+            Ok(rules) if rules.is_empty() => error.set_text("The playlist needs at least one rule."),
+            Ok(rules) => break Some(SmartPlaylist { name, rules }),
+            Err(e) => error.set_text(&e),
+        }
+    };
+    dialog.close();
+    playlist
+}
+
+/// Ask for a macro's name and steps, starting from `existing` if there is one,
+/// which can also be deleted from here.
+pub(crate) async fn macro_dialog(window: &ApplicationWindow, existing: Option<&Macro>) -> Option<MacroEdit> {
+    let dialog = gtk::Dialog::with_buttons(
+        Some("Macro"),
+        Some(window),
+        gtk::DialogFlags::MODAL | gtk::DialogFlags::DESTROY_WITH_PARENT,
+        &[
+            ("Cancel", gtk::ResponseType::Cancel),
+            ("Save", gtk::ResponseType::Accept),
+        ],
+    );
+    if existing.is_some() {
+        let delete = dialog.add_button("Delete", gtk::ResponseType::Reject);
+        delete.style_context().add_class("destructive-action");
+    }
+    dialog.set_default_response(gtk::ResponseType::Accept);
+    dialog.set_default_size(360, 320);
+
+    let name_input = gtk::Entry::new();
+    name_input.set_placeholder_text(Some("Name"));
+    let steps_input = gtk::TextView::new();
+    steps_input.set_wrap_mode(gtk::WrapMode::WordChar);
+    if let Some(existing) = existing {
+        name_input.set_text(&existing.name);
+        let steps: Vec<String> = existing.steps.iter().map(|x| x.to_string()).collect();
+        if let Some(buffer) = steps_input.buffer() {
+            buffer.set_text(&steps.join("\n"));
+        }
+    }
+    let steps_window = gtk::ScrolledWindow::new(gtk::Adjustment::NONE, gtk::Adjustment::NONE);
+    steps_window.add(&steps_input);
+    steps_window.set_vexpand(true);
+
+    let help = gtk::Label::new(Some(
+        "One step per line, for example:\nclear\nload playlist Dinner\nsmart playlist Chill\nvolume 35\nrandom off\nplay",
+    ));
+    help.set_xalign(0.0);
+    help.style_context().add_class("dim-label");
+    let error = gtk::Label::new(None);
+    error.set_xalign(0.0);
+    error.set_line_wrap(true);
+
+    let content = dialog.content_area();
+    content.set_spacing(8);
+    content.set_border_width(8);
+    content.add(&name_input);
+    content.add(&steps_window);
+    content.add(&help);
+    content.add(&error);
+    dialog.show_all();
+
+    // Keep the dialog up until the steps make sense or the user gives up.
+    let edit = loop {
+        match dialog.run_future().await {
+            gtk::ResponseType::Accept => {}
+            gtk::ResponseType::Reject => break Some(MacroEdit::Delete),
+            _ => break None,
+        }
+
+        let name = name_input.text().trim().to_string();
+        let text = steps_input
+            .buffer()
+            .and_then(|buffer| buffer.text(&buffer.start_iter(), &buffer.end_iter(), false))
+            .map(|x| x.to_string())
+            .unwrap_or_default();
+        let steps: Result<Vec<MacroStep>, String> = text
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(MacroStep::parse)
+            .collect();
+        match steps {
+            _ if name.is_empty() => error.set_text("The macro needs a name."),
+            Ok(steps) if steps.is_empty() => error.set_text("The macro needs at least one step."),
+            Ok(steps) => break Some(MacroEdit::Save(Macro { name, steps })),
+            Err(e) => error.set_text(&e),
+        }
+    };
+    dialog.close();
+    edit
+}
+
+/// Ask what to call the server in the preferences. `existing` are the names
+/// already saved, since saving over one is how it's changed.
+pub(crate) async fn server_name_dialog(window: &ApplicationWindow, existing: &[String]) -> Option<String> {
+    let dialog = gtk::Dialog::with_buttons(
+        Some("Save Server"),
+        Some(window),
+        gtk::DialogFlags::MODAL | gtk::DialogFlags::DESTROY_WITH_PARENT,
+        &[
+            ("Cancel", gtk::ResponseType::Cancel),
+            ("Save", gtk::ResponseType::Accept),
+        ],
+    );
+    dialog.set_default_response(gtk::ResponseType::Accept);
+
+    let label = gtk::Label::new(Some(
+        "Name the server in the preferences, like “Living Room”, to switch back to it from the menu.",
+    ));
+    label.set_line_wrap(true);
+
+    let name_input = gtk::ComboBoxText::with_entry();
+    for name in existing {
+        name_input.append_text(name);
+    }
+    if let Some(entry) = name_input
+        .child()
+        .and_then(|child| child.downcast::<gtk::Entry>().ok())
+    {
+        entry.set_activates_default(true);
+    }
+
+    let content = dialog.content_area();
+    content.set_spacing(8);
+    content.set_border_width(8);
+    content.add(&label);
+    content.add(&name_input);
+    dialog.show_all();
+
+    let response = dialog.run_future().await;
+    let name = name_input
+        .active_text()
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty());
+    dialog.close();
+
+    match response {
+        gtk::ResponseType::Accept => name,
+        _ => None,
+    }
+}
+
+/// Ask how long a queue to build and which songs can go in it, as smart
+/// playlist rules. No rules means anything goes.
+pub(crate) async fn timed_queue_dialog(window: &ApplicationWindow) -> Option<(Duration, Vec<Rule>)> {
+    let dialog = gtk::Dialog::with_buttons(
+        Some("Timed Queue"),
+        Some(window),
+        gtk::DialogFlags::MODAL | gtk::DialogFlags::DESTROY_WITH_PARENT,
+        &[
+            ("Cancel", gtk::ResponseType::Cancel),
+            ("Queue", gtk::ResponseType::Accept),
+        ],
+    );
+    dialog.set_default_response(gtk::ResponseType::Accept);
+    dialog.set_default_size(360, 320);
+
+    let minutes_input = gtk::SpinButton::with_range(5.0, 600.0, 5.0);
+    minutes_input.set_value(45.0);
+    let minutes = gtk::Box::new(gtk::Orientation::Horizontal, 8);
+    minutes.add(&minutes_input);
+    minutes.add(&gtk::Label::new(Some("minutes of")));
+
+    let rules_input = gtk::TextView::new();
+    rules_input.set_wrap_mode(gtk::WrapMode::WordChar);
+    let rules_window = gtk::ScrolledWindow::new(gtk::Adjustment::NONE, gtk::Adjustment::NONE);
+    rules_window.add(&rules_input);
+    rules_window.set_vexpand(true);
+
+    let help = gtk::Label::new(Some(
+        "One rule per line, like a smart playlist, for example:\nGenre is Jazz\nMood is Relaxed\nlabelled workout\nLeave it empty for anything at all.",
+    ));
+    help.set_xalign(0.0);
+    help.style_context().add_class("dim-label");
+    let error = gtk::Label::new(None);
+    error.set_xalign(0.0);
+    error.set_line_wrap(true);
+
+    let content = dialog.content_area();
+    content.set_spacing(8);
+    content.set_border_width(8);
+    content.add(&minutes);
+    content.add(&rules_window);
+    content.add(&help);
+    content.add(&error);
+    dialog.show_all();
+
+    let request = loop {
+        if dialog.run_future().await != gtk::ResponseType::Accept {
+            break None;
+        }
+
+        let text = rules_input
+            .buffer()
+            .and_then(|buffer| buffer.text(&buffer.start_iter(), &buffer.end_iter(), false))
+            .map(|x| x.to_string())
+            .unwrap_or_default();
+        let rules: Result<Vec<Rule>, String> = text
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(Rule::parse)
+            .collect();
+        match rules {
+            Ok(rules) => {
+                let target = Duration::from_secs(minutes_input.value_as_int() as u64 * 60);
+                break Some((target, rules));
+            }
+            Err(e) => error.set_text(&e),
+        }
+    };
+    dialog.close();
+    request
+}
+
+/// Ask the user which stored playlist to save a selection of songs into. The
+/// user can either pick one of the `existing` playlists or type a new name.
+/// Describe what Tunes is running on and how it's set up, for bug reports.
+pub(crate) fn debug_info(
+    conn: &Client,
+    options: &Options,
+    config: &Config,
+    form_factor: Option<FormFactor>,
+) -> String {
+    let mpd::Version(major, minor, patch) = &conn.version;
+    let mut lines = vec![
+        format!("Tunes {}", env!("CARGO_PKG_VERSION")),
+        format!(
+            "GTK {}.{}.{}",
+            gtk::major_version(),
+            gtk::minor_version(),
+            gtk::micro_version()
+        ),
+        // libhandy doesn't tell us its exact version at runtime.
+        "libhandy 1".to_string(),
+        format!("MPD protocol {}.{}.{}", major, minor, patch),
+    ];
+    if let Some(form_factor) = form_factor {
+        let profile = DisplayProfile::load(config, form_factor);
+        lines.push(format!(
+            "Profile: {} ({:?}, {:?} art, {:?})",
+            form_factor.title(),
+            profile.density,
+            profile.art_size,
+            profile.layout
+        ));
+    }
+    lines.push(format!(
+        "Mode: {}",
+        if options.kiosk {
+            "kiosk"
+        } else if options.follow {
+            "follow"
+        } else {
+            "normal"
+        }
+    ));
+    if let Some(partition) = &options.partition {
+        lines.push(format!("Partition: {}", partition));
+    }
+    lines.push(format!(
+        "Features: diagnostics {}, warm next track {}",
+        if options.diagnostics { "on" } else { "off" },
+        if config.get("playback", "warm-next-track") == Some("true") { "on" } else { "off" },
+    ));
+    lines.join("\n")
+}
+
+/// Show the about dialog, with `info` from `debug_info` tucked away in an
+/// expander and a button to copy it for pasting into an issue.
+pub(crate) fn show_about(window: &ApplicationWindow, info: &str) {
+    let dialog = gtk::AboutDialog::builder()
+        .transient_for(window)
+        .modal(true)
+        .program_name("Tunes")
+        .version(env!("CARGO_PKG_VERSION"))
+        .comments("A client for the Music Player Daemon")
+        .license_type(gtk::License::Agpl30)
+        .build();
+
+    let label = gtk::Label::builder()
+        .label(info)
+        .selectable(true)
+        .xalign(0.0)
+        .build();
+    label.style_context().add_class("monospace");
+    let expander = gtk::Expander::new(Some("Runtime Environment"));
+    expander.add(&label);
+    dialog.content_area().add(&expander);
+
+    const COPY_RESPONSE: gtk::ResponseType = gtk::ResponseType::Other(1);
+    dialog.add_button("Copy Debug Info", COPY_RESPONSE);
+    let info = info.to_string();
+    dialog.connect_response(move |dialog, response| {
+        if response == COPY_RESPONSE {
+            if let Some(clipboard) = gtk::Clipboard::default(&dialog.display()) {
+                clipboard.set_text(&info);
+            }
+        } else {
+            dialog.close();
+        }
+    });
+    dialog.show_all();
+}
+
+/// Show every tag MPD has for `song`, for when the list rows don't say enough,
+/// along with the user's own `labels` for it.
+pub(crate) fn show_song_details(
+    window: &ApplicationWindow,
+    sender: &mpsc::Sender<StateUpdateKind>,
+    song: &mpd::song::Song,
+    labels: &[String],
+) {
+    const EDIT_LABELS_RESPONSE: gtk::ResponseType = gtk::ResponseType::Other(1);
+    let dialog = gtk::Dialog::with_buttons(
+        Some("Song Details"),
+        Some(window),
+        gtk::DialogFlags::MODAL | gtk::DialogFlags::DESTROY_WITH_PARENT,
+        &[
+            ("Edit Labels…", EDIT_LABELS_RESPONSE),
+            ("Close", gtk::ResponseType::Close),
+        ],
+    );
+
+    let mut fields = vec![("File".to_string(), song.file.clone())];
+    if let Some(title) = &song.title {
+        fields.push(("Title".into(), title.clone()));
+    }
+    if let Some(artist) = &song.artist {
+        fields.push(("Artist".into(), artist.clone()));
+    }
+    if let Some(duration) = song.duration {
+        fields.push(("Duration".into(), format_duration(duration)));
+    }
+    fields.extend(song.tags.iter().map(|(tag, value)| (tag.clone(), value.clone())));
+    if !labels.is_empty() {
+        fields.push(("Labels".into(), labels.join(", ")));
+    }
+
+    let grid = gtk::Grid::builder()
+        .row_spacing(6)
+        .column_spacing(12)
+        .border_width(12)
+        .build();
+    for (row, (tag, value)) in fields.iter().enumerate() {
+        let row = row as i32;
+        let name = gtk::Label::builder().label(tag).xalign(1.0).yalign(0.0).build();
+        name.style_context().add_class("dim-label");
+        grid.attach(&name, 0, row, 1, 1);
+        let value = gtk::Label::builder()
+            .label(value)
+            .xalign(0.0)
+            .selectable(true)
+            .wrap(true)
+            .wrap_mode(pango::WrapMode::WordChar)
+            .build();
+        grid.attach(&value, 1, row, 1, 1);
+    }
+    dialog.content_area().add(&grid);
+
+    let filename = song.file.clone();
+    let sender = sender.clone();
+    dialog.connect_response(move |dialog, response| {
+        if response == EDIT_LABELS_RESPONSE {
+            let mut sender = sender.clone();
+            sender
+                .try_send(StateUpdateKind::SongLabelsEditRequest(filename.clone()))
+                .expect("Couldn't notify thread");
+        }
+        dialog.close();
+    });
+    dialog.show_all();
+}
+
+/// Hold on to `event`, an add to the queue, until the server's back, and let
+/// the user know it's been saved.
+pub(crate) fn save_offline_add(toast: &Toast, offline_adds: &mut Vec<StateUpdateKind>, event: StateUpdateKind) {
+    offline_adds.push(event);
+    toast.show(&match offline_adds.len() {
+        1 => "Offline, so that's saved to add when the server's back".to_string(),
+        n => format!("Offline, so {} adds are saved for when the server's back", n),
+    });
+}
+
+/// Ask whether to go ahead with the `count` adds to the queue saved while
+/// the server was away, now that it's back. The queue may well have moved
+/// on since.
+pub(crate) async fn confirm_offline_adds(window: &ApplicationWindow, count: usize) -> bool {
+    let dialog = gtk::MessageDialog::builder()
+        .transient_for(window)
+        .modal(true)
+        .destroy_with_parent(true)
+        .message_type(gtk::MessageType::Question)
+        .text("Add Songs Saved While Offline?")
+        .secondary_text(&match count {
+            1 => "One add to the queue was saved while the server was away.".to_string(),
+            n => format!("{} adds to the queue were saved while the server was away.", n),
+        })
+        .build();
+    dialog.add_buttons(&[
+        ("Discard", gtk::ResponseType::Reject),
+        ("Add to Queue", gtk::ResponseType::Accept),
+    ]);
+    dialog.set_default_response(gtk::ResponseType::Accept);
+    let response = dialog.run_future().await;
+    dialog.close();
+    response == gtk::ResponseType::Accept
+}
+
+/// Check the user really means it, unless they've said not to ask. Ticking
+/// "Don't ask again" turns the question off in the preferences too.
+pub(crate) async fn confirm(
+    window: &ApplicationWindow,
+    config: &Rc<RefCell<Config>>,
+    confirmation: Confirmation,
+    message: &str,
+    action: &str,
+) -> bool {
+    if !confirmation.wanted(&config.borrow()) {
+        return true;
+    }
+
+    let dialog = gtk::MessageDialog::new(
+        Some(window),
+        gtk::DialogFlags::MODAL | gtk::DialogFlags::DESTROY_WITH_PARENT,
+        gtk::MessageType::Question,
+        gtk::ButtonsType::None,
+        message,
+    );
+    dialog.add_button("Cancel", gtk::ResponseType::Cancel);
+    dialog
+        .add_button(action, gtk::ResponseType::Accept)
+        .style_context()
+        .add_class("destructive-action");
+    let dont_ask = gtk::CheckButton::with_label("Don't ask again");
+    if let Ok(area) = dialog.message_area().downcast::<gtk::Box>() {
+        area.add(&dont_ask);
+    }
+    dialog.show_all();
+
+    let response = dialog.run_future().await;
+    dialog.close();
+
+    let confirmed = response == gtk::ResponseType::Accept;
+    if confirmed && dont_ask.is_active() {
+        config.borrow_mut().set(Confirmation::SECTION, confirmation.key(), false);
+    }
+    confirmed
+}
+
+pub(crate) async fn playlist_name_dialog(window: &ApplicationWindow, existing: &[String]) -> Option<String> {
+    let dialog = gtk::Dialog::with_buttons(
+        Some("Save to Playlist"),
+        Some(window),
+        gtk::DialogFlags::MODAL | gtk::DialogFlags::DESTROY_WITH_PARENT,
+        &[
+            ("Cancel", gtk::ResponseType::Cancel),
+            ("Save", gtk::ResponseType::Accept),
+        ],
+    );
+    dialog.set_default_response(gtk::ResponseType::Accept);
+
+    let label = gtk::Label::new(Some(
+        "Pick a playlist to append to, or type a name for a new one.",
+    ));
+    label.set_line_wrap(true);
+
+    let name_input = gtk::ComboBoxText::with_entry();
+    for name in existing {
+        name_input.append_text(name);
+    }
+    if let Some(entry) = name_input
+        .child()
+        .and_then(|child| child.downcast::<gtk::Entry>().ok())
+    {
+        entry.set_activates_default(true);
+    }
+
+    let content = dialog.content_area();
+    content.set_spacing(8);
+    content.set_border_width(8);
+    content.add(&label);
+    content.add(&name_input);
+    dialog.show_all();
+
+    let response = dialog.run_future().await;
+    let name = name_input
+        .active_text()
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty());
+    dialog.close();
+
+    match response {
+        gtk::ResponseType::Accept => name,
+        _ => None,
+    }
+}
+
+/// Ask for a song's labels, starting from `current`. `known` are all the
+/// labels in use, to jog the user's memory.
+pub(crate) async fn labels_dialog(window: &ApplicationWindow, current: &[String], known: &[String]) -> Option<Vec<String>> {
+    let dialog = gtk::Dialog::with_buttons(
+        Some("Edit Labels"),
+        Some(window),
+        gtk::DialogFlags::MODAL | gtk::DialogFlags::DESTROY_WITH_PARENT,
+        &[
+            ("Cancel", gtk::ResponseType::Cancel),
+            ("Save", gtk::ResponseType::Accept),
+        ],
+    );
+    dialog.set_default_response(gtk::ResponseType::Accept);
+
+    let label = gtk::Label::new(Some("Labels for this song, separated by commas."));
+    label.set_line_wrap(true);
+    let labels_input = gtk::Entry::new();
+    labels_input.set_text(&current.join(", "));
+    labels_input.set_activates_default(true);
+
+    let content = dialog.content_area();
+    content.set_spacing(8);
+    content.set_border_width(8);
+    content.add(&label);
+    content.add(&labels_input);
+    if !known.is_empty() {
+        let in_use = gtk::Label::new(Some(&format!("In use: {}", known.join(", "))));
+        in_use.set_line_wrap(true);
+        in_use.set_xalign(0.0);
+        in_use.style_context().add_class("dim-label");
+        content.add(&in_use);
+    }
+    dialog.show_all();
+
+    let response = dialog.run_future().await;
+    let labels = parse_labels(&labels_input.text());
+    dialog.close();
+
+    match response {
+        gtk::ResponseType::Accept => Some(labels),
+        _ => None,
+    }
+}
@@ -0,0 +1,1000 @@
+// Copyright © 2021-2022 Jakob L. Kreuze <zerodaysfordays@sdf.org>
+//
+// This file is part of Tunes.
+//
+// Tunes is free software; you can redistribute it and/or modify it
+// under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation; either version 3 of the
+// License, or (at your option) any later version.
+//
+// Tunes is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY
+// or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Affero General
+// Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public
+// License along with Tunes. If not, see <http://www.gnu.org/licenses/>.
+
+use anyhow::Context;
+use futures::channel::mpsc;
+use glib::clone;
+use gtk::prelude::*;
+use gtk::{gio, glib};
+use libhandy::prelude::*;
+use libhandy::{ApplicationWindow, HeaderBar};
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
+use std::rc::Rc;
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tunes_core::{
+    album_artist, album_order, album_songs, check_filter_expression, fill_duration, labelled_songs, note_last_played,
+    parse_labels, queue_next, queue_shuffled, set_song_labels, song_labels, songs_in_years, warm_next_song, year_of,
+    ArtSize, Client, Config, Confirmation, ConnectionState, DisplayProfile, FormFactor, FuzzyIndex, Macro, MacroEdit,
+    SearchResults, SelectionAction, ServerProfile, SmartPlaylist, StateUpdateKind, LABELS_STICKER, RATING_STICKER,
+};
+
+use crate::MiniPlayer;
+use crate::backend::{
+    clean_up_in_background, connect_in_background, database_uri, dispatch_playback_state_change, enqueue,
+    find_near_misses, music_directory, search_page, shuffle, update_idle_inhibit, Mpris, Options, Server, TagIndex,
+    ONLINE_ART,
+};
+use crate::models::{format_duration, search_page_size};
+use crate::ui::{
+    apply_display_profile, confirm, confirm_offline_adds, debug_info, fill_macro_menu, fill_server_menu, header_title,
+    labels_dialog, macro_dialog, playlist_name_dialog, server_name_dialog, show_about, show_preferences,
+    show_shortcuts, show_song_details, smart_playlist_dialog, timed_queue_dialog, update_ticker, BrowseInfo,
+    ConnectionIndicator, HeaderTitle, QueryInfo, SongInfo, Toast, TrayIcon,
+};
+
+/// The widgets and settings the main loop shows its work on. These are set
+/// up once, when the window's built.
+pub(crate) struct Ui {
+    pub(crate) window: ApplicationWindow,
+    pub(crate) header_bar: HeaderBar,
+    pub(crate) current_title: Rc<RefCell<HeaderTitle>>,
+    pub(crate) song_info: SongInfo,
+    pub(crate) query_info: QueryInfo,
+    pub(crate) browse_info: BrowseInfo,
+    pub(crate) stack: gtk::Stack,
+    pub(crate) leaflet: libhandy::Leaflet,
+    pub(crate) separator: gtk::Separator,
+    pub(crate) macro_menu: gtk::Box,
+    pub(crate) server_menu: gtk::Box,
+    pub(crate) mini_player: Rc<MiniPlayer>,
+    pub(crate) toast: Toast,
+    pub(crate) connection: ConnectionIndicator,
+    pub(crate) tray: Option<TrayIcon>,
+    pub(crate) mpris: Option<Mpris>,
+    pub(crate) config: Rc<RefCell<Config>>,
+    pub(crate) form_factor: Rc<Cell<Option<FormFactor>>>,
+    pub(crate) options: Options,
+    pub(crate) sender: mpsc::Sender<StateUpdateKind>,
+}
+
+/// What the main loop keeps track of from one event to the next.
+pub(crate) struct Session {
+    pub(crate) conn: Client,
+    /// Which server `conn` is to, as of how many times it's been changed.
+    pub(crate) conn_server_changes: u64,
+    pub(crate) last_added: Option<(String, Instant)>,
+    pub(crate) last_played: Option<String>,
+    pub(crate) last_warmed: Option<String>,
+    /// Set while we're keeping the screen on.
+    pub(crate) idle_inhibit: Option<u32>,
+    /// Shared with the threads that look for near misses.
+    pub(crate) fuzzy_index: Arc<Mutex<Option<FuzzyIndex>>>,
+    pub(crate) search_results: Option<SearchResults>,
+    pub(crate) tag_index: Option<TagIndex>,
+    /// Events waiting their turn, for `next_event`.
+    pub(crate) pending: VecDeque<StateUpdateKind>,
+    /// Songs asked for while offline, to add once the server's back.
+    pub(crate) offline_adds: Vec<StateUpdateKind>,
+}
+
+/// Do whatever `event_type` calls for. Errors are left to the main loop,
+/// which shows them and gets the connection going again if need be.
+pub(crate) async fn handle_event(ui: &Ui, session: &mut Session, event_type: StateUpdateKind) -> anyhow::Result<()> {
+    let Ui {
+        window,
+        header_bar,
+        current_title,
+        song_info,
+        query_info,
+        browse_info,
+        stack,
+        leaflet,
+        separator,
+        macro_menu,
+        server_menu,
+        mini_player,
+        toast,
+        connection,
+        tray,
+        mpris,
+        config,
+        form_factor,
+        options,
+        sender,
+    } = ui;
+    let Session {
+        conn,
+        conn_server_changes,
+        last_added,
+        last_played,
+        last_warmed,
+        idle_inhibit,
+        fuzzy_index,
+        search_results,
+        tag_index,
+        pending,
+        offline_adds,
+    } = session;
+    match event_type {
+        StateUpdateKind::MpdEvent => {
+            if let Ok(title) = header_title(conn) {
+                header_bar.set_title(Some(&title.render(header_bar.allocated_width())));
+                if let Some(tray) = &tray {
+                    tray.set_status(&title.render(i32::MAX));
+                }
+                current_title.replace(title);
+                song_info
+                    .update(conn)
+                    .context("Couldn't update song info")?;
+                if let Some(mpris) = &mpris {
+                    mpris.update(conn).context("Couldn't update media controls")?;
+                }
+                update_ticker(&song_info, &window, &options);
+                update_idle_inhibit(&window, &config.borrow(), song_info.playing.get(), idle_inhibit);
+            }
+            // Smart playlists can pick out songs that haven't
+            // been played lately, which only works if we keep
+            // track. We leave other people's servers alone.
+            if !options.read_only() {
+                note_last_played(conn, last_played);
+            }
+            if config.borrow().get("playback", "warm-next-track") == Some("true") {
+                warm_next_song(conn, last_warmed);
+            }
+        }
+        // Checking the connection is all there is to it, and
+        // that's already been done.
+        StateUpdateKind::KeepAlive => {}
+        StateUpdateKind::Resumed => {
+            // Whatever played on while we were suspended, the UI
+            // should catch up.
+            let mut sender = sender.clone();
+            sender
+                .try_send(StateUpdateKind::MpdEvent)
+                .expect("Couldn't notify thread");
+        }
+        StateUpdateKind::WindowFocusChange => {
+            update_ticker(&song_info, &window, &options);
+        }
+        StateUpdateKind::FormFactorChange(_) | StateUpdateKind::DisplayProfileChange => {
+            if let Some(form_factor) = form_factor.get() {
+                let mut profile = DisplayProfile::load(&config.borrow(), form_factor);
+                // A kiosk is for reading across the room.
+                if options.kiosk {
+                    profile.art_size = ArtSize::Large;
+                }
+                apply_display_profile(&window, &song_info, &profile);
+                // Rows are laid out as they're built, so the ones
+                // already there have to be built again to pick up
+                // any change to the columns.
+                song_info.rebuild_rows();
+                query_info.rebuild_rows();
+                song_info
+                    .update_album_art(conn)
+                    .context("Couldn't update album art")?;
+                // Genre colors are per profile too, so redraw the
+                // queue in case they changed.
+                song_info
+                    .update(conn)
+                    .context("Couldn't update song info")?;
+            }
+        }
+        StateUpdateKind::QueueCollapseChange(collapsed) => {
+            if let Some(form_factor) = form_factor.get() {
+                config
+                    .borrow_mut()
+                    .set(form_factor.section(), "queue-collapsed", collapsed);
+            }
+        }
+        StateUpdateKind::WindowResizeEvent => {
+            song_info
+                .update_album_art(conn)
+                .context("Couldn't update album art")?;
+        }
+        StateUpdateKind::QueryUpdateEvent(request) => {
+            // The form's changed since this was sent, so there'll
+            // be a newer request along shortly.
+            if !query_info.is_current(&request) {
+                return Ok(());
+            }
+            // Let's not produce massive queries while the user is typing :)
+            if request.fields.is_empty() && request.text.len() <= 2 {
+                return Ok(());
+            }
+            // Searching holds up the main loop, so the spinner
+            // gets a frame to be drawn in before it starts.
+            // Every way out of here ends with `set_error`,
+            // which puts it away again.
+            query_info.set_busy(true);
+            glib::timeout_future(Duration::from_millis(20)).await;
+
+            let (songs, fetched, more) = if request.is_filter_expression() {
+                // Power users can type an MPD filter expression
+                // straight in. It's handed over as is, so the
+                // picked tag and the advanced fields don't apply.
+                // Catch the obvious mistakes before MPD does, and
+                // leave the last results up either way.
+                if let Err(error) = check_filter_expression(&request.text) {
+                    query_info.set_error(Some(&error));
+                    return Ok(());
+                }
+                match conn.find_filter(request.text.trim()) {
+                    Ok(songs) => {
+                        let fetched = songs.len() as u32;
+                        (songs, fetched, false)
+                    }
+                    Err(error) => {
+                        query_info.set_error(Some(&error.to_string()));
+                        return Ok(());
+                    }
+                }
+            } else if request.is_label_search() {
+                // Nobody labels that many songs, so they all come
+                // at once.
+                let songs = labelled_songs(conn, &request.text).context("Couldn't find songs")?;
+                let fetched = songs.len() as u32;
+                (songs, fetched, false)
+            } else {
+                // Broad searches can match most of the library, so
+                // we start with a page of results and fetch more
+                // as the user asks for them.
+                let page_size = search_page_size(&config.borrow());
+                let songs = search_page(conn, tag_index.as_ref(), &request, (0, page_size))
+                    .context("Couldn't search the library")?;
+                let fetched = songs.len() as u32;
+                let more = fetched == page_size;
+
+                // MPD only finds the text exactly as typed, so
+                // also look for near misses among the library's
+                // tags, and pull in their songs once they're in.
+                if request.is_fuzzy() {
+                    find_near_misses(&options, fuzzy_index, &sender, request.clone(), page_size);
+                }
+                (songs, fetched, more)
+            };
+            query_info.set_error(None);
+
+            let results = SearchResults {
+                request,
+                songs,
+                fetched,
+                more,
+            };
+            query_info.show_results(&results);
+            *search_results = Some(results);
+        }
+        StateUpdateKind::NearMissesFound(request, near_misses) => {
+            // The search may have moved on while these were
+            // being looked for.
+            if let Some(results) = search_results
+                .as_mut()
+                .filter(|results| results.request.generation == request.generation)
+            {
+                let mut seen: std::collections::HashSet<String> =
+                    results.songs.iter().map(|song| song.file.clone()).collect();
+                results
+                    .songs
+                    .extend(near_misses.into_iter().filter(|song| seen.insert(song.file.clone())));
+                query_info.show_results(results);
+            }
+        }
+        StateUpdateKind::ErrorNotice(message) => toast.show_error(&message),
+        StateUpdateKind::OnlineArtStopped => {
+            // Only for this session. The preferences still say to, so it's
+            // back on next time.
+            toast.show_with_action("Stopped looking up covers online, since it kept failing", "Turn Back On", || {
+                ONLINE_ART.store(true, Ordering::Relaxed);
+            });
+        }
+        StateUpdateKind::AlbumArtLoaded(generation, art) => {
+            song_info.show_album_art(generation, art);
+            // The cover's been cached now, so media controls
+            // can show it too.
+            if let Some(mpris) = &mpris {
+                mpris.update(conn).context("Couldn't update media controls")?;
+            }
+        }
+        StateUpdateKind::TagIndexUpdated => {
+            // Once it's there, the index stays current by itself.
+            if tag_index.is_none() {
+                *tag_index = TagIndex::open(&options)
+                    .map_err(|e| eprintln!("Couldn't open tag index: {}", e))
+                    .ok();
+            }
+        }
+        StateUpdateKind::QueryLoadMoreRequest => {
+            if let Some(results) = search_results.as_mut().filter(|results| results.more) {
+                let start = results.fetched;
+                let page_size = search_page_size(&config.borrow());
+                let page = search_page(conn, tag_index.as_ref(), &results.request, (start, start + page_size))
+                    .context("Couldn't fetch more results")?;
+                results.fetched += page.len() as u32;
+                results.more = page.len() == page_size as usize;
+                // Near misses from the first page may turn up
+                // again, and shouldn't be listed twice.
+                let seen: std::collections::HashSet<String> =
+                    results.songs.iter().map(|song| song.file.clone()).collect();
+                results
+                    .songs
+                    .extend(page.into_iter().filter(|song| !seen.contains(&song.file)));
+                query_info.show_results(results);
+            }
+        }
+        StateUpdateKind::BrowseTagRequest(tag) => {
+            if let Some(page) = browse_info.tag_page(&tag) {
+                let counts = conn.count_grouped(&tag).context("Couldn't count songs")?;
+                page.set_values(&counts);
+            }
+        }
+        StateUpdateKind::BrowseTagSongsRequest(tag, value) => {
+            if let Some(page) = browse_info.tag_page(&tag) {
+                let mut query = mpd::Query::new();
+                query.and(mpd::Term::Tag(tag.as_str().into()), value.as_str());
+                let mut songs = conn.find(&query, (0, 65535)).context("Couldn't find songs")?;
+                songs.sort_by(|a, b| album_order(a).cmp(&album_order(b)));
+                page.set_songs(&value, &songs);
+            }
+        }
+        StateUpdateKind::BrowseDatesRequest => {
+            let counts = conn.count_grouped("Date").context("Couldn't count songs")?;
+            browse_info.dates.set_dates(&counts);
+        }
+        StateUpdateKind::BrowseYearSongsRequest(year) => {
+            let mut songs = songs_in_years(conn, year, year).context("Couldn't find songs")?;
+            songs.sort_by(|a, b| album_order(a).cmp(&album_order(b)));
+            browse_info.dates.set_songs(year, &songs);
+        }
+        StateUpdateKind::BrowseSmartPlaylistsRequest => {
+            browse_info
+                .smart_playlists
+                .set_playlists(&SmartPlaylist::load_all(&config.borrow()));
+        }
+        StateUpdateKind::BrowsePlaylistsRequest => {
+            let playlists = conn.playlists().context("Couldn't list playlists")?;
+            browse_info.playlists.set_playlists(playlists);
+        }
+        StateUpdateKind::BrowseAlbumsRequest => {
+            let mut albums = conn
+                .list(&mpd::Term::Tag("Album".into()), &mpd::Query::new())
+                .context("Couldn't list albums")?;
+            albums.retain(|album| !album.is_empty());
+            albums.sort_by_key(|album| album.to_lowercase());
+            browse_info.albums.set_albums(&albums);
+        }
+        StateUpdateKind::ThumbnailsWanted => {
+            browse_info.albums.load_visible();
+        }
+        StateUpdateKind::ThumbnailLoaded(album, art) => {
+            browse_info.albums.show_thumbnail(album, art);
+        }
+        StateUpdateKind::CollageLoaded(name, art) => {
+            browse_info.playlists.show_collage(&name, art);
+        }
+        StateUpdateKind::QueuePlaylistRequest(name) => {
+            conn.load(&name, ..).context("Couldn't queue playlist")?;
+            toast.show(&format!("Queued “{}”", name));
+        }
+        StateUpdateKind::SmartPlaylistEditRequest(name) => {
+            let existing = name.and_then(|name| SmartPlaylist::load(&config.borrow(), &name));
+            if let Some(playlist) = smart_playlist_dialog(&window, existing.as_ref()).await {
+                // Renaming means dropping the old one.
+                if let Some(existing) = existing {
+                    existing.remove(&mut config.borrow_mut());
+                }
+                playlist.save(&mut config.borrow_mut());
+            }
+            browse_info
+                .smart_playlists
+                .set_playlists(&SmartPlaylist::load_all(&config.borrow()));
+        }
+        StateUpdateKind::MacroEditRequest(name) => {
+            let existing = name.and_then(|name| Macro::load(&config.borrow(), &name));
+            match macro_dialog(&window, existing.as_ref()).await {
+                Some(MacroEdit::Save(macro_)) => {
+                    // Renaming means dropping the old one.
+                    if let Some(existing) = existing {
+                        existing.remove(&mut config.borrow_mut());
+                    }
+                    macro_.save(&mut config.borrow_mut());
+                }
+                Some(MacroEdit::Delete) => {
+                    if let Some(existing) = existing {
+                        existing.remove(&mut config.borrow_mut());
+                    }
+                }
+                None => {}
+            }
+            fill_macro_menu(&macro_menu, &sender, &Macro::load_all(&config.borrow()));
+        }
+        StateUpdateKind::ServerSaveRequest => {
+            let existing = ServerProfile::names(&config.borrow());
+            if let Some(name) = server_name_dialog(&window, &existing).await {
+                ServerProfile::save_current(&mut config.borrow_mut(), &name);
+                fill_server_menu(&server_menu, &sender, &config.borrow());
+                toast.show(&format!("Saved “{}”", name));
+            }
+        }
+        StateUpdateKind::ServerSwitchRequest(name) => {
+            if !ServerProfile::switch_to(&mut config.borrow_mut(), &name) {
+                toast.show(&format!("There's no server called “{}”", name));
+                return Ok(());
+            }
+            fill_server_menu(&server_menu, &sender, &config.borrow());
+            // Switching is just changing the preferences, as
+            // far as the connections are concerned.
+            let mut sender = sender.clone();
+            sender
+                .try_send(StateUpdateKind::ServerChange)
+                .expect("Couldn't notify thread");
+        }
+        StateUpdateKind::ServerForgetRequest(name) => {
+            ServerProfile::forget(&mut config.borrow_mut(), &name);
+            fill_server_menu(&server_menu, &sender, &config.borrow());
+        }
+        StateUpdateKind::MacroRunRequest(name) => {
+            let macro_ = Macro::load(&config.borrow(), &name);
+            let clears_queue = macro_.as_ref().map_or(false, Macro::clears_queue);
+            if clears_queue && conn.status().context("Couldn't get status")?.queue_len > 0 {
+                let message = format!("“{}” clears the queue. Run it anyway?", name);
+                if !confirm(&window, &config, Confirmation::ClearQueue, &message, "Run").await {
+                    return Ok(());
+                }
+            }
+            match macro_.map(|macro_| macro_.run(conn, &config.borrow())) {
+                Some(Ok(())) => toast.show(&format!("Ran “{}”", name)),
+                Some(Err(e)) => toast.show(&format!("Couldn't finish “{}”: {}", name, e)),
+                None => toast.show(&format!("There's no macro called “{}”", name)),
+            }
+        }
+        StateUpdateKind::SmartPlaylistDeleteRequest(name) => {
+            let message = format!("Delete the smart playlist “{}”?", name);
+            if !confirm(&window, &config, Confirmation::DeleteSmartPlaylist, &message, "Delete").await {
+                return Ok(());
+            }
+            if let Some(playlist) = SmartPlaylist::load(&config.borrow(), &name) {
+                playlist.remove(&mut config.borrow_mut());
+            }
+            browse_info
+                .smart_playlists
+                .set_playlists(&SmartPlaylist::load_all(&config.borrow()));
+        }
+        StateUpdateKind::PlaylistDeleteRequest(name) => {
+            let message = format!("Delete the playlist “{}”?", name);
+            if !confirm(&window, &config, Confirmation::DeletePlaylist, &message, "Delete").await {
+                return Ok(());
+            }
+            conn.pl_remove(&name).context("Couldn't delete playlist")?;
+            toast.show(&format!("Deleted “{}”", name));
+            let mut sender = sender.clone();
+            sender
+                .try_send(StateUpdateKind::BrowsePlaylistsRequest)
+                .expect("Couldn't notify thread");
+        }
+        StateUpdateKind::BrowseDirectoryRequest(path) => {
+            let entries = conn.lsdir(&path).context("Couldn't list directory")?;
+            browse_info.folders.set_entries(&path, &entries);
+        }
+        StateUpdateKind::BrowseRecentRequest(days) => {
+            // MPD takes a UNIX timestamp for `modified-since`.
+            let since = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap_or_default()
+                .saturating_sub(Duration::from_secs(days * 24 * 60 * 60));
+            let mut query = mpd::Query::new();
+            query.and(mpd::Term::LastMod, since.as_secs().to_string());
+            let mut songs = conn.find(&query, (0, 65535)).context("Couldn't find songs")?;
+            songs.sort_by(|a, b| b.last_mod.cmp(&a.last_mod));
+            browse_info.recent.songs.set_songs(&songs);
+        }
+        StateUpdateKind::BrowseMediaRequest => {
+            // Without a storage plugin these commands just fail,
+            // which is the same as having nothing to show.
+            let mounts = conn.mounts().unwrap_or_default();
+            let neighbors = conn.neighbors().unwrap_or_default();
+            browse_info.media.set_sources(&mounts, &neighbors);
+        }
+        StateUpdateKind::MediaMountRequest(name, storage) => {
+            // Mounting a drive that's gone missing or a CD that
+            // won't read is common enough to not be fatal.
+            match conn.mount(&name, &storage) {
+                Ok(()) => toast.show(&format!("Mounted “{}”", name)),
+                Err(e) => toast.show(&format!("Couldn't mount “{}”: {}", name, e)),
+            }
+            let mut sender = sender.clone();
+            sender
+                .try_send(StateUpdateKind::BrowseMediaRequest)
+                .expect("Couldn't notify thread");
+        }
+        StateUpdateKind::MediaUnmountRequest(name) => {
+            match conn.unmount(&name) {
+                Ok(()) => toast.show(&format!("Unmounted “{}”", name)),
+                Err(e) => toast.show(&format!("Couldn't unmount “{}”: {}", name, e)),
+            }
+            let mut sender = sender.clone();
+            sender
+                .try_send(StateUpdateKind::BrowseMediaRequest)
+                .expect("Couldn't notify thread");
+        }
+        StateUpdateKind::OpenRequest(uris) => {
+            // Files are only in the database under the music
+            // directory.
+            let music_directory = music_directory(&config.borrow(), conn);
+            let mut added = 0;
+            for uri in &uris {
+                match database_uri(uri, music_directory.as_deref()) {
+                    Some(path) => {
+                        conn.add(&path).context("Couldn't queue file")?;
+                        added += 1;
+                    }
+                    // MPD plays any file at all for those
+                    // on the same computer, who come in
+                    // through its socket.
+                    None if options.server().is_socket() && uri.starts_with("file:") => {
+                        match gio::File::for_uri(uri).path() {
+                            Some(path) => {
+                                conn.add(&path.to_string_lossy()).context("Couldn't queue file")?;
+                                added += 1;
+                            }
+                            None => toast.show_error(&format!("Couldn't find {}", uri)),
+                        }
+                    }
+                    // Without knowing where the music is, no
+                    // file can be found in it.
+                    None if music_directory.is_none() && uri.starts_with("file:") => {
+                        toast.show_error("Set the Music Folder in the preferences to add files");
+                        break;
+                    }
+                    None => toast.show_error(&format!(
+                        "{} isn't in the music library",
+                        gio::File::for_uri(uri).parse_name()
+                    )),
+                }
+            }
+            if added > 0 {
+                toast.show(&match added {
+                    1 => "Added to the queue".to_string(),
+                    _ => format!("Added {} to the queue", added),
+                });
+            }
+        }
+        StateUpdateKind::QueueAddDirectoryRequest(path) => {
+            conn.add(&path).context("Couldn't queue directory")?;
+        }
+        StateUpdateKind::QueueAddTagRequest(tag, value) => {
+            let mut query = mpd::Query::new();
+            query.and(mpd::Term::Tag(tag.into()), value);
+            conn.findadd(&query).context("Couldn't queue songs")?;
+        }
+        StateUpdateKind::QueueRandomAlbumRequest => {
+            let albums: Vec<String> = conn
+                .list(&mpd::Term::Tag("Album".into()), &mpd::Query::new())
+                .context("Couldn't list albums")?
+                .into_iter()
+                .filter(|album| !album.is_empty())
+                .collect();
+            if albums.is_empty() {
+                toast.show("There aren't any albums to pick from");
+                return Ok(());
+            }
+            let album = &albums[glib::random_int_range(0, albums.len() as i32) as usize];
+
+            // Plenty of albums share a title ("Greatest Hits",
+            // anyone?), so pick one of the album artists too.
+            let mut query = mpd::Query::new();
+            query.and(mpd::Term::Tag("Album".into()), album.as_str());
+            let songs = conn.find(&query, (0, 65535)).context("Couldn't find songs")?;
+            // The album list can be out of date, or list a
+            // value `find` doesn't match exactly.
+            if songs.is_empty() {
+                toast.show(&format!("Couldn't find anything to play on “{}”", album));
+                return Ok(());
+            }
+            let song = &songs[glib::random_int_range(0, songs.len() as i32) as usize];
+            let album_artist = album_artist(song).unwrap_or_default().to_string();
+
+            for song in album_songs(conn, album, &album_artist).context("Couldn't find songs")? {
+                conn.push(song).context("Couldn't queue song")?;
+            }
+            toast.show(&format!("Queued “{}”", album));
+        }
+        StateUpdateKind::QueueTimedRequest => {
+            let (target, rules) = match timed_queue_dialog(&window).await {
+                Some(x) => x,
+                None => return Ok(()),
+            };
+            let rules = SmartPlaylist { name: String::new(), rules };
+            let mut songs = rules.songs(conn).context("Couldn't find songs")?;
+            shuffle(&mut songs);
+            let songs = fill_duration(songs, target);
+            if songs.is_empty() {
+                toast.show("No songs fit");
+                return Ok(());
+            }
+            let total = songs.iter().filter_map(|song| song.duration).sum();
+            for song in &songs {
+                conn.push(song).context("Couldn't queue song")?;
+            }
+            toast.show(&format!("Queued {} songs ({})", songs.len(), format_duration(total)));
+        }
+        StateUpdateKind::QueueAddAlbumRequest(album, album_artist) => {
+            for song in album_songs(conn, &album, &album_artist).context("Couldn't find songs")? {
+                conn.push(song).context("Couldn't queue song")?;
+            }
+        }
+        StateUpdateKind::QueueShuffledArtistRequest(artist) => {
+            queue_shuffled(conn, |conn| {
+                let mut query = mpd::Query::new();
+                query.and(mpd::Term::Tag("Artist".into()), artist.as_str());
+                Ok(conn.findadd(&query)?)
+            })
+            .context("Couldn't queue songs")?;
+            toast.show(&format!("Shuffled in songs by {}", artist));
+        }
+        StateUpdateKind::QueueShuffledAlbumRequest(album, album_artist) => {
+            queue_shuffled(conn, |conn| {
+                for song in album_songs(conn, &album, &album_artist)? {
+                    conn.push(song)?;
+                }
+                Ok(())
+            })
+            .context("Couldn't queue songs")?;
+            toast.show(&format!("Shuffled in “{}”", album));
+        }
+        StateUpdateKind::QueueAddYearsRequest(first, last) => {
+            let mut songs = songs_in_years(conn, first, last).context("Couldn't find songs")?;
+            // Oldest first, keeping albums together.
+            songs.sort_by(|a, b| {
+                let year = |song: &mpd::song::Song| song.tags.get("Date").and_then(|x| year_of(x));
+                (year(a), album_order(a)).cmp(&(year(b), album_order(b)))
+            });
+            for song in &songs {
+                conn.push(song).context("Couldn't queue song")?;
+            }
+        }
+        StateUpdateKind::QueueSmartPlaylistRequest(name) => {
+            let playlist = SmartPlaylist::load(&config.borrow(), &name);
+            if let Some(playlist) = playlist {
+                let mut songs = playlist.songs(conn).context("Couldn't find songs")?;
+                songs.sort_by(|a, b| album_order(a).cmp(&album_order(b)));
+                for song in &songs {
+                    conn.push(song).context("Couldn't queue song")?;
+                }
+                toast.show(&format!("Queued {} songs from “{}”", songs.len(), name));
+            }
+        }
+        StateUpdateKind::QueueDeleteRequest(index) => {
+            let current = conn
+                .currentsong()
+                .context("Couldn't get current song")?
+                .and_then(|song| song.place);
+            if current.map(|place| place.pos) == Some(index)
+                && !confirm(
+                    &window,
+                    &config,
+                    Confirmation::RemoveCurrentSong,
+                    "Remove the song that's playing from the queue?",
+                    "Remove",
+                )
+                .await
+            {
+                return Ok(());
+            }
+            conn.delete(index).context("Couldn't dequeue song")?;
+        }
+        StateUpdateKind::QueueAddRequest(filename) => {
+            if !enqueue(conn, last_added, &filename, false)
+                .context("Couldn't queue song")?
+            {
+                toast.show_with_action(
+                    "That song was just added",
+                    "Add Again",
+                    clone!(@strong sender => move || {
+                        let mut sender = sender.clone();
+                        sender
+                            .try_send(StateUpdateKind::QueueForceAddRequest(filename.clone()))
+                            .expect("Couldn't notify thread");
+                        sender
+                            .try_send(StateUpdateKind::MpdEvent)
+                            .expect("Couldn't notify thread");
+                    }),
+                );
+            }
+        }
+        StateUpdateKind::QueueForceAddRequest(filename) => {
+            enqueue(conn, last_added, &filename, true)
+                .context("Couldn't queue song")?;
+        }
+        StateUpdateKind::PlaylistSaveRequest(filenames) => {
+            let existing: Vec<String> = conn
+                .playlists()
+                .context("Couldn't list playlists")?
+                .into_iter()
+                .map(|playlist| playlist.name)
+                .collect();
+            if let Some(name) = playlist_name_dialog(&window, &existing).await {
+                if existing.contains(&name) {
+                    let message = format!("“{}” already exists. Add these songs to the end of it?", name);
+                    if !confirm(&window, &config, Confirmation::OverwritePlaylist, &message, "Save").await {
+                        return Ok(());
+                    }
+                }
+                // `playlistadd` creates the playlist if it doesn't
+                // exist yet, so this covers both saving a new one
+                // and appending to an old one.
+                conn.pl_push_many(&name, &filenames)
+                    .context("Couldn't save playlist")?;
+                toast.show(&format!("Saved {} songs to “{}”", filenames.len(), name));
+            }
+        }
+        StateUpdateKind::QueueTopResultRequest(play_next) => {
+            if let Some(filename) = query_info.results.filenames().into_iter().next() {
+                if play_next {
+                    queue_next(conn, filename).context("Couldn't queue song")?;
+                    toast.show("Playing next");
+                } else if enqueue(conn, last_added, &filename, false)
+                    .context("Couldn't queue song")?
+                {
+                    toast.show("Added to the queue");
+                }
+            }
+        }
+        StateUpdateKind::QueuePlayNextRequest(filename) => {
+            queue_next(conn, filename).context("Couldn't queue song")?;
+            toast.show("Playing next");
+        }
+        StateUpdateKind::QueuePlayNowRequest(filename) => {
+            let id = queue_next(conn, filename).context("Couldn't queue song")?;
+            conn.switch(id).context("Couldn't play song")?;
+        }
+        StateUpdateKind::QueueJumpRequest(index) => {
+            conn.switch(index).context("Couldn't play song")?;
+        }
+        StateUpdateKind::QueueMoveNextRequest(index) => {
+            if let Some(current) = conn.status().context("Couldn't get status")?.song {
+                // Taking a song out from before the current one
+                // shifts everything after it up by one.
+                let to = match index.cmp(&current.pos) {
+                    std::cmp::Ordering::Less => Some(current.pos),
+                    std::cmp::Ordering::Equal => None,
+                    std::cmp::Ordering::Greater => Some(current.pos + 1),
+                };
+                if let Some(to) = to {
+                    conn.shift(index, to as usize).context("Couldn't move song")?;
+                }
+            }
+        }
+        StateUpdateKind::ShowArtistRequest(artist) => {
+            stack.set_visible_child_name("query_songs");
+            leaflet.set_visible_child_name("library");
+            query_info.show_artist(&artist);
+        }
+        StateUpdateKind::SongDetailsRequest(filename) => {
+            let mut query = mpd::Query::new();
+            query.and(mpd::Term::File, filename.as_str());
+            let songs = conn.find(&query, (0, 1)).context("Couldn't find song")?;
+            if let Some(song) = songs.first() {
+                let labels = song_labels(conn, &song.file);
+                show_song_details(&window, &sender, song, &labels);
+            }
+        }
+        StateUpdateKind::OpenFolderRequest(filename) => {
+            let path = match music_directory(&config.borrow(), conn) {
+                Some(directory) => directory.join(&filename),
+                None => {
+                    toast.show_error("Set the Music Folder in the preferences to open folders");
+                    return Ok(());
+                }
+            };
+            let folder = path.parent().map(gio::File::for_path);
+            match folder.filter(|folder| folder.query_exists(gio::Cancellable::NONE)) {
+                Some(folder) => {
+                    gtk::show_uri_on_window(Some(&window), &folder.uri(), gtk::current_event_time())
+                        .context("Couldn't open folder")?;
+                }
+                None => toast.show_error(&format!("{} isn't on this computer", path.display())),
+            }
+        }
+        StateUpdateKind::SongLabelsEditRequest(filename) => {
+            let current = song_labels(conn, &filename);
+            let known: Vec<String> = conn
+                .find_sticker("song", "", LABELS_STICKER)
+                .unwrap_or_default()
+                .into_iter()
+                .flat_map(|(_, labels)| parse_labels(&labels))
+                .collect::<std::collections::BTreeSet<_>>()
+                .into_iter()
+                .collect();
+            if let Some(labels) = labels_dialog(&window, &current, &known).await {
+                set_song_labels(conn, &filename, &labels).context("Couldn't save labels")?;
+            }
+        }
+        StateUpdateKind::QueueReplaceWithResultsRequest => {
+            // This goes in one command list, so nobody else sees
+            // an empty queue in between, or gets to add to it.
+            let filenames = query_info.results.filenames();
+            if !filenames.is_empty() {
+                if conn.status().context("Couldn't get status")?.queue_len > 0 {
+                    let message = format!("Replace the whole queue with {} songs?", filenames.len());
+                    if !confirm(&window, &config, Confirmation::ClearQueue, &message, "Replace").await {
+                        return Ok(());
+                    }
+                }
+                conn.replace_queue(&filenames).context("Couldn't replace queue")?;
+                toast.show(&format!("Playing {} songs", filenames.len()));
+            }
+        }
+        StateUpdateKind::SelectionRequest(action) => {
+            let filenames = query_info.results.selected_filenames();
+            if filenames.is_empty() {
+                return Ok(());
+            }
+            // Each of these goes over as one command list, rather
+            // than a round trip per song.
+            match action {
+                SelectionAction::Add => {
+                    conn.push_many(&filenames).context("Couldn't queue songs")?;
+                    toast.show(&format!("Added {} songs to the queue", filenames.len()));
+                }
+                SelectionAction::PlayNext => {
+                    let status = conn.status().context("Couldn't get status")?;
+                    let pos = status.song.map_or(status.queue_len, |x| x.pos + 1);
+                    conn.insert_many(&filenames, pos as usize).context("Couldn't queue songs")?;
+                    toast.show(&format!("Playing {} songs next", filenames.len()));
+                }
+                SelectionAction::Rate(rating) => {
+                    conn.set_sticker_many("song", &filenames, RATING_STICKER, &rating.to_string())
+                        .context("Couldn't rate songs")?;
+                    toast.show(&format!("Rated {} songs", filenames.len()));
+                }
+            }
+            query_info.finish_selection();
+        }
+        StateUpdateKind::AboutRequest => {
+            let info = debug_info(conn, &options, &config.borrow(), form_factor.get());
+            show_about(&window, &info);
+        }
+        StateUpdateKind::PlaybackStateChange(action) => {
+            dispatch_playback_state_change(conn, &config.borrow(), action)
+                .context("Couldn't queue action")?;
+        }
+        StateUpdateKind::MiniPlayerChange => {
+            let mini = mini_player.wanted();
+            if mini_player.shown.replace(mini) != mini {
+                song_info.set_mini(mini);
+                separator.set_visible(!mini);
+                stack.set_visible(!mini);
+                leaflet.set_visible_child_name("current_song");
+                update_navigation(&leaflet);
+                song_info
+                    .update_album_art(conn)
+                    .context("Couldn't update album art")?;
+            }
+            // Asking for the mini player shrinks the window to
+            // fit, and it grows back when it's turned off.
+            if mini_player.requested.get() {
+                if mini_player.full_size.get().is_none() {
+                    mini_player.full_size.set(Some(window.size()));
+                    window.resize(1, 1);
+                }
+            } else if let Some((width, height)) = mini_player.full_size.take() {
+                window.resize(width, height);
+            }
+        }
+        StateUpdateKind::FocusSearchRequest => {
+            stack.set_visible_child_name("query_songs");
+            leaflet.set_visible_child_name("library");
+            query_info.focus_search();
+        }
+        StateUpdateKind::PreferencesRequest => {
+            show_preferences(&window, &config, &sender);
+        }
+        StateUpdateKind::ShortcutsRequest => {
+            show_shortcuts(&window, &config.borrow());
+        }
+        StateUpdateKind::QueueSaveRequest => {
+            let filenames: Vec<String> = conn
+                .queue()
+                .context("Couldn't get queue")?
+                .into_iter()
+                .map(|song| song.file)
+                .collect();
+            if !filenames.is_empty() {
+                let mut sender = sender.clone();
+                sender
+                    .try_send(StateUpdateKind::PlaylistSaveRequest(filenames))
+                    .expect("Couldn't notify thread");
+            }
+        }
+        StateUpdateKind::ConnectionChange(state) => {
+            // The idle thread getting through means ours can
+            // too, if it needs to, or if it's still to the
+            // server we had before the preferences changed.
+            let stale = *conn_server_changes != options.server_changes();
+            if state == ConnectionState::Connected && (stale || conn.ping().is_err()) {
+                *conn_server_changes = options.server_changes();
+                *conn = connect_in_background(options).await.context("Couldn't reconnect")?;
+            }
+            connection.set_state(state);
+            if state == ConnectionState::Connected && !offline_adds.is_empty() {
+                let adds = std::mem::take(offline_adds);
+                if confirm_offline_adds(&window, adds.len()).await {
+                    pending.extend(adds);
+                    pending.push_back(StateUpdateKind::MpdEvent);
+                }
+            }
+        }
+        StateUpdateKind::ServerChange => {
+            // A server from the command line, to follow or
+            // otherwise, trumps the one in the preferences.
+            let server = Server::from_config(&config.borrow());
+            if options.pinned_server || options.server() == server {
+                return Ok(());
+            }
+            options.set_server(server);
+            // Everything we know is about the old server.
+            *tag_index = None;
+            *fuzzy_index.lock().unwrap() = None;
+            *search_results = None;
+            browse_info.albums.forget_thumbnails();
+            fill_server_menu(&server_menu, &sender, &config.borrow());
+            connection.set_state(ConnectionState::Connecting);
+            *conn_server_changes = options.server_changes();
+            match connect_in_background(options).await {
+                Ok(new_conn) => *conn = new_conn,
+                Err(e) => {
+                    // The idle thread keeps trying, and lets
+                    // us know when it gets through.
+                    connection.set_state(ConnectionState::Offline);
+                    return Err(e.context(format!("Couldn't connect to {}", options.host())));
+                }
+            }
+            connection.set_state(ConnectionState::Connected);
+            toast.show(&format!("Connected to {}", options.host()));
+            let mut sender = sender.clone();
+            sender
+                .try_send(StateUpdateKind::MpdEvent)
+                .expect("Couldn't notify thread");
+        }
+        StateUpdateKind::ReconnectRequest => {
+            connection.set_state(ConnectionState::Connecting);
+            *conn = connect_in_background(options).await.context("Couldn't reconnect")?;
+            connection.set_state(ConnectionState::Connected);
+            let mut sender = sender.clone();
+            sender
+                .try_send(StateUpdateKind::MpdEvent)
+                .expect("Couldn't notify thread");
+        }
+        StateUpdateKind::CleanupRequest => {
+            let message = "Cached covers and collages, and ratings, labels and play times, for music that's no \
+                           longer in the library will be removed.";
+            if !confirm(&window, &config, Confirmation::CleanUp, message, "Clean Up").await {
+                return Ok(());
+            }
+            toast.show("Cleaning up…");
+            let report = clean_up_in_background(options).await?;
+            toast.show(&report.summary());
+        }
+        StateUpdateKind::DatabaseUpdateRequest => {
+            conn.update().context("Couldn't update database")?;
+            toast.show("Updating the database");
+        }
+    }
+    Ok(())
+}
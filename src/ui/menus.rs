@@ -0,0 +1,104 @@
+// Copyright © 2021-2022 Jakob L. Kreuze <zerodaysfordays@sdf.org>
+//
+// This file is part of Tunes.
+//
+// Tunes is free software; you can redistribute it and/or modify it
+// under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation; either version 3 of the
+// License, or (at your option) any later version.
+//
+// Tunes is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY
+// or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Affero General
+// Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public
+// License along with Tunes. If not, see <http://www.gnu.org/licenses/>.
+
+use futures::channel::mpsc;
+use glib::clone;
+use gtk::glib;
+use gtk::prelude::*;
+use tunes_core::{Config, Macro, ServerProfile, StateUpdateKind};
+
+/// Fill `menu` with a button to run each of `macros`, and another beside it
+/// to edit it.
+pub(crate) fn fill_macro_menu(menu: &gtk::Box, sender: &mpsc::Sender<StateUpdateKind>, macros: &[Macro]) {
+    for child in menu.children() {
+        menu.remove(&child);
+    }
+    for macro_ in macros {
+        let row = gtk::Box::new(gtk::Orientation::Horizontal, 0);
+        let run = gtk::ModelButton::builder()
+            .text(&macro_.name)
+            .hexpand(true)
+            .build();
+        let name = macro_.name.clone();
+        run.connect_clicked(clone!(@strong sender => move |_| {
+            let mut sender = sender.clone();
+            sender
+                .try_send(StateUpdateKind::MacroRunRequest(name.clone()))
+                .expect("Couldn't notify thread");
+            sender
+                .try_send(StateUpdateKind::MpdEvent)
+                .expect("Couldn't notify thread");
+        }));
+        let edit = gtk::Button::from_icon_name(Some("document-edit-symbolic"), gtk::IconSize::Menu);
+        edit.set_relief(gtk::ReliefStyle::None);
+        edit.set_tooltip_text(Some("Edit Macro"));
+        let name = macro_.name.clone();
+        edit.connect_clicked(clone!(@strong sender => move |edit| {
+            // Only model buttons close the menu by themselves.
+            if let Some(popover) = edit
+                .ancestor(gtk::Popover::static_type())
+                .and_then(|x| x.downcast::<gtk::Popover>().ok())
+            {
+                popover.popdown();
+            }
+            let mut sender = sender.clone();
+            sender
+                .try_send(StateUpdateKind::MacroEditRequest(Some(name.clone())))
+                .expect("Couldn't notify thread");
+        }));
+        row.add(&run);
+        row.add(&edit);
+        menu.add(&row);
+    }
+    menu.show_all();
+}
+
+/// Fill the menu's section of saved servers, with the one in use ticked.
+pub(crate) fn fill_server_menu(menu: &gtk::Box, sender: &mpsc::Sender<StateUpdateKind>, config: &Config) {
+    for child in menu.children() {
+        menu.remove(&child);
+    }
+    let current = ServerProfile::current(config);
+    for name in ServerProfile::names(config) {
+        let row = gtk::Box::new(gtk::Orientation::Horizontal, 0);
+        let switch = gtk::ModelButton::builder()
+            .text(&name)
+            .role(gtk::ButtonRole::Radio)
+            .active(current.as_ref() == Some(&name))
+            .hexpand(true)
+            .build();
+        switch.connect_clicked(clone!(@strong sender, @strong name => move |_| {
+            let mut sender = sender.clone();
+            sender
+                .try_send(StateUpdateKind::ServerSwitchRequest(name.clone()))
+                .expect("Couldn't notify thread");
+        }));
+        let forget = gtk::Button::from_icon_name(Some("edit-delete-symbolic"), gtk::IconSize::Menu);
+        forget.set_relief(gtk::ReliefStyle::None);
+        forget.set_tooltip_text(Some("Forget Server"));
+        forget.connect_clicked(clone!(@strong sender => move |_| {
+            let mut sender = sender.clone();
+            sender
+                .try_send(StateUpdateKind::ServerForgetRequest(name.clone()))
+                .expect("Couldn't notify thread");
+        }));
+        row.add(&switch);
+        row.add(&forget);
+        menu.add(&row);
+    }
+    menu.show_all();
+}
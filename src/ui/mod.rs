@@ -0,0 +1,57 @@
+// Copyright © 2021-2022 Jakob L. Kreuze <zerodaysfordays@sdf.org>
+//
+// This file is part of Tunes.
+//
+// Tunes is free software; you can redistribute it and/or modify it
+// under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation; either version 3 of the
+// License, or (at your option) any later version.
+//
+// Tunes is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY
+// or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Affero General
+// Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public
+// License along with Tunes. If not, see <http://www.gnu.org/licenses/>.
+
+// The widgets: the now playing page and queue, search, browsing, song lists,
+// dialogs, preferences and the bits of chrome around them, and the main
+// loop's handling of the events they send.
+
+mod browse;
+mod dialogs;
+mod dispatch;
+mod menus;
+mod preferences;
+mod query;
+mod shortcuts;
+mod song_info;
+mod song_list;
+mod theme;
+mod tray;
+mod whats_new;
+mod widgets;
+
+pub(crate) use browse::{BrowseInfo, PlaylistBrowser};
+pub(crate) use dialogs::{
+    confirm, confirm_offline_adds, debug_info, labels_dialog, macro_dialog, password_dialog, playlist_name_dialog,
+    save_offline_add, server_name_dialog, show_about, show_art_overlay, show_song_details, smart_playlist_dialog,
+    timed_queue_dialog,
+};
+pub(crate) use dispatch::{handle_event, Session, Ui};
+pub(crate) use menus::{fill_macro_menu, fill_server_menu};
+pub(crate) use preferences::show_preferences;
+pub(crate) use query::{QueryInfo, ResultView};
+pub(crate) use shortcuts::show_shortcuts;
+pub(crate) use song_info::{apply_display_profile, update_ticker, SongInfo};
+pub(crate) use song_list::{
+    album_header, attach_song_menu, disc_header, song_row, ColumnHeadings, GenreColors, ListColumns, SongList,
+    SongListKind,
+};
+pub(crate) use theme::{apply_theme, load_user_stylesheet, STYLESHEET, THEMES};
+pub(crate) use tray::{TrayIcon, TRAY_SECTION};
+pub(crate) use whats_new::{show_page_hint, show_whats_new};
+pub(crate) use widgets::{
+    header_title, toggle_label, ConnectionIndicator, ElapsedTicker, EmptyState, HeaderTitle, Toast,
+};
@@ -0,0 +1,516 @@
+// Copyright © 2021-2022 Jakob L. Kreuze <zerodaysfordays@sdf.org>
+//
+// This file is part of Tunes.
+//
+// Tunes is free software; you can redistribute it and/or modify it
+// under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation; either version 3 of the
+// License, or (at your option) any later version.
+//
+// Tunes is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY
+// or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Affero General
+// Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public
+// License along with Tunes. If not, see <http://www.gnu.org/licenses/>.
+
+use futures::channel::mpsc;
+use glib::clone;
+use gtk::glib;
+use gtk::prelude::*;
+use libhandy::ApplicationWindow;
+use libhandy::prelude::*;
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+use std::sync::atomic::Ordering;
+use tunes_core::{
+    Config, Confirmation, DisplayProfile, FormFactor, StateUpdateKind, CONNECTION_SECTION, GENERAL_SECTION,
+};
+
+use crate::backend::{
+    lookup_password, request_background, save_password, PauseBehavior, Server, ONLINE_ART, ONLINE_ART_KEY,
+    ONLINE_ART_SECTION,
+};
+use crate::models::{Shortcut, SEARCH_PAGE_SIZES};
+use crate::ui::{apply_theme, ListColumns, THEMES, TRAY_SECTION};
+
+/// Build a preferences row with a drop-down offering `options`, given as pairs
+/// of config value and label. `on_change` is called with the config value of
+/// whatever the user picks.
+fn combo_row(
+    title: &str,
+    options: &[(&str, &str)],
+    active: Option<&str>,
+    on_change: impl Fn(&str) + 'static,
+) -> libhandy::ActionRow {
+    let combo = gtk::ComboBoxText::new();
+    combo.set_valign(gtk::Align::Center);
+    for &(id, label) in options {
+        combo.append(Some(id), label);
+    }
+    combo.set_active_id(active.or_else(|| options.first().map(|x| x.0)));
+    combo.connect_changed(move |combo| {
+        if let Some(id) = combo.active_id() {
+            on_change(&id);
+        }
+    });
+
+    let row = libhandy::ActionRow::builder().title(title).build();
+    row.add(&combo);
+    row
+}
+
+/// A preferences row with a switch, calling back whenever it's flipped.
+fn switch_row(
+    title: &str,
+    subtitle: Option<&str>,
+    active: bool,
+    on_change: impl Fn(bool) + 'static,
+) -> libhandy::ActionRow {
+    let switch = gtk::Switch::builder()
+        .valign(gtk::Align::Center)
+        .active(active)
+        .build();
+    switch.connect_active_notify(move |switch| on_change(switch.is_active()));
+
+    let row = libhandy::ActionRow::builder()
+        .title(title)
+        .activatable_widget(&switch)
+        .build();
+    row.set_subtitle(subtitle);
+    row.add(&switch);
+    row
+}
+
+/// A preferences row with a text entry, calling back whenever it's edited.
+/// Passwords are given with `visible` false, so they're dotted out.
+fn entry_row(title: &str, text: &str, visible: bool, on_change: impl Fn(&str) + 'static) -> libhandy::ActionRow {
+    let entry = gtk::Entry::builder()
+        .valign(gtk::Align::Center)
+        .text(text)
+        .visibility(visible)
+        .build();
+    entry.connect_changed(move |entry| on_change(&entry.text()));
+
+    let row = libhandy::ActionRow::builder()
+        .title(title)
+        .activatable_widget(&entry)
+        .build();
+    row.add(&entry);
+    row
+}
+
+/// Pages the window can open on, as pairs of page name and label, besides
+/// whichever was showing when it was last closed.
+const STARTUP_VIEWS: [(&str, &str); 4] = [
+    ("last", "Where I Left Off"),
+    ("current_song", "Now Playing"),
+    ("query_songs", "Search"),
+    ("browse", "Browse"),
+];
+
+/// Open the preferences window. Changes are saved as soon as they're made.
+pub(crate) fn show_preferences(
+    window: &ApplicationWindow,
+    config: &Rc<RefCell<Config>>,
+    sender: &mpsc::Sender<StateUpdateKind>,
+) {
+    let preferences = libhandy::PreferencesWindow::builder()
+        .transient_for(window)
+        .modal(true)
+        .build();
+
+    let page = libhandy::PreferencesPage::builder()
+        .title("General")
+        .icon_name("emblem-system-symbolic")
+        .build();
+    let group = libhandy::PreferencesGroup::builder()
+        .title("Server")
+        .description(
+            "Tunes connects to a changed server once the preferences are closed. \
+             For a server on this computer, the host can be the path to its socket instead.",
+        )
+        .build();
+    let host = config.borrow().get(CONNECTION_SECTION, "host").unwrap_or("127.0.0.1").to_string();
+    group.add(&entry_row(
+        "Host",
+        &host,
+        true,
+        clone!(@strong config => move |text| {
+            config.borrow_mut().set(CONNECTION_SECTION, "host", text.trim());
+        }),
+    ));
+    let port = gtk::SpinButton::with_range(1.0, 65535.0, 1.0);
+    port.set_valign(gtk::Align::Center);
+    port.set_value(
+        config
+            .borrow()
+            .get(CONNECTION_SECTION, "port")
+            .and_then(|port| port.parse().ok())
+            .unwrap_or(6600.0),
+    );
+    port.connect_value_changed(clone!(@strong config => move |port| {
+        config.borrow_mut().set(CONNECTION_SECTION, "port", port.value_as_int());
+    }));
+    let row = libhandy::ActionRow::builder()
+        .title("Port")
+        .activatable_widget(&port)
+        .build();
+    row.add(&port);
+    group.add(&row);
+    let address = Server::configured_address(&config.borrow());
+    let password = lookup_password(&address)
+        .map_err(|e| eprintln!("Couldn't look up the password for {}: {:#}", address, e))
+        .ok()
+        .flatten()
+        .unwrap_or_default();
+    let password_entry = gtk::Entry::builder()
+        .valign(gtk::Align::Center)
+        .text(&password)
+        .visibility(false)
+        .input_purpose(gtk::InputPurpose::Password)
+        .build();
+    // Saved once it's been typed in, so the keyring isn't asked after every
+    // letter of it.
+    let save = clone!(@strong config, @strong sender => move |entry: &gtk::Entry| {
+        if let Err(e) = save_password(&config, &entry.text()) {
+            let mut sender = sender.clone();
+            sender
+                .try_send(StateUpdateKind::ErrorNotice(format!("{:#}", e)))
+                .expect("Couldn't notify thread");
+        }
+    });
+    password_entry.connect_activate(clone!(@strong save => move |entry| save(entry)));
+    password_entry.connect_focus_out_event(move |entry, _| {
+        save(entry);
+        gtk::Inhibit(false)
+    });
+    let password_row = libhandy::ActionRow::builder()
+        .title("Password")
+        .subtitle("Kept in the keyring")
+        .activatable_widget(&password_entry)
+        .build();
+    password_row.add(&password_entry);
+    group.add(&password_row);
+    // For servers only reachable through another machine. The host and port
+    // are then as that machine sees them.
+    let ssh = config.borrow().get(CONNECTION_SECTION, "ssh").unwrap_or_default().to_string();
+    let ssh_row = entry_row(
+        "SSH Tunnel",
+        &ssh,
+        true,
+        clone!(@strong config => move |text| {
+            config.borrow_mut().set(CONNECTION_SECTION, "ssh", text.trim());
+        }),
+    );
+    ssh_row.set_subtitle(Some("Connect through this machine, as in “me@gateway”, using an SSH key"));
+    group.add(&ssh_row);
+    // Only needed for local files when the server's elsewhere, since a local
+    // server will say where its music is. It's saved along with the server,
+    // since each has its own.
+    let music_directory = config
+        .borrow()
+        .get(CONNECTION_SECTION, "music-directory")
+        .unwrap_or_default()
+        .to_string();
+    group.add(&entry_row(
+        "Music Folder",
+        &music_directory,
+        true,
+        clone!(@strong config => move |text| {
+            config.borrow_mut().set(CONNECTION_SECTION, "music-directory", text.trim());
+        }),
+    ));
+    page.add(&group);
+
+    let group = libhandy::PreferencesGroup::builder()
+        .title("Appearance")
+        .build();
+    let active = config.borrow().get(GENERAL_SECTION, "startup-view").map(String::from);
+    group.add(&combo_row(
+        "Open On",
+        &STARTUP_VIEWS,
+        active.as_deref(),
+        clone!(@strong config => move |value| {
+            config.borrow_mut().set(GENERAL_SECTION, "startup-view", value);
+        }),
+    ));
+    let active = config.borrow().get(GENERAL_SECTION, "theme").map(String::from);
+    group.add(&combo_row(
+        "Theme",
+        &THEMES,
+        active.as_deref(),
+        clone!(@strong config => move |value| {
+            config.borrow_mut().set(GENERAL_SECTION, "theme", value);
+            apply_theme(&config.borrow());
+        }),
+    ));
+    page.add(&group);
+
+    let group = libhandy::PreferencesGroup::builder()
+        .title("Closing the Window")
+        .build();
+    group.add(&switch_row(
+        "Show in System Tray",
+        Some("Closing the window leaves Tunes running in the tray. Takes effect the next time Tunes starts."),
+        config.borrow().get(TRAY_SECTION, "enabled") == Some("true"),
+        clone!(@strong config => move |active| {
+            config.borrow_mut().set(TRAY_SECTION, "enabled", active);
+        }),
+    ));
+    group.add(&switch_row(
+        "Run in Background",
+        Some("Closing the window leaves Tunes running for media controls. Opening Tunes again brings the window back."),
+        config.borrow().get(GENERAL_SECTION, "run-in-background") == Some("true"),
+        clone!(@strong config => move |active| {
+            config.borrow_mut().set(GENERAL_SECTION, "run-in-background", active);
+            if active {
+                request_background();
+            }
+        }),
+    ));
+    page.add(&group);
+
+    let group = libhandy::PreferencesGroup::builder()
+        .title("Search")
+        .build();
+    let active = config.borrow().get("search", "page-size").map(String::from);
+    group.add(&combo_row(
+        "Results at a Time",
+        &SEARCH_PAGE_SIZES,
+        active.as_deref(),
+        clone!(@strong config => move |value| {
+            config.borrow_mut().set("search", "page-size", value);
+        }),
+    ));
+    page.add(&group);
+    preferences.add(&page);
+
+    // Each form factor gets its own page, so that a convertible can look one
+    // way docked and another way in the hand.
+    for (form_factor, icon) in [
+        (FormFactor::Phone, "phone-symbolic"),
+        (FormFactor::Desktop, "computer-symbolic"),
+    ] {
+        let page = libhandy::PreferencesPage::builder()
+            .title(form_factor.title())
+            .icon_name(icon)
+            .build();
+        let group = libhandy::PreferencesGroup::builder()
+            .title("Display")
+            .build();
+
+        let section = form_factor.section();
+        for (title, key, options) in [
+            ("Density", "density", &DisplayProfile::DENSITIES[..]),
+            ("Album Art Size", "art-size", &DisplayProfile::ART_SIZES[..]),
+            ("Layout", "layout", &DisplayProfile::LAYOUTS[..]),
+        ] {
+            let active = config.borrow().get(section, key).map(String::from);
+            let row = combo_row(
+                title,
+                options,
+                active.as_deref(),
+                clone!(@strong config, @strong sender => move |value| {
+                    config.borrow_mut().set(section, key, value);
+                    let mut sender = sender.clone();
+                    sender
+                        .try_send(StateUpdateKind::DisplayProfileChange)
+                        .expect("Couldn't notify thread");
+                }),
+            );
+            group.add(&row);
+        }
+        page.add(&group);
+
+        let group = libhandy::PreferencesGroup::builder()
+            .title("List Columns")
+            .build();
+        for (column, title) in ListColumns::ALL {
+            group.add(&switch_row(
+                title,
+                None,
+                ListColumns::shown_on(&config.borrow(), form_factor, column),
+                clone!(@strong config, @strong sender => move |active| {
+                    config.borrow_mut().set(section, &ListColumns::key(column), active);
+                    let mut sender = sender.clone();
+                    sender
+                        .try_send(StateUpdateKind::DisplayProfileChange)
+                        .expect("Couldn't notify thread");
+                }),
+            ));
+        }
+        page.add(&group);
+
+        let group = libhandy::PreferencesGroup::builder()
+            .title("Search Result Buttons")
+            .description("Everything is also in each result's menu.")
+            .build();
+        for (action, title, _) in ListColumns::ACTIONS {
+            group.add(&switch_row(
+                title,
+                None,
+                ListColumns::action_shown_on(&config.borrow(), form_factor, action),
+                clone!(@strong config, @strong sender => move |active| {
+                    config.borrow_mut().set(section, &ListColumns::action_key(action), active);
+                    let mut sender = sender.clone();
+                    sender
+                        .try_send(StateUpdateKind::DisplayProfileChange)
+                        .expect("Couldn't notify thread");
+                }),
+            ));
+        }
+        page.add(&group);
+
+        preferences.add(&page);
+    }
+
+    let page = libhandy::PreferencesPage::builder()
+        .title("Playback")
+        .icon_name("media-playback-start-symbolic")
+        .build();
+    let group = libhandy::PreferencesGroup::builder()
+        .title("Network Libraries")
+        .build();
+    group.add(&switch_row(
+        "Warm Up Next Track",
+        Some("Read ahead so slow network shares don't interrupt gapless playback"),
+        config.borrow().get("playback", "warm-next-track") == Some("true"),
+        clone!(@strong config => move |active| {
+            config.borrow_mut().set("playback", "warm-next-track", active);
+        }),
+    ));
+    page.add(&group);
+
+    let group = libhandy::PreferencesGroup::builder()
+        .title("Screen")
+        .build();
+    group.add(&switch_row(
+        "Keep Screen On While Playing",
+        Some("Stop the screen blanking and the system suspending, for dashboards and wall-mounted tablets"),
+        config.borrow().get("playback", "inhibit-idle") == Some("true"),
+        clone!(@strong config, @strong sender => move |active| {
+            config.borrow_mut().set("playback", "inhibit-idle", active);
+            // Catching up with MPD takes or lets go of the inhibitor.
+            let mut sender = sender.clone();
+            sender
+                .try_send(StateUpdateKind::MpdEvent)
+                .expect("Couldn't notify thread");
+        }),
+    ));
+    page.add(&group);
+
+    let group = libhandy::PreferencesGroup::builder()
+        .title("Pausing")
+        .build();
+    for (title, stream, options) in [
+        ("Pausing a Stream", true, &PauseBehavior::STREAM_OPTIONS),
+        ("Pausing a File", false, &PauseBehavior::FILE_OPTIONS),
+    ] {
+        let active = config.borrow().get(PauseBehavior::SECTION, PauseBehavior::key(stream)).map(String::from);
+        group.add(&combo_row(
+            title,
+            options,
+            active.as_deref(),
+            clone!(@strong config => move |value| {
+                config.borrow_mut().set(PauseBehavior::SECTION, PauseBehavior::key(stream), value);
+            }),
+        ));
+    }
+    page.add(&group);
+
+    let group = libhandy::PreferencesGroup::builder()
+        .title("Album Art")
+        .build();
+    group.add(&switch_row(
+        "Look Up Missing Covers Online",
+        Some("Ask MusicBrainz and the Cover Art Archive about albums without a cover"),
+        config.borrow().get(ONLINE_ART_SECTION, ONLINE_ART_KEY) == Some("true"),
+        clone!(@strong config => move |active| {
+            config.borrow_mut().set(ONLINE_ART_SECTION, ONLINE_ART_KEY, active);
+            ONLINE_ART.store(active, Ordering::Relaxed);
+        }),
+    ));
+    page.add(&group);
+    preferences.add(&page);
+
+    let page = libhandy::PreferencesPage::builder()
+        .title("Shortcuts")
+        .icon_name("input-keyboard-symbolic")
+        .build();
+    let group = libhandy::PreferencesGroup::builder()
+        .title("Keyboard Shortcuts")
+        .description("Click a shortcut, then press the keys to use instead. Backspace turns it off, and Escape leaves it be.")
+        .build();
+    for shortcut in Shortcut::ALL {
+        let button = gtk::Button::builder()
+            .label(&shortcut.label(&config.borrow()))
+            .valign(gtk::Align::Center)
+            .build();
+        let listening = Rc::new(Cell::new(false));
+        button.connect_clicked(clone!(@strong listening => move |button| {
+            listening.set(true);
+            button.set_label("Press a key…");
+        }));
+        button.connect_key_press_event(clone!(@strong config, @strong listening => move |button, event| {
+            // Modifiers on their own are the start of a shortcut, not one.
+            if !listening.get() || event.is_modifier() {
+                return gtk::Inhibit(listening.get());
+            }
+            let keyval = event.keyval().to_lower();
+            let modifiers = event.state() & gtk::accelerator_get_default_mod_mask();
+            let accelerator = if keyval == gtk::gdk::keys::constants::Escape {
+                None
+            } else if keyval == gtk::gdk::keys::constants::BackSpace && modifiers.is_empty() {
+                Some(String::new())
+            } else {
+                gtk::accelerator_name(*keyval, modifiers).map(String::from)
+            };
+            if let Some(accelerator) = accelerator {
+                config.borrow_mut().set(Shortcut::SECTION, shortcut.key(), accelerator);
+            }
+            listening.set(false);
+            button.set_label(&shortcut.label(&config.borrow()));
+            gtk::Inhibit(true)
+        }));
+        let row = libhandy::ActionRow::builder()
+            .title(shortcut.title())
+            .activatable_widget(&button)
+            .build();
+        row.add(&button);
+        group.add(&row);
+    }
+    page.add(&group);
+    preferences.add(&page);
+
+    let page = libhandy::PreferencesPage::builder()
+        .title("Confirmations")
+        .icon_name("dialog-question-symbolic")
+        .build();
+    let group = libhandy::PreferencesGroup::builder()
+        .title("Ask Before")
+        .build();
+    for confirmation in Confirmation::ALL {
+        group.add(&switch_row(
+            confirmation.title(),
+            None,
+            confirmation.wanted(&config.borrow()),
+            clone!(@strong config => move |active| {
+                config.borrow_mut().set(Confirmation::SECTION, confirmation.key(), active);
+            }),
+        ));
+    }
+    page.add(&group);
+    preferences.add(&page);
+
+    // Typing a new address would connect to each half-typed one on the way,
+    // so changes to the server only take once the preferences are closed.
+    preferences.connect_destroy(clone!(@strong sender => move |_| {
+        let mut sender = sender.clone();
+        sender
+            .try_send(StateUpdateKind::ServerChange)
+            .expect("Couldn't notify thread");
+    }));
+    preferences.show_all();
+}
@@ -0,0 +1,987 @@
+// Copyright © 2021-2022 Jakob L. Kreuze <zerodaysfordays@sdf.org>
+//
+// This file is part of Tunes.
+//
+// Tunes is free software; you can redistribute it and/or modify it
+// under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation; either version 3 of the
+// License, or (at your option) any later version.
+//
+// Tunes is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY
+// or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Affero General
+// Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public
+// License along with Tunes. If not, see <http://www.gnu.org/licenses/>.
+
+use futures::channel::mpsc;
+use glib::clone;
+use gtk::prelude::*;
+use gtk::{gio, glib};
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+use std::time::Duration;
+use tunes_core::{
+    album_order, disc_count, leading_number, rank_albums, song_match_score, sort_songs, Config, SearchRequest,
+    SearchResults, SelectionAction, StateUpdateKind, LABELS_STICKER,
+};
+
+use crate::models::{Debounce, SongObject};
+use crate::ui::{
+    album_header, attach_song_menu, disc_header, song_row, ColumnHeadings, EmptyState, GenreColors, ListColumns,
+    SongListKind,
+};
+
+/// View for selecting songs to add to the queue.
+pub(crate) struct QueryInfo {
+    pub(crate) container: gtk::Box,
+    query_input: gtk::Entry,
+    /// Spins while a search runs.
+    busy: gtk::Spinner,
+    pub(crate) results: ResultView,
+    /// Whether rows should have columns for the tags classical collections
+    /// rely on. This is decided per result set, before rows are built.
+    show_classical: Rc<Cell<bool>>,
+    load_more: gtk::Button,
+    form: Rc<SearchForm>,
+    select_button: gtk::ToggleButton,
+    headings: Rc<ColumnHeadings>,
+    pub(crate) list_columns: ListColumns,
+}
+
+impl QueryInfo {
+    /// The tags a search can be narrowed to, as pairs of MPD tag name and
+    /// label. An empty tag name means any tag, and the user's own labels
+    /// go by the name of their sticker.
+    pub(crate) const SEARCH_TAGS: [(&'static str, &'static str); 5] = [
+        ("", "Any"),
+        ("Composer", "Composer"),
+        ("Work", "Work"),
+        ("Performer", "Performer"),
+        (LABELS_STICKER, "Labels"),
+    ];
+
+    /// Ways to sort the results, as pairs of ID and label. The empty ID keeps
+    /// albums together, closest match first, and "server" leaves them in
+    /// whatever order MPD gave them.
+    pub(crate) const SORT_ORDERS: [(&'static str, &'static str); 9] = [
+        ("", "Best Match"),
+        ("artist", "Artist"),
+        ("album", "Album"),
+        ("title", "Title"),
+        ("year", "Year"),
+        ("duration", "Duration"),
+        ("newest", "Newest First"),
+        ("oldest", "Oldest First"),
+        ("server", "Server Order"),
+    ];
+
+    /// Tags in the advanced search form, as pairs of MPD tag name and label.
+    pub(crate) const ADVANCED_FIELDS: [(&'static str, &'static str); 5] = [
+        ("Artist", "Artist"),
+        ("Album", "Album"),
+        ("Title", "Title"),
+        ("Genre", "Genre"),
+        ("Date", "Year"),
+    ];
+
+    pub(crate) fn new(
+        sender: mpsc::Sender<StateUpdateKind>,
+        genre_colors: GenreColors,
+        list_columns: ListColumns,
+        config: Rc<RefCell<Config>>,
+    ) -> Self {
+        let container = gtk::Box::new(gtk::Orientation::Vertical, 2);
+
+        let search_bar = gtk::Box::new(gtk::Orientation::Horizontal, 2);
+        let query_input = gtk::Entry::builder().visible(true).hexpand(true).build();
+
+        // Recent searches are offered as the user types. A search counts once
+        // it's been entered, or the user's moved on to its results.
+        let history = Rc::new(SearchHistory::new(config.clone()));
+        let completion = gtk::EntryCompletion::builder()
+            .model(&history.store)
+            .text_column(0)
+            .minimum_key_length(0)
+            .build();
+        query_input.set_completion(Some(&completion));
+        query_input.connect_activate(clone!(@strong history => move |entry| {
+            history.remember(&entry.text());
+        }));
+        query_input.connect_focus_out_event(clone!(@strong history => @default-return gtk::Inhibit(false), move |entry, _| {
+            history.remember(&entry.text());
+            gtk::Inhibit(false)
+        }));
+        let search_tag = gtk::ComboBoxText::new();
+        for (tag, label) in Self::SEARCH_TAGS {
+            search_tag.append(Some(tag), label);
+        }
+        search_tag.set_active(Some(0));
+        let sort_order = gtk::ComboBoxText::new();
+        for (order, label) in Self::SORT_ORDERS {
+            sort_order.append(Some(order), label);
+        }
+        // Whichever order was picked last time sticks.
+        let saved_order = config.borrow().get("search", "sort-order").map(String::from);
+        if !sort_order.set_active_id(saved_order.as_deref()) {
+            sort_order.set_active(Some(0));
+        }
+        sort_order.connect_changed(clone!(@strong config => move |sort_order| {
+            if let Some(order) = sort_order.active_id() {
+                config.borrow_mut().set("search", "sort-order", order);
+            }
+        }));
+        sort_order.set_tooltip_text(Some("Sort Results By"));
+        let more_fields = gtk::ToggleButton::new();
+        more_fields.set_image(Some(&gtk::Image::from_icon_name(
+            Some("pan-down-symbolic"),
+            gtk::IconSize::SmallToolbar,
+        )));
+        more_fields.set_tooltip_text(Some("More Search Fields"));
+        search_bar.add(&query_input);
+        let busy = gtk::Spinner::new();
+        busy.set_no_show_all(true);
+        search_bar.add(&busy);
+        search_bar.add(&search_tag);
+        search_bar.add(&sort_order);
+        search_bar.add(&more_fields);
+
+        let play_results = gtk::Button::from_icon_name(
+            Some("media-playback-start-symbolic"),
+            gtk::IconSize::SmallToolbar,
+        );
+        play_results.set_tooltip_text(Some("Replace Queue with Results"));
+        play_results.connect_clicked(clone!(@strong sender => move |_| {
+            let mut sender = sender.clone();
+            sender
+                .try_send(StateUpdateKind::QueueReplaceWithResultsRequest)
+                .expect("Couldn't notify thread");
+            sender
+                .try_send(StateUpdateKind::MpdEvent)
+                .expect("Couldn't notify thread");
+        }));
+        search_bar.add(&play_results);
+
+        let select_button = gtk::ToggleButton::new();
+        select_button.set_image(Some(&gtk::Image::from_icon_name(
+            Some("object-select-symbolic"),
+            gtk::IconSize::SmallToolbar,
+        )));
+        select_button.set_tooltip_text(Some("Select Songs"));
+        search_bar.add(&select_button);
+
+        // The advanced form has a field per tag, all of which have to match.
+        let advanced = gtk::Revealer::new();
+        more_fields
+            .bind_property("active", &advanced, "reveal-child")
+            .flags(glib::BindingFlags::DEFAULT | glib::BindingFlags::SYNC_CREATE)
+            .build();
+        let fields_grid = gtk::Grid::builder()
+            .row_spacing(2)
+            .column_spacing(8)
+            .border_width(4)
+            .build();
+        let mut fields = Vec::new();
+        for (row, (tag, label)) in Self::ADVANCED_FIELDS.into_iter().enumerate() {
+            let field_label = gtk::Label::new(Some(label));
+            field_label.set_xalign(1.0);
+            let entry = gtk::Entry::builder().hexpand(true).build();
+            fields_grid.attach(&field_label, 0, row as i32, 1, 1);
+            fields_grid.attach(&entry, 1, row as i32, 1, 1);
+            fields.push((tag, entry));
+        }
+        advanced.add(&fields_grid);
+
+        let form = Rc::new(SearchForm {
+            query_input: query_input.clone(),
+            search_tag: search_tag.clone(),
+            sort_order: sort_order.clone(),
+            more_fields: more_fields.clone(),
+            fields,
+            generation: Cell::new(0),
+            pending: Debounce::default(),
+        });
+        // Rather than searching on every keypress, wait for a pause in the
+        // typing. Every change still counts as a new search, though, so one
+        // that was already on its way can be told it's out of date.
+        let send_request = clone!(@strong sender, @strong form => move || {
+            form.generation.set(form.generation.get() + 1);
+            form.pending.set(
+                SEARCH_DEBOUNCE,
+                clone!(@strong sender, @strong form => move || {
+                    let mut sender = sender.clone();
+                    sender
+                        .try_send(StateUpdateKind::QueryUpdateEvent(form.request()))
+                        .expect("Couldn't notify thread");
+                }),
+            );
+        });
+        query_input.connect_changed(clone!(@strong send_request => move |_| send_request()));
+        search_tag.connect_changed(clone!(@strong send_request => move |_| send_request()));
+        sort_order.connect_changed(clone!(@strong send_request => move |_| send_request()));
+        more_fields.connect_toggled(clone!(@strong send_request => move |_| send_request()));
+        for (_, entry) in &form.fields {
+            entry.connect_changed(clone!(@strong send_request => move |_| send_request()));
+        }
+
+        // Enter queues the top result, or with Ctrl held, plays it next. A
+        // search still waiting out the typing goes first, so the top result is
+        // the one for what's in the entry now.
+        query_input.connect_activate(clone!(@strong sender, @strong form => move |_| {
+            let mut sender = sender.clone();
+            if form.pending.cancel() {
+                sender
+                    .try_send(StateUpdateKind::QueryUpdateEvent(form.request()))
+                    .expect("Couldn't notify thread");
+            }
+            let play_next = gtk::current_event_state()
+                .map_or(false, |state| state.contains(gtk::gdk::ModifierType::CONTROL_MASK));
+            sender
+                .try_send(StateUpdateKind::QueueTopResultRequest(play_next))
+                .expect("Couldn't notify thread");
+            sender
+                .try_send(StateUpdateKind::MpdEvent)
+                .expect("Couldn't notify thread");
+        }));
+
+        let show_classical = Rc::new(Cell::new(false));
+        let results = ResultView::new(sender.clone(), clone!(@strong sender, @strong show_classical, @strong genre_colors, @strong list_columns => move |item| {
+            let sender = sender.clone();
+            let item = item
+                .downcast_ref::<SongObject>()
+                .expect("Row data is of wrong type");
+
+            let filename = item.property::<String>("filename");
+            let album = item.property::<String>("album");
+            let actions = gtk::Box::new(gtk::Orientation::Horizontal, 0);
+            for (action, label, icon) in ListColumns::ACTIONS {
+                if !list_columns.action_shown(action) {
+                    continue;
+                }
+                let request = match action {
+                    "add" => StateUpdateKind::QueueAddRequest(filename.clone()),
+                    "play-now" => StateUpdateKind::QueuePlayNowRequest(filename.clone()),
+                    "play-next" => StateUpdateKind::QueuePlayNextRequest(filename.clone()),
+                    "add-album" if !album.is_empty() => {
+                        StateUpdateKind::QueueAddTagRequest("Album".into(), album.clone())
+                    }
+                    "details" => StateUpdateKind::SongDetailsRequest(filename.clone()),
+                    _ => continue,
+                };
+                let button = gtk::Button::from_icon_name(Some(icon), gtk::IconSize::SmallToolbar);
+                button.set_tooltip_text(Some(label));
+                button.connect_clicked(clone!(@strong sender => move |_| {
+                    let mut sender = sender.clone();
+                    sender
+                        .try_send(request.clone())
+                        .expect("Couldn't notify thread");
+                    sender
+                        .try_send(StateUpdateKind::MpdEvent)
+                        .expect("Couldn't notify thread");
+                }));
+                actions.add(&button);
+            }
+            let actions = (!actions.children().is_empty()).then(|| actions.upcast::<gtk::Widget>());
+
+            let extra: &[&str] = if show_classical.get() {
+                &["composer", "work", "performer"]
+            } else {
+                &[]
+            };
+            song_row(item, actions.as_ref(), extra, &genre_colors, &list_columns)
+        }));
+        // Until there's been a search, this says what to do instead.
+        results.set_empty_state(
+            "system-search-symbolic",
+            "Search for Music",
+            "Find songs by artist, album or title to add to the queue",
+        );
+
+        let load_more = gtk::Button::with_label("Load More");
+        load_more.set_halign(gtk::Align::Center);
+        let send_load_more = clone!(@strong sender => move || {
+            let mut sender = sender.clone();
+            sender
+                .try_send(StateUpdateKind::QueryLoadMoreRequest)
+                .expect("Couldn't notify thread");
+        });
+        load_more.connect_clicked(clone!(@strong send_load_more => move |_| send_load_more()));
+        // Scrolling to the bottom does the same, so nobody has to aim for the
+        // button. If there's nothing more, the request is just ignored.
+        results.connect_end_reached(send_load_more);
+
+        // Down from the entry goes to the results, as it would in a list.
+        let focus_results = results.first_row_focuser();
+        query_input.connect_key_press_event(move |_, event| {
+            if event.keyval() == gtk::gdk::keys::constants::Down {
+                gtk::Inhibit(focus_results())
+            } else {
+                gtk::Inhibit(false)
+            }
+        });
+
+        // Once a few songs are picked out, they can be dealt with all at once.
+        let selection_bar = gtk::Box::new(gtk::Orientation::Horizontal, 2);
+        let selection_label = gtk::Label::new(None);
+        selection_label.set_hexpand(true);
+        selection_label.set_xalign(0.0);
+        selection_bar.add(&selection_label);
+        let send_selection = clone!(@strong sender => move |action: SelectionAction| {
+            let mut sender = sender.clone();
+            sender
+                .try_send(StateUpdateKind::SelectionRequest(action))
+                .expect("Couldn't notify thread");
+            sender
+                .try_send(StateUpdateKind::MpdEvent)
+                .expect("Couldn't notify thread");
+        });
+        for (icon, tooltip, action) in [
+            ("list-add-symbolic", "Add Selection to Queue", SelectionAction::Add),
+            ("media-skip-forward-symbolic", "Play Selection Next", SelectionAction::PlayNext),
+        ] {
+            let button = gtk::Button::from_icon_name(Some(icon), gtk::IconSize::SmallToolbar);
+            button.set_tooltip_text(Some(tooltip));
+            button.connect_clicked(clone!(@strong send_selection => move |_| send_selection(action)));
+            selection_bar.add(&button);
+        }
+        let ratings = gtk::Box::new(gtk::Orientation::Vertical, 0);
+        ratings.set_border_width(6);
+        for rating in 1..=5 {
+            let button = gtk::ModelButton::builder().text(&"★".repeat(rating as usize)).build();
+            button.connect_clicked(clone!(@strong send_selection => move |_| {
+                send_selection(SelectionAction::Rate(rating))
+            }));
+            ratings.add(&button);
+        }
+        ratings.show_all();
+        let rate = gtk::MenuButton::builder()
+            .image(&gtk::Image::from_icon_name(
+                Some("starred-symbolic"),
+                gtk::IconSize::SmallToolbar,
+            ))
+            .tooltip_text("Rate Selection")
+            .popover(&gtk::Popover::builder().child(&ratings).build())
+            .build();
+        selection_bar.add(&rate);
+
+        let selection_revealer = gtk::Revealer::new();
+        selection_revealer.add(&selection_bar);
+        // A single selected row is just where the user's looking.
+        let show_selection = clone!(@weak select_button, @weak selection_revealer, @weak selection_label => move |count: usize| {
+            selection_label.set_text(&format!("{} selected", count));
+            selection_revealer.set_reveal_child(count > 1 || select_button.is_active());
+        });
+        results.connect_selection_changed(show_selection.clone());
+        let switch_selecting = results.selection_switch();
+        select_button.connect_toggled(move |button| {
+            switch_selecting(button.is_active());
+            show_selection(0);
+        });
+
+        container.add(&search_bar);
+        container.add(&advanced);
+        // Clicking a heading sorts by its column, the same as picking that
+        // order from the drop-down.
+        let headings = ColumnHeadings::new(
+            sender.clone(),
+            list_columns.clone(),
+            &[("title", "title"), ("artist", "artist"), ("album", "album")],
+            clone!(@strong sort_order => move |order| {
+                sort_order.set_active_id(Some(order));
+            }),
+        );
+        container.add(&selection_revealer);
+        container.add(&headings.container);
+        container.add(results.as_ref());
+        container.add(&load_more);
+        container.show_all();
+        load_more.hide();
+
+        QueryInfo {
+            container,
+            query_input,
+            busy,
+            results,
+            show_classical,
+            load_more,
+            form,
+            select_button,
+            headings,
+            list_columns,
+        }
+    }
+
+    /// Build the result rows and headings over again, for when the way
+    /// they're built has changed.
+    pub(crate) fn rebuild_rows(&self) {
+        self.results.rebuild_rows();
+        self.headings.rebuild();
+    }
+
+    /// Put the selection down once something's been done with it.
+    pub(crate) fn finish_selection(&self) {
+        self.select_button.set_active(false);
+        self.results.unselect_all();
+    }
+
+    /// Fill in the form to search for everything by `artist`. The search goes
+    /// out on its own as the form changes.
+    pub(crate) fn focus_search(&self) {
+        self.query_input.grab_focus();
+    }
+
+    pub(crate) fn show_artist(&self, artist: &str) {
+        self.form.query_input.set_text("");
+        self.form.search_tag.set_active(Some(0));
+        for (tag, entry) in &self.form.fields {
+            entry.set_text(if *tag == "Artist" { artist } else { "" });
+        }
+        self.form.more_fields.set_active(true);
+    }
+
+    /// Whether `request` reflects the search form as it is now.
+    pub(crate) fn is_current(&self, request: &SearchRequest) -> bool {
+        request.generation == self.form.generation.get()
+    }
+
+    pub(crate) fn show_results(&self, search: &SearchResults) {
+        let request = &search.request;
+
+        // Classical collections need a few more columns, but there's no sense
+        // cluttering other results with them.
+        self.show_classical.set(search.songs.iter().any(|song| {
+            ["Composer", "Work", "Performer"]
+                .iter()
+                .any(|tag| song.tags.contains_key(*tag))
+        }));
+        let actions = ListColumns::ACTIONS
+            .iter()
+            .filter(|(action, _, _)| self.list_columns.action_shown(action))
+            .count();
+        self.headings.set_columns(
+            actions as i32,
+            if self.show_classical.get() { &["composer", "work", "performer"] } else { &[] },
+        );
+
+        // Keep each album together, in track order, so the results can be
+        // grouped under album headings. Compilations are kept together by
+        // going by album artist rather than whoever's on each track. Fuzzy
+        // results put the closest albums first. Any other order is applied
+        // on top of that, so ties stay in album order.
+        let mut songs = search.songs.clone();
+        if request.sort == "server" {
+            // Just as MPD (or the tag index) gave them.
+        } else if request.is_fuzzy() && !request.is_filter_expression() {
+            songs = rank_albums(songs, |song| song_match_score(&request.text, song));
+        } else {
+            songs.sort_by(|a, b| album_order(a).cmp(&album_order(b)));
+        }
+        let grouped = sort_songs(&mut songs, &request.sort);
+        self.results.set_grouped(grouped);
+        self.results
+            .set_empty_state("edit-find-symbolic", "No Results", "Try fewer words, or searching any tag");
+        self.results.set_songs(songs.iter());
+        self.load_more.set_visible(search.more);
+    }
+
+    /// Show the spinner while a search runs, or put it away again.
+    pub(crate) fn set_busy(&self, busy: bool) {
+        self.busy.set_visible(busy);
+        if busy {
+            self.busy.start();
+        } else {
+            self.busy.stop();
+        }
+    }
+
+    /// Flag the search text as unusable, explaining why in a tooltip, or clear
+    /// the flag when given `None`. Either way, the search is over.
+    pub(crate) fn set_error(&self, error: Option<&str>) {
+        self.set_busy(false);
+        let style = self.query_input.style_context();
+        match error {
+            Some(error) => {
+                style.add_class("error");
+                self.query_input
+                    .set_icon_from_icon_name(gtk::EntryIconPosition::Secondary, Some("dialog-error-symbolic"));
+                self.query_input
+                    .set_icon_tooltip_text(gtk::EntryIconPosition::Secondary, Some(error));
+            }
+            None => {
+                style.remove_class("error");
+                self.query_input
+                    .set_icon_from_icon_name(gtk::EntryIconPosition::Secondary, None);
+            }
+        }
+    }
+}
+
+/// Searches the user's made lately, most recent first, kept in the config.
+struct SearchHistory {
+    pub(crate) config: Rc<RefCell<Config>>,
+    /// The same searches, for completing the search entry from.
+    pub(crate) store: gtk::ListStore,
+}
+
+impl SearchHistory {
+    pub(crate) const SECTION: &'static str = "search-history";
+
+    /// How many searches to hang on to.
+    pub(crate) const LENGTH: usize = 20;
+
+    pub(crate) fn new(config: Rc<RefCell<Config>>) -> Self {
+        let history = SearchHistory {
+            config,
+            store: gtk::ListStore::new(&[glib::Type::STRING]),
+        };
+        history.fill_store();
+        history
+    }
+
+    /// Searches, most recent first. Keys are numbered so they sort that way.
+    pub(crate) fn entries(&self) -> Vec<String> {
+        self.config
+            .borrow()
+            .section(Self::SECTION)
+            .map(|section| section.values().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    pub(crate) fn fill_store(&self) {
+        self.store.clear();
+        for entry in self.entries() {
+            self.store.insert_with_values(None, &[(0, &entry)]);
+        }
+    }
+
+    /// Put a search at the top of the history, moving it up if it's already
+    /// there. Searches too short to have been run aren't worth keeping.
+    pub(crate) fn remember(&self, text: &str) {
+        let text = text.trim();
+        if text.len() <= 2 {
+            return;
+        }
+        let mut entries = self.entries();
+        if entries.first().map(String::as_str) == Some(text) {
+            return;
+        }
+        entries.retain(|entry| entry != text);
+        entries.insert(0, text.to_string());
+        entries.truncate(Self::LENGTH);
+        let keys = entries
+            .into_iter()
+            .enumerate()
+            .map(|(i, entry)| (format!("query-{:03}", i), entry))
+            .collect();
+        self.config.borrow_mut().replace_section(Self::SECTION, keys);
+        self.fill_store();
+    }
+}
+
+/// The inputs making up a search, so a request can be put together from all
+/// of them whenever any one changes.
+pub(crate) struct SearchForm {
+    query_input: gtk::Entry,
+    search_tag: gtk::ComboBoxText,
+    sort_order: gtk::ComboBoxText,
+    more_fields: gtk::ToggleButton,
+    /// The advanced form's entries, by MPD tag name.
+    pub(crate) fields: Vec<(&'static str, gtk::Entry)>,
+    /// Counts up with every change to the form, so requests from before the
+    /// latest change can be told apart.
+    pub(crate) generation: Cell<u64>,
+    /// The timeout that'll send off the search once the user stops typing.
+    pub(crate) pending: Debounce,
+}
+
+impl SearchForm {
+    pub(crate) fn request(&self) -> SearchRequest {
+        let mut request =
+            SearchRequest::new(&self.query_input.text(), self.search_tag.active_id().as_deref());
+        request.generation = self.generation.get();
+        request.sort = self.sort_order.active_id().map(String::from).unwrap_or_default();
+        // A hidden field shouldn't quietly narrow down the results.
+        if self.more_fields.is_active() {
+            request.fields = self
+                .fields
+                .iter()
+                .map(|(tag, entry)| (tag.to_string(), entry.text().trim().to_string()))
+                .filter(|(_, value)| !value.is_empty())
+                .collect();
+        }
+        request
+    }
+}
+
+/// How long to wait after the search form last changed before searching.
+const SEARCH_DEBOUNCE: Duration = Duration::from_millis(250);
+
+impl AsRef<gtk::Widget> for QueryInfo {
+    fn as_ref(&self) -> &gtk::Widget {
+        self.container.upcast_ref()
+    }
+}
+
+/// One of the two lists backing a `ResultView`.
+pub(crate) struct ResultPane {
+    /// Shown in place of the list when it has no songs.
+    pub(crate) empty: EmptyState,
+    scrolled_window: gtk::ScrolledWindow,
+    listbox: gtk::ListBox,
+    pub(crate) model: gio::ListStore,
+    /// Filenames of the songs in `model`, in order, so result sets can be
+    /// compared without going through GObject properties.
+    pub(crate) filenames: RefCell<Vec<String>>,
+    /// Opens the row menu on touchscreens. Only held to keep it alive.
+    _menu_gesture: gtk::GestureLongPress,
+}
+
+impl ResultPane {
+    pub(crate) fn new(
+        sender: mpsc::Sender<StateUpdateKind>,
+        create_row: Rc<dyn Fn(&glib::Object) -> gtk::Widget>,
+        grouped: Rc<Cell<bool>>,
+        selecting: Rc<Cell<bool>>,
+    ) -> Self {
+        let model = gio::ListStore::new(SongObject::static_type());
+        let listbox = gtk::ListBox::new();
+        listbox.bind_model(Some(&model), move |item| create_row(item));
+        let empty = EmptyState::new("folder-music-symbolic", "No Songs", "");
+        listbox.set_placeholder(Some(empty.as_ref()));
+
+        // Ctrl and Shift pick out several rows, as usual. There's no holding
+        // those down on a phone, so in selection mode a tap toggles a row
+        // instead.
+        listbox.set_selection_mode(gtk::SelectionMode::Multiple);
+        listbox.connect_button_press_event(move |listbox, event| {
+            if !selecting.get() || event.button() != 1 {
+                return gtk::Inhibit(false);
+            }
+            if let Some(row) = listbox.row_at_y(event.position().1 as i32) {
+                if row.is_selected() {
+                    listbox.unselect_row(&row);
+                } else {
+                    listbox.select_row(Some(&row));
+                }
+            }
+            gtk::Inhibit(true)
+        });
+        let menu_gesture = attach_song_menu(&sender, &listbox, &model, SongListKind::Library);
+
+        // Runs of songs from the same album get a heading, which also offers
+        // to queue the whole album. Albums spanning several discs get one for
+        // each disc, too.
+        listbox.set_header_func(Some(Box::new(clone!(@strong model => move |row: &gtk::ListBoxRow, before: Option<&gtk::ListBoxRow>| {
+            if !grouped.get() {
+                row.set_header(gtk::Widget::NONE);
+                return;
+            }
+            let song_at = |index: i32| {
+                u32::try_from(index)
+                    .ok()
+                    .and_then(|index| model.item(index))
+                    .and_then(|x| x.downcast::<SongObject>().ok())
+                    .map(|item| {
+                        (
+                            (
+                                item.property::<String>("album"),
+                                item.property::<String>("album-artist"),
+                            ),
+                            item.property::<String>("disc"),
+                        )
+                    })
+            };
+            let (album, disc) = match song_at(row.index()) {
+                Some(x) => x,
+                None => {
+                    row.set_header(gtk::Widget::NONE);
+                    return;
+                }
+            };
+            let before = before.and_then(|x| song_at(x.index()));
+            let after = song_at(row.index() + 1);
+            let new_album = before.as_ref().map(|(x, _)| x) != Some(&album);
+            let new_disc = new_album || before.as_ref().map(|(_, x)| x) != Some(&disc);
+
+            // Either the tag says how many discs there are ("1/2"), or we can
+            // see another disc of the same album right next to this one.
+            let multi_disc = disc_count(&disc) > 1
+                || [&before, &after]
+                    .iter()
+                    .any(|x| matches!(x, Some((a, d)) if *a == album && *d != disc));
+
+            let header = gtk::Box::new(gtk::Orientation::Vertical, 0);
+            if new_album {
+                header.add(&album_header(&sender, &album.0, &album.1));
+            }
+            if new_disc && multi_disc {
+                header.add(&disc_header(leading_number(&disc)));
+            }
+            if header.children().is_empty() {
+                row.set_header(gtk::Widget::NONE);
+            } else {
+                header.show_all();
+                row.set_header(Some(&header));
+            }
+        }))));
+
+        let scrolled_window =
+            gtk::ScrolledWindow::new(gtk::Adjustment::NONE, gtk::Adjustment::NONE);
+        scrolled_window.add(&listbox);
+        scrolled_window.set_vexpand(true);
+        listbox.set_focus_vadjustment(&scrolled_window.vadjustment());
+        ResultPane {
+            empty,
+            scrolled_window,
+            listbox,
+            model,
+            filenames: RefCell::new(Vec::new()),
+            _menu_gesture: menu_gesture,
+        }
+    }
+
+    /// The row the user was last looking at: the focused one if there is one,
+    /// otherwise the selected one.
+    pub(crate) fn focused_row(&self) -> Option<gtk::ListBoxRow> {
+        self.listbox
+            .focus_child()
+            .and_then(|x| x.downcast::<gtk::ListBoxRow>().ok())
+            .or_else(|| self.listbox.selected_row())
+    }
+}
+
+/// A list of songs that swaps in new results by crossfading rather than
+/// emptying the visible list and refilling it. New results are built in a
+/// second, hidden list, which also keeps the previous result set around so
+/// flipping back to it is free.
+pub(crate) struct ResultView {
+    pub(crate) stack: gtk::Stack,
+    panes: [ResultPane; 2],
+    /// Index into `panes` of the list currently on screen.
+    front: Cell<usize>,
+    /// Whether the songs come in runs by album, which get headings.
+    grouped: Rc<Cell<bool>>,
+    /// Whether a tap picks out rows instead of just focusing them.
+    selecting: Rc<Cell<bool>>,
+}
+
+impl ResultView {
+    /// `create_row` builds the row widget for each `SongObject`, just like the
+    /// function given to `gtk::ListBox::bind_model`.
+    pub(crate) fn new(
+        sender: mpsc::Sender<StateUpdateKind>,
+        create_row: impl Fn(&glib::Object) -> gtk::Widget + 'static,
+    ) -> Self {
+        let create_row: Rc<dyn Fn(&glib::Object) -> gtk::Widget> = Rc::new(create_row);
+        let grouped = Rc::new(Cell::new(true));
+        let selecting = Rc::new(Cell::new(false));
+        let panes = [
+            ResultPane::new(sender.clone(), create_row.clone(), grouped.clone(), selecting.clone()),
+            ResultPane::new(sender, create_row, grouped.clone(), selecting.clone()),
+        ];
+
+        let stack = gtk::Stack::new();
+        stack.set_transition_type(gtk::StackTransitionType::Crossfade);
+        stack.set_vexpand(true);
+        stack.add_named(&panes[0].scrolled_window, "0");
+        stack.add_named(&panes[1].scrolled_window, "1");
+        stack.show_all();
+        stack.set_visible_child_name("0");
+
+        ResultView {
+            stack,
+            panes,
+            front: Cell::new(0),
+            grouped,
+            selecting,
+        }
+    }
+
+    /// Filenames of the selected songs on screen, in order.
+    pub(crate) fn selected_filenames(&self) -> Vec<String> {
+        let pane = &self.panes[self.front.get()];
+        let mut indices: Vec<usize> = pane
+            .listbox
+            .selected_rows()
+            .iter()
+            .filter_map(|row| usize::try_from(row.index()).ok())
+            .collect();
+        indices.sort_unstable();
+        let filenames = pane.filenames.borrow();
+        indices.iter().filter_map(|&i| filenames.get(i).cloned()).collect()
+    }
+
+    /// Something to call to turn selection mode on or off. Either way, we
+    /// start from nothing selected.
+    pub(crate) fn selection_switch(&self) -> impl Fn(bool) {
+        let selecting = self.selecting.clone();
+        let lists = [self.panes[0].listbox.clone(), self.panes[1].listbox.clone()];
+        move |on| {
+            selecting.set(on);
+            for list in &lists {
+                list.unselect_all();
+            }
+        }
+    }
+
+    pub(crate) fn unselect_all(&self) {
+        for pane in &self.panes {
+            pane.listbox.unselect_all();
+        }
+    }
+
+    /// Build the rows on screen over again, for when the way they're built
+    /// has changed.
+    pub(crate) fn rebuild_rows(&self) {
+        let model = &self.panes[self.front.get()].model;
+        model.items_changed(0, model.n_items(), model.n_items());
+    }
+
+    /// Call `f` with the number of selected songs on screen whenever that
+    /// might have changed.
+    pub(crate) fn connect_selection_changed(&self, f: impl Fn(usize) + 'static) {
+        let f = Rc::new(f);
+        for pane in &self.panes {
+            pane.listbox
+                .connect_selected_rows_changed(clone!(@strong f => move |listbox| {
+                    f(listbox.selected_rows().len());
+                }));
+        }
+    }
+
+    /// Call `f` whenever the user scrolls to the end of the list.
+    pub(crate) fn connect_end_reached(&self, f: impl Fn() + 'static) {
+        let f = Rc::new(f);
+        for pane in &self.panes {
+            pane.scrolled_window
+                .connect_edge_reached(clone!(@strong f => move |_, position| {
+                    if position == gtk::PositionType::Bottom {
+                        f();
+                    }
+                }));
+        }
+    }
+
+    /// Something to call to move focus to the first result on screen, which
+    /// returns whether there was one.
+    pub(crate) fn first_row_focuser(&self) -> impl Fn() -> bool {
+        let stack = self.stack.clone();
+        let lists = [self.panes[0].listbox.clone(), self.panes[1].listbox.clone()];
+        move || {
+            let front = stack
+                .visible_child_name()
+                .and_then(|name| name.parse::<usize>().ok())
+                .unwrap_or(0);
+            match lists[front].row_at_index(0) {
+                Some(row) => {
+                    row.grab_focus();
+                    true
+                }
+                None => false,
+            }
+        }
+    }
+
+    /// Filenames of the songs on screen, in order.
+    pub(crate) fn filenames(&self) -> Vec<String> {
+        self.panes[self.front.get()].filenames.borrow().clone()
+    }
+
+    /// Say whether the next songs come in runs by album. Songs sorted any
+    /// other way would just get a heading on every other row.
+    pub(crate) fn set_grouped(&self, grouped: bool) {
+        if self.grouped.replace(grouped) != grouped {
+            for pane in &self.panes {
+                pane.listbox.invalidate_headers();
+            }
+        }
+    }
+
+    pub(crate) fn set_songs<'a>(&self, songs: impl Iterator<Item = &'a mpd::Song>) {
+        self.set_items(songs.map(SongObject::new).collect());
+    }
+
+    /// Change what's shown when there are no songs to list.
+    pub(crate) fn set_empty_state(&self, icon: &str, title: &str, hint: &str) {
+        for pane in &self.panes {
+            pane.empty.set(icon, title, hint);
+        }
+    }
+
+    /// Replace the results with `items`, crossfading over from the old ones.
+    pub(crate) fn set_items(&self, items: Vec<SongObject>) {
+        let front = &self.panes[self.front.get()];
+        let back_index = 1 - self.front.get();
+        let back = &self.panes[back_index];
+
+        let filenames: Vec<String> = items
+            .iter()
+            .map(|item| item.property::<String>("filename"))
+            .collect();
+        if *front.filenames.borrow() == filenames {
+            return;
+        }
+
+        // Remember which song the user was looking at, and how far down the
+        // visible part of the list it was, so we can put it back in the same
+        // spot if it's in the new results too.
+        let focused = front.focused_row().and_then(|row| {
+            let index = usize::try_from(row.index()).ok()?;
+            let filename = front.filenames.borrow().get(index)?.clone();
+            let (_, y) = row.translate_coordinates(&front.listbox, 0, 0)?;
+            let offset = f64::from(y) - front.scrolled_window.vadjustment().value();
+            Some((filename, offset))
+        });
+
+        // The hidden list still holds the result set before this one, in which
+        // case there's nothing to rebuild.
+        if *back.filenames.borrow() != filenames {
+            back.model.remove_all();
+            for item in &items {
+                back.model.append(item);
+            }
+            back.filenames.replace(filenames);
+            // Disc headings depend on the rows after them too, which weren't
+            // there yet as each row was added.
+            back.listbox.invalidate_headers();
+        }
+
+        // A selection in the old results doesn't mean anything in the new
+        // ones.
+        front.listbox.unselect_all();
+        back.listbox.unselect_all();
+        self.front.set(back_index);
+        self.stack.set_visible_child_name(&back_index.to_string());
+
+        let restored = focused.and_then(|(filename, offset)| {
+            let index = back.filenames.borrow().iter().position(|x| *x == filename)?;
+            let row = back.listbox.row_at_index(i32::try_from(index).ok()?)?;
+            Some((row, offset))
+        });
+        match restored {
+            Some((row, offset)) => {
+                // In selection mode, that'd be picking out a song for the user.
+                if !self.selecting.get() {
+                    back.listbox.select_row(Some(&row));
+                }
+                // The new rows won't have been laid out until GTK gets a
+                // chance to, so we wait for that before scrolling.
+                let listbox = back.listbox.clone();
+                let adjustment = back.scrolled_window.vadjustment();
+                glib::idle_add_local_once(move || {
+                    if let Some((_, y)) = row.translate_coordinates(&listbox, 0, 0) {
+                        adjustment.set_value(f64::from(y) - offset);
+                    }
+                });
+            }
+            None => back.scrolled_window.vadjustment().set_value(0.0),
+        }
+    }
+}
+
+impl AsRef<gtk::Widget> for ResultView {
+    fn as_ref(&self) -> &gtk::Widget {
+        self.stack.upcast_ref()
+    }
+}
@@ -0,0 +1,48 @@
+// Copyright © 2021-2022 Jakob L. Kreuze <zerodaysfordays@sdf.org>
+//
+// This file is part of Tunes.
+//
+// Tunes is free software; you can redistribute it and/or modify it
+// under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation; either version 3 of the
+// License, or (at your option) any later version.
+//
+// Tunes is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY
+// or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Affero General
+// Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public
+// License along with Tunes. If not, see <http://www.gnu.org/licenses/>.
+
+use gtk::prelude::*;
+use libhandy::ApplicationWindow;
+use tunes_core::Config;
+
+use crate::models::Shortcut;
+
+/// List the keyboard shortcuts, as they're bound at the moment.
+pub(crate) fn show_shortcuts(window: &ApplicationWindow, config: &Config) {
+    let group = gtk::ShortcutsGroup::builder().title("Tunes").build();
+    for shortcut in Shortcut::ALL {
+        if let Some((keyval, modifiers)) = shortcut.binding(config) {
+            group.add(
+                &gtk::ShortcutsShortcut::builder()
+                    .title(shortcut.title())
+                    .accelerator(&gtk::accelerator_name(keyval, modifiers).unwrap_or_default())
+                    .build(),
+            );
+        }
+    }
+    let section = gtk::ShortcutsSection::builder()
+        .section_name("shortcuts")
+        .visible(true)
+        .build();
+    section.add(&group);
+    let shortcuts = gtk::ShortcutsWindow::builder()
+        .transient_for(window)
+        .modal(true)
+        .build();
+    shortcuts.add(&section);
+    shortcuts.show_all();
+}
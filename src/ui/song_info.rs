@@ -0,0 +1,871 @@
+// Copyright © 2021-2022 Jakob L. Kreuze <zerodaysfordays@sdf.org>
+//
+// This file is part of Tunes.
+//
+// Tunes is free software; you can redistribute it and/or modify it
+// under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation; either version 3 of the
+// License, or (at your option) any later version.
+//
+// Tunes is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY
+// or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Affero General
+// Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public
+// License along with Tunes. If not, see <http://www.gnu.org/licenses/>.
+
+use futures::channel::mpsc;
+use glib::clone;
+use gtk::prelude::*;
+use gtk::{gdk_pixbuf, gio, glib, pango};
+use libhandy::ApplicationWindow;
+use std::cell::{Cell, RefCell};
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+use tunes_core::{
+    blend, readable_on, ArtSize, Client, DecodedArt, Density, DisplayProfile, Layout, PlaybackStateChange, Rgb,
+    StateUpdateKind, CONTROL_CONTRAST,
+};
+
+use crate::MINI_PLAYER_ART_SIZE;
+use crate::backend::{placeholder_art, AlbumArtCache, AlbumArtRequest, Options};
+use crate::models::{art_pixbuf, format_total_duration, SongObject, TIMER_WAKEUPS};
+use crate::ui::{
+    attach_song_menu, show_art_overlay, song_row, toggle_label, ColumnHeadings, ElapsedTicker, EmptyState, GenreColors,
+    ListColumns, SongListKind,
+};
+
+/// Apply the display profile for the current form factor to the window.
+pub(crate) fn apply_display_profile(window: &ApplicationWindow, song_info: &SongInfo, profile: &DisplayProfile) {
+    let style_context = window.style_context();
+    match profile.density {
+        Density::Compact => style_context.add_class("compact"),
+        Density::Comfortable => style_context.remove_class("compact"),
+    }
+    song_info.apply_profile(profile);
+}
+
+/// How long something can take before it's worth showing a spinner for.
+const LOADING_DELAY: Duration = Duration::from_millis(300);
+
+/// Size (in pixels) the cover's shrunk to for the backdrop. The fewer, the
+/// blurrier it is once it's stretched back out.
+const BACKDROP_SIZE: i32 = 8;
+
+/// How strongly the backdrop shows through, over the theme's background.
+const BACKDROP_ALPHA: f64 = 0.35;
+
+/// Only keep the elapsed time ticking while something's playing and someone
+/// could be looking at it. A kiosk is always being looked at.
+pub(crate) fn update_ticker(song_info: &SongInfo, window: &ApplicationWindow, options: &Options) {
+    let running = song_info.playing.get() && (window.is_active() || options.kiosk);
+    if song_info.elapsed.set_running(running) && options.diagnostics {
+        eprintln!(
+            "Elapsed ticker {} ({} timer wakeups so far)",
+            if running { "started" } else { "stopped" },
+            TIMER_WAKEUPS.load(Ordering::Relaxed)
+        );
+    }
+}
+
+/// View for information about the currently playing song.
+pub(crate) struct SongInfo {
+    pub(crate) container: gtk::Box,
+    album_art: gtk::Image,
+    /// Fraction of the window's least dimension to scale the album art to.
+    album_art_scale: Cell<f64>,
+    album_art_requests: std::sync::mpsc::Sender<AlbumArtRequest>,
+    /// For saying so when the art loader has stopped.
+    pub(crate) sender: mpsc::Sender<StateUpdateKind>,
+    /// The song and size we last asked for art for, with what to name on a
+    /// placeholder if it has none, and which request that was, so that
+    /// answers to older ones can be ignored.
+    album_art_wanted: RefCell<Option<(String, i32, String)>>,
+    album_art_generation: Rc<Cell<u64>>,
+    /// Spins over the cover while art's being fetched, on a slow network.
+    album_art_loading: gtk::Spinner,
+    /// Where the full-size cover for the current song is cached, if it has
+    /// one, for showing it fullscreen.
+    album_art_path: Rc<RefCell<Option<PathBuf>>>,
+    /// Styles tinting the window with the cover's color.
+    accent: gtk::CssProvider,
+    /// The cover, shrunk to a few pixels so that stretched back out it's a
+    /// soft blur of its colors, drawn behind the current song.
+    backdrop: Rc<RefCell<Option<gdk_pixbuf::Pixbuf>>>,
+    song_text: gtk::Label,
+    pub(crate) elapsed: ElapsedTicker,
+    /// Whether MPD was playing as of the last update.
+    pub(crate) playing: Cell<bool>,
+    pub(crate) model: gio::ListStore,
+    /// The whole queue, of which `model` holds the songs passing the filter.
+    queue_songs: Rc<RefCell<Vec<SongObject>>>,
+    queue_filter: gtk::SearchEntry,
+    pub(crate) queue_list: gtk::ListBox,
+    queue_window: gtk::ScrolledWindow,
+    queue_headings: Rc<ColumnHeadings>,
+    /// Expands and collapses the queue, showing a summary of it.
+    queue_toggle: gtk::ToggleButton,
+    queue_summary: gtk::Label,
+    /// The queue and everything to do with it, which the mini player hides.
+    pub(crate) queue: gtk::Box,
+    /// Playback buttons.
+    pub(crate) controls: gtk::Box,
+    pub(crate) options_bar: gtk::Box,
+    /// Whether we're showing the mini player, with a thumbnail for a cover.
+    pub(crate) mini: Cell<bool>,
+    single_button: gtk::Button,
+    consume_button: gtk::Button,
+    /// Opens the row menu on touchscreens. Only held to keep it alive.
+    _menu_gesture: Option<gtk::GestureLongPress>,
+}
+
+impl SongInfo {
+    /// A `read_only` view leaves out everything that would change the queue or
+    /// playback.
+    pub(crate) fn new(
+        sender: mpsc::Sender<StateUpdateKind>,
+        album_art_requests: std::sync::mpsc::Sender<AlbumArtRequest>,
+        genre_colors: GenreColors,
+        list_columns: ListColumns,
+        read_only: bool,
+    ) -> Self {
+        // The view is split into the current song (art, text, and controls)
+        // and the queue, which get stacked or placed side-by-side depending on
+        // the display profile.
+        let container = gtk::Box::new(gtk::Orientation::Vertical, 16);
+        let now_playing = gtk::Box::new(gtk::Orientation::Vertical, 16);
+        let album_art = gtk::Image::new();
+        let song_text = gtk::Label::new(None);
+        song_text.style_context().add_class("now-playing");
+        song_text.set_widget_name("now-playing-title");
+        album_art.set_widget_name("album-art");
+        song_text.set_justify(gtk::Justification::Center);
+        song_text.set_line_wrap(true);
+        song_text.set_line_wrap_mode(pango::WrapMode::WordChar);
+        // The cover is small on a phone, so clicking it shows it as big as
+        // it'll go.
+        let album_art_path = Rc::new(RefCell::new(None::<PathBuf>));
+        let album_art_box = gtk::EventBox::new();
+        let album_art_overlay = gtk::Overlay::new();
+        album_art_overlay.add(&album_art);
+        let album_art_loading = gtk::Spinner::builder()
+            .halign(gtk::Align::Center)
+            .valign(gtk::Align::Center)
+            .width_request(32)
+            .height_request(32)
+            .build();
+        album_art_loading.set_no_show_all(true);
+        album_art_overlay.add_overlay(&album_art_loading);
+        album_art_box.add(&album_art_overlay);
+        album_art_box.connect_button_release_event(clone!(@strong album_art_path => move |album_art_box, _| {
+            if let (Some(path), Some(window)) = (
+                album_art_path.borrow().as_ref(),
+                album_art_box.toplevel().and_then(|x| x.downcast::<gtk::Window>().ok()),
+            ) {
+                show_art_overlay(&window, path);
+            }
+            gtk::Inhibit(true)
+        }));
+        now_playing.add(&album_art_box);
+        now_playing.add(&song_text);
+
+        // Moving to a screen with a different scale calls for art at a
+        // different resolution.
+        container.connect_scale_factor_notify(clone!(@strong sender => move |_| {
+            let mut sender = sender.clone();
+            sender
+                .try_send(StateUpdateKind::WindowResizeEvent)
+                .expect("Couldn't notify thread");
+        }));
+
+        // Like GNOME Music, the window takes on a hint of the cover's color.
+        let accent = gtk::CssProvider::new();
+        if let Some(screen) = gtk::gdk::Screen::default() {
+            gtk::StyleContext::add_provider_for_screen(
+                &screen,
+                &accent,
+                gtk::STYLE_PROVIDER_PRIORITY_APPLICATION,
+            );
+        }
+        let backdrop = Rc::new(RefCell::new(None::<gdk_pixbuf::Pixbuf>));
+        now_playing.connect_draw(clone!(@strong backdrop => move |now_playing, context| {
+            if let Some(backdrop) = backdrop.borrow().as_ref() {
+                let (width, height) = (now_playing.allocated_width(), now_playing.allocated_height());
+                // Stretched to cover the whole area, cropping whichever way
+                // it overflows.
+                let scale = f64::from(width.max(height)) / f64::from(BACKDROP_SIZE);
+                context.save().ok();
+                context.translate(
+                    (f64::from(width) - f64::from(BACKDROP_SIZE) * scale) / 2.0,
+                    (f64::from(height) - f64::from(BACKDROP_SIZE) * scale) / 2.0,
+                );
+                context.scale(scale, scale);
+                context.set_source_pixbuf(backdrop, 0.0, 0.0);
+                context.source().set_filter(gtk::cairo::Filter::Good);
+                context.paint_with_alpha(BACKDROP_ALPHA).ok();
+                context.restore().ok();
+            }
+            gtk::Inhibit(false)
+        }));
+        let elapsed = ElapsedTicker::new();
+        now_playing.add(elapsed.as_ref());
+
+        let action_bar = gtk::Box::new(gtk::Orientation::Horizontal, 16);
+        action_bar.set_halign(gtk::Align::Center);
+        action_bar.style_context().add_class("transport");
+
+        let control_previous_song = gtk::Button::from_icon_name(
+            Some("media-skip-backward-symbolic"),
+            gtk::IconSize::SmallToolbar,
+        );
+        action_bar.add(&control_previous_song);
+        control_previous_song.connect_clicked(clone!(@strong sender => move |_| {
+            let mut sender = sender.clone();
+            sender
+                .try_send(StateUpdateKind::PlaybackStateChange(
+                    PlaybackStateChange::SkipBackwards,
+                ))
+                .expect("Couldn't notify thread");
+        }));
+
+        let control_start_song = gtk::Button::from_icon_name(
+            Some("media-playback-start-symbolic"),
+            gtk::IconSize::SmallToolbar,
+        );
+        action_bar.add(&control_start_song);
+        control_start_song.connect_clicked(clone!(@strong sender => move |_| {
+            let mut sender = sender.clone();
+            sender
+                .try_send(StateUpdateKind::PlaybackStateChange(
+                    PlaybackStateChange::Start,
+                ))
+                .expect("Couldn't notify thread");
+        }));
+
+        let control_pause_song = gtk::Button::from_icon_name(
+            Some("media-playback-pause-symbolic"),
+            gtk::IconSize::SmallToolbar,
+        );
+        action_bar.add(&control_pause_song);
+        control_pause_song.connect_clicked(clone!(@strong sender => move |_| {
+            let mut sender = sender.clone();
+            sender
+                .try_send(StateUpdateKind::PlaybackStateChange(
+                    PlaybackStateChange::Pause,
+                ))
+                .expect("Couldn't notify thread");
+        }));
+
+        let control_stop_song = gtk::Button::from_icon_name(
+            Some("media-playback-stop-symbolic"),
+            gtk::IconSize::SmallToolbar,
+        );
+        action_bar.add(&control_stop_song);
+        control_stop_song.connect_clicked(clone!(@strong sender => move |_| {
+            let mut sender = sender.clone();
+            sender
+                .try_send(StateUpdateKind::PlaybackStateChange(
+                    PlaybackStateChange::Stop,
+                ))
+                .expect("Couldn't notify thread");
+        }));
+
+        let control_next_song = gtk::Button::from_icon_name(
+            Some("media-skip-forward-symbolic"),
+            gtk::IconSize::SmallToolbar,
+        );
+        action_bar.add(&control_next_song);
+        control_next_song.connect_clicked(clone!(@strong sender => move |_| {
+            let mut sender = sender.clone();
+            sender
+                .try_send(StateUpdateKind::PlaybackStateChange(
+                    PlaybackStateChange::SkipForwards,
+                ))
+                .expect("Couldn't notify thread");
+        }));
+
+        // Single and consume modes each have a third setting on newer servers,
+        // where they only last until the current song's done, so they get
+        // buttons that cycle through them rather than switches.
+        let options_bar = gtk::Box::new(gtk::Orientation::Horizontal, 8);
+        options_bar.set_halign(gtk::Align::Center);
+        let single_button = gtk::Button::with_label(&toggle_label("Single", mpd::Toggle::Off));
+        single_button.set_tooltip_text(Some("Stop after the current song"));
+        options_bar.add(&single_button);
+        single_button.connect_clicked(clone!(@strong sender => move |_| {
+            let mut sender = sender.clone();
+            sender
+                .try_send(StateUpdateKind::PlaybackStateChange(
+                    PlaybackStateChange::CycleSingle,
+                ))
+                .expect("Couldn't notify thread");
+        }));
+        let consume_button = gtk::Button::with_label(&toggle_label("Consume", mpd::Toggle::Off));
+        consume_button.set_tooltip_text(Some("Remove songs from the queue once played"));
+        options_bar.add(&consume_button);
+        consume_button.connect_clicked(clone!(@strong sender => move |_| {
+            let mut sender = sender.clone();
+            sender
+                .try_send(StateUpdateKind::PlaybackStateChange(
+                    PlaybackStateChange::CycleConsume,
+                ))
+                .expect("Couldn't notify thread");
+        }));
+
+        let model = gio::ListStore::new(SongObject::static_type());
+        let listbox = gtk::ListBox::new();
+        listbox.bind_model(
+            Some(&model),
+            clone!(@strong sender, @strong genre_colors, @strong list_columns => move |item| {
+                let sender = sender.clone();
+                let item = item
+                    .downcast_ref::<SongObject>()
+                    .expect("Row data is of wrong type");
+
+                let remove_individual_song = gtk::Button::from_icon_name(
+                    Some("list-remove-symbolic"),
+                    gtk::IconSize::SmallToolbar,
+                );
+                let index = item.property::<u32>("index");
+                remove_individual_song.connect_clicked(move |_| {
+                    let mut sender = sender.clone();
+                    sender
+                        .try_send(StateUpdateKind::QueueDeleteRequest(index))
+                        .expect("Couldn't notify thread");
+                    sender
+                        .try_send(StateUpdateKind::MpdEvent)
+                        .expect("Couldn't notify thread");
+                });
+                let action = (!read_only).then(|| remove_individual_song.upcast_ref());
+
+                song_row(item, action, &[], &genre_colors, &list_columns)
+            }),
+        );
+
+        // Holding Ctrl or Shift lets the user pick out several rows of the
+        // queue, which can then be saved off into a stored playlist.
+        listbox.set_selection_mode(if read_only {
+            gtk::SelectionMode::None
+        } else {
+            gtk::SelectionMode::Multiple
+        });
+
+        // Everything in the menu changes the queue, apart from the details.
+        let menu_gesture = (!read_only)
+            .then(|| attach_song_menu(&sender, &listbox, &model, SongListKind::Queue));
+
+        let queue_bar = gtk::Box::new(gtk::Orientation::Horizontal, 4);
+        queue_bar.set_halign(gtk::Align::End);
+
+        let save_selection = gtk::Button::from_icon_name(
+            Some("document-save-symbolic"),
+            gtk::IconSize::SmallToolbar,
+        );
+        save_selection.set_tooltip_text(Some("Save Selection to Playlist"));
+        queue_bar.add(&save_selection);
+        save_selection.connect_clicked(
+            clone!(@strong sender, @weak listbox, @weak model => move |_| {
+                let filenames: Vec<String> = listbox
+                    .selected_rows()
+                    .iter()
+                    .filter_map(|row| model.item(row.index().try_into().ok()?))
+                    .filter_map(|item| item.downcast::<SongObject>().ok())
+                    .map(|item| item.property::<String>("filename"))
+                    .collect();
+                if filenames.is_empty() {
+                    return;
+                }
+
+                let mut sender = sender.clone();
+                sender
+                    .try_send(StateUpdateKind::PlaylistSaveRequest(filenames))
+                    .expect("Couldn't notify thread");
+            }),
+        );
+
+        // Long queues are easier to find things in by typing than scrolling,
+        // so the queue can be narrowed down to songs with matching titles,
+        // artists or albums. List boxes can't filter rows of a bound model
+        // themselves, so we keep the whole queue aside and fill the model
+        // with just the matches.
+        let queue_filter = gtk::SearchEntry::builder()
+            .placeholder_text("Filter Queue")
+            .hexpand(true)
+            .build();
+        let queue_songs = Rc::new(RefCell::new(Vec::new()));
+        // An empty queue says how to fill it, unless there's no filling it
+        // from here, and a filter with no matches says so instead.
+        let queue_hint = if read_only { "" } else { "Search for music to add" };
+        let queue_empty = Rc::new(EmptyState::new("view-list-symbolic", "Queue is Empty", queue_hint));
+        listbox.set_placeholder(Some(queue_empty.as_ref().as_ref()));
+        queue_filter.connect_search_changed(clone!(@weak model, @strong queue_songs, @strong queue_empty => move |queue_filter| {
+            let filter = queue_filter.text();
+            fill_queue_model(&model, &queue_songs.borrow(), &filter);
+            if filter.is_empty() {
+                queue_empty.set("view-list-symbolic", "Queue is Empty", queue_hint);
+            } else {
+                queue_empty.set("edit-find-symbolic", "No Matches", "Nothing in the queue matches the filter");
+            }
+        }));
+
+        let queue_header = gtk::Box::new(gtk::Orientation::Horizontal, 4);
+        queue_header.add(&queue_filter);
+
+        let scrolled_window =
+            gtk::ScrolledWindow::new(gtk::Adjustment::NONE, gtk::Adjustment::NONE);
+        scrolled_window.add(&listbox);
+        scrolled_window.set_vexpand(true);
+
+        scrolled_window.set_hexpand(true);
+
+        // Files dragged in from a file manager are queued just like ones
+        // opened with us.
+        if !read_only {
+            scrolled_window.drag_dest_set(
+                gtk::DestDefaults::ALL,
+                &[gtk::TargetEntry::new("text/uri-list", gtk::TargetFlags::OTHER_APP, 0)],
+                gtk::gdk::DragAction::COPY,
+            );
+            scrolled_window.connect_drag_data_received(clone!(@strong sender => move |_, _, _, _, data, _, _| {
+                let uris: Vec<String> = data.uris().iter().map(|uri| uri.to_string()).collect();
+                if uris.is_empty() {
+                    return;
+                }
+                let mut sender = sender.clone();
+                sender
+                    .try_send(StateUpdateKind::OpenRequest(uris))
+                    .expect("Couldn't notify thread");
+                sender
+                    .try_send(StateUpdateKind::MpdEvent)
+                    .expect("Couldn't notify thread");
+            }));
+        }
+
+        // The queue can be folded away under a summary of what's in it, for
+        // screens without room for it and the art both.
+        let queue_summary = gtk::Label::new(Some("Queue"));
+        queue_summary.set_hexpand(true);
+        queue_summary.set_xalign(0.0);
+        let queue_arrow = gtk::Image::from_icon_name(Some("pan-down-symbolic"), gtk::IconSize::Button);
+        let queue_toggle_content = gtk::Box::new(gtk::Orientation::Horizontal, 6);
+        queue_toggle_content.add(&queue_summary);
+        queue_toggle_content.add(&queue_arrow);
+        let queue_toggle = gtk::ToggleButton::builder()
+            .child(&queue_toggle_content)
+            .relief(gtk::ReliefStyle::None)
+            .active(true)
+            .build();
+        let queue_headings = ColumnHeadings::new(sender.clone(), list_columns.clone(), &[], |_| {});
+        queue_headings.set_columns(if read_only { 0 } else { 1 }, &[]);
+        let queue_contents = gtk::Box::new(gtk::Orientation::Vertical, 2);
+        queue_contents.add(&queue_header);
+        queue_contents.add(&queue_headings.container);
+        queue_contents.add(&scrolled_window);
+        let queue_revealer = gtk::Revealer::builder()
+            .child(&queue_contents)
+            .reveal_child(true)
+            .build();
+        queue_toggle.connect_toggled(clone!(@strong sender, @weak queue_revealer => move |queue_toggle| {
+            let expanded = queue_toggle.is_active();
+            queue_revealer.set_reveal_child(expanded);
+            // Otherwise the scrolled window would still claim the space.
+            queue_revealer.set_vexpand(expanded);
+            queue_arrow.set_from_icon_name(
+                Some(if expanded { "pan-down-symbolic" } else { "pan-end-symbolic" }),
+                gtk::IconSize::Button,
+            );
+            let mut sender = sender.clone();
+            sender
+                .try_send(StateUpdateKind::QueueCollapseChange(!expanded))
+                .expect("Couldn't notify thread");
+        }));
+
+        let queue = gtk::Box::new(gtk::Orientation::Vertical, 2);
+        if !read_only {
+            now_playing.add(&action_bar);
+            now_playing.add(&options_bar);
+            queue_header.add(&queue_bar);
+        }
+        queue.add(&queue_toggle);
+        queue.add(&queue_revealer);
+        container.add(&now_playing);
+        container.add(&queue);
+        container.show_all();
+
+        SongInfo {
+            container,
+            album_art,
+            album_art_scale: Cell::new(0.5),
+            album_art_requests,
+            sender: sender.clone(),
+            album_art_wanted: RefCell::new(None),
+            album_art_generation: Rc::new(Cell::new(0)),
+            album_art_loading,
+            album_art_path,
+            accent,
+            backdrop,
+            song_text,
+            elapsed,
+            playing: Cell::new(false),
+            model,
+            queue_songs,
+            queue_filter,
+            queue_list: listbox,
+            queue_window: scrolled_window,
+            queue_headings,
+            queue_toggle,
+            queue_summary,
+            queue,
+            controls: action_bar,
+            options_bar,
+            mini: Cell::new(false),
+            single_button,
+            consume_button,
+            _menu_gesture: menu_gesture,
+        }
+    }
+
+    pub(crate) fn queue_id_at(&self, row: &gtk::ListBoxRow) -> Option<u32> {
+        self.model
+            .item(u32::try_from(row.index()).ok()?)?
+            .downcast::<SongObject>()
+            .ok()?
+            .queue_id()
+    }
+
+    pub(crate) fn queue_position(&self) -> QueuePosition {
+        let selected = self
+            .queue_list
+            .selected_rows()
+            .iter()
+            .filter_map(|row| self.queue_id_at(row))
+            .collect();
+        // Go by the focused row if there is one, and otherwise the first
+        // selected one.
+        let anchor = self
+            .queue_list
+            .focus_child()
+            .and_then(|x| x.downcast::<gtk::ListBoxRow>().ok())
+            .or_else(|| self.queue_list.selected_rows().into_iter().next())
+            .and_then(|row| {
+                let id = self.queue_id_at(&row)?;
+                let (_, y) = row.translate_coordinates(&self.queue_list, 0, 0)?;
+                let offset = f64::from(y) - self.queue_window.vadjustment().value();
+                Some((id, offset))
+            });
+        QueuePosition {
+            selected,
+            anchor,
+            scroll: self.queue_window.vadjustment().value(),
+        }
+    }
+
+    pub(crate) fn restore_queue_position(&self, position: QueuePosition) {
+        let row_with_id = |id: u32| {
+            (0..self.model.n_items())
+                .find(|&index| {
+                    self.model
+                        .item(index)
+                        .and_then(|item| item.downcast::<SongObject>().ok())
+                        .and_then(|item| item.queue_id())
+                        == Some(id)
+                })
+                .and_then(|index| self.queue_list.row_at_index(i32::try_from(index).ok()?))
+        };
+        for id in &position.selected {
+            if let Some(row) = row_with_id(*id) {
+                self.queue_list.select_row(Some(&row));
+            }
+        }
+
+        // The new rows won't have been laid out until GTK gets a chance to,
+        // so we wait for that before scrolling. If the song we were looking at
+        // is gone, we at least stay as far down as we were.
+        let anchor = position
+            .anchor
+            .and_then(|(id, offset)| Some((row_with_id(id)?, offset)));
+        let listbox = self.queue_list.clone();
+        let adjustment = self.queue_window.vadjustment();
+        glib::idle_add_local_once(move || match anchor {
+            Some((row, offset)) => {
+                if let Some((_, y)) = row.translate_coordinates(&listbox, 0, 0) {
+                    adjustment.set_value(f64::from(y) - offset);
+                }
+            }
+            None => adjustment.set_value(position.scroll),
+        });
+    }
+
+    /// Switch to or from the mini player, which leaves just a thumbnail of
+    /// the cover, the song, and the playback buttons.
+    pub(crate) fn set_mini(&self, mini: bool) {
+        self.mini.set(mini);
+        self.queue.set_visible(!mini);
+        self.options_bar.set_visible(!mini);
+        self.elapsed.as_ref().set_visible(!mini);
+    }
+
+    pub(crate) fn apply_profile(&self, profile: &DisplayProfile) {
+        self.album_art_scale.set(profile.art_size.scale());
+        self.album_art.set_visible(profile.art_size != ArtSize::Hidden);
+        self.container.set_orientation(match profile.layout {
+            Layout::Stacked => gtk::Orientation::Vertical,
+            Layout::SideBySide => gtk::Orientation::Horizontal,
+        });
+        self.queue_toggle.set_active(!profile.queue_collapsed);
+    }
+
+    pub(crate) fn update_album_art(&self, conn: &mut Client) -> anyhow::Result<()> {
+        // There's no sense fetching art nobody will see.
+        if !self.album_art.is_visible() {
+            return Ok(());
+        }
+        let mini = self.mini.get();
+        if let Some(song) = conn.currentsong()? {
+            // If we've been allocated a window, pick the least dimension (width
+            // or height) and scale that dimension by the display profile's art
+            // size to get the size (in pixels) that we'll scale the album art
+            // to. Otherwise, we default to 128. On HiDPI screens, that's in
+            // logical pixels, so the art is made at the screen's real
+            // resolution and drawn back at the logical size.
+            let scale = self.album_art_scale.get();
+            let album_art_size = if mini {
+                MINI_PLAYER_ART_SIZE
+            } else {
+                std::cmp::min(
+                    self.container
+                        .window()
+                        .map(|x| (f64::from(x.width()) * scale) as i32)
+                        .unwrap_or(128),
+                    self.container
+                        .window()
+                        .map(|x| (f64::from(x.height()) * scale) as i32)
+                        .unwrap_or(128),
+                )
+            } * self.container.scale_factor();
+
+            // Fetching happens on another thread, which lets us know when
+            // it's done. There's no need to ask again for what we've got.
+            let name = song
+                .tags
+                .get("Album")
+                .or(song.title.as_ref())
+                .cloned()
+                .unwrap_or_default();
+            let wanted = Some((song.file.clone(), album_art_size, name));
+            if *self.album_art_wanted.borrow() == wanted {
+                return Ok(());
+            }
+            self.album_art_wanted.replace(wanted);
+            self.album_art_path.replace(Some(AlbumArtCache::path(&song)));
+            let generation = self.album_art_generation.get() + 1;
+            self.album_art_generation.set(generation);
+            let request = AlbumArtRequest {
+                song,
+                size: album_art_size,
+                generation,
+            };
+            // The loader thread only stops if something's gone badly wrong
+            // in it, and the rest of the view can carry on without art.
+            if self.album_art_requests.send(request).is_err() {
+                eprintln!("Couldn't request album art: the loader has stopped");
+                let mut sender = self.sender.clone();
+                let _ = sender.try_send(StateUpdateKind::ErrorNotice("Album art has stopped loading".into()));
+                return Ok(());
+            }
+            // Cached art turns up straight away, so the spinner only comes
+            // out when it hasn't after a moment.
+            let current = self.album_art_generation.clone();
+            let loading = self.album_art_loading.clone();
+            glib::timeout_add_local_once(LOADING_DELAY, move || {
+                if current.get() == generation && !loading.is_visible() {
+                    loading.show();
+                    loading.start();
+                }
+            });
+        }
+        Ok(())
+    }
+
+    /// Show `art` fetched for the request numbered `generation`, unless it's
+    /// been asked for since. Songs without any get a placeholder instead.
+    pub(crate) fn show_album_art(&self, generation: u64, art: Option<DecodedArt>) {
+        if generation != self.album_art_generation.get() {
+            return;
+        }
+        self.album_art_loading.stop();
+        self.album_art_loading.hide();
+        let backdrop = art
+            .as_ref()
+            .and_then(|art| art_pixbuf(art).scale_simple(BACKDROP_SIZE, BACKDROP_SIZE, gdk_pixbuf::InterpType::Tiles));
+        self.set_accent(art.as_ref().and_then(|art| art.accent), backdrop.as_ref());
+        self.backdrop.replace(backdrop);
+        self.container.queue_draw();
+        let pixbuf = match art {
+            Some(art) => Some(art_pixbuf(&art)),
+            None => self
+                .album_art_wanted
+                .borrow()
+                .as_ref()
+                .and_then(|(_, size, name)| placeholder_art(name, *size)),
+        };
+        // Images show pixbufs a pixel per logical pixel, which looks soft on
+        // HiDPI screens, but a surface can say what scale it's at.
+        let surface = pixbuf.and_then(|pixbuf| {
+            pixbuf.create_surface(self.container.scale_factor(), self.container.window().as_ref())
+        });
+        self.album_art.set_from_surface(surface.as_ref());
+    }
+
+    /// Tint the header bar with `accent`, the current cover's color, and
+    /// color the playback buttons and progress bar with it too, or put them
+    /// back to normal without one. They sit on the `backdrop`, so the color's
+    /// adjusted until they stand out against it enough.
+    pub(crate) fn set_accent(&self, accent: Option<Rgb>, backdrop: Option<&gdk_pixbuf::Pixbuf>) {
+        let (r, g, b) = match accent {
+            Some(accent) => accent,
+            None => {
+                self.accent.load_from_data(b"").expect("Couldn't load accent stylesheet");
+                return;
+            }
+        };
+        let theme = self
+            .container
+            .style_context()
+            .lookup_color("theme_bg_color")
+            .map_or((255, 255, 255), |x| {
+                ((x.red() * 255.0) as u8, (x.green() * 255.0) as u8, (x.blue() * 255.0) as u8)
+            });
+        // Blurred that far, the backdrop's near enough its average color all
+        // over, which is what shrinking it to a single pixel leaves.
+        let behind = backdrop
+            .and_then(|x| x.scale_simple(1, 1, gdk_pixbuf::InterpType::Tiles))
+            .and_then(|x| match x.read_pixel_bytes().get(..3) {
+                Some(&[r, g, b]) => Some(blend((r, g, b), theme, BACKDROP_ALPHA)),
+                _ => None,
+            })
+            .unwrap_or(theme);
+        let (fr, fg, fb) = readable_on((r, g, b), behind, CONTROL_CONTRAST);
+        let css = format!(
+            "headerbar {{ background-image: linear-gradient(rgba({r}, {g}, {b}, 0.25), rgba({r}, {g}, {b}, 0.25)); }}\n\
+             .transport button {{ color: rgb({fr}, {fg}, {fb}); }}\n\
+             progressbar progress {{ background-color: rgb({fr}, {fg}, {fb}); border-color: rgb({fr}, {fg}, {fb}); }}\n",
+            r = r,
+            g = g,
+            b = b,
+            fr = fr,
+            fg = fg,
+            fb = fb
+        );
+        self.accent
+            .load_from_data(css.as_bytes())
+            .expect("Couldn't load accent stylesheet");
+    }
+
+    /// Build the queue's rows over again, for when the way they're built has
+    /// changed.
+    pub(crate) fn rebuild_rows(&self) {
+        self.model.items_changed(0, self.model.n_items(), self.model.n_items());
+        self.queue_headings.rebuild();
+    }
+
+    /// Stand in for the current song until there's one to show.
+    pub(crate) fn show_placeholder(&self, text: &str) {
+        self.song_text.set_text(text);
+    }
+
+    pub(crate) fn update(&self, conn: &mut Client) -> anyhow::Result<()> {
+        self.update_album_art(conn)?;
+
+        if let Some(song) = conn.currentsong()? {
+            let title = song.title.as_deref().unwrap_or("[Unknown]");
+            let artist = song.artist.as_deref().unwrap_or("[Unknown]");
+            let album = song
+                .tags
+                .get("Album")
+                .map(|x| x.as_str())
+                .unwrap_or("[Unknown]");
+            let text = format!("{}\n{} - {}", title, artist, album);
+            self.song_text.set_text(&text);
+
+            // We'll use `pango` attributes to make the display look nice and
+            // pretty. Scale the title of the song the most, and still make the
+            // other info reasonably large.
+            let attr_list = gtk::pango::AttrList::new();
+            let mut attr = gtk::pango::AttrFloat::new_scale(2.0);
+            attr.set_start_index(0);
+            attr.set_end_index(title.len() as u32);
+            attr_list.insert(attr);
+            let mut attr = gtk::pango::AttrFloat::new_scale(1.5);
+            attr.set_start_index(title.len() as u32 + 1);
+            attr_list.insert(attr);
+
+            self.song_text.set_attributes(Some(&attr_list));
+        }
+
+        let status = conn.status()?;
+        self.playing.set(status.state == mpd::State::Play);
+        self.single_button.set_label(&toggle_label("Single", status.single_mode));
+        self.consume_button.set_label(&toggle_label("Consume", status.consume_mode));
+        self.elapsed.set_position(status.elapsed, status.duration);
+
+        let mut queue_songs = self.queue_songs.borrow_mut();
+        queue_songs.clear();
+        let queue = conn.queue()?;
+        for (i, song) in queue.iter().enumerate() {
+            let object = SongObject::new(song);
+            object.set_index(i.try_into().unwrap());
+            object.set_queue_id(song.place.map(|place| place.id.0));
+            queue_songs.push(object);
+        }
+        let total: Duration = queue.iter().filter_map(|song| song.duration).sum();
+        self.queue_summary.set_text(&format!(
+            "Queue · {} {} · {}",
+            queue.len(),
+            if queue.len() == 1 { "song" } else { "songs" },
+            format_total_duration(total)
+        ));
+
+        // Refilling the model throws away the rows, and with them where the
+        // user was in the queue, so we put that back afterwards.
+        let position = self.queue_position();
+        fill_queue_model(&self.model, &queue_songs, &self.queue_filter.text());
+        self.restore_queue_position(position);
+
+        Ok(())
+    }
+}
+
+/// Where the user was in the queue: the IDs of the selected songs, the song
+/// they were looking at, and how far down the visible part of the queue it was.
+pub(crate) struct QueuePosition {
+    selected: Vec<u32>,
+    anchor: Option<(u32, f64)>,
+    scroll: f64,
+}
+
+/// Fill the queue's model with the songs whose title, artist or album
+/// contains `filter`, ignoring case. Each song keeps its place in the queue
+/// as its index, so removing it still removes the right one.
+fn fill_queue_model(model: &gio::ListStore, songs: &[SongObject], filter: &str) {
+    let filter = filter.to_lowercase();
+    model.remove_all();
+    for song in songs {
+        if filter.is_empty()
+            || ["title", "artist", "album"]
+                .iter()
+                .any(|name| song.property::<String>(name).to_lowercase().contains(&filter))
+        {
+            model.append(song);
+        }
+    }
+}
+
+impl AsRef<gtk::Widget> for SongInfo {
+    fn as_ref(&self) -> &gtk::Widget {
+        self.container.upcast_ref()
+    }
+}
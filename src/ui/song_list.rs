@@ -0,0 +1,742 @@
+// Copyright © 2021-2022 Jakob L. Kreuze <zerodaysfordays@sdf.org>
+//
+// This file is part of Tunes.
+//
+// Tunes is free software; you can redistribute it and/or modify it
+// under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation; either version 3 of the
+// License, or (at your option) any later version.
+//
+// Tunes is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY
+// or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Affero General
+// Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public
+// License along with Tunes. If not, see <http://www.gnu.org/licenses/>.
+
+use futures::channel::mpsc;
+use glib::clone;
+use gtk::prelude::*;
+use gtk::{gio, glib, pango};
+use std::cell::{Cell, RefCell};
+use std::path::PathBuf;
+use std::rc::Rc;
+use tunes_core::{default_genre_color, Config, FormFactor, StateUpdateKind, CONNECTION_SECTION};
+
+use crate::models::SongObject;
+use crate::ui::ResultView;
+
+/// Which of the optional columns song lists show. The title is always there,
+/// and the rest can be turned on or off for each form factor.
+#[derive(Clone)]
+pub(crate) struct ListColumns {
+    pub(crate) config: Rc<RefCell<Config>>,
+    pub(crate) form_factor: Rc<Cell<Option<FormFactor>>>,
+}
+
+impl ListColumns {
+    /// Every optional column, as pairs of `SongObject` property and label.
+    pub(crate) const ALL: [(&'static str, &'static str); 6] = [
+        ("album", "Album"),
+        ("artist", "Artist"),
+        ("genre", "Genre"),
+        ("track", "Track Number"),
+        ("date", "Date"),
+        ("duration", "Duration"),
+    ];
+
+    /// The columns holding tags of arbitrary length, which share out the row
+    /// with the title. The others are short and get a narrow column each.
+    pub(crate) const TEXT: [&'static str; 3] = ["album", "artist", "genre"];
+
+    /// Whether `column` is shown on `form_factor` when nobody's said. Phones
+    /// only have room for the essentials.
+    pub(crate) fn default_shown(form_factor: FormFactor, column: &str) -> bool {
+        match column {
+            "album" | "artist" => true,
+            "genre" => false,
+            _ => form_factor == FormFactor::Desktop,
+        }
+    }
+
+    pub(crate) fn key(column: &str) -> String {
+        format!("column-{}", column)
+    }
+
+    pub(crate) fn shown_on(config: &Config, form_factor: FormFactor, column: &str) -> bool {
+        match config.get(form_factor.section(), &Self::key(column)) {
+            Some("true") => true,
+            Some("false") => false,
+            _ => Self::default_shown(form_factor, column),
+        }
+    }
+
+    /// Whether `column` is shown for the current form factor.
+    pub(crate) fn shown(&self, column: &str) -> bool {
+        let form_factor = self.form_factor.get().unwrap_or(FormFactor::Desktop);
+        Self::shown_on(&self.config.borrow(), form_factor, column)
+    }
+
+    /// Wide windows lay rows out as a table, with columns of a set width and
+    /// no wrapping. Otherwise the tags share out the row, wrapping as they
+    /// need to, which suits narrow screens and fingers better.
+    pub(crate) fn tabular(&self) -> bool {
+        self.form_factor.get() == Some(FormFactor::Desktop)
+    }
+
+    /// Where the widths of table columns are kept.
+    pub(crate) const WIDTHS_SECTION: &'static str = "column-widths";
+
+    /// Width (in pixels) of each action button starting a row, so headings
+    /// can leave room for them.
+    pub(crate) const ACTION_WIDTH: i32 = 34;
+
+    /// The width (in characters) of the table column showing `property`.
+    pub(crate) fn width(&self, property: &str) -> i32 {
+        self.config
+            .borrow()
+            .get(Self::WIDTHS_SECTION, property)
+            .and_then(|x| x.parse().ok())
+            .unwrap_or(match property {
+                "title" => 32,
+                "album" => 26,
+                "artist" => 22,
+                _ => 16,
+            })
+    }
+
+    pub(crate) fn set_width(&self, property: &str, width: i32) {
+        self.config.borrow_mut().set(Self::WIDTHS_SECTION, property, width);
+    }
+
+    /// Quick actions that can go at the start of each search result, as
+    /// triples of name, label and icon.
+    pub(crate) const ACTIONS: [(&'static str, &'static str, &'static str); 5] = [
+        ("add", "Add to Queue", "list-add-symbolic"),
+        ("play-now", "Play Now", "media-playback-start-symbolic"),
+        ("play-next", "Play Next", "media-skip-forward-symbolic"),
+        ("add-album", "Add Album", "media-optical-symbolic"),
+        ("details", "Details", "dialog-information-symbolic"),
+    ];
+
+    /// Whether `action` is on search results for `form_factor` when nobody's
+    /// said. Everything is in the row's menu anyway, so phones just get the
+    /// one button.
+    pub(crate) fn default_action_shown(form_factor: FormFactor, action: &str) -> bool {
+        match action {
+            "add" => true,
+            "play-next" => form_factor == FormFactor::Desktop,
+            _ => false,
+        }
+    }
+
+    pub(crate) fn action_key(action: &str) -> String {
+        format!("action-{}", action)
+    }
+
+    pub(crate) fn action_shown_on(config: &Config, form_factor: FormFactor, action: &str) -> bool {
+        match config.get(form_factor.section(), &Self::action_key(action)) {
+            Some("true") => true,
+            Some("false") => false,
+            _ => Self::default_action_shown(form_factor, action),
+        }
+    }
+
+    /// Whether `action` is on search results for the current form factor.
+    pub(crate) fn action_shown(&self, action: &str) -> bool {
+        let form_factor = self.form_factor.get().unwrap_or(FormFactor::Desktop);
+        Self::action_shown_on(&self.config.borrow(), form_factor, action)
+    }
+}
+
+/// Colors for telling genres apart at a glance. Every genre gets a color picked
+/// from its name, which can be overridden in each form factor's profile.
+#[derive(Clone)]
+pub(crate) struct GenreColors {
+    pub(crate) config: Rc<RefCell<Config>>,
+    pub(crate) form_factor: Rc<Cell<Option<FormFactor>>>,
+}
+
+impl GenreColors {
+    /// Config section holding overrides for the current form factor.
+    pub(crate) fn section(&self) -> String {
+        let form_factor = self.form_factor.get().unwrap_or(FormFactor::Desktop);
+        format!("{}-genre-colors", form_factor.section())
+    }
+
+    /// The color for `genre`, as something like "#4ab3c9".
+    pub(crate) fn color(&self, genre: &str) -> String {
+        self.config
+            .borrow()
+            .get(&self.section(), genre)
+            .filter(|x| gtk::gdk::RGBA::parse(x).is_ok())
+            .map(|x| x.to_string())
+            .unwrap_or_else(|| default_genre_color(genre))
+    }
+
+    pub(crate) fn set_color(&self, genre: &str, rgba: &gtk::gdk::RGBA) {
+        let channel = |x: f64| (x * 255.0).round() as u8;
+        let color = format!(
+            "#{:02x}{:02x}{:02x}",
+            channel(rgba.red()),
+            channel(rgba.green()),
+            channel(rgba.blue())
+        );
+        let section = self.section();
+        self.config.borrow_mut().set(&section, genre, color);
+    }
+
+    /// `label` with a dot in the song's genre color in front of it. Songs
+    /// without a genre don't get a dot.
+    pub(crate) fn with_dot(&self, item: &SongObject, label: &gtk::Label) -> gtk::Box {
+        let container = gtk::Box::new(gtk::Orientation::Horizontal, 4);
+        container.set_halign(gtk::Align::Center);
+
+        let genre = item.property::<String>("genre");
+        if !genre.is_empty() {
+            let dot = gtk::Label::new(None);
+            dot.set_markup(&format!(
+                "<span foreground=\"{}\">●</span>",
+                self.color(&genre)
+            ));
+            dot.set_tooltip_text(Some(&genre));
+            dot.set_valign(gtk::Align::Start);
+            container.add(&dot);
+        }
+        container.add(label);
+        container.show_all();
+        container
+    }
+}
+
+/// A scrollable list of songs, each with a button to add it to the queue.
+pub(crate) struct SongList {
+    pub(crate) results: ResultView,
+}
+
+impl SongList {
+    pub(crate) fn new(sender: mpsc::Sender<StateUpdateKind>, genre_colors: GenreColors, list_columns: ListColumns) -> Self {
+        let results = ResultView::new(sender.clone(), clone!(@strong sender, @strong genre_colors, @strong list_columns => move |item| {
+            let sender = sender.clone();
+            let item = item
+                .downcast_ref::<SongObject>()
+                .expect("Row data is of wrong type");
+
+            let add_individual_song =
+                gtk::Button::from_icon_name(Some("list-add-symbolic"), gtk::IconSize::SmallToolbar);
+            let filename = item.property::<String>("filename");
+            add_individual_song.connect_clicked(move |_| {
+                let filename = filename.clone();
+                let mut sender = sender.clone();
+                sender
+                    .try_send(StateUpdateKind::QueueAddRequest(filename))
+                    .expect("Couldn't notify thread");
+                sender
+                    .try_send(StateUpdateKind::MpdEvent)
+                    .expect("Couldn't notify thread");
+            });
+
+            song_row(item, Some(add_individual_song.upcast_ref()), &[], &genre_colors, &list_columns)
+        }));
+
+        SongList { results }
+    }
+
+    pub(crate) fn set_songs(&self, songs: &[mpd::Song]) {
+        self.results.set_songs(songs.iter());
+    }
+}
+
+impl AsRef<gtk::Widget> for SongList {
+    fn as_ref(&self) -> &gtk::Widget {
+        self.results.as_ref()
+    }
+}
+
+/// Headings for a song list laid out as a table, lined up with the columns of
+/// its rows. Dragging the edge of a heading resizes its column, and clicking
+/// one sorts by it, for lists that can be sorted. They're only shown while
+/// rows are laid out as a table.
+pub(crate) struct ColumnHeadings {
+    pub(crate) container: gtk::Box,
+    pub(crate) list_columns: ListColumns,
+    pub(crate) sender: mpsc::Sender<StateUpdateKind>,
+    /// How many action buttons start each row.
+    actions: Cell<i32>,
+    /// Any columns after the usual ones, as in `song_row`.
+    extra: RefCell<Vec<&'static str>>,
+    /// Pairs of column property and the sort order that goes by it, for the
+    /// columns that can be sorted by.
+    sorts: &'static [(&'static str, &'static str)],
+    /// Called with a sort order when its heading's clicked.
+    pub(crate) sort: Box<dyn Fn(&str)>,
+    /// The resize handles' gestures, which only last as long as they're held.
+    gestures: RefCell<Vec<gtk::GestureDrag>>,
+}
+
+impl ColumnHeadings {
+    /// Width (in pixels) of the grip at the end of each heading.
+    pub(crate) const HANDLE_WIDTH: i32 = 6;
+
+    pub(crate) fn new(
+        sender: mpsc::Sender<StateUpdateKind>,
+        list_columns: ListColumns,
+        sorts: &'static [(&'static str, &'static str)],
+        sort: impl Fn(&str) + 'static,
+    ) -> Rc<Self> {
+        let container = gtk::Box::new(gtk::Orientation::Horizontal, 6);
+        container.style_context().add_class("column-headings");
+        container.set_no_show_all(true);
+        let headings = Rc::new(ColumnHeadings {
+            container,
+            list_columns,
+            sender,
+            actions: Cell::new(0),
+            extra: RefCell::new(Vec::new()),
+            sorts,
+            sort: Box::new(sort),
+            gestures: RefCell::new(Vec::new()),
+        });
+        headings.rebuild();
+        headings
+    }
+
+    /// Line up with rows starting with `actions` buttons and ending with
+    /// `extra` columns.
+    pub(crate) fn set_columns(self: &Rc<Self>, actions: i32, extra: &[&'static str]) {
+        if self.actions.get() != actions || *self.extra.borrow() != extra {
+            self.actions.set(actions);
+            self.extra.replace(extra.to_vec());
+            self.rebuild();
+        }
+    }
+
+    /// Build the headings over again, for when the columns have changed.
+    pub(crate) fn rebuild(self: &Rc<Self>) {
+        for child in self.container.children() {
+            self.container.remove(&child);
+        }
+        self.gestures.borrow_mut().clear();
+        if !self.list_columns.tabular() {
+            self.container.hide();
+            return;
+        }
+
+        let number = |property: &str, width: i32| {
+            let label = gtk::Label::new(Some(column_title(property)));
+            label.set_width_chars(width);
+            label.set_max_width_chars(width);
+            label.set_ellipsize(pango::EllipsizeMode::End);
+            label.set_xalign(1.0);
+            label.style_context().add_class("dim-label");
+            label
+        };
+        if self.list_columns.shown("track") {
+            self.container.add(&number("track", 3));
+        }
+
+        let text_columns = gtk::Box::new(gtk::Orientation::Horizontal, 0);
+        text_columns.set_hexpand(true);
+        let spacer = gtk::Box::new(gtk::Orientation::Horizontal, 0);
+        spacer.set_size_request(self.actions.get() * ListColumns::ACTION_WIDTH, -1);
+        text_columns.add(&spacer);
+        let extra = self.extra.borrow().clone();
+        let properties = ["title"]
+            .into_iter()
+            .chain(ListColumns::TEXT.into_iter().filter(|x| self.list_columns.shown(x)))
+            .chain(extra);
+        for property in properties {
+            text_columns.add(&self.heading(property));
+        }
+        self.container.add(&text_columns);
+
+        for (property, width) in [("date", 4), ("duration", 5)] {
+            if self.list_columns.shown(property) {
+                self.container.add(&number(property, width));
+            }
+        }
+        self.container.show_all();
+        self.container.show();
+    }
+
+    /// The heading for the text column showing `property`, with a grip to
+    /// resize it.
+    pub(crate) fn heading(self: &Rc<Self>, property: &'static str) -> gtk::Box {
+        let width = self.list_columns.width(property);
+        let label = gtk::Label::new(Some(column_title(property)));
+        label.set_width_chars(width);
+        label.set_max_width_chars(width);
+        label.set_ellipsize(pango::EllipsizeMode::End);
+        label.set_xalign(0.0);
+
+        let cell = gtk::Box::new(gtk::Orientation::Horizontal, 0);
+        match self.sorts.iter().find(|(column, _)| *column == property) {
+            Some(&(_, order)) => {
+                let button = gtk::Button::builder()
+                    .child(&label)
+                    .relief(gtk::ReliefStyle::None)
+                    .tooltip_text(&format!("Sort by {}", column_title(property)))
+                    .build();
+                let headings = Rc::downgrade(self);
+                button.connect_clicked(move |_| {
+                    if let Some(headings) = headings.upgrade() {
+                        (headings.sort)(order);
+                    }
+                });
+                cell.add(&button);
+            }
+            None => cell.add(&label),
+        }
+
+        // Dragging the grip previews the new width, and letting go keeps it
+        // and lays the rows out again to match.
+        let handle = gtk::EventBox::new();
+        handle.set_size_request(Self::HANDLE_WIDTH, -1);
+        handle.connect_realize(|handle| {
+            if let Some(window) = handle.window() {
+                window.set_cursor(gtk::gdk::Cursor::from_name(&handle.display(), "col-resize").as_ref());
+            }
+        });
+        let char_width = f64::from(label.pango_context().metrics(None, None).approximate_char_width())
+            / f64::from(pango::SCALE);
+        let resized = move |offset: f64| (width + (offset / char_width).round() as i32).max(4);
+        let gesture = gtk::GestureDrag::new(&handle);
+        gesture.connect_drag_update(clone!(@weak label => move |_, offset, _| {
+            label.set_width_chars(resized(offset));
+            label.set_max_width_chars(resized(offset));
+        }));
+        let (list_columns, sender) = (self.list_columns.clone(), self.sender.clone());
+        gesture.connect_drag_end(move |_, offset, _| {
+            list_columns.set_width(property, resized(offset));
+            let mut sender = sender.clone();
+            sender
+                .try_send(StateUpdateKind::DisplayProfileChange)
+                .expect("Couldn't notify thread");
+        });
+        self.gestures.borrow_mut().push(gesture);
+        cell.add(&handle);
+        cell
+    }
+}
+
+/// What to call the column showing `property` in its heading.
+fn column_title(property: &str) -> &'static str {
+    match property {
+        "title" => "Title",
+        "composer" => "Composer",
+        "work" => "Work",
+        "performer" => "Performer",
+        "track" => "#",
+        _ => ListColumns::ALL
+            .iter()
+            .find(|(column, _)| *column == property)
+            .map_or("", |(_, title)| title),
+    }
+}
+
+/// Build the usual row for a song: an action button (or a few) followed by the
+/// title, whichever other columns are shown, and then any `extra` properties.
+pub(crate) fn song_row(
+    item: &SongObject,
+    action: Option<&gtk::Widget>,
+    extra: &[&str],
+    genre_colors: &GenreColors,
+    list_columns: &ListColumns,
+) -> gtk::Widget {
+    let box_ = gtk::ListBoxRow::new();
+    box_.style_context().add_class("song-row");
+    let tabular = list_columns.tabular();
+    let grid = gtk::Grid::builder().column_homogeneous(!tabular).build();
+    if let Some(action) = action {
+        grid.attach(action, 0, 0, 1, 1);
+    }
+
+    let properties = ["title"]
+        .into_iter()
+        .chain(ListColumns::TEXT.into_iter().filter(|x| list_columns.shown(x)))
+        .chain(extra.iter().copied());
+    for (column, property) in properties.enumerate() {
+        let label = gtk::Label::new(None);
+        if tabular {
+            // Every row's columns are the same width, so they line up
+            // under the headings. Anything too long is cut short.
+            let width = list_columns.width(property);
+            label.set_width_chars(width);
+            label.set_max_width_chars(width);
+            label.set_ellipsize(pango::EllipsizeMode::End);
+            label.set_xalign(0.0);
+            label.set_margin_end(ColumnHeadings::HANDLE_WIDTH);
+            item.bind_property(property, &label, "tooltip-text")
+                .flags(glib::BindingFlags::DEFAULT | glib::BindingFlags::SYNC_CREATE)
+                .build();
+        } else {
+            label.set_line_wrap(true);
+            label.set_line_wrap_mode(pango::WrapMode::WordChar);
+        }
+        item.bind_property(property, &label, "label")
+            .flags(glib::BindingFlags::DEFAULT | glib::BindingFlags::SYNC_CREATE)
+            .build();
+        if property == "title" {
+            let title = genre_colors.with_dot(item, &label);
+            if tabular {
+                title.set_halign(gtk::Align::Start);
+            }
+            grid.attach(&title, column as i32 + 1, 0, 1, 1);
+        } else {
+            grid.attach(&label, column as i32 + 1, 0, 1, 1);
+        }
+    }
+
+    let columns = with_number_columns(item, &grid, list_columns);
+    columns.show_all();
+    // On a desktop, songs can be dragged out into other apps. Phones need
+    // the same gesture for scrolling.
+    if tabular {
+        let drag_box = gtk::EventBox::new();
+        drag_box.add(&columns);
+        attach_song_drag(&drag_box, item, &list_columns.config.borrow());
+        drag_box.show();
+        box_.add(&drag_box);
+    } else {
+        box_.add(&columns);
+    }
+    box_.upcast::<gtk::Widget>()
+}
+
+/// Let `widget` be dragged out as the song `item`: as its file, when we know
+/// where the music is on disk, and as "Artist - Title" text either way.
+fn attach_song_drag(widget: &gtk::EventBox, item: &SongObject, config: &Config) {
+    let file = item.property::<String>("filename");
+    let text = format!("{} - {}", item.property::<String>("artist"), item.property::<String>("title"));
+    let uri = if file.contains("://") {
+        Some(file)
+    } else {
+        config
+            .get(CONNECTION_SECTION, "music-directory")
+            .filter(|x| !x.is_empty())
+            .and_then(|directory| glib::filename_to_uri(PathBuf::from(directory).join(&file), None).ok())
+            .map(String::from)
+    };
+
+    // The target's info says which of the two is being asked for.
+    let mut targets = vec![
+        gtk::TargetEntry::new("UTF8_STRING", gtk::TargetFlags::empty(), 1),
+        gtk::TargetEntry::new("text/plain;charset=utf-8", gtk::TargetFlags::empty(), 1),
+    ];
+    if uri.is_some() {
+        targets.insert(0, gtk::TargetEntry::new("text/uri-list", gtk::TargetFlags::empty(), 0));
+    }
+    widget.drag_source_set(
+        gtk::gdk::ModifierType::BUTTON1_MASK,
+        &targets,
+        gtk::gdk::DragAction::COPY,
+    );
+    widget.drag_source_set_icon_name("audio-x-generic");
+    widget.connect_drag_data_get(move |_, _, data, info, _| match (info, &uri) {
+        (0, Some(uri)) => {
+            data.set_uris(&[uri.as_str()]);
+        }
+        _ => {
+            data.set_text(&text);
+        }
+    });
+}
+
+/// Wrap a row's `grid` of tag columns with the song's track number before it,
+/// and its date and length after. These are short, so they get narrow columns
+/// of their own rather than an equal share of the row.
+fn with_number_columns(item: &SongObject, grid: &gtk::Grid, list_columns: &ListColumns) -> gtk::Box {
+    let column = |property: &str, width: i32| {
+        let label = gtk::Label::new(None);
+        label.set_width_chars(width);
+        label.set_xalign(1.0);
+        label.style_context().add_class("dim-label");
+        item.bind_property(property, &label, "label")
+            .flags(glib::BindingFlags::DEFAULT | glib::BindingFlags::SYNC_CREATE)
+            .build();
+        label
+    };
+
+    let columns = gtk::Box::new(gtk::Orientation::Horizontal, 6);
+    grid.set_hexpand(true);
+    if list_columns.shown("track") {
+        columns.add(&column("track", 3));
+    }
+    columns.add(grid);
+    for (property, width) in [("date", 4), ("duration", 5)] {
+        if list_columns.shown(property) {
+            columns.add(&column(property, width));
+        }
+    }
+    columns
+}
+
+/// Which list a song row is in, which decides what its menu offers.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum SongListKind {
+    /// Songs from the database, as found by searching or browsing.
+    Library,
+    Queue,
+}
+
+/// Give every row of `listbox` a menu of things to do with its song, opened by
+/// right-clicking or long-pressing. The long-press gesture only lasts as long
+/// as it's held onto, so it's returned for the caller to keep.
+pub(crate) fn attach_song_menu(
+    sender: &mpsc::Sender<StateUpdateKind>,
+    listbox: &gtk::ListBox,
+    model: &gio::ListStore,
+    kind: SongListKind,
+) -> gtk::GestureLongPress {
+    let popup = Rc::new(clone!(@strong sender, @weak listbox, @weak model => move |y: f64| {
+        let row = match listbox.row_at_y(y as i32) {
+            Some(row) => row,
+            None => return,
+        };
+        let item = u32::try_from(row.index())
+            .ok()
+            .and_then(|index| model.item(index))
+            .and_then(|x| x.downcast::<SongObject>().ok());
+        if let Some(item) = item {
+            show_song_menu(&sender, &row, &item, kind);
+        }
+    }));
+
+    listbox.connect_button_press_event(clone!(@strong popup => move |_, event| {
+        if event.button() == 3 {
+            popup(event.position().1);
+            gtk::Inhibit(true)
+        } else {
+            gtk::Inhibit(false)
+        }
+    }));
+
+    let long_press = gtk::GestureLongPress::new(listbox);
+    long_press.set_touch_only(true);
+    long_press.connect_pressed(move |_, _, y| popup(y));
+    long_press
+}
+
+/// Pop up the menu for `item`, pointing at its `row`.
+fn show_song_menu(
+    sender: &mpsc::Sender<StateUpdateKind>,
+    row: &gtk::ListBoxRow,
+    item: &SongObject,
+    kind: SongListKind,
+) {
+    let filename = item.property::<String>("filename");
+    let artist = item.property::<String>("artist");
+    let album = item.property::<String>("album");
+    let album_artist = item.property::<String>("album-artist");
+
+    let mut actions = match kind {
+        SongListKind::Library => vec![
+            ("Add to Queue", StateUpdateKind::QueueAddRequest(filename.clone())),
+            ("Play Next", StateUpdateKind::QueuePlayNextRequest(filename.clone())),
+            ("Play Now", StateUpdateKind::QueuePlayNowRequest(filename.clone())),
+        ],
+        SongListKind::Queue => {
+            let index = item.property::<u32>("index");
+            vec![
+                ("Play Now", StateUpdateKind::QueueJumpRequest(index)),
+                ("Play Next", StateUpdateKind::QueueMoveNextRequest(index)),
+                ("Remove from Queue", StateUpdateKind::QueueDeleteRequest(index)),
+            ]
+        }
+    };
+    // These are the placeholders for missing tags, which MPD can't search for.
+    if album != "[Untitled]" {
+        actions.push((
+            "Add Album",
+            StateUpdateKind::QueueAddAlbumRequest(album.clone(), album_artist.clone()),
+        ));
+        actions.push((
+            "Shuffle Album",
+            StateUpdateKind::QueueShuffledAlbumRequest(album, album_artist),
+        ));
+    }
+    if artist != "[No Artist]" {
+        actions.push(("Shuffle Artist", StateUpdateKind::QueueShuffledArtistRequest(artist.clone())));
+        actions.push(("Go to Artist", StateUpdateKind::ShowArtistRequest(artist)));
+    }
+    actions.push(("Edit Labels…", StateUpdateKind::SongLabelsEditRequest(filename.clone())));
+    // Streams aren't anywhere to open.
+    if !filename.contains("://") {
+        actions.push(("Open Containing Folder", StateUpdateKind::OpenFolderRequest(filename.clone())));
+    }
+    actions.push(("Details", StateUpdateKind::SongDetailsRequest(filename)));
+
+    let menu = gtk::Box::new(gtk::Orientation::Vertical, 0);
+    menu.set_border_width(6);
+    for (label, event) in actions {
+        let button = gtk::ModelButton::builder().text(label).build();
+        let sender = sender.clone();
+        button.connect_clicked(move |_| {
+            let mut sender = sender.clone();
+            sender.try_send(event.clone()).expect("Couldn't notify thread");
+            sender
+                .try_send(StateUpdateKind::MpdEvent)
+                .expect("Couldn't notify thread");
+        });
+        menu.add(&button);
+    }
+    menu.show_all();
+
+    let popover = gtk::Popover::builder().relative_to(row).child(&menu).build();
+    popover.popup();
+}
+
+/// Heading for a run of songs from one album in a result list.
+pub(crate) fn album_header(
+    sender: &mpsc::Sender<StateUpdateKind>,
+    album: &str,
+    album_artist: &str,
+) -> gtk::Widget {
+    let header = gtk::Box::new(gtk::Orientation::Horizontal, 8);
+    header.set_border_width(4);
+
+    let label = gtk::Label::new(None);
+    label.set_markup(&format!(
+        "<b>{}</b> — {}",
+        glib::markup_escape_text(album),
+        glib::markup_escape_text(if album_artist.is_empty() { "[No Artist]" } else { album_artist }),
+    ));
+    label.set_hexpand(true);
+    label.set_xalign(0.0);
+    label.set_ellipsize(pango::EllipsizeMode::End);
+    header.add(&label);
+
+    // Songs without an album all get lumped under "[Untitled]", which isn't
+    // something we can ask MPD for.
+    if album != "[Untitled]" {
+        let add_album =
+            gtk::Button::from_icon_name(Some("list-add-symbolic"), gtk::IconSize::SmallToolbar);
+        add_album.set_tooltip_text(Some("Add Album to Queue"));
+        let album = album.to_string();
+        let album_artist = album_artist.to_string();
+        let sender = sender.clone();
+        add_album.connect_clicked(move |_| {
+            let mut sender = sender.clone();
+            sender
+                .try_send(StateUpdateKind::QueueAddAlbumRequest(album.clone(), album_artist.clone()))
+                .expect("Couldn't notify thread");
+            sender
+                .try_send(StateUpdateKind::MpdEvent)
+                .expect("Couldn't notify thread");
+        });
+        header.add(&add_album);
+    }
+
+    header.show_all();
+    header.upcast::<gtk::Widget>()
+}
+
+/// Heading for one disc of a multi-disc album in a result list.
+pub(crate) fn disc_header(disc: u32) -> gtk::Widget {
+    let label = gtk::Label::new(Some(&format!("Disc {}", disc.max(1))));
+    label.set_xalign(0.0);
+    label.set_margin_start(12);
+    label.style_context().add_class("dim-label");
+    label.upcast::<gtk::Widget>()
+}
@@ -0,0 +1,129 @@
+// Copyright © 2021-2022 Jakob L. Kreuze <zerodaysfordays@sdf.org>
+//
+// This file is part of Tunes.
+//
+// Tunes is free software; you can redistribute it and/or modify it
+// under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation; either version 3 of the
+// License, or (at your option) any later version.
+//
+// Tunes is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY
+// or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Affero General
+// Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public
+// License along with Tunes. If not, see <http://www.gnu.org/licenses/>.
+
+use glib::clone;
+use gtk::prelude::*;
+use gtk::{gio, glib};
+use std::cell::Cell;
+use tunes_core::{Config, GENERAL_SECTION};
+
+/// Extra styling that GTK themes don't give us out of the box.
+pub(crate) const STYLESHEET: &str = "
+.compact list row { padding: 0; min-height: 0; }
+.compact button { padding: 2px; min-height: 0; min-width: 0; }
+.kiosk .now-playing { font-size: 200%; }
+.art-overlay { background-color: black; }
+.app-notification.error { border: 1px solid @error_color; }
+.empty-state .title { font-size: 120%; font-weight: bold; }
+";
+
+/// Load the user's own stylesheet, if they've written one, on top of the
+/// theme and ours. It's reloaded whenever it's saved, so the returned monitor
+/// needs keeping around. The major widgets have stable names to style them
+/// by: `#header`, `#now-playing` with `#album-art` and `#now-playing-title`
+/// in it, `#queue`, `#search` and `#browse`. Rows of songs have the
+/// `song-row` class.
+pub(crate) fn load_user_stylesheet() -> Option<gio::FileMonitor> {
+    let path = glib::user_config_dir().join("tunes").join("style.css");
+    let screen = gtk::gdk::Screen::default()?;
+    let provider = gtk::CssProvider::new();
+    // Mistakes in it are only worth a warning; the app looks fine without.
+    provider.connect_parsing_error(|_, section, error| {
+        eprintln!("Couldn't parse user stylesheet, line {}: {}", section.start_line() + 1, error);
+    });
+    let load = clone!(@strong provider, @strong path => move || {
+        let css = std::fs::read(&path).unwrap_or_default();
+        let _ = provider.load_from_data(&css);
+    });
+    load();
+    gtk::StyleContext::add_provider_for_screen(&screen, &provider, gtk::STYLE_PROVIDER_PRIORITY_USER);
+
+    let monitor = gio::File::for_path(&path)
+        .monitor_file(gio::FileMonitorFlags::NONE, gio::Cancellable::NONE)
+        .map_err(|e| eprintln!("Couldn't watch user stylesheet: {}", e))
+        .ok()?;
+    monitor.connect_changed(move |_, _, _, event| {
+        if matches!(
+            event,
+            gio::FileMonitorEvent::ChangesDoneHint | gio::FileMonitorEvent::Created | gio::FileMonitorEvent::Deleted
+        ) {
+            load();
+        }
+    });
+    Some(monitor)
+}
+
+/// Ways the window can look, as pairs of config value and label.
+pub(crate) const THEMES: [(&str, &str); 3] = [("system", "Follow System"), ("light", "Light"), ("dark", "Dark")];
+
+thread_local! {
+    /// What GTK's dark theme setting was before we touched it, to go back to
+    /// when following a system that doesn't say what it prefers.
+    pub(crate) static GTK_PREFERS_DARK: Cell<Option<bool>> = Cell::new(None);
+}
+
+/// Whether the desktop has asked for dark styles, going by the settings
+/// portal's color scheme (1 is dark, 2 light, and 0 no preference).
+fn system_prefers_dark() -> Option<bool> {
+    let bus = gio::bus_get_sync(gio::BusType::Session, gio::Cancellable::NONE).ok()?;
+    let reply = bus
+        .call_sync(
+            Some("org.freedesktop.portal.Desktop"),
+            "/org/freedesktop/portal/desktop",
+            "org.freedesktop.portal.Settings",
+            "Read",
+            Some(&("org.freedesktop.appearance", "color-scheme").to_variant()),
+            None,
+            gio::DBusCallFlags::NONE,
+            1000,
+            gio::Cancellable::NONE,
+        )
+        .ok()?;
+    // Older portals wrap the value in one more variant than they should.
+    let mut value = reply.child_value(0);
+    while let Some(inner) = value.as_variant() {
+        value = inner;
+    }
+    match value.get::<u32>()? {
+        1 => Some(true),
+        2 => Some(false),
+        _ => None,
+    }
+}
+
+/// Switch to the light or dark theme the user picked, or whichever the
+/// system prefers.
+pub(crate) fn apply_theme(config: &Config) {
+    let settings = match gtk::Settings::default() {
+        Some(settings) => settings,
+        None => return,
+    };
+    let original = GTK_PREFERS_DARK.with(|original| match original.get() {
+        Some(dark) => dark,
+        None => {
+            let dark = settings.is_gtk_application_prefer_dark_theme();
+            original.set(Some(dark));
+            dark
+        }
+    });
+    let dark = match config.get(GENERAL_SECTION, "theme") {
+        Some("light") => false,
+        Some("dark") => true,
+        _ => system_prefers_dark().unwrap_or(original),
+    };
+    settings.set_gtk_application_prefer_dark_theme(dark);
+}
@@ -0,0 +1,172 @@
+// Copyright © 2021-2022 Jakob L. Kreuze <zerodaysfordays@sdf.org>
+//
+// This file is part of Tunes.
+//
+// Tunes is free software; you can redistribute it and/or modify it
+// under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation; either version 3 of the
+// License, or (at your option) any later version.
+//
+// Tunes is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY
+// or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Affero General
+// Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public
+// License along with Tunes. If not, see <http://www.gnu.org/licenses/>.
+
+use futures::channel::mpsc;
+use glib::clone;
+use gtk::prelude::*;
+use gtk::{gio, glib};
+use libhandy::ApplicationWindow;
+use std::cell::RefCell;
+use std::rc::Rc;
+use tunes_core::{PlaybackStateChange, StateUpdateKind};
+
+/// Where the system tray switch lives.
+pub(crate) const TRAY_SECTION: &str = "tray";
+
+/// An icon in the system tray, for playback with the window closed. It's a
+/// StatusNotifierItem, which KDE, most other panels, and GNOME's AppIndicator
+/// extension all show. There's no menu over D-Bus, so right-clicking pops up
+/// one of our own.
+pub(crate) struct TrayIcon {
+    pub(crate) bus: gio::DBusConnection,
+    /// The status line for the tooltip.
+    pub(crate) status: Rc<RefCell<String>>,
+}
+
+impl TrayIcon {
+    pub(crate) const PATH: &'static str = "/StatusNotifierItem";
+    pub(crate) const INTERFACE: &'static str = "org.kde.StatusNotifierItem";
+    pub(crate) const XML: &'static str = r#"
+        <node>
+          <interface name="org.kde.StatusNotifierItem">
+            <property name="Category" type="s" access="read"/>
+            <property name="Id" type="s" access="read"/>
+            <property name="Title" type="s" access="read"/>
+            <property name="Status" type="s" access="read"/>
+            <property name="IconName" type="s" access="read"/>
+            <property name="ItemIsMenu" type="b" access="read"/>
+            <property name="ToolTip" type="(sa(iiay)ss)" access="read"/>
+            <method name="Activate"><arg name="x" type="i" direction="in"/><arg name="y" type="i" direction="in"/></method>
+            <method name="SecondaryActivate"><arg name="x" type="i" direction="in"/><arg name="y" type="i" direction="in"/></method>
+            <method name="ContextMenu"><arg name="x" type="i" direction="in"/><arg name="y" type="i" direction="in"/></method>
+            <method name="Scroll"><arg name="delta" type="i" direction="in"/><arg name="orientation" type="s" direction="in"/></method>
+            <signal name="NewToolTip"/>
+          </interface>
+        </node>"#;
+
+    /// Put an icon in the tray, if there's a tray to put it in. Clicking it
+    /// brings back `window`, middle-clicking pauses or plays, and scrolling
+    /// changes the volume.
+    pub(crate) fn new(
+        app: &gtk::Application,
+        window: &ApplicationWindow,
+        sender: &mpsc::Sender<StateUpdateKind>,
+    ) -> Option<Self> {
+        let bus = gio::bus_get_sync(gio::BusType::Session, gio::Cancellable::NONE).ok()?;
+        let interface = gio::DBusNodeInfo::for_xml(Self::XML)
+            .expect("Couldn't parse tray interface")
+            .lookup_interface(Self::INTERFACE)
+            .expect("Couldn't find tray interface");
+        let status = Rc::new(RefCell::new(String::new()));
+
+        let send = clone!(@strong sender => move |action| {
+            let mut sender = sender.clone();
+            sender
+                .try_send(StateUpdateKind::PlaybackStateChange(action))
+                .expect("Couldn't notify thread");
+        });
+        let menu = gtk::Menu::new();
+        for (label, action) in [
+            ("Play or Pause", PlaybackStateChange::TogglePause),
+            ("Next Song", PlaybackStateChange::SkipForwards),
+            ("Previous Song", PlaybackStateChange::SkipBackwards),
+        ] {
+            let item = gtk::MenuItem::with_label(label);
+            item.connect_activate(clone!(@strong send => move |_| send(action.clone())));
+            menu.append(&item);
+        }
+        menu.append(&gtk::SeparatorMenuItem::new());
+        let show = gtk::MenuItem::with_label("Show Tunes");
+        show.connect_activate(clone!(@weak window => move |_| window.present()));
+        menu.append(&show);
+        let quit = gtk::MenuItem::with_label("Quit");
+        quit.connect_activate(clone!(@weak app => move |_| app.quit()));
+        menu.append(&quit);
+        menu.show_all();
+
+        bus.register_object(
+            Self::PATH,
+            &interface,
+            clone!(@weak window => move |_, _, _, _, method, parameters, invocation| {
+                match method {
+                    // Clicking a window that's up front puts it away again.
+                    "Activate" if window.is_visible() && window.is_active() => window.hide(),
+                    "Activate" => window.present(),
+                    "SecondaryActivate" => send(PlaybackStateChange::TogglePause),
+                    "ContextMenu" => menu.popup_easy(0, gtk::current_event_time()),
+                    "Scroll" => {
+                        if let Some((delta, _)) = parameters.get::<(i32, String)>() {
+                            send(PlaybackStateChange::ChangeVolume(if delta > 0 { 5 } else { -5 }));
+                        }
+                    }
+                    _ => {}
+                }
+                invocation.return_value(None);
+            }),
+            clone!(@strong status => move |_, _, _, _, property| {
+                match property {
+                    "Category" => "ApplicationStatus".to_variant(),
+                    "Id" => "space.jakob.Tunes".to_variant(),
+                    "Title" => "Tunes".to_variant(),
+                    "Status" => "Active".to_variant(),
+                    "IconName" => "audio-x-generic".to_variant(),
+                    "ItemIsMenu" => false.to_variant(),
+                    "ToolTip" => (
+                        "",
+                        Vec::<(i32, i32, Vec<u8>)>::new(),
+                        "Tunes",
+                        status.borrow().as_str(),
+                    )
+                        .to_variant(),
+                    _ => "".to_variant(),
+                }
+            }),
+            |_, _, _, _, _, _| false,
+        )
+        .map_err(|e| eprintln!("Couldn't make tray icon: {}", e))
+        .ok()?;
+
+        // Without a tray to show it, the icon isn't there at all.
+        let name = bus.unique_name()?;
+        bus.call_sync(
+            Some("org.kde.StatusNotifierWatcher"),
+            "/StatusNotifierWatcher",
+            "org.kde.StatusNotifierWatcher",
+            "RegisterStatusNotifierItem",
+            Some(&(name.as_str(),).to_variant()),
+            None,
+            gio::DBusCallFlags::NONE,
+            1000,
+            gio::Cancellable::NONE,
+        )
+        .map_err(|e| eprintln!("Couldn't find a system tray: {}", e))
+        .ok()?;
+
+        Some(TrayIcon { bus, status })
+    }
+
+    /// Show `status` (the same line as the header's) in the icon's tooltip.
+    pub(crate) fn set_status(&self, status: &str) {
+        if *self.status.borrow() == status {
+            return;
+        }
+        self.status.replace(status.to_string());
+        let _ = self
+            .bus
+            .emit_signal(None, Self::PATH, Self::INTERFACE, "NewToolTip", None);
+    }
+}
@@ -0,0 +1,146 @@
+// Copyright © 2021-2022 Jakob L. Kreuze <zerodaysfordays@sdf.org>
+//
+// This file is part of Tunes.
+//
+// Tunes is free software; you can redistribute it and/or modify it
+// under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation; either version 3 of the
+// License, or (at your option) any later version.
+//
+// Tunes is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY
+// or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Affero General
+// Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public
+// License along with Tunes. If not, see <http://www.gnu.org/licenses/>.
+
+use gtk::prelude::*;
+use libhandy::ApplicationWindow;
+use std::cell::RefCell;
+use std::rc::Rc;
+use tunes_core::Config;
+
+/// What's new in each version, newest first, for the panel shown once after
+/// an upgrade.
+const CHANGELOG: &[(&str, &[&str])] = &[(
+    "0.1.0",
+    &[
+        "Macros run a series of steps, like loading a playlist and setting the volume, from the menu",
+        "Stored playlists have a page of their own, with a collage of their covers",
+        "Search results can be sorted newest or oldest first",
+        "Click the cover to see it fullscreen",
+        "Wide windows show song lists as a table with resizable columns",
+        "Wide windows show Now Playing beside the library, and phones get a page for each",
+        "Preferences for the server to connect to, the page to open on, and a dark theme",
+        "The window opens at the size and on the page it was left at",
+        "Keyboard shortcuts for playback, volume and searching, which can be changed in the preferences",
+        "A mini player with just the cover, song and playback buttons, from the menu or by making the window short",
+        "An optional system tray icon, with playback controls, that the window can be closed to",
+        "The menu can save the whole queue, update the database, and list the keyboard shortcuts",
+        "The header shows whether the server can be reached, with a button to reconnect when it can't",
+        "Spinners show while searches run and while covers download",
+        "An empty queue or search says so, with a hint, instead of showing a blank list",
+        "On a phone, swipe left from Now Playing to get to the library, and right to get back",
+        "Tunes can be restyled with a style.css next to its config file",
+        "Desktop media controls and playerctl can see and control what's playing, over MPRIS",
+        "The keyboard's media keys play, pause and skip, even with the window in the background on GNOME",
+        "A preference to keep the screen on while playing",
+        "Open files from the file manager with Tunes, or drag them onto the queue, to add them",
+        "Songs can be dragged out of the queue and search results into other apps",
+        "An option to keep running in the background with the window closed",
+        "Changing the server in the preferences connects to it straight away, without a restart",
+        "Without a server in the preferences, Tunes uses MPD_HOST and MPD_PORT like other MPD clients",
+        "Command-line options for the server to use, separate settings profiles, and --help",
+        "Tunes asks for a password when the server turns it away for want of one",
+        "Connect through a Unix socket, such as /run/mpd/socket, by giving its path as the server",
+        "Save servers under names of their own, and switch between them from the menu",
+        "Reconnecting after the server or network drops out is quicker at first, and backs off the longer it's away",
+        "A server that stops answering shows as disconnected after ten seconds, instead of freezing Tunes",
+        "Reach servers through an SSH tunnel, set per server in the preferences",
+        "Each saved server keeps its own Music Folder, and songs can open their containing folder",
+        "While the server's away, the queue stays up, and songs added are saved to add once it's back",
+    ],
+)];
+
+/// Where we keep track of what the user's already been shown, so nothing's
+/// pointed out twice.
+const SEEN_SECTION: &str = "seen";
+
+/// Hints pointing out something that's easy to miss on each page, given as
+/// pairs of the page's name and the hint.
+const PAGE_HINTS: [(&str, &str); 3] = [
+    ("current_song", "Click the cover to see it bigger, or tap the queue's heading to fold it away."),
+    ("query_songs", "Right-click or long-press a result for more things to do with it."),
+    ("browse", "Stored playlists and smart playlists have pages of their own here."),
+];
+
+/// The parts of a version like "0.10.2", for comparing them.
+fn version_parts(version: &str) -> Vec<u32> {
+    version.split('.').map(|x| x.parse().unwrap_or(0)).collect()
+}
+
+/// After an upgrade, show what's changed since the version last run. A fresh
+/// install has nothing to compare to, so it just makes a note of the version.
+pub(crate) fn show_whats_new(window: &ApplicationWindow, config: &Rc<RefCell<Config>>) {
+    let current = env!("CARGO_PKG_VERSION");
+    let previous = config.borrow().get(SEEN_SECTION, "version").map(String::from);
+    if previous.as_deref() != Some(current) {
+        config.borrow_mut().set(SEEN_SECTION, "version", current);
+    }
+    let previous = match previous {
+        Some(previous) => version_parts(&previous),
+        None => return,
+    };
+    let news: Vec<String> = CHANGELOG
+        .iter()
+        .filter(|(version, _)| version_parts(version) > previous)
+        .flat_map(|(_, items)| items.iter().map(|item| format!("• {}", item)))
+        .collect();
+    if news.is_empty() {
+        return;
+    }
+
+    let dialog = gtk::MessageDialog::builder()
+        .transient_for(window)
+        .modal(true)
+        .destroy_with_parent(true)
+        .message_type(gtk::MessageType::Info)
+        .buttons(gtk::ButtonsType::Close)
+        .text(&format!("What's New in Tunes {}", current))
+        .secondary_text(&news.join("\n"))
+        .build();
+    dialog.connect_response(|dialog, _| dialog.close());
+    dialog.show_all();
+}
+
+/// The first time the user opens a page, point out anything on it that's easy
+/// to miss.
+pub(crate) fn show_page_hint(page: &str, child: &gtk::Widget, config: &Rc<RefCell<Config>>) {
+    let hint = match PAGE_HINTS.iter().find(|(name, _)| *name == page) {
+        Some((_, hint)) => hint,
+        None => return,
+    };
+    let key = format!("hint-{}", page);
+    if config.borrow().get(SEEN_SECTION, &key).is_some() {
+        return;
+    }
+    config.borrow_mut().set(SEEN_SECTION, &key, true);
+
+    let label = gtk::Label::new(Some(hint));
+    label.set_line_wrap(true);
+    label.set_max_width_chars(32);
+    label.set_border_width(8);
+    let popover = gtk::Popover::builder()
+        .relative_to(child)
+        .position(gtk::PositionType::Top)
+        .child(&label)
+        .build();
+    // Pointing at the middle of the page would cover the very thing it's
+    // about, so it comes down from the top instead.
+    let width = child.allocated_width();
+    popover.set_pointing_to(&gtk::gdk::Rectangle::new(width / 2, 0, 1, 1));
+    popover.set_position(gtk::PositionType::Bottom);
+    label.show();
+    popover.popup();
+}
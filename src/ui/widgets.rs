@@ -0,0 +1,491 @@
+// Copyright © 2021-2022 Jakob L. Kreuze <zerodaysfordays@sdf.org>
+//
+// This file is part of Tunes.
+//
+// Tunes is free software; you can redistribute it and/or modify it
+// under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation; either version 3 of the
+// License, or (at your option) any later version.
+//
+// Tunes is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY
+// or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Affero General
+// Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public
+// License along with Tunes. If not, see <http://www.gnu.org/licenses/>.
+
+use futures::channel::mpsc;
+use glib::clone;
+use gtk::glib;
+use gtk::prelude::*;
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+use std::sync::atomic::Ordering;
+use std::time::{Duration, Instant};
+use tunes_core::{Client, ConnectionState, StateUpdateKind};
+
+use crate::backend::Options;
+use crate::models::{format_duration, Clock, ClockSource, TIMER_WAKEUPS};
+
+/// A small icon in the header saying whether we're connected, with a button
+/// for trying again straight away when we're not.
+pub(crate) struct ConnectionIndicator {
+    pub(crate) container: gtk::Box,
+    icon: gtk::Image,
+    reconnect: gtk::Button,
+    /// For the server's address, which can change.
+    pub(crate) options: Options,
+    pub(crate) state: Cell<ConnectionState>,
+    /// Widgets that are no use while we're offline.
+    offline_widgets: RefCell<Vec<gtk::Widget>>,
+}
+
+impl ConnectionIndicator {
+    pub(crate) fn new(sender: &mpsc::Sender<StateUpdateKind>, options: &Options) -> Self {
+        let container = gtk::Box::new(gtk::Orientation::Horizontal, 4);
+        let icon = gtk::Image::new();
+        container.add(&icon);
+        let reconnect = gtk::Button::with_label("Reconnect");
+        reconnect.connect_clicked(clone!(@strong sender => move |_| {
+            let mut sender = sender.clone();
+            sender
+                .try_send(StateUpdateKind::ReconnectRequest)
+                .expect("Couldn't notify thread");
+        }));
+        // It's only for when we're offline, whatever `show_all` says.
+        reconnect.set_no_show_all(true);
+        container.add(&reconnect);
+
+        let indicator = ConnectionIndicator {
+            container,
+            icon,
+            reconnect,
+            options: options.clone(),
+            state: Cell::new(ConnectionState::Connecting),
+            offline_widgets: Default::default(),
+        };
+        indicator.set_state(ConnectionState::Connecting);
+        indicator
+    }
+
+    pub(crate) fn set_state(&self, state: ConnectionState) {
+        let (icon, tooltip) = match state {
+            ConnectionState::Connecting => ("network-wireless-acquiring-symbolic", "Connecting to"),
+            ConnectionState::Connected => ("network-transmit-receive-symbolic", "Connected to"),
+            ConnectionState::Offline => ("network-offline-symbolic", "Couldn't reach"),
+        };
+        self.icon.set_from_icon_name(Some(icon), gtk::IconSize::SmallToolbar);
+        self.icon.set_tooltip_text(Some(&format!("{} {}", tooltip, self.options.host())));
+        self.reconnect.set_visible(state == ConnectionState::Offline);
+        for widget in self.offline_widgets.borrow().iter() {
+            widget.set_sensitive(state != ConnectionState::Offline);
+        }
+        self.state.set(state);
+    }
+
+    pub(crate) fn state(&self) -> ConnectionState {
+        self.state.get()
+    }
+
+    /// Grey out `widget` whenever we're offline.
+    pub(crate) fn disable_while_offline(&self, widget: &impl IsA<gtk::Widget>) {
+        self.offline_widgets.borrow_mut().push(widget.clone().upcast());
+    }
+}
+
+impl AsRef<gtk::Widget> for ConnectionIndicator {
+    fn as_ref(&self) -> &gtk::Widget {
+        self.container.upcast_ref()
+    }
+}
+
+/// How long a toast stays up before hiding itself.
+const TOAST_TIMEOUT: Duration = Duration::from_secs(4);
+
+/// A short message that pops up over the bottom of the window for a few
+/// seconds, optionally with a button offering something to do about it.
+/// Errors stay up until they're dismissed.
+pub(crate) struct Toast {
+    revealer: gtk::Revealer,
+    frame: gtk::Box,
+    pub(crate) label: gtk::Label,
+    action_button: gtk::Button,
+    action: Rc<RefCell<Option<Box<dyn Fn()>>>>,
+    pub(crate) timeout: Rc<RefCell<Option<ClockSource>>>,
+}
+
+impl Toast {
+    pub(crate) fn new() -> Self {
+        let revealer = gtk::Revealer::builder()
+            .halign(gtk::Align::Center)
+            .valign(gtk::Align::End)
+            .transition_type(gtk::RevealerTransitionType::SlideUp)
+            .build();
+
+        let frame = gtk::Box::new(gtk::Orientation::Horizontal, 8);
+        frame.style_context().add_class("app-notification");
+
+        let label = gtk::Label::new(None);
+        label.set_line_wrap(true);
+        frame.add(&label);
+
+        let action: Rc<RefCell<Option<Box<dyn Fn()>>>> = Rc::new(RefCell::new(None));
+        let timeout: Rc<RefCell<Option<ClockSource>>> = Rc::new(RefCell::new(None));
+
+        let action_button = gtk::Button::new();
+        action_button.connect_clicked(
+            clone!(@strong action, @strong timeout, @weak revealer => move |_| {
+                if let Some(action) = action.borrow().as_ref() {
+                    action();
+                }
+                Toast::dismiss(&revealer, &timeout);
+            }),
+        );
+        frame.add(&action_button);
+
+        let close_button =
+            gtk::Button::from_icon_name(Some("window-close-symbolic"), gtk::IconSize::SmallToolbar);
+        close_button.set_relief(gtk::ReliefStyle::None);
+        close_button.connect_clicked(clone!(@strong timeout, @weak revealer => move |_| {
+            Toast::dismiss(&revealer, &timeout);
+        }));
+        frame.add(&close_button);
+
+        revealer.add(&frame);
+        revealer.show_all();
+
+        Toast {
+            revealer,
+            frame,
+            label,
+            action_button,
+            action,
+            timeout,
+        }
+    }
+
+    /// Pop up `message`, replacing whatever toast was already showing.
+    pub(crate) fn show(&self, message: &str) {
+        self.action.replace(None);
+        self.action_button.hide();
+        self.reveal(message, true);
+    }
+
+    /// Pop up an error, which stays until it's dismissed so it can't be
+    /// missed.
+    pub(crate) fn show_error(&self, message: &str) {
+        self.action.replace(None);
+        self.action_button.hide();
+        self.frame.style_context().add_class("error");
+        self.reveal(message, false);
+    }
+
+    /// Pop up `message` with a button labelled `action_label` that calls
+    /// `action` when clicked.
+    pub(crate) fn show_with_action(&self, message: &str, action_label: &str, action: impl Fn() + 'static) {
+        self.action.replace(Some(Box::new(action)));
+        self.action_button.set_label(action_label);
+        self.action_button.show();
+        self.reveal(message, true);
+    }
+
+    /// Show `message`, hiding it again after a while if `expires`.
+    pub(crate) fn reveal(&self, message: &str, expires: bool) {
+        if expires {
+            self.frame.style_context().remove_class("error");
+        }
+        self.label.set_text(message);
+        self.revealer.set_reveal_child(true);
+
+        if let Some(timeout) = self.timeout.take() {
+            timeout.remove();
+        }
+        if !expires {
+            return;
+        }
+        let revealer = self.revealer.clone();
+        let timeout = self.timeout.clone();
+        let source = Clock::get().timeout_once(TOAST_TIMEOUT, move || {
+            // The source is finished once this runs, so it mustn't be removed
+            // again when dismissing.
+            timeout.replace(None);
+            revealer.set_reveal_child(false);
+        });
+        self.timeout.replace(Some(source));
+    }
+
+    pub(crate) fn dismiss(revealer: &gtk::Revealer, timeout: &RefCell<Option<ClockSource>>) {
+        if let Some(timeout) = timeout.take() {
+            timeout.remove();
+        }
+        revealer.set_reveal_child(false);
+    }
+}
+
+impl AsRef<gtk::Widget> for Toast {
+    fn as_ref(&self) -> &gtk::Widget {
+        self.revealer.upcast_ref()
+    }
+}
+
+/// Label for a button cycling through a mode.
+pub(crate) fn toggle_label(name: &str, mode: mpd::Toggle) -> String {
+    let state = match mode {
+        mpd::Toggle::Off => "Off",
+        mpd::Toggle::On => "On",
+        mpd::Toggle::Oneshot => "Once",
+    };
+    format!("{}: {}", name, state)
+}
+
+/// Below this width (in pixels), the header shows an icon for the playback
+/// state instead of spelling it out.
+const HEADER_COMPACT_WIDTH: i32 = 500;
+
+/// Below this width (in pixels), the header drops the artist entirely.
+const HEADER_NARROW_WIDTH: i32 = 360;
+
+/// The pieces of the short status line shown in the header bar. We hold on to
+/// these rather than a finished string so that the line can be re-rendered to
+/// fit whenever the header bar changes size.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct HeaderTitle {
+    pub(crate) state: mpd::status::State,
+    /// Title and artist of the current song, if there is one.
+    pub(crate) song: Option<(String, String)>,
+    /// Whether we're still waiting to hear from the server at all.
+    connecting: bool,
+}
+
+impl HeaderTitle {
+    /// What to show before the server's said anything.
+    pub(crate) fn connecting() -> Self {
+        HeaderTitle {
+            state: mpd::status::State::Stop,
+            song: None,
+            connecting: true,
+        }
+    }
+
+    /// Render the status line for a header bar that's `width` pixels wide.
+    pub(crate) fn render(&self, width: i32) -> String {
+        let (title, artist) = match &self.song {
+            Some(song) => song,
+            None if self.connecting => return "Tunes: Connecting…".into(),
+            None => return "Tunes: No Song".into(),
+        };
+
+        let state_descriptor = match (self.state, width < HEADER_COMPACT_WIDTH) {
+            (mpd::status::State::Stop, false) => "[STOPPED]",
+            (mpd::status::State::Pause, false) => "[PAUSED]",
+            (mpd::status::State::Play, false) => "[PLAYING]",
+            (mpd::status::State::Stop, true) => "⏹",
+            (mpd::status::State::Pause, true) => "⏸",
+            (mpd::status::State::Play, true) => "▶",
+        };
+
+        // This is a rough guess at how many characters fit, but it keeps the
+        // title from shoving the view switcher out of the way on phones.
+        let budget = usize::try_from(width / 10).unwrap_or(0).max(12);
+        if width < HEADER_NARROW_WIDTH {
+            format!("{} {}", state_descriptor, truncate_chars(title, budget))
+        } else {
+            format!(
+                "{} {} - {}",
+                state_descriptor,
+                truncate_chars(title, budget * 2 / 3),
+                truncate_chars(artist, budget / 3),
+            )
+        }
+    }
+}
+
+/// Shorten `text` to at most `max` characters, marking the cut with an
+/// ellipsis. This counts `char`s rather than bytes, so it never splits a
+/// multi-byte character.
+fn truncate_chars(text: &str, max: usize) -> String {
+    if text.chars().count() <= max {
+        text.into()
+    } else {
+        let mut truncated: String = text.chars().take(max.saturating_sub(1)).collect();
+        truncated.push('…');
+        truncated
+    }
+}
+
+/// Produce a short status line for the current state of `conn`.
+pub(crate) fn header_title(conn: &mut Client) -> anyhow::Result<HeaderTitle> {
+    let state = conn.status()?.state;
+    let song = conn.currentsong()?.map(|song| {
+        (
+            song.title.unwrap_or_else(|| "Untitled".into()),
+            song.artist.unwrap_or_else(|| "Untitled".into()),
+        )
+    });
+    Ok(HeaderTitle {
+        state,
+        song,
+        connecting: false,
+    })
+}
+
+/// How far into the current song we are, kept moving between MPD events by a
+/// once-a-second timer.
+pub(crate) struct ElapsedTicker {
+    pub(crate) container: gtk::Box,
+    bar: gtk::ProgressBar,
+    pub(crate) label: gtk::Label,
+    /// Elapsed time as of some instant, and the song's duration.
+    pub(crate) position: Rc<Cell<Option<(Duration, Instant, Option<Duration>)>>>,
+    pub(crate) source: RefCell<Option<ClockSource>>,
+}
+
+impl ElapsedTicker {
+    pub(crate) fn new() -> Self {
+        let container = gtk::Box::new(gtk::Orientation::Vertical, 2);
+        let bar = gtk::ProgressBar::new();
+        let label = gtk::Label::new(None);
+        label.style_context().add_class("dim-label");
+        container.add(&bar);
+        container.add(&label);
+
+        ElapsedTicker {
+            container,
+            bar,
+            label,
+            position: Rc::new(Cell::new(None)),
+            source: RefCell::new(None),
+        }
+    }
+
+    pub(crate) fn set_position(&self, elapsed: Option<Duration>, duration: Option<Duration>) {
+        self.position
+            .set(elapsed.map(|elapsed| (elapsed, Clock::get().now(), duration)));
+        Self::render(&self.bar, &self.label, self.position.get(), false);
+    }
+
+    /// Start or stop the timer, returning whether that changed anything.
+    pub(crate) fn set_running(&self, running: bool) -> bool {
+        let mut source = self.source.borrow_mut();
+        match (running, source.is_some()) {
+            (true, false) => {
+                let (bar, label, position) =
+                    (self.bar.clone(), self.label.clone(), self.position.clone());
+                *source = Some(Clock::get().timeout_seconds(1, move || {
+                    TIMER_WAKEUPS.fetch_add(1, Ordering::Relaxed);
+                    ElapsedTicker::render(&bar, &label, position.get(), true);
+                    glib::Continue(true)
+                }));
+                true
+            }
+            (false, true) => {
+                source.take().unwrap().remove();
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Show the position. While `playing`, time keeps moving on from when
+    /// MPD last told us where it was.
+    pub(crate) fn render(
+        bar: &gtk::ProgressBar,
+        label: &gtk::Label,
+        position: Option<(Duration, Instant, Option<Duration>)>,
+        playing: bool,
+    ) {
+        let (elapsed, since, duration) = match position {
+            Some(x) => x,
+            None => {
+                bar.set_visible(false);
+                label.set_visible(false);
+                return;
+            }
+        };
+        let mut elapsed = elapsed;
+        if playing {
+            elapsed += Clock::get().now().saturating_duration_since(since);
+        }
+        if let Some(duration) = duration {
+            elapsed = elapsed.min(duration);
+        }
+
+        // Streams don't have a duration, so there's nothing to fill a bar.
+        bar.set_visible(duration.is_some());
+        bar.set_fraction(match duration {
+            Some(duration) if !duration.is_zero() => {
+                elapsed.as_secs_f64() / duration.as_secs_f64()
+            }
+            _ => 0.0,
+        });
+        label.set_visible(true);
+        label.set_text(&match duration {
+            Some(duration) => format!("{} / {}", format_duration(elapsed), format_duration(duration)),
+            None => format_duration(elapsed),
+        });
+    }
+}
+
+impl AsRef<gtk::Widget> for ElapsedTicker {
+    fn as_ref(&self) -> &gtk::Widget {
+        self.container.upcast_ref()
+    }
+}
+
+/// A big dim icon over a line or two of text, for lists with nothing in them,
+/// so they don't just look blank.
+pub(crate) struct EmptyState {
+    pub(crate) container: gtk::Box,
+    icon: gtk::Image,
+    pub(crate) title: gtk::Label,
+    hint: gtk::Label,
+}
+
+impl EmptyState {
+    pub(crate) fn new(icon: &str, title: &str, hint: &str) -> Self {
+        let container = gtk::Box::new(gtk::Orientation::Vertical, 8);
+        container.set_valign(gtk::Align::Center);
+        container.set_margin_top(24);
+        container.set_margin_bottom(24);
+        container.style_context().add_class("empty-state");
+        let icon_image = gtk::Image::new();
+        icon_image.set_pixel_size(64);
+        icon_image.style_context().add_class("dim-label");
+        container.add(&icon_image);
+        let title_label = gtk::Label::new(None);
+        title_label.style_context().add_class("title");
+        title_label.set_line_wrap(true);
+        title_label.set_justify(gtk::Justification::Center);
+        container.add(&title_label);
+        let hint_label = gtk::Label::new(None);
+        hint_label.style_context().add_class("dim-label");
+        hint_label.set_line_wrap(true);
+        hint_label.set_justify(gtk::Justification::Center);
+        container.add(&hint_label);
+        container.show_all();
+
+        let empty = EmptyState {
+            container,
+            icon: icon_image,
+            title: title_label,
+            hint: hint_label,
+        };
+        empty.set(icon, title, hint);
+        empty
+    }
+
+    pub(crate) fn set(&self, icon: &str, title: &str, hint: &str) {
+        self.icon.set_from_icon_name(Some(icon), gtk::IconSize::Dialog);
+        self.icon.set_pixel_size(64);
+        self.title.set_text(title);
+        self.hint.set_text(hint);
+        self.hint.set_visible(!hint.is_empty());
+    }
+}
+
+impl AsRef<gtk::Widget> for EmptyState {
+    fn as_ref(&self) -> &gtk::Widget {
+        self.container.upcast_ref()
+    }
+}
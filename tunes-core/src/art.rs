@@ -0,0 +1,117 @@
+// Copyright © 2021-2022 Jakob L. Kreuze <zerodaysfordays@sdf.org>
+//
+// This file is part of Tunes.
+//
+// Tunes is free software; you can redistribute it and/or modify it
+// under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation; either version 3 of the
+// License, or (at your option) any later version.
+//
+// Tunes is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY
+// or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Affero General
+// Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public
+// License along with Tunes. If not, see <http://www.gnu.org/licenses/>.
+
+use crate::Rgb;
+
+/// Album art that's been decoded and scaled, ready to show. Pixbufs can't go
+/// between threads, so it comes over as raw pixels, eight bits a channel.
+#[derive(Debug, Clone)]
+pub struct DecodedArt {
+    pub pixels: Vec<u8>,
+    pub width: i32,
+    pub height: i32,
+    /// How many bytes there are to a row, which can be more than the pixels
+    /// in it take up.
+    pub rowstride: i32,
+    pub has_alpha: bool,
+    /// The cover's most prominent color, if it has one to speak of.
+    pub accent: Option<Rgb>,
+}
+
+impl DecodedArt {
+    pub fn new(pixels: Vec<u8>, width: i32, height: i32, rowstride: i32, has_alpha: bool) -> Self {
+        let channels = if has_alpha { 4 } else { 3 };
+        let accent = Self::accent(&pixels, width, height, rowstride, channels);
+        DecodedArt {
+            pixels,
+            width,
+            height,
+            rowstride,
+            has_alpha,
+            accent,
+        }
+    }
+
+    /// The most prominent color in some pixels, favoring colorful ones over
+    /// the greys, blacks and whites that most covers are mostly made of.
+    /// Similar colors are counted together, then averaged.
+    fn accent(pixels: &[u8], width: i32, height: i32, rowstride: i32, channels: i32) -> Option<Rgb> {
+        // Four bits per channel is plenty to tell colors apart.
+        let mut buckets = vec![(0.0f64, 0.0f64, 0.0f64, 0.0f64); 1 << 12];
+        // Every pixel is far more than we need.
+        let step = (width.max(height) / 64).max(1) as usize;
+        for y in (0..height as usize).step_by(step) {
+            for x in (0..width as usize).step_by(step) {
+                let offset = y * rowstride as usize + x * channels as usize;
+                let (r, g, b) = match pixels.get(offset..offset + 3) {
+                    Some(&[r, g, b]) => (r, g, b),
+                    _ => continue,
+                };
+                let max = r.max(g).max(b);
+                let min = r.min(g).min(b);
+                let saturation = if max == 0 { 0.0 } else { f64::from(max - min) / f64::from(max) };
+                // Dark colors and greys don't make for much of an accent.
+                if max < 48 || saturation < 0.2 {
+                    continue;
+                }
+                let bucket = &mut buckets[(r as usize >> 4) << 8 | (g as usize >> 4) << 4 | b as usize >> 4];
+                bucket.0 += saturation * f64::from(r);
+                bucket.1 += saturation * f64::from(g);
+                bucket.2 += saturation * f64::from(b);
+                bucket.3 += saturation;
+            }
+        }
+        let &(r, g, b, weight) = buckets
+            .iter()
+            .max_by(|a, b| a.3.partial_cmp(&b.3).unwrap_or(std::cmp::Ordering::Equal))?;
+        if weight == 0.0 {
+            return None;
+        }
+        Some(((r / weight) as u8, (g / weight) as u8, (b / weight) as u8))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A `width` by `width` picture, each pixel colored by `color`.
+    fn picture(width: i32, color: impl Fn(i32, i32) -> Rgb) -> DecodedArt {
+        let mut pixels = Vec::new();
+        for y in 0..width {
+            for x in 0..width {
+                let (r, g, b) = color(x, y);
+                pixels.extend([r, g, b]);
+            }
+        }
+        DecodedArt::new(pixels, width, width, width * 3, false)
+    }
+
+    #[test]
+    fn picks_out_the_colorful_part() {
+        // Mostly a grey page, with a red band across the top.
+        let art = picture(16, |_, y| if y < 4 { (200, 30, 30) } else { (128, 128, 128) });
+        let (r, g, b) = art.accent.unwrap();
+        assert!(r > 180 && g < 50 && b < 50);
+    }
+
+    #[test]
+    fn has_no_accent_in_black_and_white() {
+        let art = picture(16, |x, y| if (x + y) % 2 == 0 { (0, 0, 0) } else { (255, 255, 255) });
+        assert_eq!(art.accent, None);
+    }
+}
@@ -0,0 +1,141 @@
+// Copyright © 2021-2022 Jakob L. Kreuze <zerodaysfordays@sdf.org>
+//
+// This file is part of Tunes.
+//
+// Tunes is free software; you can redistribute it and/or modify it
+// under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation; either version 3 of the
+// License, or (at your option) any later version.
+//
+// Tunes is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY
+// or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Affero General
+// Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public
+// License along with Tunes. If not, see <http://www.gnu.org/licenses/>.
+
+/// A color in sRGB, eight bits a channel.
+pub type Rgb = (u8, u8, u8);
+
+/// How much contrast WCAG asks for between controls and what's behind them.
+/// Text wants more, but icons and bars get by with this.
+pub const CONTROL_CONTRAST: f64 = 3.0;
+
+/// How bright `color` looks, from 0 for black to 1 for white, the way WCAG
+/// works it out.
+pub fn luminance((r, g, b): Rgb) -> f64 {
+    let linear = |channel: u8| {
+        let x = f64::from(channel) / 255.0;
+        if x <= 0.03928 {
+            x / 12.92
+        } else {
+            ((x + 0.055) / 1.055).powf(2.4)
+        }
+    };
+    0.2126 * linear(r) + 0.7152 * linear(g) + 0.0722 * linear(b)
+}
+
+/// WCAG's contrast ratio between two colors, from 1 for the same color to 21
+/// for black on white. Which is which doesn't matter.
+pub fn contrast_ratio(a: Rgb, b: Rgb) -> f64 {
+    let (a, b) = (luminance(a), luminance(b));
+    (a.max(b) + 0.05) / (a.min(b) + 0.05)
+}
+
+/// `over` painted at `alpha` on top of `under`.
+pub fn blend(over: Rgb, under: Rgb, alpha: f64) -> Rgb {
+    let mix = |over: u8, under: u8| (f64::from(over) * alpha + f64::from(under) * (1.0 - alpha)).round() as u8;
+    (mix(over.0, under.0), mix(over.1, under.1), mix(over.2, under.2))
+}
+
+/// `color`, made only as much lighter or darker as it takes to have
+/// `minimum` contrast against `background`. It heads for whichever of black
+/// and white stands out more, and both of those have plenty against anything.
+pub fn readable_on(color: Rgb, background: Rgb, minimum: f64) -> Rgb {
+    let target = match contrast_ratio((0, 0, 0), background) > contrast_ratio((255, 255, 255), background) {
+        true => (0, 0, 0),
+        false => (255, 255, 255),
+    };
+    (0..=20)
+        .map(|step| blend(target, color, f64::from(step) / 20.0))
+        .find(|&x| contrast_ratio(x, background) >= minimum)
+        .unwrap_or(target)
+}
+
+/// A color for `genre` that's the same every time, so genres keep their colors
+/// between runs without having to store them.
+pub fn default_genre_color(genre: &str) -> String {
+    // FNV-1a picks the hue; saturation and lightness are fixed so every color
+    // reads well on both light and dark themes.
+    let hash = genre
+        .to_lowercase()
+        .bytes()
+        .fold(0x811c9dc5u32, |hash, byte| (hash ^ byte as u32).wrapping_mul(0x01000193));
+    let hue = (hash % 360) as f64 / 60.0;
+    let (saturation, lightness) = (0.6, 0.5);
+
+    let chroma = (1.0 - (2.0 * lightness - 1.0f64).abs()) * saturation;
+    let x = chroma * (1.0 - (hue % 2.0 - 1.0).abs());
+    let (r, g, b) = match hue as u32 {
+        0 => (chroma, x, 0.0),
+        1 => (x, chroma, 0.0),
+        2 => (0.0, chroma, x),
+        3 => (0.0, x, chroma),
+        4 => (x, 0.0, chroma),
+        _ => (chroma, 0.0, x),
+    };
+    let m = lightness - chroma / 2.0;
+    let channel = |x: f64| ((x + m) * 255.0).round() as u8;
+    format!("#{:02x}{:02x}{:02x}", channel(r), channel(g), channel(b))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn works_out_contrast_ratios() {
+        assert!((contrast_ratio((0, 0, 0), (255, 255, 255)) - 21.0).abs() < 0.01);
+        assert!((contrast_ratio((255, 255, 255), (0, 0, 0)) - 21.0).abs() < 0.01);
+        assert!((contrast_ratio((120, 40, 200), (120, 40, 200)) - 1.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn blends() {
+        assert_eq!(blend((255, 255, 255), (0, 0, 0), 0.5), (128, 128, 128));
+        assert_eq!(blend((10, 20, 30), (200, 200, 200), 1.0), (10, 20, 30));
+        assert_eq!(blend((10, 20, 30), (200, 200, 200), 0.0), (200, 200, 200));
+    }
+
+    #[test]
+    fn leaves_readable_colors_be() {
+        let navy = (20, 30, 120);
+        assert_eq!(readable_on(navy, (250, 250, 250), CONTROL_CONTRAST), navy);
+    }
+
+    #[test]
+    fn darkens_on_light_backgrounds() {
+        let yellow = (240, 220, 60);
+        let fixed = readable_on(yellow, (250, 250, 250), CONTROL_CONTRAST);
+        assert!(contrast_ratio(fixed, (250, 250, 250)) >= CONTROL_CONTRAST);
+        assert!(luminance(fixed) < luminance(yellow));
+    }
+
+    #[test]
+    fn lightens_on_dark_backgrounds() {
+        let maroon = (90, 20, 30);
+        let fixed = readable_on(maroon, (30, 30, 30), CONTROL_CONTRAST);
+        assert!(contrast_ratio(fixed, (30, 30, 30)) >= CONTROL_CONTRAST);
+        assert!(luminance(fixed) > luminance(maroon));
+    }
+
+    #[test]
+    fn gives_genres_the_same_color_every_time() {
+        assert_eq!(default_genre_color("Jazz"), default_genre_color("jazz"));
+        assert_ne!(default_genre_color("Jazz"), default_genre_color("Ska"));
+        let color = default_genre_color("Shoegaze");
+        assert_eq!(color.len(), 7);
+        assert!(color.starts_with('#'));
+    }
+}
@@ -0,0 +1,334 @@
+// Copyright © 2021-2022 Jakob L. Kreuze <zerodaysfordays@sdf.org>
+//
+// This file is part of Tunes.
+//
+// Tunes is free software; you can redistribute it and/or modify it
+// under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation; either version 3 of the
+// License, or (at your option) any later version.
+//
+// Tunes is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY
+// or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Affero General
+// Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public
+// License along with Tunes. If not, see <http://www.gnu.org/licenses/>.
+
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+use std::path::PathBuf;
+
+/// Where the server to connect to is kept, unless one is given on the
+/// command line.
+pub const CONNECTION_SECTION: &str = "connection";
+
+/// Where settings that apply everywhere, rather than to one form factor,
+/// are kept.
+pub const GENERAL_SECTION: &str = "general";
+
+/// Where the window's size is kept between runs.
+pub const WINDOW_SECTION: &str = "window";
+
+/// Persistent settings, kept in a small INI-style file in the user's config
+/// directory. Values are stored as strings and parsed by whoever reads them.
+#[derive(Debug, Default)]
+pub struct Config {
+    pub path: PathBuf,
+    sections: BTreeMap<String, BTreeMap<String, String>>,
+}
+
+impl Config {
+    /// Read the config file at `path`, falling back to an empty config if it
+    /// doesn't exist or can't be read.
+    pub fn load(path: PathBuf) -> Self {
+        let contents = std::fs::read_to_string(&path).unwrap_or_default();
+        Self::parse(path, &contents)
+    }
+
+    /// A config read from `contents`, to be saved to `path`. Lines that are
+    /// neither a section header nor a key and value are skipped.
+    pub fn parse(path: PathBuf, contents: &str) -> Self {
+        let mut sections: BTreeMap<String, BTreeMap<String, String>> = BTreeMap::new();
+        let mut section = String::new();
+        for line in contents.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if let Some(name) = line.strip_prefix('[').and_then(|x| x.strip_suffix(']')) {
+                section = name.to_string();
+            } else if let Some((key, value)) = line.split_once('=') {
+                sections
+                    .entry(section.clone())
+                    .or_default()
+                    .insert(key.trim().to_string(), value.trim().to_string());
+            }
+        }
+        Config { path, sections }
+    }
+
+    pub fn get(&self, section: &str, key: &str) -> Option<&str> {
+        self.sections
+            .get(section)
+            .and_then(|keys| keys.get(key))
+            .map(|x| x.as_str())
+    }
+
+    /// Change a value and write the whole config back out to disk.
+    pub fn set(&mut self, section: &str, key: &str, value: impl ToString) {
+        self.sections
+            .entry(section.into())
+            .or_default()
+            .insert(key.into(), value.to_string());
+        self.save_or_complain();
+    }
+
+    /// Remove a value and write the whole config back out to disk.
+    pub fn unset(&mut self, section: &str, key: &str) {
+        if let Some(keys) = self.sections.get_mut(section) {
+            if keys.remove(key).is_some() {
+                self.save_or_complain();
+            }
+        }
+    }
+
+    pub fn section(&self, section: &str) -> Option<&BTreeMap<String, String>> {
+        self.sections.get(section)
+    }
+
+    /// Names of all the sections starting with `prefix`.
+    pub fn section_names<'a>(&'a self, prefix: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        self.sections
+            .keys()
+            .map(|x| x.as_str())
+            .filter(move |x| x.starts_with(prefix))
+    }
+
+    /// Swap out a whole section and write the config back out to disk.
+    pub fn replace_section(&mut self, section: &str, keys: BTreeMap<String, String>) {
+        self.sections.insert(section.into(), keys);
+        self.save_or_complain();
+    }
+
+    pub fn remove_section(&mut self, section: &str) {
+        self.sections.remove(section);
+        self.save_or_complain();
+    }
+
+    pub fn save_or_complain(&self) {
+        if let Err(e) = self.save() {
+            eprintln!("Couldn't save config to {}: {}", self.path.display(), e);
+        }
+    }
+
+    pub fn save(&self) -> std::io::Result<()> {
+        let mut contents = String::new();
+        for (section, keys) in &self.sections {
+            contents.push_str(&format!("[{}]\n", section));
+            for (key, value) in keys {
+                contents.push_str(&format!("{}={}\n", key, value));
+            }
+            contents.push('\n');
+        }
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        // Only we have any business reading it.
+        let mut file = std::fs::OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .mode(0o600)
+            .open(&self.path)?;
+        // Files from before this was the case keep whatever they had.
+        file.set_permissions(std::fs::Permissions::from_mode(0o600))?;
+        file.write_all(contents.as_bytes())
+    }
+}
+
+/// A server saved under a name of the user's choosing, like "Living Room",
+/// to switch to from the menu. Each one lives in its own config section,
+/// with the same keys as the connection preferences.
+pub struct ServerProfile;
+
+impl ServerProfile {
+    pub const SECTION_PREFIX: &'static str = "server:";
+    /// The password isn't one of them. It's in the keyring, under the
+    /// server's address, so every saved server at that address shares it.
+    pub const KEYS: [(&'static str, &'static str); 4] = [
+        ("host", "127.0.0.1"),
+        ("port", "6600"),
+        ("ssh", ""),
+        ("music-directory", ""),
+    ];
+
+    pub fn names(config: &Config) -> Vec<String> {
+        config
+            .section_names(Self::SECTION_PREFIX)
+            .map(|section| section[Self::SECTION_PREFIX.len()..].to_string())
+            .collect()
+    }
+
+    /// Save the server in the preferences as `name`.
+    pub fn save_current(config: &mut Config, name: &str) {
+        let keys = Self::KEYS
+            .iter()
+            .map(|(key, default)| {
+                let value = config.get(CONNECTION_SECTION, key).unwrap_or(*default);
+                (key.to_string(), value.to_string())
+            })
+            .collect();
+        config.replace_section(&format!("{}{}", Self::SECTION_PREFIX, name), keys);
+    }
+
+    /// Make the server saved as `name` the one in the preferences, if
+    /// there's one by that name.
+    pub fn switch_to(config: &mut Config, name: &str) -> bool {
+        let keys = match config.section(&format!("{}{}", Self::SECTION_PREFIX, name)) {
+            Some(keys) => keys.clone(),
+            None => return false,
+        };
+        for (key, default) in Self::KEYS {
+            config.set(CONNECTION_SECTION, key, keys.get(key).map_or(default, String::as_str));
+        }
+        true
+    }
+
+    /// Name of the saved server that's the one in the preferences, if any.
+    pub fn current(config: &Config) -> Option<String> {
+        Self::names(config).into_iter().find(|name| {
+            let keys = config.section(&format!("{}{}", Self::SECTION_PREFIX, name));
+            Self::KEYS.iter().all(|(key, default)| {
+                let saved = keys.and_then(|keys| keys.get(*key)).map_or(*default, String::as_str);
+                config.get(CONNECTION_SECTION, key).unwrap_or(*default) == saved
+            })
+        })
+    }
+
+    pub fn forget(config: &mut Config, name: &str) {
+        config.remove_section(&format!("{}{}", Self::SECTION_PREFIX, name));
+    }
+}
+
+/// Destructive actions the user can choose to be asked about first. Everything
+/// asks by default.
+#[derive(Debug, Clone, Copy)]
+pub enum Confirmation {
+    ClearQueue,
+    RemoveCurrentSong,
+    DeletePlaylist,
+    /// Saving songs under the name of a playlist that's already there.
+    OverwritePlaylist,
+    DeleteSmartPlaylist,
+    /// Clearing out stickers, such as ratings, for songs that have gone.
+    CleanUp,
+}
+
+impl Confirmation {
+    pub const SECTION: &'static str = "confirmations";
+
+    pub const ALL: [Confirmation; 6] = [
+        Confirmation::ClearQueue,
+        Confirmation::RemoveCurrentSong,
+        Confirmation::DeletePlaylist,
+        Confirmation::OverwritePlaylist,
+        Confirmation::DeleteSmartPlaylist,
+        Confirmation::CleanUp,
+    ];
+
+    pub fn key(self) -> &'static str {
+        match self {
+            Confirmation::ClearQueue => "clear-queue",
+            Confirmation::RemoveCurrentSong => "remove-current-song",
+            Confirmation::DeletePlaylist => "delete-playlist",
+            Confirmation::OverwritePlaylist => "overwrite-playlist",
+            Confirmation::DeleteSmartPlaylist => "delete-smart-playlist",
+            Confirmation::CleanUp => "clean-up",
+        }
+    }
+
+    pub fn title(self) -> &'static str {
+        match self {
+            Confirmation::ClearQueue => "Clearing the Queue",
+            Confirmation::RemoveCurrentSong => "Removing the Playing Song",
+            Confirmation::DeletePlaylist => "Deleting a Playlist",
+            Confirmation::OverwritePlaylist => "Saving Into an Existing Playlist",
+            Confirmation::DeleteSmartPlaylist => "Deleting a Smart Playlist",
+            Confirmation::CleanUp => "Cleaning Up",
+        }
+    }
+
+    pub fn wanted(self, config: &Config) -> bool {
+        config.get(Self::SECTION, self.key()) != Some("false")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A config to be saved somewhere of its own, so tests don't trip over
+    /// each other or anyone's real settings.
+    fn scratch(name: &str, contents: &str) -> Config {
+        let path = std::env::temp_dir()
+            .join(format!("tunes-core-{}", std::process::id()))
+            .join(format!("{}.conf", name));
+        Config::parse(path, contents)
+    }
+
+    #[test]
+    fn reads_sections() {
+        let config = scratch(
+            "reads",
+            "# Written by hand\n[connection]\nhost = music.local\nport=6601\n\n[phone]\ndensity=compact\nnonsense\n",
+        );
+        assert_eq!(config.get(CONNECTION_SECTION, "host"), Some("music.local"));
+        assert_eq!(config.get(CONNECTION_SECTION, "port"), Some("6601"));
+        assert_eq!(config.get("phone", "density"), Some("compact"));
+        assert_eq!(config.get("phone", "nonsense"), None);
+        assert_eq!(config.get(GENERAL_SECTION, "host"), None);
+    }
+
+    #[test]
+    fn keeps_values_with_equals_signs() {
+        let config = scratch("equals", "[macro:Dinner]\nstep-000=load playlist a=b\n");
+        assert_eq!(config.get("macro:Dinner", "step-000"), Some("load playlist a=b"));
+    }
+
+    #[test]
+    fn saves_what_it_reads() {
+        let mut config = scratch("saves", "");
+        config.set(CONNECTION_SECTION, "host", "music.local");
+        config.set(WINDOW_SECTION, "width", 800);
+        config.unset(WINDOW_SECTION, "width");
+        let saved = Config::load(config.path.clone());
+        assert_eq!(saved.get(CONNECTION_SECTION, "host"), Some("music.local"));
+        assert_eq!(saved.get(WINDOW_SECTION, "width"), None);
+        let mode = std::fs::metadata(&config.path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        std::fs::remove_file(&config.path).unwrap();
+    }
+
+    #[test]
+    fn switches_between_saved_servers() {
+        let mut config = scratch("servers", "[connection]\nhost=music.local\nport=6601\n");
+        ServerProfile::save_current(&mut config, "Living Room");
+        assert_eq!(ServerProfile::names(&config), ["Living Room"]);
+        assert_eq!(ServerProfile::current(&config).as_deref(), Some("Living Room"));
+
+        config.set(CONNECTION_SECTION, "host", "kitchen.local");
+        assert_eq!(ServerProfile::current(&config), None);
+        ServerProfile::save_current(&mut config, "Kitchen");
+
+        assert!(ServerProfile::switch_to(&mut config, "Living Room"));
+        assert_eq!(config.get(CONNECTION_SECTION, "host"), Some("music.local"));
+        assert_eq!(ServerProfile::current(&config).as_deref(), Some("Living Room"));
+        assert!(!ServerProfile::switch_to(&mut config, "Garage"));
+
+        ServerProfile::forget(&mut config, "Kitchen");
+        assert_eq!(ServerProfile::names(&config), ["Living Room"]);
+        std::fs::remove_file(&config.path).unwrap();
+    }
+}
@@ -0,0 +1,169 @@
+// Copyright © 2021-2022 Jakob L. Kreuze <zerodaysfordays@sdf.org>
+//
+// This file is part of Tunes.
+//
+// Tunes is free software; you can redistribute it and/or modify it
+// under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation; either version 3 of the
+// License, or (at your option) any later version.
+//
+// Tunes is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY
+// or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Affero General
+// Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public
+// License along with Tunes. If not, see <http://www.gnu.org/licenses/>.
+
+use std::io::{Read, Write};
+use std::time::Duration;
+
+/// A connection to the server, over the network or, for one on this
+/// computer, a Unix socket.
+pub type Client = mpd::Client<Stream>;
+
+/// The socket underneath a `Client`.
+#[derive(Debug)]
+pub enum Stream {
+    Tcp(std::net::TcpStream),
+    Unix(std::os::unix::net::UnixStream),
+}
+
+impl Stream {
+    pub fn try_clone(&self) -> std::io::Result<Self> {
+        Ok(match self {
+            Stream::Tcp(stream) => Stream::Tcp(stream.try_clone()?),
+            Stream::Unix(stream) => Stream::Unix(stream.try_clone()?),
+        })
+    }
+
+    /// Give up on reads and writes that take longer than `timeout`.
+    pub fn set_timeout(&self, timeout: Option<Duration>) -> std::io::Result<()> {
+        match self {
+            Stream::Tcp(stream) => {
+                stream.set_read_timeout(timeout)?;
+                stream.set_write_timeout(timeout)
+            }
+            Stream::Unix(stream) => {
+                stream.set_read_timeout(timeout)?;
+                stream.set_write_timeout(timeout)
+            }
+        }
+    }
+
+    pub fn shutdown(&self) -> std::io::Result<()> {
+        match self {
+            Stream::Tcp(stream) => stream.shutdown(std::net::Shutdown::Both),
+            Stream::Unix(stream) => stream.shutdown(std::net::Shutdown::Both),
+        }
+    }
+}
+
+impl Read for Stream {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match self {
+            Stream::Tcp(stream) => stream.read(buf),
+            Stream::Unix(stream) => stream.read(buf),
+        }
+    }
+}
+
+impl Write for Stream {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self {
+            Stream::Tcp(stream) => stream.write(buf),
+            Stream::Unix(stream) => stream.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            Stream::Tcp(stream) => stream.flush(),
+            Stream::Unix(stream) => stream.flush(),
+        }
+    }
+}
+
+/// How long to wait on the server, whether connecting or for an answer,
+/// before deciding it's gone.
+pub const COMMAND_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Connect to `host`, trying each address it has in turn, and giving up on
+/// each after `COMMAND_TIMEOUT`.
+pub fn connect_tcp(host: &str) -> std::io::Result<std::net::TcpStream> {
+    use std::net::ToSocketAddrs;
+    let mut last_error = None;
+    for address in host.to_socket_addrs()? {
+        match std::net::TcpStream::connect_timeout(&address, COMMAND_TIMEOUT) {
+            Ok(stream) => return Ok(stream),
+            Err(e) => last_error = Some(e),
+        }
+    }
+    Err(last_error.unwrap_or_else(|| std::io::Error::new(std::io::ErrorKind::NotFound, "No addresses for host")))
+}
+
+/// Whether `error` came down to the server not answering in time, which
+/// leaves the connection in no state to be used again.
+pub fn timed_out(error: &anyhow::Error) -> bool {
+    error.chain().any(|cause| match cause.downcast_ref::<mpd::error::Error>() {
+        Some(mpd::error::Error::Io(e)) => {
+            matches!(e.kind(), std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut)
+        }
+        _ => false,
+    })
+}
+
+/// Whether `error` came down to the server turning us away for want of the
+/// right password.
+pub fn needs_password(error: &anyhow::Error) -> bool {
+    error.chain().any(|cause| {
+        matches!(
+            cause.downcast_ref::<mpd::error::Error>(),
+            Some(mpd::error::Error::Server(mpd::error::ServerError {
+                code: mpd::error::ErrorCode::Permission | mpd::error::ErrorCode::Password,
+                ..
+            }))
+        )
+    })
+}
+
+/// Pick apart `MPD_HOST`, which can have a password in front, as in
+/// "password@host". Abstract sockets, starting with "@", are left out.
+pub fn parse_mpd_host(value: Option<String>) -> (Option<String>, Option<String>) {
+    let value = match value.filter(|x| !x.is_empty()) {
+        Some(value) => value,
+        None => return (None, None),
+    };
+    let (password, host) = match value.rsplit_once('@') {
+        Some((password, host)) if !password.is_empty() => (Some(password.to_string()), host.to_string()),
+        _ => (None, value),
+    };
+    if host.is_empty() || host.starts_with('@') {
+        eprintln!("Can't connect to an abstract socket from MPD_HOST");
+        return (password, None);
+    }
+    (password, Some(host))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn picks_apart_mpd_host() {
+        assert_eq!(parse_mpd_host(None), (None, None));
+        assert_eq!(parse_mpd_host(Some("".into())), (None, None));
+        assert_eq!(parse_mpd_host(Some("music.local".into())), (None, Some("music.local".into())));
+        assert_eq!(
+            parse_mpd_host(Some("hunter2@music.local".into())),
+            (Some("hunter2".into()), Some("music.local".into()))
+        );
+        assert_eq!(parse_mpd_host(Some("/run/mpd/socket".into())), (None, Some("/run/mpd/socket".into())));
+    }
+
+    #[test]
+    fn leaves_out_hosts_it_cant_reach() {
+        assert_eq!(parse_mpd_host(Some("@mpd".into())), (None, None));
+        assert_eq!(parse_mpd_host(Some("hunter2@".into())), (Some("hunter2".into()), None));
+    }
+}
@@ -0,0 +1,156 @@
+// Copyright © 2021-2022 Jakob L. Kreuze <zerodaysfordays@sdf.org>
+//
+// This file is part of Tunes.
+//
+// Tunes is free software; you can redistribute it and/or modify it
+// under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation; either version 3 of the
+// License, or (at your option) any later version.
+//
+// Tunes is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY
+// or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Affero General
+// Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public
+// License along with Tunes. If not, see <http://www.gnu.org/licenses/>.
+
+use crate::Config;
+
+/// Below this width (in pixels), we assume we're running on a phone.
+const PHONE_WIDTH: i32 = 600;
+
+/// The rough kind of device we're running on, judged by the window width. This
+/// changes as a convertible folds or unfolds, or as the window is resized.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FormFactor {
+    Phone,
+    Desktop,
+}
+
+impl FormFactor {
+    pub fn from_width(width: i32) -> Self {
+        if width < PHONE_WIDTH {
+            FormFactor::Phone
+        } else {
+            FormFactor::Desktop
+        }
+    }
+
+    /// Name of the config section holding this form factor's display profile.
+    pub fn section(self) -> &'static str {
+        match self {
+            FormFactor::Phone => "phone",
+            FormFactor::Desktop => "desktop",
+        }
+    }
+
+    pub fn title(self) -> &'static str {
+        match self {
+            FormFactor::Phone => "Phone",
+            FormFactor::Desktop => "Desktop",
+        }
+    }
+}
+
+/// How tightly packed rows and buttons are.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Density {
+    Comfortable,
+    Compact,
+}
+
+/// How large the album art in Now Playing is.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ArtSize {
+    Hidden,
+    Small,
+    Medium,
+    Large,
+}
+
+impl ArtSize {
+    /// Fraction of the window's least dimension taken up by the art.
+    pub fn scale(self) -> f64 {
+        match self {
+            ArtSize::Hidden => 0.0,
+            ArtSize::Small => 0.25,
+            ArtSize::Medium => 0.5,
+            ArtSize::Large => 0.75,
+        }
+    }
+}
+
+/// Where the queue goes relative to the current song in Now Playing.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Layout {
+    Stacked,
+    SideBySide,
+}
+
+/// Display preferences, kept separately for each `FormFactor`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DisplayProfile {
+    pub density: Density,
+    pub art_size: ArtSize,
+    pub layout: Layout,
+    /// Whether the queue is folded away under its header, leaving the art
+    /// and controls the whole height.
+    pub queue_collapsed: bool,
+}
+
+impl DisplayProfile {
+    pub const DENSITIES: [(&'static str, &'static str); 2] =
+        [("comfortable", "Comfortable"), ("compact", "Compact")];
+    pub const ART_SIZES: [(&'static str, &'static str); 4] =
+        [("none", "Hidden"), ("small", "Small"), ("medium", "Medium"), ("large", "Large")];
+    pub const LAYOUTS: [(&'static str, &'static str); 2] =
+        [("stacked", "Stacked"), ("side-by-side", "Side by Side")];
+
+    pub fn load(config: &Config, form_factor: FormFactor) -> Self {
+        let section = form_factor.section();
+        DisplayProfile {
+            density: match config.get(section, "density") {
+                Some("compact") => Density::Compact,
+                _ => Density::Comfortable,
+            },
+            art_size: match config.get(section, "art-size") {
+                Some("none") => ArtSize::Hidden,
+                Some("small") => ArtSize::Small,
+                Some("large") => ArtSize::Large,
+                _ => ArtSize::Medium,
+            },
+            layout: match config.get(section, "layout") {
+                Some("side-by-side") => Layout::SideBySide,
+                _ => Layout::Stacked,
+            },
+            // Phones don't have the room for both unless asked.
+            queue_collapsed: match config.get(section, "queue-collapsed") {
+                Some("true") => true,
+                Some("false") => false,
+                _ => form_factor == FormFactor::Phone,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn judges_form_factor_by_width() {
+        assert_eq!(FormFactor::from_width(360), FormFactor::Phone);
+        assert_eq!(FormFactor::from_width(PHONE_WIDTH), FormFactor::Desktop);
+    }
+
+    #[test]
+    fn fills_in_defaults() {
+        let config = Config::parse("tunes.conf".into(), "[phone]\nart-size=large\n");
+        let phone = DisplayProfile::load(&config, FormFactor::Phone);
+        assert_eq!(phone.art_size, ArtSize::Large);
+        assert_eq!(phone.density, Density::Comfortable);
+        assert!(phone.queue_collapsed);
+        assert!(!DisplayProfile::load(&config, FormFactor::Desktop).queue_collapsed);
+    }
+}
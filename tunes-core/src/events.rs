@@ -0,0 +1,342 @@
+// Copyright © 2021-2022 Jakob L. Kreuze <zerodaysfordays@sdf.org>
+//
+// This file is part of Tunes.
+//
+// Tunes is free software; you can redistribute it and/or modify it
+// under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation; either version 3 of the
+// License, or (at your option) any later version.
+//
+// Tunes is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY
+// or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Affero General
+// Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public
+// License along with Tunes. If not, see <http://www.gnu.org/licenses/>.
+
+use crate::{DecodedArt, FormFactor, SearchRequest};
+use futures::StreamExt;
+use futures::channel::mpsc;
+use std::collections::VecDeque;
+use std::time::Duration;
+
+/// Kind of event we can notify the UI future about
+#[derive(Debug, Clone)]
+pub enum StateUpdateKind {
+    MpdEvent,
+    WindowResizeEvent,
+    /// The window gained or lost focus.
+    WindowFocusChange,
+    QueryUpdateEvent(SearchRequest),
+    BrowseTagRequest(String),
+    BrowseTagSongsRequest(String, String),
+    BrowseDirectoryRequest(String),
+    BrowseDatesRequest,
+    BrowseSmartPlaylistsRequest,
+    /// List the stored playlists, and have their collages made.
+    BrowsePlaylistsRequest,
+    /// List every album for the album grid.
+    BrowseAlbumsRequest,
+    /// The album grid has scrolled or changed size, so different covers are
+    /// on screen.
+    ThumbnailsWanted,
+    /// A thumbnail has been made for the given album, if it has a cover.
+    ThumbnailLoaded(String, Option<DecodedArt>),
+    /// A collage has been made for the given playlist, if it has any covers.
+    CollageLoaded(String, Option<DecodedArt>),
+    /// Queue the stored playlist with the given name.
+    QueuePlaylistRequest(String),
+    /// Delete the stored playlist with the given name.
+    PlaylistDeleteRequest(String),
+    /// Create a smart playlist, or edit the one with the given name.
+    SmartPlaylistEditRequest(Option<String>),
+    SmartPlaylistDeleteRequest(String),
+    BrowseYearSongsRequest(u32),
+    /// List songs added within the given number of days.
+    BrowseRecentRequest(u64),
+    BrowseMediaRequest,
+    /// Mount the storage URI (second) at the given name (first).
+    MediaMountRequest(String, String),
+    MediaUnmountRequest(String),
+    QueueAddRequest(String),
+    /// Like `QueueAddRequest`, but skips the check for accidental duplicates.
+    QueueForceAddRequest(String),
+    QueueAddDirectoryRequest(String),
+    QueueAddTagRequest(String, String),
+    /// Queue every song dated from the first year through the second.
+    QueueAddYearsRequest(u32, u32),
+    QueueSmartPlaylistRequest(String),
+    QueueDeleteRequest(u32),
+    /// Pick an album at random and queue it in track order.
+    QueueRandomAlbumRequest,
+    /// Ask for a length of time and some rules, then queue that much music.
+    QueueTimedRequest,
+    /// Queue an album (first) by an album artist (second) in track order.
+    QueueAddAlbumRequest(String, String),
+    /// Edit the macro with the given name, or make a new one.
+    MacroEditRequest(Option<String>),
+    /// Run the macro with the given name.
+    MacroRunRequest(String),
+    /// Save the server in the preferences under a name, to switch back to.
+    ServerSaveRequest,
+    /// Switch to the saved server with the given name.
+    ServerSwitchRequest(String),
+    /// Forget the saved server with the given name.
+    ServerForgetRequest(String),
+    /// Queue files opened with us, given as URIs.
+    OpenRequest(Vec<String>),
+    /// Queue everything by the given artist, in a random order.
+    QueueShuffledArtistRequest(String),
+    /// Queue the given album (by album artist), in a random order.
+    QueueShuffledAlbumRequest(String, String),
+    /// Queue a song just after the current one.
+    QueuePlayNextRequest(String),
+    /// Queue a song just after the current one and skip to it.
+    QueuePlayNowRequest(String),
+    /// Play the song at the given position in the queue.
+    QueueJumpRequest(u32),
+    /// Move the song at the given position in the queue to play next.
+    QueueMoveNextRequest(u32),
+    /// Search for everything by the given artist.
+    ShowArtistRequest(String),
+    /// Show all the tags of the song with the given filename.
+    SongDetailsRequest(String),
+    /// Change the labels on the song with the given filename.
+    SongLabelsEditRequest(String),
+    /// Open the folder the song with the given filename is in, in the file
+    /// manager.
+    OpenFolderRequest(String),
+    PlaylistSaveRequest(Vec<String>),
+    /// The device has woken up from being suspended.
+    Resumed,
+    /// Time to check the connection's still there.
+    KeepAlive,
+    /// The local copy of the database's tags has been brought up to date.
+    TagIndexUpdated,
+    /// Songs for the near misses to a search, found in the background.
+    NearMissesFound(SearchRequest, Vec<mpd::Song>),
+    /// Album art has been fetched for the request with the given generation.
+    AlbumArtLoaded(u64, Option<DecodedArt>),
+    /// Something went wrong away from the main loop, to show as an error.
+    ErrorNotice(String),
+    /// Looking up covers online kept failing, so it's off until the user
+    /// turns it back on.
+    OnlineArtStopped,
+    /// Fetch the next page of the current search's results.
+    QueryLoadMoreRequest,
+    /// Queue the first search result, or if true, have it play next.
+    QueueTopResultRequest(bool),
+    /// Swap out the whole queue for the current search results and play them.
+    QueueReplaceWithResultsRequest,
+    /// Do something with the selected search results.
+    SelectionRequest(SelectionAction),
+    FormFactorChange(FormFactor),
+    /// The queue has been collapsed (or expanded) on the current form factor.
+    QueueCollapseChange(bool),
+    DisplayProfileChange,
+    PlaybackStateChange(PlaybackStateChange),
+    /// Show the about dialog, which reports on the server too.
+    AboutRequest,
+    /// Go to the search page and put the cursor in the search box.
+    FocusSearchRequest,
+    /// The mini player's been asked for, or the window's size has changed
+    /// whether it should be shown.
+    MiniPlayerChange,
+    PreferencesRequest,
+    /// List the keyboard shortcuts.
+    ShortcutsRequest,
+    /// Save the whole queue into a stored playlist.
+    QueueSaveRequest,
+    /// Have the server rescan the library for new and changed files.
+    DatabaseUpdateRequest,
+    /// Clear out cached covers and stickers for music that's left the
+    /// library, and say what went.
+    CleanupRequest,
+    /// The idle thread has lost the server, or found it again.
+    ConnectionChange(ConnectionState),
+    /// Try to reach the server again straight away.
+    ReconnectRequest,
+    /// The server in the preferences may have changed, and if it has, it's
+    /// time to move over to it.
+    ServerChange,
+}
+
+impl StateUpdateKind {
+    /// Whether all this does is add to the queue, which can wait until the
+    /// server's back if it's away.
+    pub fn is_queue_add(&self) -> bool {
+        matches!(
+            self,
+            StateUpdateKind::QueueAddRequest(_)
+                | StateUpdateKind::QueueForceAddRequest(_)
+                | StateUpdateKind::QueueAddDirectoryRequest(_)
+                | StateUpdateKind::QueueAddTagRequest(..)
+                | StateUpdateKind::QueueAddYearsRequest(..)
+                | StateUpdateKind::QueueAddAlbumRequest(..)
+                | StateUpdateKind::OpenRequest(_)
+        )
+    }
+
+    /// Whether this should be handled ahead of anything else that's waiting.
+    /// Someone pressing pause shouldn't have to wait for a big search or a
+    /// page of browsing to come back first.
+    pub fn is_urgent(&self) -> bool {
+        matches!(
+            self,
+            StateUpdateKind::PlaybackStateChange(_) | StateUpdateKind::QueueJumpRequest(_)
+        )
+    }
+
+    /// Whether this only looks something up to show, without changing the
+    /// queue or anything else on the server, so something urgent can safely
+    /// be handled first.
+    pub fn is_lookup(&self) -> bool {
+        matches!(
+            self,
+            StateUpdateKind::WindowResizeEvent
+                | StateUpdateKind::QueryUpdateEvent(_)
+                | StateUpdateKind::QueryLoadMoreRequest
+                | StateUpdateKind::NearMissesFound(..)
+                | StateUpdateKind::BrowseTagRequest(_)
+                | StateUpdateKind::BrowseTagSongsRequest(..)
+                | StateUpdateKind::BrowseDirectoryRequest(_)
+                | StateUpdateKind::BrowseDatesRequest
+                | StateUpdateKind::BrowseSmartPlaylistsRequest
+                | StateUpdateKind::BrowsePlaylistsRequest
+                | StateUpdateKind::BrowseAlbumsRequest
+                | StateUpdateKind::BrowseYearSongsRequest(_)
+                | StateUpdateKind::BrowseRecentRequest(_)
+                | StateUpdateKind::BrowseMediaRequest
+                | StateUpdateKind::ThumbnailsWanted
+                | StateUpdateKind::ThumbnailLoaded(..)
+                | StateUpdateKind::CollageLoaded(..)
+                | StateUpdateKind::AlbumArtLoaded(..)
+        )
+    }
+}
+
+/// The next event for the main loop to handle. Everything that's arrived is
+/// gathered into `pending` first, so urgent events can jump the queue. They
+/// only go ahead of lookups, though: pressing play straight after adding an
+/// album has to wait for the album, and a jump to a position in the queue
+/// has to wait for the edits made before it. The rest are handled in the
+/// order they came, since plenty of them rely on the ones before (queueing
+/// the top result needs the search done).
+pub async fn next_event(
+    receiver: &mut mpsc::Receiver<StateUpdateKind>,
+    pending: &mut VecDeque<StateUpdateKind>,
+) -> Option<StateUpdateKind> {
+    while let Ok(Some(event)) = receiver.try_next() {
+        pending.push_back(event);
+    }
+    let urgent = pending
+        .iter()
+        .position(|event| !event.is_lookup())
+        .filter(|&i| pending[i].is_urgent());
+    match urgent {
+        Some(i) => pending.remove(i),
+        None => match pending.pop_front() {
+            Some(event) => Some(event),
+            None => receiver.next().await,
+        },
+    }
+}
+
+/// A simple action that affects playback state.
+#[derive(Debug, Clone)]
+pub enum PlaybackStateChange {
+    Start,
+    Stop,
+    Pause,
+    SkipBackwards,
+    SkipForwards,
+    /// Pause if playing, and play otherwise.
+    TogglePause,
+    /// Turn the volume up (or down, if negative) by this much.
+    ChangeVolume(i8),
+    /// Set the volume, out of 100.
+    SetVolume(i8),
+    /// Jump to this far into the current song.
+    SeekTo(Duration),
+    SetRandom(bool),
+    /// Go from off, to on, to on for just this song, and back to off.
+    CycleSingle,
+    CycleConsume,
+}
+
+/// Something to do with all the selected search results at once.
+#[derive(Debug, Clone, Copy)]
+pub enum SelectionAction {
+    Add,
+    PlayNext,
+    /// Rate them all from 1 to 5.
+    Rate(u32),
+}
+
+/// Whether we can reach the server.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ConnectionState {
+    Connecting,
+    Connected,
+    Offline,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::executor::block_on;
+
+    /// The order the main loop would handle `sent` in, if it all arrived
+    /// while it was busy.
+    fn handled(sent: Vec<StateUpdateKind>) -> Vec<String> {
+        let (mut sender, mut receiver) = mpsc::channel(sent.len());
+        for event in sent {
+            sender.try_send(event).unwrap();
+        }
+        drop(sender);
+        let mut pending = VecDeque::new();
+        let mut order = Vec::new();
+        while let Some(event) = block_on(next_event(&mut receiver, &mut pending)) {
+            order.push(format!("{:?}", event));
+        }
+        order
+    }
+
+    #[test]
+    fn playback_goes_ahead_of_lookups() {
+        let order = handled(vec![
+            StateUpdateKind::BrowseAlbumsRequest,
+            StateUpdateKind::BrowseTagRequest("Genre".into()),
+            StateUpdateKind::PlaybackStateChange(PlaybackStateChange::Pause),
+        ]);
+        assert_eq!(order, ["PlaybackStateChange(Pause)", "BrowseAlbumsRequest", "BrowseTagRequest(\"Genre\")"]);
+    }
+
+    #[test]
+    fn jumps_wait_for_queue_edits() {
+        let order = handled(vec![
+            StateUpdateKind::QueueDeleteRequest(3),
+            StateUpdateKind::ThumbnailsWanted,
+            StateUpdateKind::QueueJumpRequest(5),
+        ]);
+        assert_eq!(order, ["QueueDeleteRequest(3)", "QueueJumpRequest(5)", "ThumbnailsWanted"]);
+    }
+
+    #[test]
+    fn play_waits_for_adds() {
+        let order = handled(vec![
+            StateUpdateKind::BrowseAlbumsRequest,
+            StateUpdateKind::QueueAddAlbumRequest("Blue".into(), "Joni Mitchell".into()),
+            StateUpdateKind::PlaybackStateChange(PlaybackStateChange::Start),
+        ]);
+        assert_eq!(
+            order,
+            [
+                "BrowseAlbumsRequest",
+                "QueueAddAlbumRequest(\"Blue\", \"Joni Mitchell\")",
+                "PlaybackStateChange(Start)"
+            ]
+        );
+    }
+}
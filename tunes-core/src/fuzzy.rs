@@ -0,0 +1,236 @@
+// Copyright © 2021-2022 Jakob L. Kreuze <zerodaysfordays@sdf.org>
+//
+// This file is part of Tunes.
+//
+// Tunes is free software; you can redistribute it and/or modify it
+// under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation; either version 3 of the
+// License, or (at your option) any later version.
+//
+// Tunes is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY
+// or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Affero General
+// Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public
+// License along with Tunes. If not, see <http://www.gnu.org/licenses/>.
+
+use crate::{album_artist, album_order, Client};
+use std::time::Duration;
+
+/// Every artist, album and title in the library, for finding the ones a
+/// search comes close to when MPD finds nothing exact.
+pub struct FuzzyIndex {
+    /// When the database was last updated, so we know when to reload.
+    pub db_update: Duration,
+    /// Pairs of tag and value.
+    pub values: Vec<(&'static str, String)>,
+}
+
+impl FuzzyIndex {
+    pub const TAGS: [&'static str; 4] = ["Artist", "AlbumArtist", "Album", "Title"];
+
+    /// Most near misses to look up songs for. Past that, the results aren't
+    /// going to be what anyone meant anyway.
+    pub const MAX_NEAR_MISSES: usize = 20;
+
+    /// Load the index, or reload it if the database has changed since. If
+    /// that fails, searches just go without near misses.
+    pub fn refresh(index: &mut Option<FuzzyIndex>, conn: &mut Client) {
+        let db_update = match conn.stats() {
+            Ok(stats) => stats.db_update,
+            Err(_) => return,
+        };
+        if index.as_ref().map_or(false, |index| index.db_update == db_update) {
+            return;
+        }
+        let mut values = Vec::new();
+        for tag in Self::TAGS {
+            match conn.list(&mpd::Term::Tag(tag.into()), &mpd::Query::new()) {
+                Ok(tag_values) => values.extend(tag_values.into_iter().map(|value| (tag, value))),
+                Err(_) => return,
+            }
+        }
+        *index = Some(FuzzyIndex { db_update, values });
+    }
+
+    /// Tag values that come close to the text without containing it, best
+    /// first. The ones containing it, MPD will have found already.
+    pub fn near_misses(&self, text: &str) -> Vec<(&'static str, String)> {
+        let mut matches: Vec<_> = self
+            .values
+            .iter()
+            .map(|(tag, value)| (fuzzy_score(text, value), *tag, value))
+            .filter(|(score, _, _)| (1..100).contains(score))
+            .collect();
+        matches.sort_by_key(|(score, _, _)| std::cmp::Reverse(*score));
+        matches
+            .into_iter()
+            .take(Self::MAX_NEAR_MISSES)
+            .map(|(_, tag, value)| (tag, value.clone()))
+            .collect()
+    }
+}
+
+/// How closely `text` contains `query`, ignoring case: 100 when it's in there
+/// as is, a bit less for each typo, and 0 when it's too far off to count.
+pub fn fuzzy_score(query: &str, text: &str) -> u32 {
+    let query: Vec<char> = query.to_lowercase().chars().collect();
+    let text: Vec<char> = text.to_lowercase().chars().collect();
+    if query.is_empty() {
+        return 0;
+    }
+
+    // Work out the fewest edits that turn the query into some stretch of the
+    // text, one character of the query at a time. Each entry is the best we
+    // can do for a stretch ending at that point in the text.
+    let mut row = vec![0; text.len() + 1];
+    for (i, q) in query.iter().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, t) in text.iter().enumerate() {
+            let substitution = diagonal + usize::from(q != t);
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(diagonal + 1);
+        }
+    }
+    let distance = row.into_iter().min().unwrap_or(query.len());
+
+    // Allow about one typo every four characters.
+    if distance > query.len() / 4 {
+        0
+    } else {
+        (100 - distance * 100 / query.len()) as u32
+    }
+}
+
+/// How well a song matches a search, going by whichever of its artist,
+/// album and title comes closest.
+pub fn song_match_score(query: &str, song: &mpd::song::Song) -> u32 {
+    [
+        song.artist.as_ref(),
+        song.title.as_ref(),
+        song.tags.get("Album"),
+        song.tags.get("AlbumArtist"),
+    ]
+    .into_iter()
+    .flatten()
+    .map(|value| fuzzy_score(query, value))
+    .max()
+    .unwrap_or(0)
+}
+
+/// Order songs by how well their album scored, best first, keeping each album
+/// together in track order so it can still go under one heading. An album
+/// scores as well as its best song.
+pub fn rank_albums(
+    mut songs: Vec<mpd::song::Song>,
+    score: impl Fn(&mpd::song::Song) -> u32,
+) -> Vec<mpd::song::Song> {
+    songs.sort_by(|a, b| album_order(a).cmp(&album_order(b)));
+    let mut albums: Vec<(u32, Vec<mpd::song::Song>)> = Vec::new();
+    for song in songs {
+        let song_score = score(&song);
+        match albums.last_mut() {
+            Some((best, album))
+                if album.last().map_or(false, |last| {
+                    album_artist(last) == album_artist(&song)
+                        && last.tags.get("Album") == song.tags.get("Album")
+                }) =>
+            {
+                *best = (*best).max(song_score);
+                album.push(song);
+            }
+            _ => albums.push((song_score, vec![song])),
+        }
+    }
+    // This sort is stable, so albums that did equally well stay in order.
+    albums.sort_by_key(|(best, _)| std::cmp::Reverse(*best));
+    albums.into_iter().flat_map(|(_, album)| album).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_text_as_typed() {
+        assert_eq!(fuzzy_score("nirvana", "Nirvana"), 100);
+        assert_eq!(fuzzy_score("beatles", "The Beatles"), 100);
+    }
+
+    #[test]
+    fn fewer_typos_score_higher() {
+        let exact = fuzzy_score("radiohead", "Radiohead");
+        let one_typo = fuzzy_score("radiohed", "Radiohead");
+        let two_typos = fuzzy_score("radhed", "Radiohead");
+        assert!(exact > one_typo, "{} > {}", exact, one_typo);
+        assert!(one_typo > 0);
+        assert_eq!(fuzzy_score("radiohead", "Rdiohed"), 78);
+        assert!(fuzzy_score("radiohead", "Radiohed") > fuzzy_score("radiohead", "Rdiohed"));
+        // Six letters only allow one typo.
+        assert_eq!(two_typos, 0);
+    }
+
+    #[test]
+    fn gives_up_past_the_cutoff() {
+        // About one typo every four characters, so short queries have to
+        // match exactly.
+        assert_eq!(fuzzy_score("abx", "abc"), 0);
+        assert_eq!(fuzzy_score("nirvna", "Nirvana"), 84);
+        assert_eq!(fuzzy_score("metallica", "Megadeth"), 0);
+        assert_eq!(fuzzy_score("", "Anything"), 0);
+    }
+
+    #[test]
+    fn lists_near_misses_best_first() {
+        let index = FuzzyIndex {
+            db_update: Duration::ZERO,
+            values: vec![
+                ("Album", "Rdiohed".into()),
+                ("Artist", "Radiohead".into()),
+                ("Artist", "Radiohed".into()),
+                ("Album", "Kid A".into()),
+            ],
+        };
+        // Exact matches are left to MPD.
+        assert_eq!(
+            index.near_misses("radiohead"),
+            [("Artist", "Radiohed".to_string()), ("Album", "Rdiohed".to_string())]
+        );
+    }
+
+    fn song(album: &str, track: &str, title: &str) -> mpd::Song {
+        let mut song = mpd::Song {
+            title: Some(title.into()),
+            ..Default::default()
+        };
+        song.tags.insert("AlbumArtist".into(), "Someone".into());
+        song.tags.insert("Album".into(), album.into());
+        song.tags.insert("Track".into(), track.into());
+        song
+    }
+
+    fn titles(songs: &[mpd::Song]) -> Vec<&str> {
+        songs.iter().map(|song| song.title.as_deref().unwrap()).collect()
+    }
+
+    #[test]
+    fn ranks_albums_by_their_best_song() {
+        let songs = vec![
+            song("B", "2", "b2"),
+            song("A", "1", "a1"),
+            song("B", "1", "b1"),
+            song("A", "2", "love"),
+            song("C", "1", "c1"),
+        ];
+        let ranked = rank_albums(songs, |song| fuzzy_score("love", song.title.as_deref().unwrap()));
+        // A has the only match, and the rest stay in album order.
+        assert_eq!(titles(&ranked), ["a1", "love", "b1", "b2", "c1"]);
+    }
+
+    #[test]
+    fn ranks_nothing_from_nothing() {
+        assert!(rank_albums(Vec::new(), |_| 0).is_empty());
+    }
+}
@@ -0,0 +1,57 @@
+// Copyright © 2021-2022 Jakob L. Kreuze <zerodaysfordays@sdf.org>
+//
+// This file is part of Tunes.
+//
+// Tunes is free software; you can redistribute it and/or modify it
+// under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation; either version 3 of the
+// License, or (at your option) any later version.
+//
+// Tunes is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY
+// or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Affero General
+// Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public
+// License along with Tunes. If not, see <http://www.gnu.org/licenses/>.
+
+// The parts of Tunes that only talk to MPD and work things out, with nothing
+// to do with GTK: the connection itself, queueing, stickers, fuzzy matching,
+// searches, smart playlists, macros, settings, color contrast, and the events
+// the main loop handles along with the order it takes them in. Keeping them
+// apart means they can be built, and tested, on a machine without a display
+// or GTK installed.
+
+mod art;
+mod color;
+mod config;
+mod connection;
+mod display;
+mod events;
+mod fuzzy;
+mod macros;
+mod queue;
+mod rules;
+mod search;
+mod smart_playlist;
+mod stickers;
+
+pub use art::DecodedArt;
+pub use color::{blend, contrast_ratio, default_genre_color, luminance, readable_on, Rgb, CONTROL_CONTRAST};
+pub use config::{Config, Confirmation, ServerProfile, CONNECTION_SECTION, GENERAL_SECTION, WINDOW_SECTION};
+pub use connection::{connect_tcp, needs_password, parse_mpd_host, timed_out, Client, Stream, COMMAND_TIMEOUT};
+pub use display::{ArtSize, Density, DisplayProfile, FormFactor, Layout};
+pub use events::{next_event, ConnectionState, PlaybackStateChange, SelectionAction, StateUpdateKind};
+pub use fuzzy::{fuzzy_score, rank_albums, song_match_score, FuzzyIndex};
+pub use macros::{Macro, MacroEdit, MacroStep};
+pub use queue::{
+    album_artist, album_order, album_songs, disc_count, fill_duration, is_stream, leading_number, next_toggle,
+    queue_next, queue_shuffled, songs_in_years, tag_number, year_of,
+};
+pub use rules::{Comparison, Rule};
+pub use search::{check_filter_expression, sort_songs, SearchRequest, SearchResults};
+pub use smart_playlist::SmartPlaylist;
+pub use stickers::{
+    labelled_songs, note_last_played, parse_labels, set_song_labels, song_labels, unix_now, warm_next_song,
+    LABELS_STICKER, LAST_PLAYED_STICKER, RATING_STICKER,
+};
@@ -0,0 +1,209 @@
+// Copyright © 2021-2022 Jakob L. Kreuze <zerodaysfordays@sdf.org>
+//
+// This file is part of Tunes.
+//
+// Tunes is free software; you can redistribute it and/or modify it
+// under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation; either version 3 of the
+// License, or (at your option) any later version.
+//
+// Tunes is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY
+// or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Affero General
+// Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public
+// License along with Tunes. If not, see <http://www.gnu.org/licenses/>.
+
+use crate::{album_order, Client, Config, SmartPlaylist};
+
+/// One step of a macro, written the way the user types it, like "volume 35".
+#[derive(Debug, Clone, PartialEq)]
+pub enum MacroStep {
+    Clear,
+    LoadPlaylist(String),
+    QueueSmartPlaylist(String),
+    Volume(i8),
+    /// One of MPD's modes ("random", "repeat", "single" or "consume") and
+    /// whether it's on.
+    Mode(&'static str, bool),
+    Play,
+    Pause,
+    Stop,
+    Next,
+}
+
+impl MacroStep {
+    pub const MODES: [&'static str; 4] = ["random", "repeat", "single", "consume"];
+
+    pub fn parse(text: &str) -> Result<MacroStep, String> {
+        let text = text.trim();
+        let words: Vec<&str> = text.split_whitespace().collect();
+        let rest = |prefix: &str| text[prefix.len()..].trim().to_string();
+        match words.as_slice() {
+            ["clear"] => Ok(MacroStep::Clear),
+            ["play"] => Ok(MacroStep::Play),
+            ["pause"] => Ok(MacroStep::Pause),
+            ["stop"] => Ok(MacroStep::Stop),
+            ["next"] => Ok(MacroStep::Next),
+            ["load", "playlist", _, ..] => Ok(MacroStep::LoadPlaylist(rest("load playlist"))),
+            ["smart", "playlist", _, ..] => Ok(MacroStep::QueueSmartPlaylist(rest("smart playlist"))),
+            ["volume", volume] => volume
+                .parse()
+                .ok()
+                .filter(|volume| (0..=100).contains(volume))
+                .map(MacroStep::Volume)
+                .ok_or_else(|| format!("“{}” isn't a volume from 0 to 100", volume)),
+            [mode, state @ ("on" | "off")] => Self::MODES
+                .iter()
+                .find(|x| *x == mode)
+                .map(|mode| MacroStep::Mode(mode, *state == "on"))
+                .ok_or_else(|| format!("“{}” isn't something to turn on or off", mode)),
+            _ => Err(format!("“{}” doesn't look like a step", text)),
+        }
+    }
+
+    pub fn run(&self, conn: &mut Client, config: &Config) -> anyhow::Result<()> {
+        match self {
+            MacroStep::Clear => conn.clear()?,
+            MacroStep::LoadPlaylist(name) => conn.load(name, ..)?,
+            MacroStep::QueueSmartPlaylist(name) => {
+                let playlist = SmartPlaylist::load(config, name)
+                    .ok_or_else(|| anyhow::anyhow!("there's no smart playlist called “{}”", name))?;
+                let mut songs = playlist.songs(conn)?;
+                songs.sort_by(|a, b| album_order(a).cmp(&album_order(b)));
+                for song in &songs {
+                    conn.push(song)?;
+                }
+            }
+            MacroStep::Volume(volume) => conn.volume(*volume)?,
+            MacroStep::Mode("random", on) => conn.random(*on)?,
+            MacroStep::Mode("repeat", on) => conn.repeat(*on)?,
+            MacroStep::Mode("single", on) => conn.single(*on)?,
+            MacroStep::Mode(_, on) => conn.consume(*on)?,
+            MacroStep::Play => conn.play()?,
+            MacroStep::Pause => conn.pause(true)?,
+            MacroStep::Stop => conn.stop()?,
+            MacroStep::Next => conn.next()?,
+        }
+        Ok(())
+    }
+}
+
+impl std::fmt::Display for MacroStep {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            MacroStep::Clear => write!(f, "clear"),
+            MacroStep::LoadPlaylist(name) => write!(f, "load playlist {}", name),
+            MacroStep::QueueSmartPlaylist(name) => write!(f, "smart playlist {}", name),
+            MacroStep::Volume(volume) => write!(f, "volume {}", volume),
+            MacroStep::Mode(mode, on) => write!(f, "{} {}", mode, if *on { "on" } else { "off" }),
+            MacroStep::Play => write!(f, "play"),
+            MacroStep::Pause => write!(f, "pause"),
+            MacroStep::Stop => write!(f, "stop"),
+            MacroStep::Next => write!(f, "next"),
+        }
+    }
+}
+
+/// A named run of steps to set things up in one go, like "Dinner": load a
+/// playlist, turn the volume down and start playing. Each one lives in its own
+/// config section, like smart playlists.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Macro {
+    pub name: String,
+    pub steps: Vec<MacroStep>,
+}
+
+impl Macro {
+    pub const SECTION_PREFIX: &'static str = "macro:";
+
+    pub fn load(config: &Config, name: &str) -> Option<Self> {
+        let keys = config.section(&format!("{}{}", Self::SECTION_PREFIX, name))?;
+        let steps = keys.values().filter_map(|x| MacroStep::parse(x).ok()).collect();
+        Some(Macro {
+            name: name.into(),
+            steps,
+        })
+    }
+
+    pub fn load_all(config: &Config) -> Vec<Self> {
+        config
+            .section_names(Self::SECTION_PREFIX)
+            .filter_map(|section| Self::load(config, &section[Self::SECTION_PREFIX.len()..]))
+            .collect()
+    }
+
+    pub fn save(&self, config: &mut Config) {
+        let keys = self
+            .steps
+            .iter()
+            .enumerate()
+            .map(|(i, step)| (format!("step-{:03}", i), step.to_string()))
+            .collect();
+        config.replace_section(&format!("{}{}", Self::SECTION_PREFIX, self.name), keys);
+    }
+
+    pub fn remove(&self, config: &mut Config) {
+        config.remove_section(&format!("{}{}", Self::SECTION_PREFIX, self.name));
+    }
+
+    /// Whether running this empties the queue on the way.
+    pub fn clears_queue(&self) -> bool {
+        self.steps.iter().any(|step| matches!(step, MacroStep::Clear))
+    }
+
+    /// Run the steps in order, stopping at the first that fails.
+    pub fn run(&self, conn: &mut Client, config: &Config) -> anyhow::Result<()> {
+        for step in &self.steps {
+            step.run(conn, config)?;
+        }
+        Ok(())
+    }
+}
+
+/// What to do with a macro after editing it.
+pub enum MacroEdit {
+    Save(Macro),
+    Delete,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_steps() {
+        assert_eq!(MacroStep::parse("clear"), Ok(MacroStep::Clear));
+        assert_eq!(MacroStep::parse("  play "), Ok(MacroStep::Play));
+        assert_eq!(MacroStep::parse("volume 35"), Ok(MacroStep::Volume(35)));
+        assert_eq!(MacroStep::parse("random on"), Ok(MacroStep::Mode("random", true)));
+        assert_eq!(MacroStep::parse("consume off"), Ok(MacroStep::Mode("consume", false)));
+        assert_eq!(
+            MacroStep::parse("load playlist Sunday  Morning"),
+            Ok(MacroStep::LoadPlaylist("Sunday  Morning".into()))
+        );
+        assert_eq!(
+            MacroStep::parse("smart playlist Five Stars"),
+            Ok(MacroStep::QueueSmartPlaylist("Five Stars".into()))
+        );
+    }
+
+    #[test]
+    fn turns_down_nonsense() {
+        assert!(MacroStep::parse("volume 101").is_err());
+        assert!(MacroStep::parse("volume loud").is_err());
+        assert!(MacroStep::parse("shuffle on").is_err());
+        assert!(MacroStep::parse("load playlist").is_err());
+        assert!(MacroStep::parse("").is_err());
+    }
+
+    #[test]
+    fn writes_steps_back_the_way_they_read() {
+        for text in ["clear", "load playlist Dinner", "smart playlist Five Stars", "volume 0", "single on", "next"] {
+            let step = MacroStep::parse(text).unwrap();
+            assert_eq!(step.to_string(), text);
+            assert_eq!(MacroStep::parse(&step.to_string()), Ok(step));
+        }
+    }
+}
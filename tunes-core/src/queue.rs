@@ -0,0 +1,287 @@
+// Copyright © 2021-2022 Jakob L. Kreuze <zerodaysfordays@sdf.org>
+//
+// This file is part of Tunes.
+//
+// Tunes is free software; you can redistribute it and/or modify it
+// under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation; either version 3 of the
+// License, or (at your option) any later version.
+//
+// Tunes is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY
+// or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Affero General
+// Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public
+// License along with Tunes. If not, see <http://www.gnu.org/licenses/>.
+
+use crate::Client;
+use std::time::Duration;
+
+/// Queue whatever `add` adds, shuffled among itself. This leaves the rest of
+/// the queue in order, and random mode alone, unlike shuffling everything.
+pub fn queue_shuffled(
+    conn: &mut Client,
+    add: impl FnOnce(&mut Client) -> anyhow::Result<()>,
+) -> anyhow::Result<()> {
+    let start = conn.status()?.queue_len;
+    add(conn)?;
+    let end = conn.status()?.queue_len;
+    if end > start + 1 {
+        conn.shuffle(start..end)?;
+    }
+    Ok(())
+}
+
+/// Queue `filename` to play right after the current song, returning its ID.
+pub fn queue_next(conn: &mut Client, filename: String) -> anyhow::Result<mpd::Id> {
+    // Songs go on the end of the queue, so we move it up to just after the
+    // current one.
+    let id = conn.push_str(filename)?;
+    if let Some(current) = conn.status()?.song {
+        conn.shift(id, current.pos as usize + 1)?;
+    }
+    Ok(id)
+}
+
+/// Who an album is credited to: its `AlbumArtist`, or the track artist for
+/// files that don't have one.
+pub fn album_artist(song: &mpd::song::Song) -> Option<&str> {
+    song.tags
+        .get("AlbumArtist")
+        .or(song.artist.as_ref())
+        .map(|x| x.as_str())
+}
+
+/// Every song on `album` credited to `album_artist`, in track order.
+pub fn album_songs(
+    conn: &mut Client,
+    album: &str,
+    album_artist: &str,
+) -> anyhow::Result<Vec<mpd::song::Song>> {
+    let mut query = mpd::Query::new();
+    query.and(mpd::Term::Tag("Album".into()), album);
+    let mut songs: Vec<_> = conn
+        .find(&query, (0, 65535))?
+        .into_iter()
+        .filter(|song| crate::album_artist(song).unwrap_or_default() == album_artist)
+        .collect();
+    songs.sort_by_key(|song| (tag_number(song, "Disc"), tag_number(song, "Track")));
+    Ok(songs)
+}
+
+/// Sort key that keeps each album together and in track order.
+pub fn album_order(song: &mpd::song::Song) -> (Option<&str>, Option<&String>, u32, u32) {
+    (
+        album_artist(song),
+        song.tags.get("Album"),
+        tag_number(song, "Disc"),
+        tag_number(song, "Track"),
+    )
+}
+
+/// The year a `Date` tag starts with, for dates like "1994" or "1994-05-03".
+pub fn year_of(date: &str) -> Option<u32> {
+    date.get(..4)
+        .filter(|x| x.chars().all(|c| c.is_ascii_digit()))
+        .and_then(|x| x.parse().ok())
+}
+
+/// Every song dated from `first` through `last`.
+pub fn songs_in_years(
+    conn: &mut Client,
+    first: u32,
+    last: u32,
+) -> anyhow::Result<Vec<mpd::song::Song>> {
+    // MPD only matches dates as text, so we search for whatever the years
+    // have in common ("199" for the 1990s) and weed out the rest ourselves.
+    let (first_text, last_text) = (first.to_string(), last.to_string());
+    let prefix: String = first_text
+        .chars()
+        .zip(last_text.chars())
+        .take_while(|(a, b)| a == b)
+        .map(|(a, _)| a)
+        .collect();
+    let mut query = mpd::Query::new();
+    query.and(mpd::Term::Tag("Date".into()), prefix.as_str());
+    Ok(conn
+        .search(&query, (0, 65535))?
+        .into_iter()
+        .filter(|song| {
+            song.tags
+                .get("Date")
+                .and_then(|x| year_of(x))
+                .map_or(false, |year| (first..=last).contains(&year))
+        })
+        .collect())
+}
+
+/// Pick songs from `songs`, in order, whose lengths add up to as close to
+/// `target` as we can get without going over. Songs are taken greedily, so
+/// shuffle them first for a different mix each time. Streams and anything
+/// else without a known length get left out.
+pub fn fill_duration(songs: Vec<mpd::song::Song>, target: Duration) -> Vec<mpd::song::Song> {
+    let mut total = Duration::ZERO;
+    let mut picked = Vec::new();
+    for song in songs {
+        let duration = match song.duration {
+            Some(duration) if duration > Duration::ZERO => duration,
+            _ => continue,
+        };
+        if total + duration <= target {
+            total += duration;
+            picked.push(song);
+        }
+        // Nothing's going to fit in the last few seconds.
+        if target - total < Duration::from_secs(30) {
+            break;
+        }
+    }
+    picked
+}
+
+/// The leading number of a numeric tag like `Track` or `Disc`, which are often
+/// written as "3/12". Songs without one sort first.
+pub fn tag_number(song: &mpd::song::Song, tag: &str) -> u32 {
+    song.tags.get(tag).map(|x| leading_number(x)).unwrap_or(0)
+}
+
+pub fn leading_number(value: &str) -> u32 {
+    let digits: String = value.trim().chars().take_while(|x| x.is_ascii_digit()).collect();
+    digits.parse().unwrap_or(0)
+}
+
+/// How many discs a `Disc` tag like "1/2" says the set has, or 0 if it
+/// doesn't say.
+pub fn disc_count(disc: &str) -> u32 {
+    disc.split_once('/')
+        .map(|(_, total)| leading_number(total))
+        .unwrap_or(0)
+}
+
+/// Whether `song` is a stream (an internet radio station, say) rather than a
+/// file in the library.
+pub fn is_stream(song: &mpd::song::Song) -> bool {
+    song.file.contains("://")
+}
+
+/// The mode after `mode` when cycling through them, skipping "oneshot" where
+/// the server doesn't support it.
+pub fn next_toggle(mode: mpd::Toggle, oneshot: bool) -> mpd::Toggle {
+    match mode {
+        mpd::Toggle::Off => mpd::Toggle::On,
+        mpd::Toggle::On if oneshot => mpd::Toggle::Oneshot,
+        _ => mpd::Toggle::Off,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_years_from_dates() {
+        assert_eq!(year_of("1994"), Some(1994));
+        assert_eq!(year_of("1994-05-03"), Some(1994));
+        assert_eq!(year_of("94"), None);
+        assert_eq!(year_of("May 1994"), None);
+        assert_eq!(year_of(""), None);
+    }
+
+    #[test]
+    fn reads_leading_numbers() {
+        assert_eq!(leading_number("3"), 3);
+        assert_eq!(leading_number(" 3/12"), 3);
+        assert_eq!(leading_number("03"), 3);
+        assert_eq!(leading_number("A1"), 0);
+        assert_eq!(leading_number(""), 0);
+    }
+
+    #[test]
+    fn counts_discs() {
+        assert_eq!(disc_count("1/2"), 2);
+        assert_eq!(disc_count("1 / 3"), 3);
+        assert_eq!(disc_count("1"), 0);
+        assert_eq!(disc_count(""), 0);
+    }
+
+    #[test]
+    fn sorts_songs_without_numbers_first() {
+        let mut song = mpd::Song::default();
+        assert_eq!(tag_number(&song, "Track"), 0);
+        song.tags.insert("Track".into(), "7/10".into());
+        assert_eq!(tag_number(&song, "Track"), 7);
+    }
+
+    fn songs(seconds: &[u64]) -> Vec<mpd::Song> {
+        seconds
+            .iter()
+            .enumerate()
+            .map(|(i, &seconds)| mpd::Song {
+                file: i.to_string(),
+                duration: Some(Duration::from_secs(seconds)),
+                ..Default::default()
+            })
+            .collect()
+    }
+
+    fn files(songs: &[mpd::Song]) -> Vec<&str> {
+        songs.iter().map(|song| song.file.as_str()).collect()
+    }
+
+    #[test]
+    fn fills_up_to_the_target() {
+        let picked = fill_duration(songs(&[240, 240, 240, 120]), Duration::from_secs(600));
+        assert_eq!(files(&picked), ["0", "1", "3"]);
+    }
+
+    #[test]
+    fn skips_songs_longer_than_the_target() {
+        let picked = fill_duration(songs(&[900, 180]), Duration::from_secs(600));
+        assert_eq!(files(&picked), ["1"]);
+        assert!(fill_duration(songs(&[900]), Duration::from_secs(600)).is_empty());
+    }
+
+    #[test]
+    fn stops_once_nothing_else_fits() {
+        let picked = fill_duration(songs(&[585, 10]), Duration::from_secs(600));
+        assert_eq!(files(&picked), ["0"]);
+    }
+
+    #[test]
+    fn leaves_out_songs_without_a_length() {
+        let mut input = songs(&[0, 120]);
+        input.push(mpd::Song {
+            file: "http://radio.example/live".into(),
+            ..Default::default()
+        });
+        let picked = fill_duration(input, Duration::from_secs(600));
+        assert_eq!(files(&picked), ["1"]);
+    }
+
+    #[test]
+    fn fills_nothing_from_nothing() {
+        assert!(fill_duration(Vec::new(), Duration::from_secs(600)).is_empty());
+        assert!(fill_duration(songs(&[120]), Duration::ZERO).is_empty());
+    }
+
+    #[test]
+    fn cycles_toggles() {
+        assert_eq!(next_toggle(mpd::Toggle::Off, true), mpd::Toggle::On);
+        assert_eq!(next_toggle(mpd::Toggle::On, true), mpd::Toggle::Oneshot);
+        assert_eq!(next_toggle(mpd::Toggle::Oneshot, true), mpd::Toggle::Off);
+        // Older servers skip oneshot.
+        assert_eq!(next_toggle(mpd::Toggle::Off, false), mpd::Toggle::On);
+        assert_eq!(next_toggle(mpd::Toggle::On, false), mpd::Toggle::Off);
+    }
+
+    #[test]
+    fn spots_streams() {
+        let song = |file: &str| mpd::Song {
+            file: file.into(),
+            ..Default::default()
+        };
+        assert!(is_stream(&song("https://radio.example/live")));
+        assert!(!is_stream(&song("Artist/Album/01 Song.flac")));
+    }
+}
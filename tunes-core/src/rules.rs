@@ -0,0 +1,222 @@
+// Copyright © 2021-2022 Jakob L. Kreuze <zerodaysfordays@sdf.org>
+//
+// This file is part of Tunes.
+//
+// Tunes is free software; you can redistribute it and/or modify it
+// under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation; either version 3 of the
+// License, or (at your option) any later version.
+//
+// Tunes is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY
+// or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Affero General
+// Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public
+// License along with Tunes. If not, see <http://www.gnu.org/licenses/>.
+
+/// How a number in a smart playlist rule is compared.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Comparison {
+    Less,
+    LessOrEqual,
+    Equal,
+    GreaterOrEqual,
+    Greater,
+}
+
+impl Comparison {
+    /// Pairs of how the comparison is written and what it is. Longer ones go
+    /// first so "<=" isn't mistaken for "<".
+    pub const OPERATORS: [(&'static str, Comparison); 7] = [
+        ("<=", Comparison::LessOrEqual),
+        (">=", Comparison::GreaterOrEqual),
+        ("≤", Comparison::LessOrEqual),
+        ("≥", Comparison::GreaterOrEqual),
+        ("<", Comparison::Less),
+        (">", Comparison::Greater),
+        ("=", Comparison::Equal),
+    ];
+
+    pub fn holds(self, a: u32, b: u32) -> bool {
+        match self {
+            Comparison::Less => a < b,
+            Comparison::LessOrEqual => a <= b,
+            Comparison::Equal => a == b,
+            Comparison::GreaterOrEqual => a >= b,
+            Comparison::Greater => a > b,
+        }
+    }
+
+    pub fn symbol(self) -> &'static str {
+        match self {
+            Comparison::Less => "<",
+            Comparison::LessOrEqual => "<=",
+            Comparison::Equal => "=",
+            Comparison::GreaterOrEqual => ">=",
+            Comparison::Greater => ">",
+        }
+    }
+}
+
+/// One condition a song has to meet to be in a smart playlist. Rules are
+/// written one per line, like "Genre is Jazz" or "year > 2010".
+#[derive(Debug, Clone, PartialEq)]
+pub enum Rule {
+    TagIs(String, String),
+    TagIsNot(String, String),
+    TagContains(String, String),
+    Year(Comparison, u32),
+    Rating(Comparison, u32),
+    /// Not played in this many days.
+    NotPlayedIn(u64),
+    /// Given this label by the user.
+    Labelled(String),
+}
+
+impl Rule {
+    pub fn parse(text: &str) -> Result<Rule, String> {
+        let text = text.trim();
+        let words: Vec<&str> = text.split_whitespace().collect();
+        match words.as_slice() {
+            ["not", "played", "in", days, "day" | "days"] => days
+                .parse()
+                .map(Rule::NotPlayedIn)
+                .map_err(|_| format!("“{}” isn't a number of days", days)),
+            ["labelled", _, ..] => Ok(Rule::Labelled(text["labelled".len()..].trim().to_lowercase())),
+            [field @ ("year" | "rating"), operator, number] => {
+                let comparison = Comparison::OPERATORS
+                    .iter()
+                    .find(|(symbol, _)| symbol == operator)
+                    .map(|&(_, comparison)| comparison)
+                    .ok_or_else(|| format!("“{}” isn't a comparison", operator))?;
+                let number = number
+                    .parse()
+                    .map_err(|_| format!("“{}” isn't a number", number))?;
+                Ok(if *field == "year" {
+                    Rule::Year(comparison, number)
+                } else {
+                    Rule::Rating(comparison, number)
+                })
+            }
+            _ => {
+                let (tag, rest) = text
+                    .split_once(char::is_whitespace)
+                    .ok_or_else(|| format!("“{}” doesn't look like a rule", text))?;
+                let rest = rest.trim_start();
+                let tag = tag.to_string();
+                if let Some(value) = rest.strip_prefix("is not ") {
+                    Ok(Rule::TagIsNot(tag, value.trim().into()))
+                } else if let Some(value) = rest.strip_prefix("is ") {
+                    Ok(Rule::TagIs(tag, value.trim().into()))
+                } else if let Some(value) = rest.strip_prefix("contains ") {
+                    Ok(Rule::TagContains(tag, value.trim().into()))
+                } else {
+                    Err(format!("“{}” doesn't look like a rule", text))
+                }
+            }
+        }
+    }
+
+    /// This rule as an MPD filter expression, if MPD can check it itself.
+    pub fn expression(&self) -> Option<String> {
+        // Values go in single quotes, with backslash escapes.
+        let quote = |x: &str| format!("'{}'", x.replace('\\', "\\\\").replace('\'', "\\'"));
+        match self {
+            Rule::TagIs(tag, value) => Some(format!("({} == {})", tag, quote(value))),
+            Rule::TagIsNot(tag, value) => Some(format!("({} != {})", tag, quote(value))),
+            Rule::TagContains(tag, value) => Some(format!("({} contains {})", tag, quote(value))),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for Rule {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Rule::TagIs(tag, value) => write!(f, "{} is {}", tag, value),
+            Rule::TagIsNot(tag, value) => write!(f, "{} is not {}", tag, value),
+            Rule::TagContains(tag, value) => write!(f, "{} contains {}", tag, value),
+            Rule::Year(comparison, year) => write!(f, "year {} {}", comparison.symbol(), year),
+            Rule::Rating(comparison, rating) => {
+                write!(f, "rating {} {}", comparison.symbol(), rating)
+            }
+            Rule::NotPlayedIn(days) => write!(f, "not played in {} days", days),
+            Rule::Labelled(label) => write!(f, "labelled {}", label),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_tag_rules() {
+        assert_eq!(Rule::parse("Genre is Jazz"), Ok(Rule::TagIs("Genre".into(), "Jazz".into())));
+        assert_eq!(Rule::parse("Genre is not Jazz"), Ok(Rule::TagIsNot("Genre".into(), "Jazz".into())));
+        assert_eq!(
+            Rule::parse("  Title contains  love song "),
+            Ok(Rule::TagContains("Title".into(), "love song".into()))
+        );
+    }
+
+    #[test]
+    fn parses_number_rules() {
+        assert_eq!(Rule::parse("year > 2010"), Ok(Rule::Year(Comparison::Greater, 2010)));
+        assert_eq!(Rule::parse("rating <= 3"), Ok(Rule::Rating(Comparison::LessOrEqual, 3)));
+        assert_eq!(Rule::parse("year ≥ 1990"), Ok(Rule::Year(Comparison::GreaterOrEqual, 1990)));
+        assert_eq!(Rule::parse("not played in 30 days"), Ok(Rule::NotPlayedIn(30)));
+        assert_eq!(Rule::parse("not played in 1 day"), Ok(Rule::NotPlayedIn(1)));
+    }
+
+    #[test]
+    fn labels_are_lowercase() {
+        assert_eq!(Rule::parse("labelled Chill Out"), Ok(Rule::Labelled("chill out".into())));
+    }
+
+    #[test]
+    fn rejects_nonsense() {
+        assert!(Rule::parse("year > soon").is_err());
+        assert!(Rule::parse("year ~ 2010").is_err());
+        assert!(Rule::parse("not played in a days").is_err());
+        assert!(Rule::parse("Genre sounds like Jazz").is_err());
+        assert!(Rule::parse("Genre").is_err());
+        assert!(Rule::parse("").is_err());
+    }
+
+    #[test]
+    fn reads_back_what_it_writes() {
+        for text in [
+            "Genre is Jazz",
+            "Genre is not Jazz",
+            "Title contains love",
+            "year >= 1990",
+            "rating = 5",
+            "not played in 30 days",
+            "labelled chill",
+        ] {
+            let rule = Rule::parse(text).unwrap();
+            assert_eq!(Rule::parse(&rule.to_string()), Ok(rule));
+        }
+    }
+
+    #[test]
+    fn quotes_values_in_expressions() {
+        assert_eq!(
+            Rule::TagIs("Artist".into(), "Guns N' Roses".into()).expression(),
+            Some("(Artist == 'Guns N\\' Roses')".into())
+        );
+        assert_eq!(Rule::Year(Comparison::Less, 2000).expression(), None);
+    }
+
+    #[test]
+    fn comparisons_hold() {
+        assert!(Comparison::Less.holds(1, 2));
+        assert!(!Comparison::Less.holds(2, 2));
+        assert!(Comparison::LessOrEqual.holds(2, 2));
+        assert!(Comparison::Equal.holds(3, 3));
+        assert!(Comparison::GreaterOrEqual.holds(3, 2));
+        assert!(!Comparison::Greater.holds(2, 2));
+    }
+}
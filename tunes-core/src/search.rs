@@ -0,0 +1,223 @@
+// Copyright © 2021-2022 Jakob L. Kreuze <zerodaysfordays@sdf.org>
+//
+// This file is part of Tunes.
+//
+// Tunes is free software; you can redistribute it and/or modify it
+// under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation; either version 3 of the
+// License, or (at your option) any later version.
+//
+// Tunes is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY
+// or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Affero General
+// Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public
+// License along with Tunes. If not, see <http://www.gnu.org/licenses/>.
+
+use crate::{year_of, LABELS_STICKER};
+
+/// What a search has turned up so far.
+pub struct SearchResults {
+    pub request: SearchRequest,
+    pub songs: Vec<mpd::Song>,
+    /// How many results of the MPD search proper have been fetched, which
+    /// doesn't count near misses.
+    pub fetched: u32,
+    /// Whether MPD might have more results to fetch.
+    pub more: bool,
+}
+
+/// Sort songs already in album order by one of the orders the search view
+/// offers ("artist", "year" and so on), returning whether albums are still
+/// kept together. Ties stay in album order.
+pub fn sort_songs(songs: &mut [mpd::song::Song], order: &str) -> bool {
+    let text = |value: Option<&String>| value.map(|x| x.to_lowercase());
+    match order {
+        "artist" => songs.sort_by_key(|song| text(song.artist.as_ref())),
+        "album" => songs.sort_by_key(|song| text(song.tags.get("Album"))),
+        "year" => songs.sort_by_key(|song| song.tags.get("Date").and_then(|x| year_of(x))),
+        "title" => songs.sort_by_key(|song| text(song.title.as_ref())),
+        "duration" => songs.sort_by_key(|song| song.duration),
+        // These go by when the file last changed, which for most libraries is
+        // when it was added.
+        "newest" => songs.sort_by_key(|song| std::cmp::Reverse(song.last_mod)),
+        "oldest" => songs.sort_by_key(|song| song.last_mod),
+        _ => {}
+    }
+    !matches!(order, "artist" | "title" | "duration" | "newest" | "oldest" | "server")
+}
+
+/// What the user asked the search view for.
+#[derive(Debug, Clone, Default)]
+pub struct SearchRequest {
+    /// Text to look for.
+    pub text: String,
+    /// Tag the text should be found in, or `None` for any tag.
+    pub tag: Option<String>,
+    /// Pairs of tag and text from the advanced form, which all have to match.
+    pub fields: Vec<(String, String)>,
+    /// How to order the results, as `sort_songs` takes it.
+    pub sort: String,
+    /// Which change to the search form this request came from.
+    pub generation: u64,
+}
+
+impl SearchRequest {
+    pub fn new(text: &str, tag: Option<&str>) -> Self {
+        SearchRequest {
+            text: text.into(),
+            tag: tag.filter(|x| !x.is_empty()).map(String::from),
+            fields: Vec::new(),
+            sort: String::new(),
+            generation: 0,
+        }
+    }
+
+    /// The MPD query for a plain search. The text is looked for in all tags
+    /// (or just the one the user picked), case-insensitively, and anything in
+    /// the advanced fields has to match as well.
+    pub fn query(&self) -> mpd::Query<'_> {
+        let mut query = mpd::Query::new();
+        if !self.text.is_empty() {
+            let term = match &self.tag {
+                Some(tag) => mpd::Term::Tag(tag.as_str().into()),
+                None => mpd::Term::Any,
+            };
+            query.and(term, self.text.as_str());
+        }
+        for (tag, value) in &self.fields {
+            query.and(mpd::Term::Tag(tag.as_str().into()), value.as_str());
+        }
+        query
+    }
+
+    /// Whether this is a plain search that near misses should count for.
+    /// Narrowed-down searches are taken to mean exactly what they say.
+    pub fn is_fuzzy(&self) -> bool {
+        self.tag.is_none() && self.fields.is_empty()
+    }
+
+    /// Whether this looks for songs by the user's labels, which MPD can't
+    /// search for by itself.
+    pub fn is_label_search(&self) -> bool {
+        self.tag.as_deref() == Some(LABELS_STICKER)
+    }
+
+    /// Whether the text is an MPD filter expression rather than plain text.
+    /// Expressions are always wrapped in parentheses, and nobody searches for
+    /// a song starting with one.
+    pub fn is_filter_expression(&self) -> bool {
+        self.text.trim_start().starts_with('(')
+    }
+}
+
+/// Check that a filter expression is at least well-formed, so half-typed ones
+/// get a useful message rather than whatever MPD makes of them.
+pub fn check_filter_expression(expression: &str) -> Result<(), String> {
+    let mut depth = 0;
+    let mut quote = None;
+    let mut chars = expression.chars();
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (Some(_), '\\') => {
+                chars.next();
+            }
+            (Some(open), c) if c == open => quote = None,
+            (Some(_), _) => {}
+            (None, '"' | '\'') => quote = Some(c),
+            (None, '(') => depth += 1,
+            (None, ')') if depth == 0 => return Err("Unexpected closing parenthesis".into()),
+            (None, ')') => depth -= 1,
+            (None, _) => {}
+        }
+    }
+    if quote.is_some() {
+        Err("Unterminated quoted value".into())
+    } else if depth > 0 {
+        Err("Missing closing parenthesis".into())
+    } else {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn song(artist: &str, album: &str, date: &str) -> mpd::Song {
+        let mut song = mpd::Song {
+            artist: Some(artist.into()),
+            ..Default::default()
+        };
+        song.tags.insert("Album".into(), album.into());
+        song.tags.insert("Date".into(), date.into());
+        song
+    }
+
+    fn artists(songs: &[mpd::Song]) -> Vec<&str> {
+        songs.iter().map(|song| song.artist.as_deref().unwrap()).collect()
+    }
+
+    #[test]
+    fn sorts_by_artist_ignoring_case() {
+        let mut songs = vec![song("b", "1", "2000"), song("C", "2", "2000"), song("A", "3", "2000")];
+        assert!(!sort_songs(&mut songs, "artist"));
+        assert_eq!(artists(&songs), ["A", "b", "C"]);
+    }
+
+    #[test]
+    fn sorts_by_year_keeping_ties_in_order() {
+        let mut songs = vec![
+            song("A", "1", "2001"),
+            song("B", "2", "1999-04-01"),
+            song("C", "3", "2001"),
+            song("D", "4", ""),
+        ];
+        assert!(sort_songs(&mut songs, "year"));
+        assert_eq!(artists(&songs), ["D", "B", "A", "C"]);
+    }
+
+    #[test]
+    fn leaves_unknown_orders_alone() {
+        let mut songs = vec![song("B", "1", "2000"), song("A", "2", "2000")];
+        assert!(sort_songs(&mut songs, ""));
+        assert_eq!(artists(&songs), ["B", "A"]);
+        assert!(!sort_songs(&mut songs, "server"));
+        assert_eq!(artists(&songs), ["B", "A"]);
+    }
+
+    #[test]
+    fn accepts_well_formed_expressions() {
+        assert_eq!(check_filter_expression("(Artist == 'Nirvana')"), Ok(()));
+        assert_eq!(check_filter_expression("((Artist == \"A\") AND (Date >= '1990'))"), Ok(()));
+        // Parentheses and escaped quotes inside values don't count.
+        assert_eq!(check_filter_expression("(Title == 'a)b(')"), Ok(()));
+        assert_eq!(check_filter_expression("(Title == 'it\\'s')"), Ok(()));
+    }
+
+    #[test]
+    fn rejects_malformed_expressions() {
+        assert_eq!(
+            check_filter_expression("(Artist == 'Nirvana'"),
+            Err("Missing closing parenthesis".into())
+        );
+        assert_eq!(
+            check_filter_expression("(Artist == 'Nirvana)"),
+            Err("Unterminated quoted value".into())
+        );
+        assert_eq!(
+            check_filter_expression("(Artist == 'Nirvana'))"),
+            Err("Unexpected closing parenthesis".into())
+        );
+    }
+
+    #[test]
+    fn recognizes_requests() {
+        assert!(SearchRequest::new(" (Artist == 'x')", None).is_filter_expression());
+        assert!(!SearchRequest::new("Nirvana", Some("")).is_filter_expression());
+        assert!(SearchRequest::new("Nirvana", Some("")).is_fuzzy());
+        assert!(!SearchRequest::new("Nirvana", Some("Artist")).is_fuzzy());
+        assert!(SearchRequest::new("chill", Some(LABELS_STICKER)).is_label_search());
+    }
+}
@@ -0,0 +1,134 @@
+// Copyright © 2021-2022 Jakob L. Kreuze <zerodaysfordays@sdf.org>
+//
+// This file is part of Tunes.
+//
+// Tunes is free software; you can redistribute it and/or modify it
+// under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation; either version 3 of the
+// License, or (at your option) any later version.
+//
+// Tunes is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY
+// or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Affero General
+// Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public
+// License along with Tunes. If not, see <http://www.gnu.org/licenses/>.
+
+use crate::{
+    leading_number, parse_labels, unix_now, year_of, Client, Config, Rule, LABELS_STICKER, LAST_PLAYED_STICKER,
+    RATING_STICKER,
+};
+use std::collections::BTreeMap;
+
+/// A saved set of rules that can be turned into a queue whenever it's wanted.
+/// Each one lives in its own config section.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SmartPlaylist {
+    pub name: String,
+    pub rules: Vec<Rule>,
+}
+
+impl SmartPlaylist {
+    pub const SECTION_PREFIX: &'static str = "smart-playlist:";
+
+    pub fn load(config: &Config, name: &str) -> Option<Self> {
+        let keys = config.section(&format!("{}{}", Self::SECTION_PREFIX, name))?;
+        // Rules are stored under their (zero-padded) position, so they come
+        // back out in order. Anything unparseable was hand-edited; skip it.
+        let rules = keys.values().filter_map(|x| Rule::parse(x).ok()).collect();
+        Some(SmartPlaylist {
+            name: name.into(),
+            rules,
+        })
+    }
+
+    pub fn load_all(config: &Config) -> Vec<Self> {
+        config
+            .section_names(Self::SECTION_PREFIX)
+            .filter_map(|section| Self::load(config, &section[Self::SECTION_PREFIX.len()..]))
+            .collect()
+    }
+
+    pub fn save(&self, config: &mut Config) {
+        let keys = self
+            .rules
+            .iter()
+            .enumerate()
+            .map(|(i, rule)| (format!("rule-{:03}", i), rule.to_string()))
+            .collect();
+        config.replace_section(&format!("{}{}", Self::SECTION_PREFIX, self.name), keys);
+    }
+
+    pub fn remove(&self, config: &mut Config) {
+        config.remove_section(&format!("{}{}", Self::SECTION_PREFIX, self.name));
+    }
+
+    /// Every song meeting all of the rules. Tag rules are left to MPD; the
+    /// rest get checked here, with ratings and play times from stickers.
+    pub fn songs(&self, conn: &mut Client) -> anyhow::Result<Vec<mpd::song::Song>> {
+        let expressions: Vec<String> = self.rules.iter().filter_map(Rule::expression).collect();
+        let filter = match expressions.len() {
+            // Everything's been modified since 1970.
+            0 => "(modified-since '0')".to_string(),
+            1 => expressions[0].clone(),
+            _ => format!("({})", expressions.join(" AND ")),
+        };
+        let mut songs = conn.find_filter(&filter)?;
+
+        let needs = |wanted: fn(&Rule) -> bool| self.rules.iter().any(wanted);
+        // A server without a sticker database has no ratings or play times,
+        // which is the same as none being set.
+        let ratings: BTreeMap<String, String> = if needs(|x| matches!(x, Rule::Rating(..))) {
+            conn.find_sticker("song", "", RATING_STICKER)
+                .unwrap_or_default()
+                .into_iter()
+                .collect()
+        } else {
+            BTreeMap::new()
+        };
+        let last_played: BTreeMap<String, String> = if needs(|x| matches!(x, Rule::NotPlayedIn(..))) {
+            conn.find_sticker("song", "", LAST_PLAYED_STICKER)
+                .unwrap_or_default()
+                .into_iter()
+                .collect()
+        } else {
+            BTreeMap::new()
+        };
+
+        let labels: BTreeMap<String, Vec<String>> = if needs(|x| matches!(x, Rule::Labelled(..))) {
+            conn.find_sticker("song", "", LABELS_STICKER)
+                .unwrap_or_default()
+                .into_iter()
+                .map(|(file, labels)| (file, parse_labels(&labels)))
+                .collect()
+        } else {
+            BTreeMap::new()
+        };
+
+        let now = unix_now();
+        songs.retain(|song| {
+            self.rules.iter().all(|rule| match rule {
+                Rule::Year(comparison, year) => song
+                    .tags
+                    .get("Date")
+                    .and_then(|x| year_of(x))
+                    .map_or(false, |x| comparison.holds(x, *year)),
+                // Unrated songs only count as rated 0.
+                Rule::Rating(comparison, rating) => {
+                    let value = ratings.get(&song.file).map_or(0, |x| leading_number(x));
+                    comparison.holds(value, *rating)
+                }
+                Rule::NotPlayedIn(days) => last_played
+                    .get(&song.file)
+                    .and_then(|x| x.parse::<u64>().ok())
+                    .map_or(true, |when| now.saturating_sub(when) > days * 24 * 60 * 60),
+                Rule::Labelled(label) => labels
+                    .get(&song.file)
+                    .map_or(false, |x| x.contains(label)),
+                _ => true,
+            })
+        });
+        Ok(songs)
+    }
+}
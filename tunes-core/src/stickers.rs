@@ -0,0 +1,118 @@
+// Copyright © 2021-2022 Jakob L. Kreuze <zerodaysfordays@sdf.org>
+//
+// This file is part of Tunes.
+//
+// Tunes is free software; you can redistribute it and/or modify it
+// under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation; either version 3 of the
+// License, or (at your option) any later version.
+//
+// Tunes is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY
+// or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Affero General
+// Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public
+// License along with Tunes. If not, see <http://www.gnu.org/licenses/>.
+
+use crate::Client;
+
+/// Sticker holding a song's rating, from 1 to 5.
+pub const RATING_STICKER: &str = "rating";
+
+/// Sticker holding when a song last started playing, as a UNIX timestamp.
+pub const LAST_PLAYED_STICKER: &str = "lastPlayed";
+
+/// Sticker holding the user's own labels for a song ("chill", "workout"),
+/// separated by commas. These are kept apart from the file's tags, which MPD
+/// can't change.
+pub const LABELS_STICKER: &str = "labels";
+
+/// Split the text of a labels sticker (or what the user typed) into labels.
+/// Labels are case-insensitive, so they're kept in lowercase.
+pub fn parse_labels(text: &str) -> Vec<String> {
+    let mut labels: Vec<String> = text
+        .split(',')
+        .map(|x| x.trim().to_lowercase())
+        .filter(|x| !x.is_empty())
+        .collect();
+    labels.sort();
+    labels.dedup();
+    labels
+}
+
+/// The labels on the song at `file`. MPD reports a missing sticker as an
+/// error, which just means there aren't any.
+pub fn song_labels(conn: &mut Client, file: &str) -> Vec<String> {
+    conn.sticker("song", file, LABELS_STICKER)
+        .map(|x| parse_labels(&x))
+        .unwrap_or_default()
+}
+
+pub fn set_song_labels(conn: &mut Client, file: &str, labels: &[String]) -> anyhow::Result<()> {
+    if labels.is_empty() {
+        // Deleting a sticker that isn't there is an error too.
+        let _ = conn.delete_sticker("song", file, LABELS_STICKER);
+    } else {
+        conn.set_sticker("song", file, LABELS_STICKER, &labels.join(", "))?;
+    }
+    Ok(())
+}
+
+/// Every song labelled with `label`.
+pub fn labelled_songs(conn: &mut Client, label: &str) -> anyhow::Result<Vec<mpd::song::Song>> {
+    let label = label.trim().to_lowercase();
+    let files: Vec<String> = conn
+        .find_sticker("song", "", LABELS_STICKER)
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|(_, labels)| parse_labels(labels).contains(&label))
+        .map(|(file, _)| file)
+        .collect();
+    let mut songs = Vec::new();
+    for file in files {
+        let mut query = mpd::Query::new();
+        query.and(mpd::Term::File, file.as_str());
+        songs.extend(conn.find(&query, (0, 1))?);
+    }
+    Ok(songs)
+}
+
+pub fn unix_now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+/// Stamp the current song with the time, if it's playing and we haven't
+/// already. Servers without a sticker database just don't get stamped.
+pub fn note_last_played(conn: &mut Client, last_played: &mut Option<String>) {
+    let playing = conn
+        .status()
+        .map(|status| status.state == mpd::State::Play)
+        .unwrap_or(false);
+    if let (true, Ok(Some(song))) = (playing, conn.currentsong()) {
+        if last_played.as_deref() != Some(song.file.as_str()) {
+            let _ = conn.set_sticker("song", &song.file, LAST_PLAYED_STICKER, &unix_now().to_string());
+            *last_played = Some(song.file);
+        }
+    }
+}
+
+/// Read the picture out of the next song in the queue, so that a library on
+/// a network mount has the file in its cache by the time MPD gets to it, and
+/// gapless playback doesn't stutter. Each song is only warmed up once.
+pub fn warm_next_song(conn: &mut Client, last_warmed: &mut Option<String>) {
+    let next = match conn.status().ok().and_then(|status| status.nextsong) {
+        Some(next) => next,
+        None => return,
+    };
+    if let Ok(Some(song)) = conn.songs(next.pos).map(|songs| songs.into_iter().next()) {
+        if last_warmed.as_deref() != Some(song.file.as_str()) {
+            // We only care that the file got read, not what was in it.
+            let _ = conn.readpicture(&song);
+            *last_warmed = Some(song.file);
+        }
+    }
+}
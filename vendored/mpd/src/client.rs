@@ -15,10 +15,11 @@ use crate::output::Output;
 use crate::playlist::Playlist;
 use crate::plugin::Plugin;
 use crate::proto::*;
+use crate::reply::Reply;
 use crate::search::{Query, Window, Term};
-use crate::song::{Id, Song};
-use crate::stats::Stats;
-use crate::status::{ReplayGain, Status};
+use crate::song::{Entry, Id, Song};
+use crate::stats::{Count, Stats};
+use crate::status::{ReplayGain, Status, Toggle};
 use crate::sticker::Sticker;
 use crate::version::Version;
 
@@ -26,6 +27,7 @@ use std::convert::From;
 use std::io::{BufRead, Lines, Read, Write};
 use std::net::{TcpStream, ToSocketAddrs};
 use std::collections::HashMap;
+use std::time::Duration;
 
 // Client {{{
 
@@ -72,6 +74,12 @@ impl<S: Read + Write> Client<S> {
                version: version,
            })
     }
+
+    /// The socket underneath, say for cloning to shut it down from another
+    /// thread while this one's blocked in `idle`.
+    pub fn get_ref(&self) -> &S {
+        self.socket.get_ref()
+    }
     // }}}
 
     // Playback options & status {{{
@@ -119,6 +127,16 @@ impl<S: Read + Write> Client<S> {
         self.run_command("consume", value as u8).and_then(|_| self.expect_ok())
     }
 
+    /// Set single mode, which can be "oneshot" since MPD 0.21
+    pub fn set_single(&mut self, value: Toggle) -> Result<()> {
+        self.run_command("single", value.to_arg()).and_then(|_| self.expect_ok())
+    }
+
+    /// Set consume mode, which can be "oneshot" since MPD 0.24
+    pub fn set_consume(&mut self, value: Toggle) -> Result<()> {
+        self.run_command("consume", value.to_arg()).and_then(|_| self.expect_ok())
+    }
+
     /// Set crossfade time in seconds
     pub fn crossfade<T: ToSeconds>(&mut self, value: T) -> Result<()> {
         self.run_command("crossfade", value.to_seconds()).and_then(|_| self.expect_ok())
@@ -231,6 +249,41 @@ impl<S: Read + Write> Client<S> {
         self.run_command("addid", path).and_then(|_| self.read_field("Id")).map(Id)
     }
 
+    /// Replace the queue with the given songs and start playing them, all in one command list
+    pub fn replace_queue(&mut self, paths: &[String]) -> Result<()> {
+        self.run_command("command_list_begin", ())?;
+        self.run_command("clear", ())?;
+        for path in paths {
+            self.run_command("add", &**path)?;
+        }
+        self.run_command("play", ())?;
+        self.run_command("command_list_end", ()).and_then(|_| self.expect_ok())
+    }
+
+    /// Append several songs into a queue, all in one command list
+    pub fn push_many(&mut self, paths: &[String]) -> Result<()> {
+        self.run_command("command_list_begin", ())?;
+        for path in paths {
+            self.run_command("add", &**path)?;
+        }
+        self.run_command("command_list_end", ()).and_then(|_| self.expect_ok())
+    }
+
+    /// Insert several songs, in order, into a queue starting at a given position, all in one
+    /// command list
+    pub fn insert_many(&mut self, paths: &[String], pos: usize) -> Result<()> {
+        self.run_command("command_list_begin", ())?;
+        for (i, path) in paths.iter().enumerate() {
+            self.run_command("addid", (&**path, pos + i))?;
+        }
+        self.run_command("command_list_end", ()).and_then(|_| self.read_list("Id")).map(|_| ())
+    }
+
+    /// Append a song, or every song in a directory (recursively), into a queue
+    pub fn add(&mut self, path: &str) -> Result<()> {
+        self.run_command("add", path).and_then(|_| self.expect_ok())
+    }
+
     /// Insert a song into a given position in a queue
     pub fn insert<P: ToSongPath>(&mut self, path: P, pos: usize) -> Result<usize> {
         self.run_command("addid", (path, pos)).and_then(|_| self.read_field("Id"))
@@ -303,6 +356,11 @@ impl<S: Read + Write> Client<S> {
     pub fn login(&mut self, password: &str) -> Result<()> {
         self.run_command("password", password).and_then(|_| self.expect_ok())
     }
+
+    /// Switch this connection over to another partition
+    pub fn switch_partition(&mut self, name: &str) -> Result<()> {
+        self.run_command("partition", name).and_then(|_| self.expect_ok())
+    }
     // }}}
 
     // Playlist methods {{{
@@ -351,6 +409,17 @@ impl<S: Read + Write> Client<S> {
         self.run_command("playlistadd", (name.to_name(), path)).and_then(|_| self.expect_ok())
     }
 
+    /// Add several songs (by URI) to a playlist in a single command list
+    ///
+    /// If playlist with given name doesn't exist, create new one.
+    pub fn pl_push_many<N: ToPlaylistName>(&mut self, name: N, paths: &[String]) -> Result<()> {
+        self.run_command("command_list_begin", ())?;
+        for path in paths {
+            self.run_command("playlistadd", (name.to_name(), &**path))?;
+        }
+        self.run_command("command_list_end", ()).and_then(|_| self.expect_ok())
+    }
+
     /// Delete a song at a given position in a playlist
     pub fn pl_delete<N: ToPlaylistName>(&mut self, name: N, pos: u32) -> Result<()> {
         self.run_command("playlistdelete", (name.to_name(), pos)).and_then(|_| self.expect_ok())
@@ -394,7 +463,12 @@ impl<S: Read + Write> Client<S> {
         let mut buf = vec![];
         loop {
             self.run_command("albumart", (path, &*format!("{}", buf.len())))?;
-            let (_, size) = self.read_pair()?;
+            // Songs without a cover file get an ACK rather than a size
+            let size = match self.read_line()?.parse::<Reply>().map_err(Error::Parse)? {
+                Reply::Ack(e) => return Err(Error::Server(e)),
+                Reply::Pair(_, size) => size,
+                Reply::Ok => return Err(ProtoError::NotPair)?,
+            };
             let (_, bytes) = self.read_pair()?;
             let mut chunk = self.read_bytes(bytes.parse()?)?;
             buf.append(&mut chunk);
@@ -412,6 +486,43 @@ impl<S: Read + Write> Client<S> {
         Ok(buf)
     }
 
+    /// Read the picture embedded in a song file, if it has one
+    pub fn readpicture<P: ToSongPath>(&mut self, path: &P) -> Result<Option<Vec<u8>>> {
+        let mut buf = vec![];
+        loop {
+            self.run_command("readpicture", (path, &*format!("{}", buf.len())))?;
+            // Songs without a picture only get an OK, and the picture's type
+            // comes along with the first chunk
+            let mut size = 0;
+            loop {
+                match self.read_line()?.parse::<Reply>().map_err(Error::Parse)? {
+                    Reply::Ok => return Ok(None),
+                    Reply::Ack(e) => return Err(Error::Server(e)),
+                    Reply::Pair(key, value) => match &*key {
+                        "size" => size = value.parse()?,
+                        "binary" => {
+                            let mut chunk = self.read_bytes(value.parse()?)?;
+                            buf.append(&mut chunk);
+                            break;
+                        }
+                        _ => {}
+                    },
+                }
+            }
+            // Read empty newline
+            let _ = self.read_line()?;
+            let result = self.read_line()?;
+            if result != "OK" {
+                return Err(ProtoError::NotOk)?;
+            }
+
+            if size == buf.len() {
+                break;
+            }
+        }
+        Ok(Some(buf))
+    }
+
     /// Case-insensitively search for songs matching Query conditions.
     pub fn search<W>(&mut self, query: &Query, window: W) -> Result<Vec<Song>>
         where W: Into<Window>
@@ -419,6 +530,34 @@ impl<S: Read + Write> Client<S> {
         self.find_generic("search", query, window.into())
     }
 
+    /// List every song in the database along with its tags, leaving out directories and playlists
+    pub fn listallinfo(&mut self) -> Result<Vec<Song>> {
+        self.run_command("listallinfo", ())?;
+        let mut songs = Vec::new();
+        let mut current: Option<Vec<(String, String)>> = None;
+        for pair in self.read_pairs() {
+            let (key, value) = pair?;
+            match &*key {
+                "file" => songs.extend(current.replace(vec![(key, value)])),
+                "directory" | "playlist" => songs.extend(current.take()),
+                _ => {
+                    if let Some(pairs) = current.as_mut() {
+                        pairs.push((key, value));
+                    }
+                }
+            }
+        }
+        songs.extend(current);
+        songs.into_iter().map(|pairs| Song::from_iter(pairs.into_iter().map(Ok))).collect()
+    }
+
+    /// Find songs matching a filter expression, like `((Genre == 'Jazz') AND (Date >= '2010'))`
+    ///
+    /// Filter expressions need MPD 0.21 or newer.
+    pub fn find_filter(&mut self, filter: &str) -> Result<Vec<Song>> {
+        self.run_command("find", filter).and_then(|_| self.read_structs("file"))
+    }
+
     fn find_generic(&mut self, cmd: &str, query: &Query, window: Window) -> Result<Vec<Song>> {
         self.run_command(cmd, (query, window)).and_then(|_| self.read_structs("file"))
     }
@@ -430,6 +569,33 @@ impl<S: Read + Write> Client<S> {
         self.run_command("list", (term, query)).and_then(|_| self.read_pairs().map(|p| p.map(|p| p.1)).collect())
     }
 
+    /// Count songs and their total playtime for every distinct value of a tag.
+    ///
+    /// `tag` should be spelled the way MPD reports it (e.g. `Genre`), since it is
+    /// also used to split the response into groups.
+    pub fn count_grouped(&mut self, tag: &str) -> Result<Vec<Count>> {
+        self.run_command("count group", tag)?;
+        self.read_pairs()
+            .split(tag)
+            .map(|map| -> Result<Count> {
+                let mut map = map?;
+                let songs = match map.get("songs") {
+                    Some(songs) => songs.parse::<u32>()?,
+                    None => 0,
+                };
+                let playtime = match map.get("playtime") {
+                    Some(playtime) => Duration::from_secs(playtime.parse::<u64>()?),
+                    None => Duration::from_secs(0),
+                };
+                Ok(Count {
+                       value: map.remove(tag).unwrap_or_default(),
+                       songs: songs,
+                       playtime: playtime,
+                   })
+            })
+            .collect()
+    }
+
     /// Find all songs in the db that match query and adds them to current playlist.
     pub fn findadd(&mut self, query: &Query) -> Result<()> {
         self.run_command("findadd", query).and_then(|_| self.expect_ok())
@@ -440,6 +606,41 @@ impl<S: Read + Write> Client<S> {
         self.run_command("lsinfo", path).and_then(|_| self.read_struct())
     }
 
+    /// Lists the directories, songs and playlists inside a directory.
+    ///
+    /// Give an empty path to list the root of the music directory.
+    pub fn lsdir(&mut self, path: &str) -> Result<Vec<Entry>> {
+        self.run_command("lsinfo", path)?;
+
+        let mut entries = Vec::new();
+        let mut song: Option<Vec<(String, String)>> = None;
+        for pair in self.read_pairs() {
+            let (key, value) = pair?;
+            match &*key {
+                "directory" | "file" | "playlist" => {
+                    if let Some(lines) = song.take() {
+                        entries.push(Entry::Song(FromIter::from_iter(lines.into_iter().map(Ok))?));
+                    }
+                    match &*key {
+                        "directory" => entries.push(Entry::Directory(value)),
+                        "playlist" => entries.push(Entry::Playlist(value)),
+                        _ => song = Some(vec![(key, value)]),
+                    }
+                }
+                _ => {
+                    if let Some(ref mut lines) = song {
+                        lines.push((key, value));
+                    }
+                }
+            }
+        }
+        if let Some(lines) = song.take() {
+            entries.push(Entry::Song(FromIter::from_iter(lines.into_iter().map(Ok))?));
+        }
+
+        Ok(entries)
+    }
+
     /// Returns raw metadata for file
     pub fn readcomments<'a, P: ToSongPath>(&'a mut self, path: P) -> Result<impl Iterator<Item = Result<(String, String)>> + 'a> {
         self.run_command("readcomments", path)?;
@@ -586,6 +787,15 @@ impl<S: Read + Write> Client<S> {
         self.run_command("sticker set", (typ, uri, name, value)).and_then(|_| self.expect_ok())
     }
 
+    /// Set the same sticker on several objects, all in one command list
+    pub fn set_sticker_many(&mut self, typ: &str, uris: &[String], name: &str, value: &str) -> Result<()> {
+        self.run_command("command_list_begin", ())?;
+        for uri in uris {
+            self.run_command("sticker set", (typ, &**uri, name, value))?;
+        }
+        self.run_command("command_list_end", ()).and_then(|_| self.expect_ok())
+    }
+
     /// Delete sticker from a given object, identified by type and uri
     pub fn delete_sticker(&mut self, typ: &str, uri: &str, name: &str) -> Result<()> {
         self.run_command("sticker delete", (typ, uri, name)).and_then(|_| self.expect_ok())
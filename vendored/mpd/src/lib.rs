@@ -61,6 +61,6 @@ pub use playlist::Playlist;
 pub use plugin::Plugin;
 pub use search::{Query, Term};
 pub use song::{Id, Song};
-pub use stats::Stats;
-pub use status::{ReplayGain, State, Status};
+pub use stats::{Count, Stats};
+pub use status::{ReplayGain, State, Status, Toggle};
 pub use version::Version;
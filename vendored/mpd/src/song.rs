@@ -208,3 +208,14 @@ impl FromIter for Song {
         Ok(result)
     }
 }
+
+/// Entry in a directory listing
+#[derive(Debug, Clone, PartialEq)]
+pub enum Entry {
+    /// sub-directory, by path relative to the music directory
+    Directory(String),
+    /// song file
+    Song(Song),
+    /// playlist file, by path relative to the music directory
+    Playlist(String),
+}
@@ -40,6 +40,17 @@ impl Encodable for Stats {
     }
 }
 
+/// Song count and total playtime for one value of a tag, as reported by `count group`
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Count {
+    /// tag value shared by the counted songs (empty for songs without the tag)
+    pub value: String,
+    /// number of songs
+    pub songs: u32,
+    /// total playback time of the songs, seconds resolution
+    pub playtime: Duration,
+}
+
 impl Default for Stats {
     fn default() -> Stats {
         Stats {
@@ -22,6 +22,10 @@ pub struct Status {
     pub single: bool,
     /// consume mode
     pub consume: bool,
+    /// single mode, including "oneshot" (MPD 0.21+)
+    pub single_mode: Toggle,
+    /// consume mode, including "oneshot" (MPD 0.24+)
+    pub consume_mode: Toggle,
     /// queue version number
     pub queue_version: u32,
     /// queue length
@@ -58,7 +62,7 @@ pub struct Status {
 
 impl Encodable for Status {
     fn encode<S: Encoder>(&self, e: &mut S) -> Result<(), S::Error> {
-        e.emit_struct("Status", 21, |e| {
+        e.emit_struct("Status", 23, |e| {
             e.emit_struct_field("volume", 0, |e| self.volume.encode(e))?;
             e.emit_struct_field("repeat", 1, |e| self.repeat.encode(e))?;
             e.emit_struct_field("random", 2, |e| self.random.encode(e))?;
@@ -114,6 +118,8 @@ impl Encodable for Status {
             e.emit_struct_field("updating_db", 18, |e| self.updating_db.encode(e))?;
             e.emit_struct_field("error", 19, |e| self.error.encode(e))?;
             e.emit_struct_field("replaygain", 20, |e| self.replaygain.encode(e))?;
+            e.emit_struct_field("single_mode", 21, |e| self.single_mode.encode(e))?;
+            e.emit_struct_field("consume_mode", 22, |e| self.consume_mode.encode(e))?;
             Ok(())
         })
 
@@ -131,8 +137,14 @@ impl FromIter for Status {
 
                 "repeat" => result.repeat = &*line.1 == "1",
                 "random" => result.random = &*line.1 == "1",
-                "single" => result.single = &*line.1 == "1",
-                "consume" => result.consume = &*line.1 == "1",
+                "single" => {
+                    result.single = &*line.1 == "1";
+                    result.single_mode = Toggle::from_reply(&line.1);
+                }
+                "consume" => {
+                    result.consume = &*line.1 == "1";
+                    result.consume_mode = Toggle::from_reply(&line.1);
+                }
 
                 "playlist" => result.queue_version = line.1.parse()?,
                 "playlistlength" => result.queue_len = line.1.parse()?,
@@ -270,6 +282,42 @@ impl FromStr for State {
     }
 }
 
+/// Mode that can be switched off, on, or on just until the current song ends
+#[derive(Debug, Clone, Copy, PartialEq, RustcEncodable, RustcDecodable)]
+pub enum Toggle {
+    /// off
+    Off,
+    /// on
+    On,
+    /// on for the current song only, then off again
+    Oneshot,
+}
+
+impl Default for Toggle {
+    fn default() -> Toggle {
+        Toggle::Off
+    }
+}
+
+impl Toggle {
+    fn from_reply(s: &str) -> Toggle {
+        match s {
+            "1" => Toggle::On,
+            "oneshot" => Toggle::Oneshot,
+            _ => Toggle::Off,
+        }
+    }
+
+    /// Value to send to MPD for this mode
+    pub fn to_arg(self) -> &'static str {
+        match self {
+            Toggle::Off => "0",
+            Toggle::On => "1",
+            Toggle::Oneshot => "oneshot",
+        }
+    }
+}
+
 /// Replay gain mode
 #[derive(Debug, Clone, Copy, PartialEq, RustcEncodable, RustcDecodable)]
 pub enum ReplayGain {
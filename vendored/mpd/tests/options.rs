@@ -71,3 +71,25 @@ fn crossfade() {
                    Some(Duration::from_secs(0))
                });
 }
+
+#[test]
+fn single_oneshot() {
+    let mut mpd = connect();
+    if mpd.version >= mpd::Version(0, 21, 0) {
+        mpd.set_single(mpd::Toggle::Oneshot).unwrap();
+        assert_eq!(mpd.status().unwrap().single_mode, mpd::Toggle::Oneshot);
+        mpd.set_single(mpd::Toggle::Off).unwrap();
+        assert_eq!(mpd.status().unwrap().single_mode, mpd::Toggle::Off);
+    }
+}
+
+#[test]
+fn consume_oneshot() {
+    let mut mpd = connect();
+    if mpd.version >= mpd::Version(0, 24, 0) {
+        mpd.set_consume(mpd::Toggle::Oneshot).unwrap();
+        assert_eq!(mpd.status().unwrap().consume_mode, mpd::Toggle::Oneshot);
+        mpd.set_consume(mpd::Toggle::Off).unwrap();
+        assert_eq!(mpd.status().unwrap().consume_mode, mpd::Toggle::Off);
+    }
+}
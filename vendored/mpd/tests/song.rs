@@ -26,3 +26,19 @@ fn rescan_update() {
     println!("update: {:?}", mpd.update());
     println!("rescan: {:?}", mpd.rescan());
 }
+
+#[test]
+/// Songs pushed or inserted in bulk all land in the queue, in order.
+fn push_and_insert_many() {
+    let mut mpd = connect();
+    mpd.clear().unwrap();
+
+    let paths = vec!["empty.flac".to_string(), "empty.flac".to_string()];
+    mpd.push_many(&paths).unwrap();
+    assert_eq!(mpd.queue().unwrap().len(), 2);
+
+    mpd.insert_many(&paths, 1).unwrap();
+    let queue = mpd.queue().unwrap();
+    assert_eq!(queue.len(), 4);
+    assert!(queue.iter().all(|song| song.file == "empty.flac"));
+}
@@ -15,3 +15,14 @@ fn set_sticker() {
     let sticker = mpd.sticker("song", "empty.flac", "test_sticker").unwrap();
     assert_eq!(sticker, VALUE);
 }
+
+#[test]
+/// Setting a sticker on several songs at once sets it on each of them.
+fn set_sticker_many() {
+    let mut mpd = connect();
+
+    mpd.set_sticker_many("song", &["empty.flac".to_string()], "test_sticker_many", "5").unwrap();
+
+    let sticker = mpd.sticker("song", "empty.flac", "test_sticker_many").unwrap();
+    assert_eq!(sticker, "5");
+}